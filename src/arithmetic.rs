@@ -0,0 +1,547 @@
+use anyhow::anyhow;
+
+use crate::{command::CommandOutput, state::ShellState};
+
+/// Where the variables of an expression are read from and assigned to.
+pub trait Variables {
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: &str);
+}
+
+impl Variables for ShellState {
+    fn get(&self, name: &str) -> Option<String> {
+        return self.variables.get(name).map(String::from);
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        self.assign(name, value);
+    }
+}
+
+/// How deeply variables whose values are expressions themselves may refer on.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+/// Longest first, so `**` is not read as two `*`.
+const OPERATORS: &[&str] = &[
+    "**", "++", "--", "+=", "-=", "*=", "/=", "%=", "<=", ">=", "==", "!=", "+", "-", "*", "/",
+    "%", "<", ">", "=", "!",
+];
+
+/// `=` and the operators that combine the old value with the new, `+=` being
+/// `x = x + value`.
+const ASSIGNMENTS: &[(&str, Option<&str>)] = &[
+    ("=", None),
+    ("+=", Some("+")),
+    ("-=", Some("-")),
+    ("*=", Some("*")),
+    ("/=", Some("/")),
+    ("%=", Some("%")),
+];
+
+#[derive(Debug)]
+enum Expression {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+    /// `name = value`, or `name op= value` with the operator.
+    Assign(String, Option<&'static str>, Box<Expression>),
+    /// `++name` and `--name` (`prefix`), or `name++` and `name--`.
+    Increment {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+/// Evaluates an arithmetic expression as in `$((...))` and `let`: 64-bit integers,
+/// `+ - * / % **`, comparisons that give 1 or 0, `( )`, `name = value` (and `+=`
+/// and the like) and `++`/`--` before or after a name. A variable that is unset or
+/// empty counts as 0.
+pub fn evaluate(expression: &str, variables: &mut impl Variables) -> anyhow::Result<i64> {
+    return evaluate_at_depth(expression, variables, 0)
+        .map_err(|e| return anyhow!("{}: {}", expression.trim(), e));
+}
+
+fn evaluate_at_depth(
+    expression: &str,
+    variables: &mut impl Variables,
+    depth: usize,
+) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(String::from("expression recursion level exceeded"));
+    }
+
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, index: 0 };
+    let parsed = parser.assignment()?;
+    if let Some(token) = parser.tokens.get(parser.index) {
+        return Err(format!(
+            "syntax error in expression (error token is \"{}\")",
+            token_text(token)
+        ));
+    }
+
+    return Evaluator { variables, depth }.evaluate(&parsed);
+}
+
+/// `let expression ...`: evaluates each argument in turn. The status is 1 when the
+/// last one comes out as 0, and 0 otherwise.
+pub fn let_builtin(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    if args.is_empty() {
+        return CommandOutput {
+            stdout: None,
+            stderr: Some(String::from("let: expression expected\n")),
+            status: 1,
+        };
+    }
+
+    let mut last = 0;
+    for arg in args {
+        match evaluate(arg, state) {
+            Ok(value) => last = value,
+            Err(e) => {
+                return CommandOutput {
+                    stdout: None,
+                    stderr: Some(format!("let: {}\n", e)),
+                    status: 1,
+                };
+            }
+        }
+    }
+
+    return CommandOutput {
+        stdout: None,
+        stderr: None,
+        status: if last == 0 { 1 } else { 0 },
+    };
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = expression.trim_start();
+
+    while let Some(current_char) = rest.chars().next() {
+        let length = if current_char.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| return !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..length])?));
+            length
+        } else if current_char.is_ascii_alphabetic() || current_char == '_' {
+            let length = rest
+                .find(|c: char| return !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..length].to_string()));
+            length
+        } else if current_char == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if current_char == ')' {
+            tokens.push(Token::Close);
+            1
+        } else {
+            let Some(operator) = OPERATORS
+                .iter()
+                .find(|operator| return rest.starts_with(**operator))
+            else {
+                return Err(format!(
+                    "syntax error: invalid arithmetic operator (error token is \"{}\")",
+                    rest
+                ));
+            };
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        };
+        rest = rest[length..].trim_start();
+    }
+
+    return Ok(tokens);
+}
+
+/// Decimal, `0x` hexadecimal or `0` octal, as in C.
+fn parse_number(text: &str) -> Result<i64, String> {
+    let (digits, radix) = match text {
+        _ if text.starts_with("0x") || text.starts_with("0X") => (&text[2..], 16),
+        _ if text.len() > 1 && text.starts_with('0') => (&text[1..], 8),
+        _ => (text, 10),
+    };
+
+    // Like bash, values too large for 64 bits wrap around.
+    let mut value: i64 = 0;
+    for digit in digits.chars() {
+        let Some(digit) = digit.to_digit(radix) else {
+            return Err(format!(
+                "value too great for base (error token is \"{}\")",
+                text
+            ));
+        };
+        value = value.wrapping_mul(radix as i64).wrapping_add(digit as i64);
+    }
+    if digits.is_empty() {
+        return Err(format!("invalid number (error token is \"{}\")", text));
+    }
+
+    return Ok(value);
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Number(value) => return value.to_string(),
+        Token::Name(name) => return name.clone(),
+        Token::Operator(operator) => return operator.to_string(),
+        Token::Open => return String::from("("),
+        Token::Close => return String::from(")"),
+    }
+}
+
+/// Precedence climbing over the tokens, from loosest to tightest binding:
+/// assignment, equality, comparison, sums, products, `**`, then unary operators.
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn next_operator(&self, operators: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.index) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                return Some(operator);
+            }
+            _ => return None,
+        }
+    }
+
+    fn assignment(&mut self) -> Result<Expression, String> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(operator))) =
+            (self.tokens.get(self.index), self.tokens.get(self.index + 1))
+        {
+            let assignment = ASSIGNMENTS
+                .iter()
+                .find(|(assignment, _)| return assignment == operator);
+            if let Some((_, combined)) = assignment {
+                let (name, combined) = (name.clone(), *combined);
+                self.index += 2;
+                return Ok(Expression::Assign(
+                    name,
+                    combined,
+                    Box::new(self.assignment()?),
+                ));
+            }
+        }
+
+        return self.binary(&[
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ]);
+    }
+
+    /// Left-associative binary operators, one precedence level per entry of `levels`.
+    fn binary(&mut self, levels: &[&[&str]]) -> Result<Expression, String> {
+        let Some((operators, tighter)) = levels.split_first() else {
+            return self.power();
+        };
+
+        let mut left = self.binary(tighter)?;
+        while let Some(operator) = self.next_operator(operators) {
+            self.index += 1;
+            let right = self.binary(tighter)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        return Ok(left);
+    }
+
+    /// `**` groups to the right: `2 ** 3 ** 2` is `2 ** 9`.
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.unary()?;
+        if self.next_operator(&["**"]).is_none() {
+            return Ok(base);
+        }
+
+        self.index += 1;
+        return Ok(Expression::Binary(
+            "**",
+            Box::new(base),
+            Box::new(self.power()?),
+        ));
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if let Some(operator) = self.next_operator(&["++", "--"]) {
+            self.index += 1;
+            if let Some(Token::Name(name)) = self.tokens.get(self.index).cloned() {
+                self.index += 1;
+                return Ok(Expression::Increment {
+                    name,
+                    delta: if operator == "++" { 1 } else { -1 },
+                    prefix: true,
+                });
+            }
+            // Without a name to change, `--5` is just two minus signs.
+            let sign = if operator == "++" { "+" } else { "-" };
+            let inner = Expression::Unary(sign, Box::new(self.unary()?));
+            return Ok(Expression::Unary(sign, Box::new(inner)));
+        }
+        if let Some(operator) = self.next_operator(&["+", "-", "!"]) {
+            self.index += 1;
+            return Ok(Expression::Unary(operator, Box::new(self.unary()?)));
+        }
+
+        return self.primary();
+    }
+
+    fn primary(&mut self) -> Result<Expression, String> {
+        let Some(token) = self.tokens.get(self.index).cloned() else {
+            return Err(String::from("syntax error: operand expected"));
+        };
+        self.index += 1;
+
+        match token {
+            Token::Number(value) => return Ok(Expression::Number(value)),
+            Token::Name(name) => match self.next_operator(&["++", "--"]) {
+                Some(operator) => {
+                    self.index += 1;
+                    return Ok(Expression::Increment {
+                        name,
+                        delta: if operator == "++" { 1 } else { -1 },
+                        prefix: false,
+                    });
+                }
+                None => return Ok(Expression::Variable(name)),
+            },
+            Token::Open => {
+                let inner = self.assignment()?;
+                if self.tokens.get(self.index) != Some(&Token::Close) {
+                    return Err(String::from("missing `)'"));
+                }
+                self.index += 1;
+                return Ok(inner);
+            }
+            token => {
+                return Err(format!(
+                    "syntax error: operand expected (error token is \"{}\")",
+                    token_text(&token)
+                ));
+            }
+        }
+    }
+}
+
+struct Evaluator<'v, V: Variables> {
+    variables: &'v mut V,
+    depth: usize,
+}
+
+impl<V: Variables> Evaluator<'_, V> {
+    fn evaluate(&mut self, expression: &Expression) -> Result<i64, String> {
+        match expression {
+            Expression::Number(value) => return Ok(*value),
+            Expression::Variable(name) => return self.variable(name),
+            Expression::Unary(operator, operand) => {
+                let operand = self.evaluate(operand)?;
+                match *operator {
+                    "-" => return Ok(operand.wrapping_neg()),
+                    "!" => return Ok((operand == 0) as i64),
+                    _ => return Ok(operand),
+                }
+            }
+            Expression::Binary(operator, left, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                return apply(operator, left, right);
+            }
+            Expression::Assign(name, operator, value) => {
+                let mut value = self.evaluate(value)?;
+                if let Some(operator) = operator {
+                    value = apply(operator, self.variable(name)?, value)?;
+                }
+                self.variables.set(name, &value.to_string());
+                return Ok(value);
+            }
+            Expression::Increment {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = self.variable(name)?;
+                let new = old.wrapping_add(*delta);
+                self.variables.set(name, &new.to_string());
+                return Ok(if *prefix { new } else { old });
+            }
+        }
+    }
+
+    /// A variable's value may itself be an expression, which is evaluated in turn.
+    fn variable(&mut self, name: &str) -> Result<i64, String> {
+        let value = self.variables.get(name).unwrap_or_default();
+        if let Ok(number) = value.trim().parse::<i64>() {
+            return Ok(number);
+        }
+
+        return evaluate_at_depth(&value, self.variables, self.depth + 1);
+    }
+}
+
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64, String> {
+    let value = match operator {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => {
+            return Err(String::from("division by 0 (error token is \"0\")"));
+        }
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err(String::from("exponent less than 0")),
+        "**" => left.wrapping_pow(right.min(u32::MAX as i64) as u32),
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        "<=" => (left <= right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        _ => return Err(format!("{}: unknown operator", operator)),
+    };
+
+    return Ok(value);
+}
+
+#[cfg(test)]
+mod arithmetic_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    fn eval(expression: &str) -> anyhow::Result<i64> {
+        return evaluate(expression, &mut ShellState::default());
+    }
+
+    #[test]
+    fn precedence_and_grouping() -> anyhow::Result<()> {
+        assert_eq!(eval(" 1 + 2 * 3 ")?, 7);
+        assert_eq!(eval("(1 + 2) * 3")?, 9);
+        assert_eq!(eval("10 - 4 - 3")?, 3);
+        assert_eq!(eval("7 / 2 + 7 % 2")?, 4);
+        assert_eq!(eval("2 ** 3 ** 2")?, 512);
+        assert_eq!(eval("-2 ** 2")?, 4);
+        assert_eq!(eval("1 + 2 < 4 == 1")?, 1);
+        assert_eq!(eval("3 >= 4")?, 0);
+        assert_eq!(eval("!0 + !5")?, 1);
+        assert_eq!(eval("0x1f + 010")?, 39);
+        assert_eq!(eval("")?, 0);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn variables_are_read_and_assigned() -> anyhow::Result<()> {
+        let mut state = ShellState::default();
+        state.variables.set("x", "4");
+        state.variables.set("sum", "x + 1");
+
+        assert_eq!(evaluate("x * 2 + unset", &mut state)?, 8);
+        assert_eq!(evaluate("sum * 2", &mut state)?, 10);
+        assert_eq!(evaluate("y = z = x + 1", &mut state)?, 5);
+        assert_eq!(state.variables.get("y"), Some("5"));
+        assert_eq!(state.variables.get("z"), Some("5"));
+
+        state.variables.set("loop", "loop");
+        assert!(evaluate("loop", &mut state).is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn increments_follow_c_order() -> anyhow::Result<()> {
+        let mut state = ShellState::default();
+        state.variables.set("x", "1");
+
+        assert_eq!(evaluate("x++", &mut state)?, 1);
+        assert_eq!(state.variables.get("x"), Some("2"));
+        assert_eq!(evaluate("++x", &mut state)?, 3);
+        assert_eq!(evaluate("x--", &mut state)?, 3);
+        assert_eq!(evaluate("--x", &mut state)?, 1);
+        assert_eq!(state.variables.get("x"), Some("1"));
+
+        // The left operand is evaluated first, so it sees the old value.
+        assert_eq!(evaluate("x++ + x", &mut state)?, 3);
+        assert_eq!(evaluate("++unset", &mut state)?, 1);
+        assert_eq!(eval("--5")?, 5);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn compound_assignments() -> anyhow::Result<()> {
+        let mut state = ShellState::default();
+        state.variables.set("i", "5");
+
+        for (expression, value) in [
+            ("i += 2", 7),
+            ("i -= 1", 6),
+            ("i *= 4", 24),
+            ("i /= 5", 4),
+            ("i %= 3", 1),
+        ] {
+            assert_eq!(evaluate(expression, &mut state)?, value, "{}", expression);
+            assert_eq!(state.variables.get("i"), Some(value.to_string().as_str()));
+        }
+        assert!(evaluate("i /= 0", &mut state).is_err());
+        assert_eq!(state.variables.get("i"), Some("1"));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            eval("5 / 0").unwrap_err().to_string(),
+            "5 / 0: division by 0 (error token is \"0\")"
+        );
+        assert_eq!(
+            eval("1 +").unwrap_err().to_string(),
+            "1 +: syntax error: operand expected"
+        );
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("2 ** -1").is_err());
+        assert!(eval("1 @ 2").is_err());
+    }
+
+    #[test]
+    fn let_status_follows_the_last_value() {
+        let mut state = ShellState::default();
+
+        assert_eq!(
+            let_builtin(&mut state, &args(&["x = 2", "y = x * 3"])).status,
+            0
+        );
+        assert_eq!(state.variables.get("y"), Some("6"));
+        assert_eq!(let_builtin(&mut state, &args(&["x - 2"])).status, 1);
+
+        let output = let_builtin(&mut state, &args(&["1 / 0"]));
+        assert_eq!(output.status, 1);
+        assert_eq!(
+            output.stderr,
+            Some(String::from(
+                "let: 1 / 0: division by 0 (error token is \"0\")\n"
+            ))
+        );
+        assert_eq!(let_builtin(&mut state, &[]).status, 1);
+    }
+}
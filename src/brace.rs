@@ -0,0 +1,242 @@
+use anyhow::anyhow;
+
+struct Group {
+    open: usize,
+    close: usize,
+    alternatives: Vec<String>,
+}
+
+/// Expands `{a,b}` alternatives and `{1..5}` / `{a..e}` ranges in a raw word.
+/// Quoted or escaped braces and `${...}` parameters are left alone. Fails once more
+/// than `limit` words would be produced.
+pub fn expand_braces(word: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+    let mut words = vec![];
+    expand_into(word, limit, &mut words)?;
+    return Ok(words);
+}
+
+pub fn too_long() -> anyhow::Error {
+    return anyhow!("argument list too long");
+}
+
+fn expand_into(word: &str, limit: usize, words: &mut Vec<String>) -> anyhow::Result<()> {
+    let Some(group) = find_group(word, limit)? else {
+        if words.len() >= limit {
+            return Err(too_long());
+        }
+        words.push(word.to_string());
+        return Ok(());
+    };
+
+    let preamble = &word[..group.open];
+    let postscript = &word[group.close + 1..];
+    for alternative in group.alternatives {
+        let word = format!("{}{}{}", preamble, alternative, postscript);
+        expand_into(&word, limit, words)?;
+    }
+
+    return Ok(());
+}
+
+/// Byte offsets of the braces and commas that take part in brace expansion.
+fn structural_chars(word: &str) -> Vec<(usize, char)> {
+    let mut structural = vec![];
+    let mut inside_single_quotes = false;
+    let mut inside_double_quotes = false;
+    let mut parameter_depth = 0;
+    let mut chars = word.char_indices().peekable();
+
+    while let Some((index, current_char)) = chars.next() {
+        match current_char {
+            '\\' if !inside_single_quotes => {
+                chars.next();
+            }
+            '\'' if !inside_double_quotes => inside_single_quotes = !inside_single_quotes,
+            '"' if !inside_single_quotes => inside_double_quotes = !inside_double_quotes,
+            _ if inside_single_quotes || inside_double_quotes => {}
+            '$' if chars.peek().is_some_and(|(_, next_char)| *next_char == '{') => {
+                chars.next();
+                parameter_depth += 1;
+            }
+            '}' if parameter_depth > 0 => parameter_depth -= 1,
+            _ if parameter_depth > 0 => {}
+            '{' | '}' | ',' => structural.push((index, current_char)),
+            _ => {}
+        }
+    }
+
+    return structural;
+}
+
+/// Finds the first brace group that expands: one with a top-level comma or a valid
+/// range. Groups like `{a}` or `{}` stay literal.
+fn find_group(word: &str, limit: usize) -> anyhow::Result<Option<Group>> {
+    let structural = structural_chars(word);
+
+    for (start, &(open, current_char)) in structural.iter().enumerate() {
+        if current_char != '{' {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = vec![];
+        let mut close = None;
+        for &(index, current_char) in &structural[start..] {
+            match current_char {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(index);
+                        break;
+                    }
+                }
+                ',' if depth == 1 => commas.push(index),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+
+        if !commas.is_empty() {
+            let mut alternatives = vec![];
+            let mut alternative_start = open + 1;
+            for comma in commas.into_iter().chain([close]) {
+                alternatives.push(word[alternative_start..comma].to_string());
+                alternative_start = comma + 1;
+            }
+            return Ok(Some(Group {
+                open,
+                close,
+                alternatives,
+            }));
+        }
+
+        if let Some(alternatives) = parse_range(&word[open + 1..close], limit)? {
+            return Ok(Some(Group {
+                open,
+                close,
+                alternatives,
+            }));
+        }
+    }
+
+    return Ok(None);
+}
+
+/// Parses `start..end` or `start..end..step` over integers or single characters.
+fn parse_range(content: &str, limit: usize) -> anyhow::Result<Option<Vec<String>>> {
+    let parts: Vec<&str> = content.split("..").collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => {
+            let Ok(step) = step.parse::<i64>() else {
+                return Ok(None);
+            };
+            (*start, *end, step.unsigned_abs().max(1))
+        }
+        _ => return Ok(None),
+    };
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let count = first.abs_diff(last) / step + 1;
+        if count > limit as u64 {
+            return Err(too_long());
+        }
+
+        let is_padded = |value: &str| {
+            let digits = value.trim_start_matches('-');
+            return digits.len() > 1 && digits.starts_with('0');
+        };
+        let width = if is_padded(start) || is_padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        let values = (0..count)
+            .map(|offset| {
+                let offset = (offset * step) as i64;
+                let value = if first <= last {
+                    first + offset
+                } else {
+                    first - offset
+                };
+                return format!("{:0width$}", value, width = width);
+            })
+            .collect();
+        return Ok(Some(values));
+    }
+
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    let (Some(first), None, Some(last), None) = (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) else {
+        return Ok(None);
+    };
+    if !first.is_ascii_alphabetic() || !last.is_ascii_alphabetic() {
+        return Ok(None);
+    }
+
+    let (first, last) = (first as u8, last as u8);
+    let mut values: Vec<String> = (first.min(last)..=first.max(last))
+        .step_by(step as usize)
+        .map(|value| return char::from(value).to_string())
+        .collect();
+    if first > last {
+        values.reverse();
+    }
+
+    return Ok(Some(values));
+}
+
+#[cfg(test)]
+mod brace_tests {
+    use super::*;
+
+    fn expand(word: &str) -> Vec<String> {
+        return expand_braces(word, 1000).unwrap();
+    }
+
+    #[test]
+    fn alternatives() {
+        assert_eq!(expand("a{b,c}d"), vec!["abd", "acd"]);
+        assert_eq!(expand("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{,y}"), vec!["x", "xy"]);
+        assert_eq!(expand("{a,{b,c}}"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(expand("{1..3}"), vec!["1", "2", "3"]);
+        assert_eq!(expand("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(expand("{1..10..4}"), vec!["1", "5", "9"]);
+        assert_eq!(expand("{08..10}"), vec!["08", "09", "10"]);
+        assert_eq!(expand("{a..c}"), vec!["a", "b", "c"]);
+        assert_eq!(expand("{c..a}"), vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn literal_braces() {
+        assert_eq!(expand("{a}"), vec!["{a}"]);
+        assert_eq!(expand("{}"), vec!["{}"]);
+        assert_eq!(expand("{a,b"), vec!["{a,b"]);
+        assert_eq!(expand("'{a,b}'"), vec!["'{a,b}'"]);
+        assert_eq!(expand(r"\{a,b}"), vec![r"\{a,b}"]);
+        assert_eq!(expand("${NAME}"), vec!["${NAME}"]);
+        assert_eq!(expand("{1..z}"), vec!["{1..z}"]);
+    }
+
+    #[test]
+    fn oversized_range_is_an_error() {
+        let error = expand_braces("{1..100000000}", 1_000_000).unwrap_err();
+        assert_eq!(error.to_string(), "argument list too long");
+
+        let error = expand_braces("{1..100}{1..100}", 1000).unwrap_err();
+        assert_eq!(error.to_string(), "argument list too long");
+    }
+}
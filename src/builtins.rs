@@ -0,0 +1,316 @@
+use crate::command::CommandOutput;
+
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+/// Every builtin the shell implements; `type`, completion and `help` read from here.
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: ".",
+        usage: ". filename [arguments]",
+        description: "Execute commands from a file in the current shell.",
+    },
+    Builtin {
+        name: "bg",
+        usage: "bg [jobspec ...]",
+        description: "Move jobs to the background.",
+    },
+    Builtin {
+        name: "break",
+        usage: "break",
+        description: "Exit the enclosing select loop.",
+    },
+    Builtin {
+        name: "builtin",
+        usage: "builtin [shell-builtin [arg ...]]",
+        description: "Run a shell builtin, bypassing functions of the same name.",
+    },
+    Builtin {
+        name: "cd",
+        usage: "cd [dir]",
+        description: "Change the shell working directory.",
+    },
+    Builtin {
+        name: "compgen",
+        usage: "compgen [-bcdf] [word]",
+        description: "Display the possible completions of a word.",
+    },
+    Builtin {
+        name: "dirs",
+        usage: "dirs [-clpv]",
+        description: "Display the directory stack.",
+    },
+    Builtin {
+        name: "disown",
+        usage: "disown [-a] [jobspec ...]",
+        description: "Remove jobs from current shell.",
+    },
+    Builtin {
+        name: "echo",
+        usage: "echo [-neE] [arg ...]",
+        description: "Write arguments to the standard output.",
+    },
+    Builtin {
+        name: "exit",
+        usage: "exit [n]",
+        description: "Exit the shell.",
+    },
+    Builtin {
+        name: "export",
+        usage: "export [-n] [-p] [name[=value] ...]",
+        description: "Set export attribute for shell variables.",
+    },
+    Builtin {
+        name: "fc",
+        usage: "fc",
+        description: "Edit the last command in $FCEDIT or $EDITOR and run the result.",
+    },
+    Builtin {
+        name: "fg",
+        usage: "fg [jobspec]",
+        description: "Move job to the foreground.",
+    },
+    Builtin {
+        name: "getopts",
+        usage: "getopts optstring name [arg ...]",
+        description: "Parse option arguments.",
+    },
+    Builtin {
+        name: "hash",
+        usage: "hash [-r] [name ...]",
+        description: "Remember or display program locations.",
+    },
+    Builtin {
+        name: "help",
+        usage: "help [pattern ...]",
+        description: "Display information about builtin commands.",
+    },
+    Builtin {
+        name: "history",
+        usage: "history [n]",
+        description: "Display the command history list.",
+    },
+    Builtin {
+        name: "jobs",
+        usage: "jobs [-l]",
+        description: "Display status of jobs.",
+    },
+    Builtin {
+        name: "kill",
+        usage: "kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]",
+        description: "Send a signal to a job.",
+    },
+    Builtin {
+        name: "let",
+        usage: "let arg [arg ...]",
+        description: "Evaluate arithmetic expressions.",
+    },
+    Builtin {
+        name: "local",
+        usage: "local [name[=value] ...]",
+        description: "Define variables local to the running function.",
+    },
+    Builtin {
+        name: "nohup",
+        usage: "nohup command [arg ...]",
+        description: "Run a command that keeps running after a hangup.",
+    },
+    Builtin {
+        name: "popd",
+        usage: "popd",
+        description: "Remove directories from stack.",
+    },
+    Builtin {
+        name: "pushd",
+        usage: "pushd [dir]",
+        description: "Add directories to stack.",
+    },
+    Builtin {
+        name: "pwd",
+        usage: "pwd",
+        description: "Print the name of the current working directory.",
+    },
+    Builtin {
+        name: "read",
+        usage: "read [-r] [-t timeout] [name ...]",
+        description: "Read a line from the standard input and split it into fields.",
+    },
+    Builtin {
+        name: "set",
+        usage: "set [-CPv] [-o option-name] [--] [arg ...]",
+        description: "Set or unset shell options and positional parameters.",
+    },
+    Builtin {
+        name: "shift",
+        usage: "shift [n]",
+        description: "Shift positional parameters.",
+    },
+    Builtin {
+        name: "shopt",
+        usage: "shopt [-psu] [optname ...]",
+        description: "Set and unset shell options.",
+    },
+    Builtin {
+        name: "source",
+        usage: "source filename [arguments]",
+        description: "Execute commands from a file in the current shell.",
+    },
+    Builtin {
+        name: "timeout",
+        usage: "timeout duration command [arg ...]",
+        description: "Run a command, stopping it after a time limit.",
+    },
+    Builtin {
+        name: "trap",
+        usage: "trap [-lp] [[action] sigspec ...]",
+        description: "Run a command when the shell receives a signal.",
+    },
+    Builtin {
+        name: "type",
+        usage: "type name",
+        description: "Display information about command type.",
+    },
+    Builtin {
+        name: "unset",
+        usage: "unset [-f] [-v] [name ...]",
+        description: "Remove shell variables or functions.",
+    },
+    Builtin {
+        name: "wait",
+        usage: "wait [jobspec or pid ...]",
+        description: "Wait for job completion and return exit status.",
+    },
+];
+
+/// Reserved words of the shell grammar. `type` reports them before builtins.
+pub const KEYWORDS: &[&str] = &[
+    "!", "[[", "]]", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for",
+    "function", "if", "in", "select", "then", "time", "until", "while",
+];
+
+pub fn is_keyword(name: &str) -> bool {
+    return KEYWORDS.contains(&name);
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    return find(name).is_some();
+}
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    return BUILTINS.iter().find(|builtin| builtin.name == name);
+}
+
+/// Where descriptions start in the `help` listing.
+const DESCRIPTION_COLUMN: usize = 36;
+
+/// `help`: lists every builtin, or prints the usage of the named ones.
+pub fn help(names: &[String]) -> CommandOutput {
+    if names.is_empty() {
+        let mut stdout = String::from("These shell commands are defined internally.\n\n");
+        for builtin in BUILTINS {
+            // A usage that reaches the column gets a line of its own.
+            let separator = match builtin.usage.len() + 2 > DESCRIPTION_COLUMN {
+                true => format!("\n{}", " ".repeat(DESCRIPTION_COLUMN)),
+                false => " ".repeat(DESCRIPTION_COLUMN - builtin.usage.len()),
+            };
+            stdout.push_str(&format!(
+                "{}{}{}\n",
+                builtin.usage, separator, builtin.description
+            ));
+        }
+
+        return CommandOutput {
+            stdout: Some(stdout),
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    for name in names {
+        match find(name) {
+            Some(builtin) => stdout.push_str(&format!(
+                "{}: {}\n    {}\n",
+                builtin.name, builtin.usage, builtin.description
+            )),
+            None => stderr.push_str(&format!(
+                "help: no help topics match `{}'.  Try `help help' or `man -k {}' or `info {}'.\n",
+                name, name, name
+            )),
+        }
+    }
+
+    let status = if stderr.is_empty() { 0 } else { 1 };
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+        status,
+    };
+}
+
+#[cfg(test)]
+mod builtins_tests {
+    use super::*;
+
+    #[test]
+    fn list_includes_core_builtins() {
+        let output = help(&[]);
+        let stdout = output.stdout.unwrap();
+
+        for name in ["cd", "echo", "exit", "pwd", "type"] {
+            let builtin = find(name).unwrap();
+            assert!(stdout.contains(builtin.usage), "{}", name);
+        }
+        assert_eq!(output.status, 0);
+    }
+
+    #[test]
+    fn descriptions_are_kept_apart_from_long_usages() {
+        let stdout = help(&[]).stdout.unwrap();
+
+        for name in ["kill", "set"] {
+            let builtin = find(name).unwrap();
+            assert!(stdout.contains(&format!(
+                "{}\n{}{}\n",
+                builtin.usage,
+                " ".repeat(DESCRIPTION_COLUMN),
+                builtin.description
+            )));
+        }
+        let pwd = find("pwd").unwrap();
+        assert!(stdout.contains(&format!(
+            "{:<width$}{}\n",
+            pwd.usage,
+            pwd.description,
+            width = DESCRIPTION_COLUMN
+        )));
+        for builtin in BUILTINS {
+            assert!(!stdout.contains(&format!("{}{}", builtin.usage, builtin.description)));
+        }
+    }
+
+    #[test]
+    fn help_for_a_single_builtin() {
+        let output = help(&["pwd".to_string()]);
+        assert_eq!(
+            output.stdout,
+            Some("pwd: pwd\n    Print the name of the current working directory.\n".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_topic() {
+        let output = help(&["nope".to_string()]);
+        assert_eq!(output.stdout, None);
+        assert!(output
+            .stderr
+            .unwrap()
+            .starts_with("help: no help topics match `nope'."));
+        assert_eq!(output.status, 1);
+    }
+}
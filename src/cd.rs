@@ -0,0 +1,321 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    command::{io_error_message, CommandOutput},
+    dirs,
+    state::ShellState,
+};
+
+/// `cd [dir]`. Without an argument goes to `HOME`, `-` goes back to `OLDPWD`, `~N`
+/// picks an entry of the directory stack and relative names are searched for in
+/// `CDPATH`. The new directory is printed when it came from `-` or a non-empty
+/// `CDPATH` entry. An empty name, as from a quoted unset variable, leaves the
+/// directory as it is.
+pub fn cd(state: &mut ShellState, path: Option<&str>) -> CommandOutput {
+    if path == Some("") {
+        return CommandOutput {
+            stdout: None,
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let home_path = state
+        .variables
+        .get("HOME")
+        .filter(|home| return !home.is_empty())
+        .map(|home| return home.to_string());
+    let uses_home = match path {
+        None => true,
+        Some(path) => path == "~" || path.starts_with("~/"),
+    };
+    let home_path = match home_path {
+        Some(home_path) => home_path,
+        None if uses_home => return failure("cd: HOME not set"),
+        None => String::new(),
+    };
+    let home_path = home_path.as_str();
+
+    let stack_entry = path.and_then(|path| return dirs::stack_reference(state, path));
+    let (target, print_dir) = match (path, stack_entry) {
+        (_, Some(Ok(entry))) => (entry, false),
+        (_, Some(Err(message))) => return failure(&format!("cd: {}", message)),
+        (Some("-"), None) => match state.variables.get("OLDPWD") {
+            Some(old_pwd) if !old_pwd.is_empty() => (PathBuf::from(old_pwd), true),
+            _ => return failure("cd: OLDPWD not set"),
+        },
+        (Some(path), None) => {
+            let path = match path.strip_prefix('~') {
+                Some(rest) if uses_home => format!("{}{}", home_path, rest),
+                _ => path.to_string(),
+            };
+            match search_cdpath(state, &path) {
+                Some(found) => (found, true),
+                None => (PathBuf::from(path), false),
+            }
+        }
+        (None, _) => (PathBuf::from(home_path), false),
+    };
+
+    if let Err(e) = state.change_dir(&target) {
+        let shown = path.unwrap_or(home_path);
+        let suggestion = match e.kind() {
+            ErrorKind::NotFound if state.options.is_set("cdsuggest") => suggest(&target)
+                .map(|suggestion| return format!(" (did you mean '{}'?)", suggestion.display())),
+            _ => None,
+        };
+        return failure(&format!(
+            "cd: {}: {}{}",
+            shown,
+            io_error_message(&e),
+            suggestion.unwrap_or_default()
+        ));
+    }
+
+    let stdout = match print_dir {
+        true => state
+            .current_dir()
+            .ok()
+            .map(|dir| return format!("{}\n", dir.display())),
+        false => None,
+    };
+    return CommandOutput {
+        stdout,
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// Looks `path` up in each non-empty `CDPATH` entry. Absolute paths and ones starting
+/// with `.` or `..` are never searched; empty entries mean the current directory,
+/// which the caller falls back to anyway.
+fn search_cdpath(state: &ShellState, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path);
+    let is_searchable = !relative.is_absolute()
+        && !matches!(path.split('/').next(), Some(".") | Some(".."))
+        && !path.is_empty();
+    if !is_searchable {
+        return None;
+    }
+
+    let cdpath = state.variables.get("CDPATH")?;
+    return cdpath
+        .split(':')
+        .filter(|entry| return !entry.is_empty())
+        .map(|entry| Path::new(entry).join(relative))
+        .find(|candidate| return candidate.is_dir());
+}
+
+/// The directory next to a missing `target` whose name is closest to it, at most two
+/// edits away.
+fn suggest(target: &Path) -> Option<PathBuf> {
+    let name = target.file_name()?.to_str()?;
+    let parent = target.parent().unwrap_or(Path::new(""));
+    let entries = fs::read_dir(if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    })
+    .ok()?;
+
+    let mut candidates: Vec<(usize, String)> = entries
+        .flatten()
+        .filter(|entry| return entry.path().is_dir())
+        .filter_map(|entry| return entry.file_name().into_string().ok())
+        .map(|candidate| return (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| return *distance <= 2)
+        .collect();
+    candidates.sort();
+
+    let (_, closest) = candidates.into_iter().next()?;
+    return Some(parent.join(closest));
+}
+
+/// The Levenshtein distance: how many characters must be inserted, deleted or
+/// replaced to turn `first` into `second`.
+fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    for (index, first_char) in first.chars().enumerate() {
+        let mut current = vec![index + 1];
+        for (other_index, second_char) in second.iter().enumerate() {
+            let replaced = previous[other_index] + usize::from(first_char != *second_char);
+            let deleted = previous[other_index + 1] + 1;
+            let inserted = current[other_index] + 1;
+            current.push(replaced.min(deleted).min(inserted));
+        }
+        previous = current;
+    }
+
+    return previous[second.len()];
+}
+
+fn failure(message: &str) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}\n", message)),
+        status: 1,
+    };
+}
+
+#[cfg(all(test, unix))]
+mod cd_tests {
+    use tempfile::TempDir;
+
+    use crate::test_support::in_dir;
+
+    use super::*;
+
+    #[test]
+    fn home_not_set() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::default();
+
+        in_dir(dir.path(), || {
+            for path in [None, Some("~"), Some("~/src")] {
+                let output = cd(&mut state, path);
+                assert_eq!(output.stderr, Some(String::from("cd: HOME not set\n")));
+                assert_eq!(output.status, 1);
+            }
+
+            assert_eq!(cd(&mut state, Some("/")).status, 0);
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn tilde_number_picks_a_stack_entry() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let names = ["first", "second", "third"];
+        for name in names {
+            std::fs::create_dir(dir.path().join(name))?;
+        }
+        let mut state = ShellState::default();
+
+        in_dir(dir.path(), || {
+            for name in names {
+                let path = dir.path().join(name).display().to_string();
+                assert_eq!(crate::dirs::pushd(&mut state, Some(&path)).status, 0);
+            }
+
+            // `dirs` now lists third, second, first and the starting directory.
+            assert_eq!(cd(&mut state, Some("~1")).status, 0);
+            assert_eq!(
+                state.variables.get("PWD"),
+                dir.path().join("second").to_str()
+            );
+            assert_eq!(cd(&mut state, Some("~-0")).status, 0);
+            assert_eq!(state.variables.get("PWD"), dir.path().to_str());
+
+            let output = cd(&mut state, Some("~9"));
+            assert_eq!(
+                output.stderr,
+                Some(String::from("cd: ~9: directory stack index out of range\n"))
+            );
+            assert_eq!(output.status, 1);
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn tilde_expands_to_home() -> anyhow::Result<()> {
+        let home = TempDir::new()?;
+        std::fs::create_dir(home.path().join("src"))?;
+        let mut state = ShellState::default();
+        state
+            .variables
+            .set("HOME", &home.path().display().to_string());
+
+        in_dir(home.path(), || {
+            assert_eq!(cd(&mut state, Some("~/src")).status, 0);
+            assert_eq!(
+                state.variables.get("PWD"),
+                Some(home.path().join("src").to_str().unwrap())
+            );
+
+            assert_eq!(cd(&mut state, None).status, 0);
+            assert_eq!(state.variables.get("PWD"), home.path().to_str());
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn physical_option_resolves_symlinks() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        std::fs::create_dir(&real)?;
+        std::os::unix::fs::symlink(&real, &link)?;
+        let mut state = ShellState::default();
+
+        in_dir(dir.path(), || {
+            assert_eq!(cd(&mut state, link.to_str()).status, 0);
+            assert_eq!(state.variables.get("PWD"), link.to_str());
+
+            crate::set::set(&mut state, &[String::from("-P")]);
+            assert_eq!(cd(&mut state, link.to_str()).status, 0);
+            let canonical = real.canonicalize().unwrap();
+            assert_eq!(state.variables.get("PWD"), canonical.to_str());
+            assert_eq!(std::env::current_dir().unwrap(), canonical);
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn suggests_a_near_miss_directory() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        for name in ["food", "src", "docs"] {
+            std::fs::create_dir(dir.path().join(name))?;
+        }
+        std::fs::write(dir.path().join("foo.txt"), "")?;
+        let mut state = ShellState::default();
+
+        in_dir(dir.path(), || {
+            let output = cd(&mut state, Some("foo"));
+            assert_eq!(
+                output.stderr,
+                Some(String::from("cd: foo: No such file or directory\n"))
+            );
+
+            state.options.set("cdsuggest", true);
+            let output = cd(&mut state, Some("foo"));
+            assert_eq!(
+                output.stderr,
+                Some(String::from(
+                    "cd: foo: No such file or directory (did you mean 'food'?)\n"
+                ))
+            );
+            assert_eq!(output.status, 1);
+
+            let nested = format!("{}/dcos", dir.path().display());
+            let output = cd(&mut state, Some(&nested));
+            assert_eq!(
+                output.stderr,
+                Some(format!(
+                    "cd: {}: No such file or directory (did you mean '{}'?)\n",
+                    nested,
+                    dir.path().join("docs").display()
+                ))
+            );
+
+            let output = cd(&mut state, Some("elsewhere"));
+            assert_eq!(
+                output.stderr,
+                Some(String::from("cd: elsewhere: No such file or directory\n"))
+            );
+        });
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "ab"), 2);
+
+        return Ok(());
+    }
+}
@@ -1,25 +1,162 @@
 use anyhow::anyhow;
-use std::str::FromStr;
+use std::{
+    cell::{Cell, RefCell},
+    fs::File,
+    io::{self, BufReader, IsTerminal, Write},
+    path::Path,
+    process::{Child, ChildStdout, Stdio},
+    str::FromStr,
+    time::Duration,
+};
 
 use crate::{
-    executable::{ExecutablePathFinder, ExecutableRunner},
-    prompt::Prompter,
-    redirection::{self, Redirection},
+    arithmetic::let_builtin,
+    builtins::{help, is_builtin, is_keyword},
+    cd::cd,
+    completion::compgen,
+    dirs::{dirs, popd, pushd},
+    echo::{echo, posix_echo, xpg_echo},
+    executable::{
+        self, is_executable, Attached, ExecutablePathFinder, ExecutableRunner, Invocation,
+    },
+    expansion::Expander,
+    export::export,
+    fc::{self, EditOutcome},
+    getopts::getopts,
+    history::history,
+    jobs::{bg, disown, jobs, wait},
+    local::local,
+    nohup, pager,
+    prompt::{Prompter, ReadTimedOut},
+    read::{self, read},
+    redirection::{self, Redirection, Target},
+    set::set,
+    shopt::shopt,
+    signals::{kill, trap},
+    state::ShellState,
+    statement::{self, Statement},
+    substitution, timeout,
+    tokenizer::{self, tokenize, Token},
+    unset::unset,
+    variables::{split_assignment, Variable},
 };
 
 #[derive(Debug, PartialEq)]
 enum TypeCommand {
+    Keyword { cmd: String },
     WellKnown { cmd: String },
     Unknown { cmd: String },
 }
 
 #[derive(Debug, PartialEq)]
 enum BuiltinCommand {
-    Exit { code: i32 },
-    Echo { input: String },
+    Exit {
+        code: Option<String>,
+    },
+    Echo {
+        args: Vec<String>,
+    },
     Type(TypeCommand),
     Pwd,
-    Cd { path: String },
+    Cd {
+        path: Option<String>,
+    },
+    Getopts {
+        optstring: String,
+        name: String,
+        args: Vec<String>,
+    },
+    Hash {
+        args: Vec<String>,
+    },
+    Set {
+        args: Vec<String>,
+    },
+    Export {
+        args: Vec<String>,
+    },
+    Unset {
+        args: Vec<String>,
+    },
+    Pushd {
+        dir: Option<String>,
+    },
+    Popd,
+    Dirs {
+        args: Vec<String>,
+    },
+    Help {
+        names: Vec<String>,
+    },
+    History {
+        args: Vec<String>,
+    },
+    Read {
+        args: Vec<String>,
+    },
+    Let {
+        args: Vec<String>,
+    },
+    Local {
+        args: Vec<String>,
+    },
+    Shift {
+        count: Option<String>,
+    },
+    Break,
+    /// `source file [args]` or `. file [args]`; `-` reads standard input.
+    Source {
+        path: String,
+        args: Vec<String>,
+    },
+    Jobs {
+        args: Vec<String>,
+    },
+    Disown {
+        args: Vec<String>,
+    },
+    Fg {
+        args: Vec<String>,
+    },
+    Bg {
+        args: Vec<String>,
+    },
+    Wait {
+        args: Vec<String>,
+    },
+    Kill {
+        args: Vec<String>,
+    },
+    Trap {
+        args: Vec<String>,
+    },
+    Shopt {
+        args: Vec<String>,
+    },
+    Compgen {
+        args: Vec<String>,
+    },
+    /// `nohup cmd [args]`: runs the program `cmd` with `SIGHUP` ignored.
+    Nohup {
+        cmd: String,
+        args: Vec<String>,
+    },
+    /// `timeout duration cmd [args]`: runs the program `cmd`, stopping it once
+    /// `limit` has passed.
+    Timeout {
+        limit: Duration,
+        cmd: String,
+        args: Vec<String>,
+    },
+    Fc,
+    /// `builtin name [args]`: runs the builtin even when a function has the same name.
+    Builtin {
+        args: Vec<String>,
+    },
+    /// Arguments a builtin cannot work with; reported with status 2.
+    Usage {
+        message: String,
+    },
 }
 
 #[derive(Debug, PartialEq)]
@@ -29,6 +166,12 @@ enum CommandKind {
 }
 
 impl CommandKind {
+    fn usage(message: &str) -> Self {
+        return Self::Builtin(BuiltinCommand::Usage {
+            message: message.to_string(),
+        });
+    }
+
     fn new(args: Vec<String>) -> anyhow::Result<Self> {
         let [cmd, args @ ..] = args.as_slice() else {
             return Err(anyhow!("Failed to construct CommandKind"));
@@ -37,29 +180,28 @@ impl CommandKind {
 
         match cmd {
             "exit" => {
-                let code = args
-                    .get(0)
-                    .ok_or(anyhow!("Invalid arguments"))?
-                    .parse::<i32>()?;
-
-                let command = Self::Builtin(BuiltinCommand::Exit { code });
+                let command = Self::Builtin(BuiltinCommand::Exit {
+                    code: args.first().cloned(),
+                });
                 return Ok(command);
             }
             "echo" => {
-                let input = args.join(" ");
-                let command = Self::Builtin(BuiltinCommand::Echo { input });
+                let command = Self::Builtin(BuiltinCommand::Echo {
+                    args: args.to_vec(),
+                });
                 return Ok(command);
             }
             "type" => {
-                let cmd = args.get(0).ok_or(anyhow!("Invalid arguments"))?;
-                let built_ins = vec![
-                    String::from("exit"),
-                    String::from("echo"),
-                    String::from("type"),
-                    String::from("pwd"),
-                ];
-
-                if built_ins.contains(cmd) {
+                let Some(cmd) = args.first() else {
+                    return Ok(Self::usage("type: usage: type name"));
+                };
+                if is_keyword(cmd) {
+                    let command = Self::Builtin(BuiltinCommand::Type(TypeCommand::Keyword {
+                        cmd: cmd.to_string(),
+                    }));
+                    return Ok(command);
+                }
+                if is_builtin(cmd) {
                     let command = Self::Builtin(BuiltinCommand::Type(TypeCommand::WellKnown {
                         cmd: cmd.to_string(),
                     }));
@@ -77,492 +219,3643 @@ impl CommandKind {
                 return Ok(command);
             }
             "cd" => {
-                let path = args.get(0).ok_or(anyhow!("Invalid arguments"))?.to_string();
-                let command = Self::Builtin(BuiltinCommand::Cd { path });
+                let command = Self::Builtin(BuiltinCommand::Cd {
+                    path: args.first().cloned(),
+                });
+                return Ok(command);
+            }
+            "getopts" => {
+                let [optstring, name, args @ ..] = args else {
+                    return Ok(Self::usage(
+                        "getopts: usage: getopts optstring name [arg ...]",
+                    ));
+                };
+
+                let command = Self::Builtin(BuiltinCommand::Getopts {
+                    optstring: optstring.to_string(),
+                    name: name.to_string(),
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "hash" => {
+                let command = Self::Builtin(BuiltinCommand::Hash {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "export" => {
+                let command = Self::Builtin(BuiltinCommand::Export {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "unset" => {
+                let command = Self::Builtin(BuiltinCommand::Unset {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "set" => {
+                let command = Self::Builtin(BuiltinCommand::Set {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "pushd" => {
+                let command = Self::Builtin(BuiltinCommand::Pushd {
+                    dir: args.first().cloned(),
+                });
+                return Ok(command);
+            }
+            "popd" => {
+                let command = Self::Builtin(BuiltinCommand::Popd);
+                return Ok(command);
+            }
+            "help" => {
+                let command = Self::Builtin(BuiltinCommand::Help {
+                    names: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "source" | "." => {
+                let [path, args @ ..] = args else {
+                    return Ok(Self::usage(&format!(
+                        "{}: filename argument required\n{}: usage: {} filename [arguments]",
+                        cmd, cmd, cmd
+                    )));
+                };
+
+                let command = Self::Builtin(BuiltinCommand::Source {
+                    path: path.to_string(),
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "history" => {
+                let command = Self::Builtin(BuiltinCommand::History {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "read" => {
+                let command = Self::Builtin(BuiltinCommand::Read {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "shift" => {
+                let command = Self::Builtin(BuiltinCommand::Shift {
+                    count: args.first().cloned(),
+                });
+                return Ok(command);
+            }
+            "break" => {
+                let command = Self::Builtin(BuiltinCommand::Break);
+                return Ok(command);
+            }
+            "let" => {
+                let command = Self::Builtin(BuiltinCommand::Let {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "local" => {
+                let command = Self::Builtin(BuiltinCommand::Local {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "fc" => {
+                let command = Self::Builtin(BuiltinCommand::Fc);
+                return Ok(command);
+            }
+            "builtin" => {
+                let command = Self::Builtin(BuiltinCommand::Builtin {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "jobs" => {
+                let command = Self::Builtin(BuiltinCommand::Jobs {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "disown" => {
+                let command = Self::Builtin(BuiltinCommand::Disown {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "fg" => {
+                let command = Self::Builtin(BuiltinCommand::Fg {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "bg" => {
+                let command = Self::Builtin(BuiltinCommand::Bg {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "wait" => {
+                let command = Self::Builtin(BuiltinCommand::Wait {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "kill" => {
+                let command = Self::Builtin(BuiltinCommand::Kill {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "compgen" => {
+                let command = Self::Builtin(BuiltinCommand::Compgen {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "shopt" => {
+                let command = Self::Builtin(BuiltinCommand::Shopt {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "trap" => {
+                let command = Self::Builtin(BuiltinCommand::Trap {
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "nohup" => {
+                let [cmd, args @ ..] = args else {
+                    return Ok(Self::usage(
+                        "nohup: missing operand\nnohup: usage: nohup command [arg ...]",
+                    ));
+                };
+
+                let command = Self::Builtin(BuiltinCommand::Nohup {
+                    cmd: cmd.to_string(),
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "timeout" => {
+                let [duration, cmd, args @ ..] = args else {
+                    return Ok(Self::usage(
+                        "timeout: missing operand\ntimeout: usage: timeout duration command [arg ...]",
+                    ));
+                };
+                let Some(limit) = timeout::parse_duration(duration) else {
+                    return Ok(Self::usage(&format!(
+                        "timeout: invalid time interval '{}'",
+                        duration
+                    )));
+                };
+
+                let command = Self::Builtin(BuiltinCommand::Timeout {
+                    limit,
+                    cmd: cmd.to_string(),
+                    args: args.to_vec(),
+                });
+                return Ok(command);
+            }
+            "dirs" => {
+                let command = Self::Builtin(BuiltinCommand::Dirs {
+                    args: args.to_vec(),
+                });
                 return Ok(command);
             }
             _ => {
                 let cmd = cmd.to_string();
                 let args: Vec<String> = args.iter().map(|v| v.to_string()).collect();
 
-                let command = Self::Unknown { cmd, args };
-                return Ok(command);
-            }
-        }
+                let command = Self::Unknown { cmd, args };
+                return Ok(command);
+            }
+        }
+    }
+}
+
+/// One simple command of a pipeline: assignments, words and redirections, before
+/// expansion.
+#[derive(Debug, PartialEq)]
+pub struct Stage {
+    /// Leading `NAME=value` words.
+    assignments: Vec<String>,
+    args: Vec<String>,
+    redirections: Vec<Redirection>,
+}
+
+impl Stage {
+    fn new(tokens: &[Token]) -> anyhow::Result<Self> {
+        let mut assignments = vec![];
+        let mut args = vec![];
+        let mut redirections = vec![];
+
+        let mut index = 0;
+        while let Some(token) = tokens.get(index) {
+            match token {
+                Token::Word(word) if args.is_empty() && split_assignment(word).is_some() => {
+                    assignments.push(word.clone());
+                    index += 1;
+                }
+                Token::Word(word) => {
+                    args.push(word.clone());
+                    index += 1;
+                }
+                Token::Redirect { operator } => {
+                    let mut words = vec![operator.clone()];
+                    match tokens.get(index + 1) {
+                        Some(Token::Word(target)) => words.push(target.clone()),
+                        Some(Token::Redirect { operator }) => words.push(operator.clone()),
+                        Some(_) | None => {}
+                    }
+                    let parsed = Redirection::parse(words)?;
+                    index += parsed.first().map_or(1, |first| return first.word_count());
+                    redirections.extend(parsed);
+                }
+                token => return Err(unexpected_token(token)),
+            }
+        }
+
+        return Ok(Self {
+            assignments,
+            args,
+            redirections,
+        });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Command {
+    /// Simple commands joined by `|`; most lines have a single stage.
+    stages: Vec<Stage>,
+    /// Set by a trailing `&`: external commands run as background jobs.
+    background: bool,
+    /// The command line as typed, shown in job listings.
+    text: String,
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        return Self::from_tokens(&tokenize(input)?);
+    }
+}
+
+impl Command {
+    /// Builds a command from the tokens of one pipeline, up to (not including) the
+    /// next `;`, `&&` or `||`.
+    pub fn from_tokens(tokens: &[Token]) -> anyhow::Result<Self> {
+        // A redirection to a file needs a word after it, which `|` and `&` are not.
+        let missing_target = tokens.windows(2).find_map(|pair| match pair {
+            [Token::Redirect { operator }, token @ (Token::Pipe | Token::Background)]
+                if !operator.contains(">&") =>
+            {
+                return Some(token);
+            }
+            _ => return None,
+        });
+        if let Some(token) = missing_target {
+            return Err(unexpected_token(token));
+        }
+        let (tokens, background) = match tokens {
+            [rest @ .., Token::Background] => (rest, true),
+            _ => (tokens, false),
+        };
+        if background && tokens.is_empty() {
+            return Err(unexpected_token(&Token::Background));
+        }
+        let text = tokenizer::reserialize(tokens);
+
+        let segments: Vec<&[Token]> = tokens.split(|token| *token == Token::Pipe).collect();
+        if segments.len() > 1 && segments.iter().any(|segment| segment.is_empty()) {
+            return Err(unexpected_token(&Token::Pipe));
+        }
+        let stages = segments
+            .into_iter()
+            .map(Stage::new)
+            .collect::<anyhow::Result<Vec<Stage>>>()?;
+
+        return Ok(Command {
+            stages,
+            background,
+            text,
+        });
+    }
+}
+
+pub fn unexpected_token(token: &Token) -> anyhow::Error {
+    return anyhow!("syntax error near unexpected token `{}'", token);
+}
+
+/// How a command that ran finished. A failing command is still a result; `Err` is
+/// kept for faults in the shell itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandResult {
+    pub status: i32,
+}
+
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub status: i32,
+}
+
+/// What a command wrote, kept as the bytes it wrote: a program's output need not be
+/// UTF-8 text.
+#[derive(Debug)]
+struct ProgramOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    status: i32,
+}
+
+impl From<CommandOutput> for ProgramOutput {
+    fn from(output: CommandOutput) -> Self {
+        return Self {
+            stdout: output.stdout.unwrap_or_default().into_bytes(),
+            stderr: output.stderr.unwrap_or_default().into_bytes(),
+            status: output.status,
+        };
+    }
+}
+
+impl From<std::process::Output> for ProgramOutput {
+    fn from(output: std::process::Output) -> Self {
+        return Self {
+            stdout: output.stdout,
+            stderr: output.stderr,
+            status: executable::status_code(output.status),
+        };
+    }
+}
+
+impl Command {
+    pub fn run(
+        self,
+        state: &mut ShellState,
+        prompter: &mut impl Prompter,
+        finder: &impl ExecutablePathFinder,
+        runner: &impl ExecutableRunner,
+    ) -> anyhow::Result<CommandResult> {
+        let mut stages = self.stages;
+        if stages.len() > 1 {
+            run_pipeline(stages, &self.text, state, prompter, finder, runner)?;
+            return Ok(CommandResult {
+                status: state.last_status,
+            });
+        }
+
+        let stage = stages.remove(0);
+        let result = run_simple_command(
+            stage,
+            self.background,
+            &self.text,
+            state,
+            prompter,
+            finder,
+            runner,
+        );
+        state.pipe_status = vec![state.last_status];
+
+        result?;
+        return Ok(CommandResult {
+            status: state.last_status,
+        });
+    }
+}
+
+fn run_simple_command(
+    stage: Stage,
+    background: bool,
+    text: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    let Stage {
+        assignments,
+        args,
+        redirections,
+    } = stage;
+    state.substitution_status = None;
+    let expanded = expand_command(args, redirections, state, prompter, finder, runner);
+    let (args, redirections) = match expanded {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            prompter.error(&format!("{}\n", e))?;
+            state.last_status = 1;
+            return Ok(());
+        }
+    };
+
+    // Assignments apply in order, so each value sees the ones before it. Before a
+    // command they only hold while it runs, exported to it.
+    let mut saved: Vec<(String, Option<Variable>)> = vec![];
+    for word in &assignments {
+        let (name, value) = match expand_assignment(word, state, prompter, finder, runner) {
+            Ok(assignment) => assignment,
+            Err(e) => {
+                prompter.error(&format!("{}\n", e))?;
+                state.last_status = 1;
+                return Ok(());
+            }
+        };
+        if args.is_empty() {
+            state.assign(&name, &value);
+        } else {
+            saved.push((name.clone(), state.variables.variable(&name).cloned()));
+            state.variables.set(&name, &value);
+            state.variables.export(&name);
+        }
+    }
+
+    if args.is_empty() {
+        state.last_status = state.substitution_status.unwrap_or(0);
+        return Ok(());
+    }
+
+    let result = run_expanded_command(
+        args,
+        redirections,
+        background,
+        text,
+        state,
+        prompter,
+        finder,
+        runner,
+    );
+    for (name, variable) in saved.into_iter().rev() {
+        state.variables.restore(&name, variable);
+    }
+
+    return result;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_expanded_command(
+    args: Vec<String>,
+    redirections: Vec<Redirection>,
+    background: bool,
+    text: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    // A function or builtin cannot run as a process of its own. With `&` it runs in a
+    // subshell instead, so nothing it changes reaches the shell. `nohup` starts one.
+    let is_in_shell = state.functions.contains_key(&args[0]) || is_builtin(&args[0]);
+    if background && is_in_shell && args[0] != "nohup" {
+        return state.in_subshell(|subshell| {
+            return run_expanded_command(
+                args,
+                redirections,
+                false,
+                text,
+                subshell,
+                prompter,
+                finder,
+                runner,
+            );
+        });
+    }
+
+    let exit_warned = std::mem::take(&mut state.exit_warned);
+    if let Some(body) = state.functions.get(&args[0]).cloned() {
+        statement::call_function(&body, &args[1..], state, prompter, finder, runner)?;
+        return Ok(());
+    }
+
+    let kind = match CommandKind::new(args)? {
+        // Functions are already bypassed; `builtin name` now runs like `name`.
+        CommandKind::Builtin(BuiltinCommand::Builtin { args })
+            if args.first().is_some_and(|name| is_builtin(name)) =>
+        {
+            CommandKind::new(args)?
+        }
+        kind => kind,
+    };
+    let redirections = match kind {
+        CommandKind::Builtin(BuiltinCommand::Nohup { .. }) => {
+            let (redirections, notice) = nohup::redirections(
+                redirections,
+                io::stdout().is_terminal(),
+                io::stderr().is_terminal(),
+            );
+            if let Some(notice) = notice {
+                prompter.error(&notice)?;
+            }
+            redirections
+        }
+        _ => redirections,
+    };
+    let (kind, _hangup_ignored) = nohup_command(kind);
+    let is_exit = matches!(kind, CommandKind::Builtin(BuiltinCommand::Exit { .. }));
+    if is_exit && !may_exit(exit_warned, state, prompter)? {
+        return Ok(());
+    }
+    if let (true, CommandKind::Unknown { cmd, args }) = (background, &kind) {
+        prompter.flush()?;
+        let output = run_background_command(state, finder, runner, cmd, args, &redirections, text);
+        state.last_status = output.status;
+        if let Some(stderr) = output.stderr {
+            prompter.error(&stderr)?;
+        }
+        return Ok(());
+    }
+
+    // Without redirections a program can use the terminal directly, which
+    // interactive ones like `vim` or `ssh` need. Under job control a redirected one
+    // does too, so Ctrl-C and Ctrl-Z reach it rather than the shell.
+    let is_direct = redirections.is_empty() || runner.job_control();
+    if let (true, CommandKind::Unknown { cmd, args }) = (is_direct && prompter.is_attached(), &kind)
+    {
+        prompter.flush()?;
+        let output = run_attached_command(state, finder, runner, cmd, args, &redirections, text);
+        state.last_status = output.status;
+        if let Some(stderr) = output.stderr {
+            prompter.error(&stderr)?;
+        }
+        return Ok(());
+    }
+
+    // These run further command lines themselves.
+    match kind {
+        CommandKind::Builtin(BuiltinCommand::Fc) => {
+            return run_fc(state, prompter, finder, runner);
+        }
+        CommandKind::Builtin(BuiltinCommand::Source { path, args }) => {
+            return run_source(&path, &args, state, prompter, finder, runner);
+        }
+        CommandKind::Builtin(BuiltinCommand::Fg { args }) => {
+            return run_fg(&args, state, prompter, runner);
+        }
+        _ => {}
+    }
+
+    // Builtins whose output can run longer than a screen.
+    let is_pageable = matches!(
+        kind,
+        CommandKind::Builtin(BuiltinCommand::History { .. } | BuiltinCommand::Help { .. })
+    );
+
+    // Of the builtins only `read` takes input, and it reads the file in place of the
+    // shell's own input.
+    let stdin = match redirection::input(&redirections) {
+        Ok(stdin) => stdin,
+        Err(e) => {
+            prompter.error(&format!("{}\n", e))?;
+            state.last_status = 1;
+            return Ok(());
+        }
+    };
+
+    let Some(output) = (match kind {
+        CommandKind::Builtin(BuiltinCommand::Read { args }) => {
+            let mut stdin = stdin.map(BufReader::new);
+            let mut line = match read::timeout(&args) {
+                Some(timeout) if stdin.is_none() => prompter.read_timeout(timeout),
+                _ => next_input_line(&mut stdin, prompter),
+            };
+            while let Ok(Some(text)) = &line {
+                let Some(joined) = read::continued(&args, text) else {
+                    break;
+                };
+                line = next_input_line(&mut stdin, prompter)
+                    .map(|next| return Some(joined + &next.unwrap_or_default()));
+            }
+            match line {
+                Ok(line) => Some(read(state, &args, line.as_deref()).into()),
+                Err(e) if e.is::<ReadTimedOut>() => Some(read::timed_out(state, &args).into()),
+                Err(e) => return Err(e),
+            }
+        }
+        CommandKind::Builtin(BuiltinCommand::Timeout { limit, cmd, args }) => Some(
+            run_timed_command(state, finder, runner, &cmd, &args, limit, stdin)?,
+        ),
+        CommandKind::Builtin(builtin_command) => {
+            if matches!(builtin_command, BuiltinCommand::Exit { .. }) {
+                run_exit_hooks(state, prompter, finder, runner)?;
+            }
+            match run_builtin_command(builtin_command, state, finder) {
+                Ok(output) => Some(output.into()),
+                Err(e) => {
+                    prompter.error(&format!("{}\n", e))?;
+                    None
+                }
+            }
+        }
+        CommandKind::Unknown { cmd, args } => {
+            match run_unknown_command(state, finder, runner, cmd, args, stdin) {
+                Ok(output) => Some(output),
+                Err(e) => {
+                    prompter.error(&format!("{}\n", e))?;
+                    None
+                }
+            }
+        }
+    }) else {
+        state.last_status = 1;
+        return Ok(());
+    };
+    state.last_status = output.status;
+
+    let (stdout, stderr) =
+        match redirection::apply_bytes(&redirections, &output.stdout, &output.stderr) {
+            Ok(terminal_output) => terminal_output,
+            Err(e) => {
+                prompter.error(&format!("{}\n", e))?;
+                state.last_status = 1;
+                return Ok(());
+            }
+        };
+    if !stdout.is_empty() {
+        if is_pageable {
+            prompter.flush()?;
+        }
+        // Only builtins are paged, and what they write is text.
+        let paged =
+            is_pageable && pager::page(state, finder, runner, &String::from_utf8_lossy(&stdout));
+        if !paged {
+            write_stdout(&stdout, state, prompter)?;
+        }
+    }
+    write_stderr(&stderr, state, prompter)?;
+
+    return Ok(());
+}
+
+/// Writes a command's output to the terminal. Outside a UTF-8 locale it goes out as
+/// the bytes the command wrote, not decoded with replacement characters.
+fn write_stdout(
+    stdout: &[u8],
+    state: &ShellState,
+    prompter: &mut impl Prompter,
+) -> anyhow::Result<()> {
+    if stdout.is_empty() {
+        return Ok(());
+    }
+    match state.uses_utf8() {
+        true => return prompter.prompt(&String::from_utf8_lossy(stdout)),
+        false => return prompter.write_bytes(stdout),
+    }
+}
+
+/// `write_stdout` for what a command wrote to its stderr.
+fn write_stderr(
+    stderr: &[u8],
+    state: &ShellState,
+    prompter: &mut impl Prompter,
+) -> anyhow::Result<()> {
+    if stderr.is_empty() {
+        return Ok(());
+    }
+    match state.uses_utf8() {
+        true => return prompter.error(&String::from_utf8_lossy(stderr)),
+        false => return prompter.error_bytes(stderr),
+    }
+}
+
+/// `nohup cmd [args]` runs `cmd` as a program, never a builtin or function. `SIGHUP`
+/// stays ignored until the returned guard is dropped, so the program inherits that.
+fn nohup_command(kind: CommandKind) -> (CommandKind, Option<nohup::HangupIgnored>) {
+    match kind {
+        CommandKind::Builtin(BuiltinCommand::Nohup { cmd, args }) => {
+            return (
+                CommandKind::Unknown { cmd, args },
+                Some(nohup::ignore_hangup()),
+            );
+        }
+        kind => return (kind, None),
+    }
+}
+
+/// Runs `expand` with an expander for `state` that can run command substitutions.
+/// What the substituted commands write to stderr is passed on to `prompter`, and
+/// variables assigned by arithmetic expansion are set afterwards, as is the status of
+/// the last substitution. The pipes of `<(command)` words are left open in the state
+/// for the command to read.
+pub fn with_expander<T>(
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    expand: impl FnOnce(&Expander) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let substitution_errors = RefCell::new(vec![]);
+    let substitution_status = Cell::new(None);
+    let process_substitutions = RefCell::new(vec![]);
+    let (expanded, assignments) = {
+        let state: &ShellState = state;
+        let substitute = |command: &str| -> anyhow::Result<String> {
+            let output = substitution::run(command, state, finder, runner)?;
+            substitution_errors.borrow_mut().extend(output.stderr);
+            substitution_status.set(Some(output.status));
+            return Ok(output.stdout);
+        };
+        let process_substitute = |command: &str| -> anyhow::Result<String> {
+            let (pipe, stderr) = substitution::run_process(command, state, finder, runner)?;
+            substitution_errors.borrow_mut().extend(stderr);
+            let path = pipe.path();
+            process_substitutions.borrow_mut().push(pipe);
+            return Ok(path);
+        };
+
+        let expander = Expander::new(state)
+            .with_substitution(&substitute)
+            .with_process_substitution(&process_substitute);
+        let expanded = expand(&expander);
+        (expanded, expander.take_assignments())
+    };
+    for (name, value) in assignments {
+        state.assign(&name, &value);
+    }
+    state
+        .process_substitutions
+        .extend(process_substitutions.into_inner());
+    if let Some(status) = substitution_status.get() {
+        state.substitution_status = Some(status);
+    }
+
+    write_stderr(&substitution_errors.into_inner(), state, prompter)?;
+    return expanded;
+}
+
+/// Expands the words and redirection targets of a command, and checks the targets
+/// may be written.
+fn expand_command(
+    args: Vec<String>,
+    redirections: Vec<Redirection>,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<(Vec<String>, Vec<Redirection>)> {
+    let (args, redirections) = with_expander(state, prompter, finder, runner, |expander| {
+        let args = expander.expand_words(&args)?;
+        let redirections = redirections
+            .into_iter()
+            .map(|redirection| return expand_redirection(redirection, expander))
+            .collect::<anyhow::Result<Vec<Redirection>>>()?;
+
+        return Ok((args, redirections));
+    })?;
+    redirection::check_clobber(&redirections, state, prompter)?;
+
+    return Ok((args, redirections));
+}
+
+/// Expands `words` as command arguments are.
+pub fn expand_words(
+    words: &[String],
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<Vec<String>> {
+    return with_expander(state, prompter, finder, runner, |expander| {
+        return expander.expand_words(words);
+    });
+}
+
+/// The next line for `read`: from its `< file` if it has one, or else the shell's
+/// own input.
+fn next_input_line(
+    stdin: &mut Option<BufReader<File>>,
+    prompter: &mut impl Prompter,
+) -> anyhow::Result<Option<String>> {
+    match stdin {
+        Some(stdin) => return Ok(read::line_from(stdin)?),
+        None => return prompter.read(),
+    }
+}
+
+/// Expands a `NAME=value` word into the name and its value.
+fn expand_assignment(
+    word: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<(String, String)> {
+    let (name, value) = split_assignment(word).ok_or(anyhow!("{}: not an assignment", word))?;
+
+    return with_expander(state, prompter, finder, runner, |expander| {
+        return Ok((name.to_string(), expander.expand_assignment(value)?));
+    });
+}
+
+fn expand_redirection(
+    redirection: Redirection,
+    expander: &Expander,
+) -> anyhow::Result<Redirection> {
+    let target = match redirection.target {
+        Target::File { path, mode } => Target::File {
+            path: expander.expand_single(&path.to_string_lossy())?.into(),
+            mode,
+        },
+        Target::Input(path) => {
+            Target::Input(expander.expand_single(&path.to_string_lossy())?.into())
+        }
+        target => target,
+    };
+    return Ok(Redirection {
+        source: redirection.source,
+        target,
+    });
+}
+
+fn run_builtin_command(
+    command: BuiltinCommand,
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+) -> anyhow::Result<CommandOutput> {
+    match command {
+        BuiltinCommand::Exit { code } => {
+            let (status, stderr) = match code.map(|code| return (code.parse::<i32>(), code)) {
+                None => (state.last_status, None),
+                Some((Ok(status), _)) => (status, None),
+                Some((Err(_), code)) => (
+                    2,
+                    Some(format!("exit: {}: numeric argument required\n", code)),
+                ),
+            };
+            state.exiting = Some(status);
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr,
+                status,
+            });
+        }
+        BuiltinCommand::Echo { args } => {
+            if state.options.is_set("posix") {
+                return Ok(posix_echo(&args));
+            }
+            if state.options.is_set("xpg_echo") {
+                return Ok(xpg_echo(&args));
+            }
+            return Ok(echo(&args));
+        }
+        BuiltinCommand::Type(command) => match command {
+            TypeCommand::Keyword { cmd } => {
+                return Ok(CommandOutput {
+                    stdout: Some(format!("{} is a shell keyword\n", cmd)),
+                    stderr: None,
+                    status: 0,
+                })
+            }
+            TypeCommand::WellKnown { cmd } => {
+                return Ok(CommandOutput {
+                    stdout: Some(format!("{} is a shell builtin\n", cmd)),
+                    stderr: None,
+                    status: 0,
+                })
+            }
+            TypeCommand::Unknown { cmd } => {
+                if let Some(hashed_path) = state.hash_table.get(&cmd) {
+                    return Ok(CommandOutput {
+                        stdout: Some(format!("{} is hashed ({})\n", cmd, hashed_path)),
+                        stderr: None,
+                        status: 0,
+                    });
+                }
+
+                // A name with a slash is a path of its own; `PATH` is not searched.
+                let result = match cmd.contains('/') {
+                    true => is_executable(Path::new(&cmd)).then(|| return cmd.clone()),
+                    false => finder.find_executable_path(&state.env_path(), &cmd),
+                };
+
+                match result {
+                    Some(full_path) => {
+                        return Ok(CommandOutput {
+                            stdout: Some(format!("{} is {}\n", cmd, full_path)),
+                            stderr: None,
+                            status: 0,
+                        });
+                    }
+                    None => {
+                        return Ok(CommandOutput {
+                            stdout: None,
+                            stderr: Some(format!("{}: not found\n", cmd)),
+                            status: 1,
+                        });
+                    }
+                }
+            }
+        },
+        BuiltinCommand::Pwd => {
+            let pwd = match state.current_dir() {
+                Ok(pwd) => pwd,
+                Err(e) => {
+                    return Ok(CommandOutput {
+                        stdout: None,
+                        stderr: Some(format!(
+                            "pwd: error retrieving current directory: {}\n",
+                            io_error_message(&e)
+                        )),
+                        status: 1,
+                    });
+                }
+            };
+            // A name that is not UTF-8 is shown with replacement characters.
+            return Ok(CommandOutput {
+                stdout: Some(format!("{}\n", pwd.display())),
+                stderr: None,
+                status: 0,
+            });
+        }
+        BuiltinCommand::Cd { path } => {
+            return Ok(cd(state, path.as_deref()));
+        }
+        BuiltinCommand::Getopts {
+            optstring,
+            name,
+            args,
+        } => {
+            return Ok(getopts(state, &optstring, &name, &args));
+        }
+        BuiltinCommand::Hash { args } => {
+            return Ok(run_hash(state, finder, &args));
+        }
+        BuiltinCommand::Set { args } => {
+            return Ok(set(state, &args));
+        }
+        BuiltinCommand::Export { args } => {
+            return Ok(export(state, &args));
+        }
+        BuiltinCommand::Unset { args } => {
+            return Ok(unset(state, &args));
+        }
+        BuiltinCommand::Pushd { dir } => {
+            return Ok(pushd(state, dir.as_deref()));
+        }
+        BuiltinCommand::Popd => {
+            return Ok(popd(state));
+        }
+        BuiltinCommand::Dirs { args } => {
+            return Ok(dirs(state, &args));
+        }
+        BuiltinCommand::Help { names } => {
+            return Ok(help(&names));
+        }
+        BuiltinCommand::History { args } => {
+            return Ok(history(state, &args));
+        }
+        BuiltinCommand::Jobs { args } => {
+            return Ok(jobs(state, &args));
+        }
+        BuiltinCommand::Disown { args } => {
+            return Ok(disown(state, &args));
+        }
+        // Only a pipeline stage gets here, which has no jobs of its own.
+        BuiltinCommand::Fg { .. } => {
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr: Some(String::from("fg: no job control\n")),
+                status: 1,
+            });
+        }
+        BuiltinCommand::Bg { args } => {
+            return Ok(bg(state, &args));
+        }
+        BuiltinCommand::Wait { args } => {
+            return wait(state, &args);
+        }
+        BuiltinCommand::Kill { args } => {
+            return Ok(kill(state, &args));
+        }
+        BuiltinCommand::Trap { args } => {
+            return Ok(trap(state, &args));
+        }
+        BuiltinCommand::Shopt { args } => {
+            return Ok(shopt(state, &args));
+        }
+        BuiltinCommand::Compgen { args } => {
+            return Ok(compgen(state, &args));
+        }
+        // Only reached in a pipeline, where builtins get no input.
+        BuiltinCommand::Read { args } => {
+            return Ok(read(state, &args, None));
+        }
+        BuiltinCommand::Let { args } => {
+            return Ok(let_builtin(state, &args));
+        }
+        BuiltinCommand::Local { args } => {
+            return Ok(local(state, &args));
+        }
+        BuiltinCommand::Shift { count } => {
+            return Ok(run_shift(state, count.as_deref()));
+        }
+        BuiltinCommand::Break => {
+            return Ok(run_break(state));
+        }
+        BuiltinCommand::Fc | BuiltinCommand::Source { .. } => {
+            return Err(anyhow!("fc and source run through Command::run"));
+        }
+        BuiltinCommand::Builtin { args } => {
+            let Some(name) = args.first() else {
+                return Ok(CommandOutput {
+                    stdout: None,
+                    stderr: None,
+                    status: 0,
+                });
+            };
+            let (true, CommandKind::Builtin(command)) =
+                (is_builtin(name), CommandKind::new(args.clone())?)
+            else {
+                return Ok(CommandOutput {
+                    stdout: None,
+                    stderr: Some(format!("builtin: {}: not a shell builtin\n", name)),
+                    status: 1,
+                });
+            };
+            return run_builtin_command(command, state, finder);
+        }
+        // Only reached through `builtin nohup`, which has no program runner.
+        BuiltinCommand::Nohup { cmd, .. } => {
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr: Some(format!("nohup: failed to run command '{}'\n", cmd)),
+                status: 126,
+            });
+        }
+        // Only reached through `builtin timeout` in a pipeline, which has no program
+        // runner.
+        BuiltinCommand::Timeout { cmd, .. } => {
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr: Some(format!("timeout: failed to run command '{}'\n", cmd)),
+                status: 126,
+            });
+        }
+        BuiltinCommand::Usage { message } => {
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr: Some(format!("{}\n", message)),
+                status: 2,
+            });
+        }
+    }
+}
+
+/// The bash-style wording for a file system error, without the `(os error N)` suffix.
+pub fn io_error_message(error: &std::io::Error) -> String {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => return String::from("No such file or directory"),
+        std::io::ErrorKind::PermissionDenied => return String::from("Permission denied"),
+        std::io::ErrorKind::NotADirectory => return String::from("Not a directory"),
+        _ => {
+            let message = error.to_string();
+            return match message.rfind(" (os error ") {
+                Some(index) => message[..index].to_string(),
+                None => message,
+            };
+        }
+    }
+}
+
+/// `shift [n]`: drops the first `n` positional parameters (1 by default) and
+/// renumbers the rest. Fails, leaving them alone, when there are fewer than `n`.
+fn run_shift(state: &mut ShellState, count: Option<&str>) -> CommandOutput {
+    let failure = |message: Option<String>| {
+        return CommandOutput {
+            stdout: None,
+            stderr: message,
+            status: 1,
+        };
+    };
+
+    let count = match count.map(|count| return count.parse::<i64>()) {
+        None => 1,
+        Some(Ok(count)) if count >= 0 => count as usize,
+        Some(Ok(count)) => {
+            return failure(Some(format!(
+                "shift: {}: shift count out of range\n",
+                count
+            )));
+        }
+        Some(Err(_)) => {
+            return failure(Some(format!(
+                "shift: {}: numeric argument required\n",
+                count.unwrap_or_default()
+            )));
+        }
+    };
+    if count > state.positional_params.len() {
+        return failure(None);
+    }
+
+    state.positional_params.drain(..count);
+    return CommandOutput {
+        stdout: None,
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// `break`: leaves the innermost loop once the running command finishes. Outside a
+/// loop it only complains.
+fn run_break(state: &mut ShellState) -> CommandOutput {
+    if state.loop_depth == 0 {
+        return CommandOutput {
+            stdout: None,
+            stderr: Some(String::from(
+                "break: only meaningful in a `for', `while', or `until' loop\n",
+            )),
+            status: 0,
+        };
+    }
+
+    state.breaking = true;
+    return CommandOutput {
+        stdout: None,
+        stderr: None,
+        status: 0,
+    };
+}
+
+fn run_hash(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    args: &[String],
+) -> CommandOutput {
+    if args.is_empty() {
+        let mut entries = state.hash_table.iter().peekable();
+        if entries.peek().is_none() {
+            return CommandOutput {
+                stdout: Some(String::from("hash: hash table empty\n")),
+                stderr: None,
+                status: 0,
+            };
+        }
+
+        let mut stdout = String::from("hits\tcommand\n");
+        for (_, command) in entries {
+            stdout.push_str(&format!("{:>4}\t{}\n", command.hits, command.path));
+        }
+        return CommandOutput {
+            stdout: Some(stdout),
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let mut stderr = String::new();
+    for arg in args {
+        if arg == "-r" {
+            state.hash_table.clear();
+            continue;
+        }
+
+        match finder.find_executable_path(&state.env_path(), arg) {
+            Some(path) => state.hash_table.insert(arg, &path),
+            None => stderr.push_str(&format!("hash: {}: not found\n", arg)),
+        }
+    }
+
+    let status = if stderr.is_empty() { 0 } else { 1 };
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+        status,
+    };
+}
+
+fn run_unknown_command(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    cmd: String,
+    args: Vec<String>,
+    stdin: Option<File>,
+) -> anyhow::Result<ProgramOutput> {
+    if let Some(output) = directory_error(&cmd) {
+        return Ok(output.into());
+    }
+
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let exec_path = state.command_path(finder, &cmd);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name: &cmd,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+
+    // `execute` hands back text, so output that must stay bytes is read here.
+    if stdin.is_some() || !state.uses_utf8() {
+        let stdin = stdin.map_or(Stdio::null(), Stdio::from);
+        let spawned = runner.spawn(&invocation, stdin, Stdio::piped(), Stdio::piped());
+        let Ok(child) = spawned else {
+            return Ok(CommandOutput {
+                stdout: None,
+                stderr: Some(format!("{}: command not found\n", cmd)),
+                status: 127,
+            }
+            .into());
+        };
+        return Ok(child.wait_with_output()?.into());
+    }
+
+    let output = runner.execute(&invocation)?;
+    return Ok(CommandOutput {
+        stdout: output.stdout,
+        stderr: output.stderr,
+        status: output.status,
+    }
+    .into());
+}
+
+/// `timeout`: runs a program with its output captured, stopping it with status 124
+/// if it is still running after `limit`. It reads `stdin` if given, or else the
+/// shell's own input.
+fn run_timed_command(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    cmd: &str,
+    args: &[String],
+    limit: Duration,
+    stdin: Option<File>,
+) -> anyhow::Result<ProgramOutput> {
+    if let Some(output) = directory_error(cmd) {
+        return Ok(output.into());
+    }
+
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let exec_path = state.command_path(finder, cmd);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name: cmd,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+
+    let stdin = stdin.map_or(Stdio::inherit(), Stdio::from);
+    let Ok(child) = runner.spawn(&invocation, stdin, Stdio::piped(), Stdio::piped()) else {
+        return Ok(CommandOutput {
+            stdout: None,
+            stderr: Some(format!("{}: command not found\n", cmd)),
+            status: 127,
+        }
+        .into());
+    };
+    let output = timeout::wait(child, limit)?;
+    return Ok(ProgramOutput {
+        stdout: output.stdout,
+        stderr: output.stderr,
+        status: match output.timed_out {
+            true => timeout::TIMED_OUT_STATUS,
+            false => executable::status_code(output.status),
+        },
+    });
+}
+
+/// A name with a slash that points at a directory cannot be run. bash reports it
+/// with status 126, like other files that exist but are not executable.
+fn directory_error(cmd: &str) -> Option<CommandOutput> {
+    if !cmd.contains('/') || !Path::new(cmd).is_dir() {
+        return None;
+    }
+
+    return Some(CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}: Is a directory\n", cmd)),
+        status: 126,
+    });
+}
+
+/// Runs a program on the shell's own stdin, stdout and stderr, or the files its
+/// redirections open, and waits for it. A program stopped with Ctrl-Z becomes a
+/// stopped job under `text`, with status 128 plus the signal that stopped it.
+#[allow(clippy::too_many_arguments)]
+fn run_attached_command(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    cmd: &str,
+    args: &[String],
+    redirections: &[Redirection],
+    text: &str,
+) -> CommandOutput {
+    if let Some(output) = directory_error(cmd) {
+        return output;
+    }
+
+    let stdio = redirection::stdio(redirections, false).and_then(|(stdout, stderr)| {
+        let stdin = redirection::input(redirections)?.map_or(Stdio::inherit(), Stdio::from);
+        return Ok((stdin, stdout, stderr));
+    });
+    let (stdin, stdout, stderr) = match stdio {
+        Ok(stdio) => stdio,
+        Err(e) => {
+            return CommandOutput {
+                stdout: None,
+                stderr: Some(format!("{}\n", e)),
+                status: 1,
+            }
+        }
+    };
+
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let exec_path = state.command_path(finder, cmd);
+    let env = state.variables.exported();
+
+    let invocation = Invocation {
+        name: cmd,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+    let status = runner.execute_attached(&invocation, stdin, stdout, stderr);
+    return match status {
+        Ok(Attached::Exited(status)) => CommandOutput {
+            stdout: None,
+            stderr: None,
+            status,
+        },
+        Ok(Attached::Stopped { child, signal }) => {
+            let id = state.jobs.add_stopped(vec![child], text);
+            CommandOutput {
+                stdout: None,
+                stderr: stopped_notice(state, id),
+                status: 128 + signal,
+            }
+        }
+        Err(_) => CommandOutput {
+            stdout: None,
+            stderr: Some(format!("{}: command not found\n", cmd)),
+            status: 127,
+        },
+    };
+}
+
+/// The notice for a foreground job that was just stopped. The terminal has echoed
+/// `^Z` without a line break.
+fn stopped_notice(state: &ShellState, id: usize) -> Option<String> {
+    return state
+        .jobs
+        .stopped_notice(id)
+        .map(|notice| return format!("\n{}", notice));
+}
+
+/// What the shell runs on its way out: with `shopt -s waitjobs` a wait for its running
+/// jobs, then the `EXIT` trap, then `~/.shell_logout` when the session is interactive.
+/// Errors in the last two are reported and the shell still exits with the status it
+/// had.
+pub fn run_exit_hooks(
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    if state.options.is_set("waitjobs") {
+        // A stopped job would never finish, so it is left behind.
+        let running: Vec<usize> = state
+            .jobs
+            .iter()
+            .filter(|job| return !job.stopped)
+            .map(|job| return job.id)
+            .collect();
+        for id in running {
+            state.jobs.wait_for(id)?;
+        }
+    }
+
+    let last_status = state.last_status;
+    // Taken out first, so an `exit` inside them does not run them again.
+    let exit_trap = state.traps.remove("EXIT");
+    let interactive = std::mem::replace(&mut state.interactive, false);
+
+    if let Some(action) = exit_trap {
+        statement::run_line(&action, state, prompter, finder, runner)?;
+    }
+    let home = state.variables.get("HOME").filter(|home| !home.is_empty());
+    let logout = home.map(|home| return Path::new(home).join(".shell_logout"));
+    if let Some(logout) = logout.filter(|logout| interactive && logout.is_file()) {
+        let logout = logout.display().to_string();
+        run_source(&logout, &[], state, prompter, finder, runner)?;
+    }
+
+    state.last_status = last_status;
+    prompter.flush()?;
+    return Ok(());
+}
+
+/// Whether the shell may exit, by `exit` or at the end of its input. With stopped
+/// jobs the first attempt only warns about them; one right after it (`exit_warned`)
+/// leaves them behind.
+pub fn may_exit(
+    exit_warned: bool,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+) -> anyhow::Result<bool> {
+    if exit_warned || !state.jobs.has_stopped() {
+        return Ok(true);
+    }
+
+    prompter.error("There are stopped jobs.\n")?;
+    state.exit_warned = true;
+    state.last_status = 1;
+    return Ok(false);
+}
+
+/// `source file [args]`: runs each line of the file in the current shell, with `args`
+/// as the positional parameters while it runs. `-` reads the lines from stdin.
+fn run_source(
+    path: &str,
+    args: &[String],
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    let contents = match path {
+        // The rest of the shell's own input, read the way the shell reads it.
+        "-" => {
+            let mut contents = String::new();
+            while let Some(line) = prompter.read()? {
+                contents.push_str(&line);
+                contents.push('\n');
+            }
+            Ok(contents)
+        }
+        _ => std::fs::read_to_string(path),
+    };
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            prompter.error(&format!("source: {}: {}\n", path, io_error_message(&e)))?;
+            state.last_status = 1;
+            return Ok(());
+        }
+    };
+
+    let caller_params = match args.is_empty() {
+        true => None,
+        false => Some(std::mem::replace(
+            &mut state.positional_params,
+            args.to_vec(),
+        )),
+    };
+
+    state.last_status = 0;
+    state.sources.push(path.to_string());
+    let caller_line_number = state.line_number;
+    let mut result = Ok(());
+    for (index, line) in contents.lines().enumerate() {
+        if state.options.is_set("verbose") {
+            prompter.error(&format!("{}\n", line))?;
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        state.line_number = index + 1;
+        if let Err(e) = statement::run_line(line, state, prompter, finder, runner) {
+            result = Err(e);
+            break;
+        }
+        if state.exiting.is_some() {
+            break;
+        }
+    }
+
+    state.line_number = caller_line_number;
+    state.sources.pop();
+    if let Some(caller_params) = caller_params {
+        state.positional_params = caller_params;
+    }
+    return result;
+}
+
+/// `fg [jobspec]`: resumes a job, the current one by default, in the foreground and
+/// waits for it, after printing its command line. Under job control the job has the
+/// terminal meanwhile. A job stopped again goes back in the table under its number.
+fn run_fg(
+    args: &[String],
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    let spec = args.first().map_or("%+", |spec| return spec.as_str());
+    let Some(job) = state.jobs.find(spec) else {
+        let name = if args.is_empty() { "current" } else { spec };
+        prompter.error(&format!("fg: {}: no such job\n", name))?;
+        state.last_status = 1;
+        return Ok(());
+    };
+    prompter.prompt(&format!("{}\n", job.command))?;
+    prompter.flush()?;
+
+    // The job gets the terminal before it is woken up, so it cannot be stopped for
+    // reading from it in the background.
+    #[cfg(unix)]
+    let foreground = match runner.job_control() {
+        // SAFETY: `getpgid` only reads the process group of a process.
+        true => crate::terminal::Foreground::give(
+            unsafe { libc::getpgid(job.pid as libc::pid_t) } as u32
+        ),
+        false => None,
+    };
+    job.resume();
+    let id = job.id;
+    let status = state.jobs.wait_for(id)?;
+    #[cfg(unix)]
+    drop(foreground);
+
+    state.last_status = status.unwrap_or(1);
+    if state.jobs.get(id).is_some() {
+        if let Some(notice) = stopped_notice(state, id) {
+            prompter.error(&notice)?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// `fc`: edits the previous command line and runs the saved lines in its place, both
+/// in history and on the terminal.
+fn run_fc(
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    // The `fc` line itself is not kept; the edited lines replace it.
+    state.history.pop();
+    let Some(last) = state.history.last().map(|last| last.to_string()) else {
+        prompter.error("fc: no command found\n")?;
+        state.last_status = 1;
+        return Ok(());
+    };
+
+    let edited = match fc::edit(state, finder, runner, &last) {
+        Ok(EditOutcome::Edited(edited)) => edited,
+        Ok(EditOutcome::Cancelled(status)) => {
+            state.last_status = status;
+            return Ok(());
+        }
+        Err(e) => {
+            prompter.error(&format!("{}\n", e))?;
+            state.last_status = 1;
+            return Ok(());
+        }
+    };
+
+    state.last_status = 0;
+    for line in edited
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        state.history.push(line);
+        prompter.prompt(&format!("{}\n", tokenizer::echoed(line)))?;
+        statement::run_line(line, state, prompter, finder, runner)?;
+        if state.exiting.is_some() {
+            break;
+        }
+    }
+
+    return Ok(());
+}
+
+fn run_background_command(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    cmd: &str,
+    args: &[String],
+    redirections: &[Redirection],
+    text: &str,
+) -> CommandOutput {
+    if let Some(output) = directory_error(cmd) {
+        return output;
+    }
+
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let exec_path = state.command_path(finder, cmd);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name: cmd,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+
+    let spawned = redirection::stdio(redirections, false).and_then(|(stdout, stderr)| {
+        let stdin = redirection::input(redirections)?.map_or(Stdio::null(), Stdio::from);
+        return Ok(runner.spawn(&invocation, stdin, stdout, stderr)?);
+    });
+    let child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            let message = match e.downcast_ref::<std::io::Error>() {
+                Some(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    format!("{}: command not found\n", cmd)
+                }
+                _ => format!("{}: {}\n", cmd, e),
+            };
+            return CommandOutput {
+                stdout: None,
+                stderr: Some(message),
+                status: 127,
+            };
+        }
+    };
+
+    let pid = child.id();
+    let id = state.jobs.add(child, text);
+    return CommandOutput {
+        stdout: None,
+        // Only an interactive shell says which job it started.
+        stderr: state
+            .interactive
+            .then(|| return format!("[{}] {}\n", id, pid)),
+        status: 0,
+    };
+}
+
+/// Where a pipeline stage reads its input from.
+enum PipeInput {
+    None,
+    /// The stage starts a foreground job and reads the shell's own stdin.
+    Terminal,
+    /// The stage redirects its input from a file.
+    File(File),
+    /// The previous stage was an external command; the OS streams its output.
+    Child(ChildStdout),
+    /// The previous stage was a builtin or `timeout` that already ran; this is what
+    /// it wrote.
+    Output(Vec<u8>),
+}
+
+/// Runs `first | second | ...`. External commands are connected with OS pipes, so data
+/// streams between them without passing through the shell; builtins run in the shell
+/// and their output is fed to the next stage. Only the last stage is captured, except
+/// under job control on the terminal: there the external commands run as one job in
+/// a process group of their own, using the terminal directly, and a pipeline stopped
+/// with Ctrl-Z becomes a stopped job under `text`.
+fn run_pipeline(
+    stages: Vec<Stage>,
+    text: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    let mut expanded_stages = vec![];
+    for stage in stages {
+        match expand_command(
+            stage.args,
+            stage.redirections,
+            state,
+            prompter,
+            finder,
+            runner,
+        ) {
+            Ok(expanded) => expanded_stages.push(expanded),
+            Err(e) => {
+                prompter.error(&format!("{}\n", e))?;
+                state.last_status = 1;
+                return Ok(());
+            }
+        }
+    }
+
+    let is_job = runner.job_control() && prompter.is_attached();
+    if is_job {
+        prompter.flush()?;
+    }
+    // The process group of the job, once its first process has started.
+    let mut group = None;
+    #[cfg(unix)]
+    let mut foreground = None;
+
+    let last_index = expanded_stages.len() - 1;
+    let mut input = match is_job {
+        true => PipeInput::Terminal,
+        false => PipeInput::None,
+    };
+    // Running stages in order, with their index, and each stage's status.
+    let mut children: Vec<(usize, Child)> = vec![];
+    let mut statuses = vec![0; expanded_stages.len()];
+
+    for (index, (args, redirections)) in expanded_stages.into_iter().enumerate() {
+        let is_last = index == last_index;
+        let stage_input = std::mem::replace(&mut input, PipeInput::None);
+
+        let output: ProgramOutput = match args.is_empty() {
+            true => ProgramOutput {
+                stdout: vec![],
+                stderr: vec![],
+                status: 0,
+            },
+            false => match nohup_command(CommandKind::new(args)?) {
+                (CommandKind::Builtin(BuiltinCommand::Timeout { limit, cmd, args }), _) => {
+                    // The stage runs to the end before the next one starts.
+                    drop(stage_input);
+                    match redirection::input(&redirections) {
+                        Ok(stdin) => {
+                            run_timed_command(state, finder, runner, &cmd, &args, limit, stdin)?
+                        }
+                        Err(e) => ProgramOutput {
+                            stdout: vec![],
+                            stderr: format!("{}\n", e).into_bytes(),
+                            status: 1,
+                        },
+                    }
+                }
+                (CommandKind::Builtin(builtin_command), _) => {
+                    // Builtins do not read their input.
+                    drop(stage_input);
+                    // Each stage is a subshell of its own, so a `cd`, assignment or
+                    // `exit` there does not reach the shell.
+                    let output = state.in_subshell(|subshell| {
+                        return run_builtin_command(builtin_command, subshell, finder);
+                    })?;
+                    output.into()
+                }
+                (CommandKind::Unknown { cmd, args }, _hangup_ignored) => {
+                    let spawned = spawn_stage(
+                        state,
+                        finder,
+                        runner,
+                        &cmd,
+                        &args,
+                        &redirections,
+                        stage_input,
+                        is_last,
+                        is_job,
+                        group,
+                    );
+                    match spawned {
+                        Ok(child) if is_last && !is_job => {
+                            // Both pipes are read at once, so a stage that fills one
+                            // while the shell waits on the other cannot stall.
+                            child.wait_with_output()?.into()
+                        }
+                        Ok(mut child) => {
+                            if is_job && group.is_none() {
+                                group = Some(child.id());
+                                #[cfg(unix)]
+                                {
+                                    foreground = crate::terminal::Foreground::give(child.id());
+                                }
+                            }
+                            if let Some(stdout) = child.stdout.take() {
+                                input = PipeInput::Child(stdout);
+                            }
+                            children.push((index, child));
+                            continue;
+                        }
+                        // A redirection that could not be opened says why.
+                        Err(e) if !e.is::<io::Error>() => ProgramOutput {
+                            stdout: vec![],
+                            stderr: format!("{}\n", e).into_bytes(),
+                            status: 1,
+                        },
+                        Err(_) => directory_error(&cmd)
+                            .unwrap_or(CommandOutput {
+                                stdout: None,
+                                stderr: Some(format!("{}: command not found\n", cmd)),
+                                status: 127,
+                            })
+                            .into(),
+                    }
+                }
+            },
+        };
+
+        statuses[index] = output.status;
+        let (stdout, stderr) =
+            match redirection::apply_bytes(&redirections, &output.stdout, &output.stderr) {
+                Ok(terminal_output) => terminal_output,
+                Err(e) => {
+                    statuses[index] = 1;
+                    (vec![], format!("{}\n", e).into_bytes())
+                }
+            };
+        write_stderr(&stderr, state, prompter)?;
+        match (is_last, stdout.is_empty()) {
+            (true, _) => write_stdout(&stdout, state, prompter)?,
+            (false, false) => input = PipeInput::Output(stdout),
+            (false, true) => {}
+        }
+    }
+
+    let (indexes, children): (Vec<usize>, Vec<Child>) = children.into_iter().unzip();
+    let mut stopped = vec![];
+    for (index, result) in indexes.into_iter().zip(executable::wait_job(children)?) {
+        match result {
+            Attached::Exited(status) => statuses[index] = status,
+            Attached::Stopped { child, signal } => {
+                statuses[index] = 128 + signal;
+                stopped.push(child);
+            }
+        }
+    }
+    #[cfg(unix)]
+    drop(foreground);
+    if !stopped.is_empty() {
+        let id = state.jobs.add_stopped(stopped, text);
+        if let Some(notice) = stopped_notice(state, id) {
+            prompter.error(&notice)?;
+        }
+    }
+    state.last_status = match state.options.is_set("pipefail") {
+        true => statuses
+            .iter()
+            .rev()
+            .find(|status| return **status != 0)
+            .copied()
+            .unwrap_or(0),
+        false => statuses[last_index],
+    };
+    state.pipe_status = statuses;
+
+    return Ok(());
+}
+
+/// Starts an external pipeline stage reading from `input`. Its stdout goes into a new
+/// pipe unless a redirection sends it elsewhere. The last stage captures both streams,
+/// but for a foreground job (`is_job`), whose stages join the process `group` of its
+/// first one and whose last stage writes to the terminal itself.
+#[allow(clippy::too_many_arguments)]
+fn spawn_stage(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    cmd: &str,
+    args: &[String],
+    redirections: &[Redirection],
+    input: PipeInput,
+    is_last: bool,
+    is_job: bool,
+    group: Option<u32>,
+) -> anyhow::Result<Child> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let exec_path = state.command_path(finder, cmd);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name: cmd,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+
+    let (stdout, stderr) = match (is_last, is_job) {
+        // Redirections of the last stage are applied to the captured output.
+        (true, false) => (Stdio::piped(), Stdio::piped()),
+        (true, true) => redirection::stdio(redirections, false)?,
+        (false, _) => redirection::stdio(redirections, true)?,
+    };
+    // A `< file` of the stage's own takes the place of the pipe.
+    let input = match redirection::input(redirections)? {
+        Some(file) => PipeInput::File(file),
+        None => input,
+    };
+    let (stdin, written) = match input {
+        PipeInput::None => (Stdio::null(), None),
+        PipeInput::Terminal => (Stdio::inherit(), None),
+        PipeInput::File(file) => (Stdio::from(file), None),
+        PipeInput::Child(stdout) => (Stdio::from(stdout), None),
+        PipeInput::Output(output) => (Stdio::piped(), Some(output)),
+    };
+
+    let mut child = match is_job {
+        true => runner.spawn_job(&invocation, stdin, stdout, stderr, group)?,
+        false => runner.spawn(&invocation, stdin, stdout, stderr)?,
+    };
+    if let (Some(output), Some(mut stdin)) = (written, child.stdin.take()) {
+        // Written from a thread so a stage that fills its own output pipe before
+        // reading everything cannot block the shell.
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&output);
+        });
+    }
+
+    return Ok(child);
+}
+
+/// The parser's view of a line for `SHELL_DEBUG=1` / `--debug-parse`: the words
+/// after quote removal and each parsed statement.
+pub fn debug_parse(input: &str, statements: &[Statement]) -> String {
+    let mut output = format!(
+        "parse_args: {:?}\n",
+        tokenizer::parse_args(input).unwrap_or_default()
+    );
+    for statement in statements {
+        match statement {
+            Statement::Command(command) => output.push_str(&format!("command: {:?}\n", command)),
+            _ => output.push_str(&format!("{:?}\n", statement)),
+        }
+    }
+
+    return output;
+}
+
+#[cfg(all(test, unix))]
+mod command_tests {
+    use std::{fs, os::unix::fs::PermissionsExt, time::Duration};
+
+    use tempfile::TempDir;
+
+    use crate::{
+        executable::{ExecutableOutput, PathFinder, Runner},
+        shell::Shell,
+        test_support::in_dir,
+    };
+
+    use super::*;
+
+    #[derive(Default)]
+    struct CapturePrompter {
+        /// Lines handed out by `read`, in order.
+        input: Vec<String>,
+        stdout: String,
+        stderr: String,
+        attached: bool,
+    }
+
+    impl Prompter for CapturePrompter {
+        fn read(&mut self) -> anyhow::Result<Option<String>> {
+            if self.input.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some(self.input.remove(0)));
+        }
+
+        fn prompt(&mut self, prompt: &str) -> anyhow::Result<()> {
+            self.stdout.push_str(prompt);
+            return Ok(());
+        }
+
+        fn error(&mut self, message: &str) -> anyhow::Result<()> {
+            self.stderr.push_str(message);
+            return Ok(());
+        }
+
+        /// Input that is not queued never arrives, so a timed read runs out.
+        fn read_timeout(&mut self, _timeout: Duration) -> anyhow::Result<Option<String>> {
+            if self.input.is_empty() {
+                return Err(ReadTimedOut.into());
+            }
+            return self.read();
+        }
+
+        fn is_attached(&self) -> bool {
+            return self.attached;
+        }
+    }
+
+    fn run(input: &str, state: &mut ShellState) -> anyhow::Result<CapturePrompter> {
+        let mut prompter = CapturePrompter::default();
+        statement::run_line(
+            input,
+            state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        return Ok(prompter);
+    }
+
+    #[test]
+    fn run_returns_the_status_of_the_command() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut prompter = CapturePrompter::default();
+        let (finder, runner) = (PathFinder::new(), Runner::new());
+
+        let result = Command::from_str("true")?.run(&mut state, &mut prompter, &finder, &runner)?;
+        assert_eq!(result, CommandResult { status: 0 });
+        let result = Command::from_str("sh -c 'exit 3'")?.run(
+            &mut state,
+            &mut prompter,
+            &finder,
+            &runner,
+        )?;
+        assert_eq!(result, CommandResult { status: 3 });
+        assert_eq!(state.last_status, 3);
+
+        // A command that cannot run fails without being a shell error.
+        let result = Command::from_str("nonexistent_command_xyz")?.run(
+            &mut state,
+            &mut prompter,
+            &finder,
+            &runner,
+        )?;
+        assert_eq!(result.status, 127);
+
+        let result = statement::run_line(
+            "false || true && false",
+            &mut state,
+            &mut prompter,
+            &finder,
+            &runner,
+        )?;
+        assert_eq!(result.status, 1);
+        let result = statement::run_line(
+            "false && echo no; true",
+            &mut state,
+            &mut prompter,
+            &finder,
+            &runner,
+        )?;
+        assert_eq!(result.status, 0);
+        assert_eq!(prompter.stdout, "");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn variable_names_are_validated() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        let output = run("export 1abc=val; unset 'bad name'", &mut state)?;
+        assert_eq!(
+            output.stderr,
+            "export: `1abc=val': not a valid identifier\nunset: `bad name': not a valid identifier\n"
+        );
+        assert_eq!(state.last_status, 1);
+        assert_eq!(state.variables.get("1abc"), None);
+
+        // A word that is not a valid assignment is run as a command instead.
+        let output = run("1abc=val", &mut state)?;
+        assert_eq!(output.stderr, "1abc=val: command not found\n");
+        assert_eq!(state.variables.get("1abc"), None);
+
+        run("_ok1=val; export A_B=1; unset _ok1 A_B", &mut state)?;
+        assert_eq!(state.last_status, 0);
+        assert_eq!(state.variables.get("_ok1"), None);
+        assert_eq!(state.variables.get("A_B"), None);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn type_reports_hashed_commands() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let foo = dir.path().join("foo");
+        fs::write(&foo, "#!/bin/sh\necho ran\n")?;
+        fs::set_permissions(&foo, fs::Permissions::from_mode(0o755))?;
+
+        let mut state = ShellState::new();
+        state
+            .variables
+            .set("PATH", &dir.path().display().to_string());
+
+        let output = run("type foo", &mut state)?;
+        assert_eq!(output.stdout, format!("foo is {}\n", foo.display()));
+
+        let output = run("foo", &mut state)?;
+        assert_eq!(output.stdout, "ran\n");
+
+        let output = run("type foo", &mut state)?;
+        assert_eq!(
+            output.stdout,
+            format!("foo is hashed ({})\n", foo.display())
+        );
+
+        fs::remove_file(&foo)?;
+        let output = run("type foo", &mut state)?;
+        assert_eq!(output.stderr, "foo: not found\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn type_reports_keywords_before_builtins() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        assert_eq!(
+            run("type if", &mut state)?.stdout,
+            "if is a shell keyword\n"
+        );
+        assert_eq!(
+            run("type '[['", &mut state)?.stdout,
+            "[[ is a shell keyword\n"
+        );
+        assert_eq!(
+            run("type echo", &mut state)?.stdout,
+            "echo is a shell builtin\n"
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn hash_builtin() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let foo = dir.path().join("foo");
+        fs::write(&foo, "#!/bin/sh\n")?;
+        fs::set_permissions(&foo, fs::Permissions::from_mode(0o755))?;
+
+        let mut state = ShellState::new();
+        state
+            .variables
+            .set("PATH", &dir.path().display().to_string());
+
+        assert_eq!(run("hash", &mut state)?.stdout, "hash: hash table empty\n");
+
+        run("hash foo", &mut state)?;
+        assert_eq!(
+            run("hash", &mut state)?.stdout,
+            format!("hits\tcommand\n   0\t{}\n", foo.display())
+        );
+
+        let output = run("hash missing", &mut state)?;
+        assert_eq!(output.stderr, "hash: missing: not found\n");
+        assert_eq!(state.last_status, 1);
+
+        run("hash -r", &mut state)?;
+        assert_eq!(run("hash", &mut state)?.stdout, "hash: hash table empty\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn echo_writes_exact_bytes_when_redirected() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let file = dir.path().join("out.txt");
+        let mut state = ShellState::new();
+
+        run(&format!("echo -n hi > {}", file.display()), &mut state)?;
+        assert_eq!(fs::metadata(&file)?.len(), 2);
+
+        run(&format!("echo hi >> {}", file.display()), &mut state)?;
+        assert_eq!(fs::read_to_string(&file)?, "hihi\n");
+        assert_eq!(fs::metadata(&file)?.len(), 5);
+
+        run(&format!("echo -n >> {}", file.display()), &mut state)?;
+        assert_eq!(fs::metadata(&file)?.len(), 5);
+
+        run(
+            &format!("echo -e 'ab\\ccd' > {}", file.display()),
+            &mut state,
+        )?;
+        assert_eq!(fs::read_to_string(&file)?, "ab");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn pwd_prints_logical_pwd_through_symlinks() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        fs::create_dir(&real)?;
+        std::os::unix::fs::symlink(&real, &link)?;
+
+        let mut state = ShellState::new();
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            run(&format!("cd {}", link.display()), &mut state)?;
+            assert_eq!(state.variables.get("PWD"), Some(link.to_str().unwrap()));
+            assert_eq!(
+                run("pwd", &mut state)?.stdout,
+                format!("{}\n", link.display())
+            );
+
+            state.variables.set("PWD", "/nonexistent");
+            let physical = std::env::current_dir()?;
+            assert_eq!(
+                run("pwd", &mut state)?.stdout,
+                format!("{}\n", physical.display())
+            );
+
+            state.variables.set("PWD", &link.display().to_string());
+            run("cd ..", &mut state)?;
+            assert_eq!(
+                run("pwd", &mut state)?.stdout,
+                format!("{}\n", dir.path().display())
+            );
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn type_reports_paths_with_a_slash_as_given() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let foo = dir.path().join("foo");
+        fs::write(&foo, "#!/bin/sh\n")?;
+        fs::set_permissions(&foo, fs::Permissions::from_mode(0o755))?;
+        let mut state = ShellState::new();
+
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            assert_eq!(run("type ./foo", &mut state)?.stdout, "./foo is ./foo\n");
+            let output = run("type ./missing", &mut state)?;
+            assert_eq!(output.stderr, "./missing: not found\n");
+            assert_eq!(state.last_status, 1);
+            return Ok(());
+        })?;
+
+        let output = run(&format!("type {}", foo.display()), &mut state)?;
+        assert_eq!(
+            output.stdout,
+            format!("{} is {}\n", foo.display(), foo.display())
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn running_a_directory_is_refused() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let path = dir.path().display();
+        let mut state = ShellState::new();
+
+        for line in [path.to_string(), format!("true | {}", path)] {
+            let output = run(&line, &mut state)?;
+            assert_eq!(output.stderr, format!("{}: Is a directory\n", path));
+            assert_eq!(state.last_status, 126);
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn pwd_and_cd_handle_names_that_are_not_utf8() -> anyhow::Result<()> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = TempDir::new()?;
+        let bad = dir.path().join(OsStr::from_bytes(b"bad\xff"));
+        let inner = bad.join("inner");
+        fs::create_dir_all(&inner)?;
+
+        let mut state = ShellState::new();
+        in_dir(&inner, || -> anyhow::Result<()> {
+            run("cd ..", &mut state)?;
+            assert_eq!(std::env::current_dir()?, bad);
+            assert_eq!(state.last_status, 0);
+
+            let output = run("pwd", &mut state)?;
+            assert_eq!(output.stdout, format!("{}\n", bad.display()));
+            assert!(output.stdout.ends_with("bad\u{FFFD}\n"));
+
+            run("cd inner", &mut state)?;
+            assert_eq!(std::env::current_dir()?, inner);
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn cd_updates_and_exports_pwd_and_oldpwd() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        fs::create_dir(&first)?;
+        fs::create_dir(&second)?;
+
+        let mut state = ShellState::new();
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            run(&format!("cd {}", first.display()), &mut state)?;
+            run(&format!("cd {}", second.display()), &mut state)?;
+            assert_eq!(state.variables.get("PWD"), Some(second.to_str().unwrap()));
+            assert_eq!(state.variables.get("OLDPWD"), Some(first.to_str().unwrap()));
+
+            let output = run("sh -c 'echo $OLDPWD $PWD'", &mut state)?;
+            assert_eq!(
+                output.stdout,
+                format!("{} {}\n", first.display(), second.display())
+            );
+
+            let output = run("cd -", &mut state)?;
+            assert_eq!(output.stdout, format!("{}\n", first.display()));
+            assert_eq!(
+                state.variables.get("OLDPWD"),
+                Some(second.to_str().unwrap())
+            );
+
+            state.variables.unset("OLDPWD");
+            let output = run("cd -", &mut state)?;
+            assert_eq!(output.stderr, "cd: OLDPWD not set\n");
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn cd_expands_variables_and_ignores_an_empty_path() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let home = dir.path().join("home");
+        fs::create_dir_all(home.join("src"))?;
+
+        let mut state = ShellState::new();
+        state.variables.set("HOME", &home.display().to_string());
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            run("cd $HOME/src", &mut state)?;
+            assert_eq!(state.last_status, 0);
+            assert_eq!(state.variables.get("PWD"), home.join("src").to_str());
+
+            let output = run("cd \"$UNSET\"", &mut state)?;
+            assert_eq!(output.stderr, "");
+            assert_eq!(state.last_status, 0);
+            assert_eq!(state.variables.get("PWD"), home.join("src").to_str());
+
+            // Unquoted, the empty word is dropped and `cd` goes home.
+            run("cd $UNSET", &mut state)?;
+            assert_eq!(state.last_status, 0);
+            assert_eq!(state.variables.get("PWD"), home.to_str());
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn cd_searches_cdpath() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let projects = dir.path().join("projects");
+        fs::create_dir_all(projects.join("shell"))?;
+        fs::create_dir(dir.path().join("local"))?;
+
+        let mut state = ShellState::new();
+        state
+            .variables
+            .set("CDPATH", &format!(":{}", projects.display()));
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            let output = run("cd shell", &mut state)?;
+            assert_eq!(
+                output.stdout,
+                format!("{}\n", projects.join("shell").display())
+            );
+
+            run(&format!("cd {}", dir.path().display()), &mut state)?;
+            let output = run("cd local", &mut state)?;
+            assert_eq!(output.stdout, "");
+            assert_eq!(
+                state.variables.get("PWD"),
+                Some(dir.path().join("local").to_str().unwrap())
+            );
+
+            let output = run("cd ./shell", &mut state)?;
+            assert!(output.stderr.starts_with("cd: ./shell: "));
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn trailing_ampersand_runs_in_background() -> anyhow::Result<()> {
+        for (input, background) in [
+            ("sleep 1 &", true),
+            ("sleep 1&", true),
+            ("echo '&'", false),
+            (r"echo a\&", false),
+            ("echo 2>&1", false),
+        ] {
+            let command = input.parse::<Command>()?;
+            assert_eq!(command.background, background, "{}", input);
+        }
+
+        let mut state = ShellState::new();
+        state.interactive = true;
+        let output = run("sleep 5 &", &mut state)?;
+        let job = state.jobs.find("%1").expect("job was added");
+        assert_eq!(output.stderr, format!("[1] {}\n", job.pid));
+        assert_eq!(job.command, "sleep 5");
+
+        run("kill -KILL %1", &mut state)?;
+        assert_eq!(state.last_status, 0);
+
+        // A script is not told which job it started.
+        state.interactive = false;
+        assert_eq!(run("sleep 0 &", &mut state)?.stderr, "");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn backgrounded_builtins_and_functions_run_in_a_subshell() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            let start = std::env::current_dir()?;
+
+            let output = run("cd /usr & pwd; set_x() { x=1; }; set_x &", &mut state)?;
+            assert_eq!(output.stdout, format!("{}\n", start.display()));
+            assert_eq!(std::env::current_dir()?, start);
+            assert_eq!(state.variables.get("x"), None);
+
+            return Ok(());
+        })?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn shell_and_last_job_pids() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let pid = std::process::id();
+
+        assert_eq!(
+            run("echo $$ ${$}", &mut state)?.stdout,
+            format!("{} {}\n", pid, pid)
+        );
+        assert_eq!(run("echo \"[$!]\"", &mut state)?.stdout, "[]\n");
+
+        for _ in 0..2 {
+            run("sleep 5 &", &mut state)?;
+            let job = state.jobs.current().expect("job was added");
+            let expected = format!("{}\n", job.pid);
+            assert_eq!(run("echo $!", &mut state)?.stdout, expected);
+            assert_eq!(run("echo $(echo $!)", &mut state)?.stdout, expected);
+        }
+        let last = state.jobs.current().map(|job| return job.pid);
+
+        run("kill -KILL %1 %2", &mut state)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        state.jobs.reap();
+        assert_eq!(
+            run("echo $!", &mut state)?.stdout,
+            format!("{}\n", last.unwrap_or(0))
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn pipes_stream_between_external_commands() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        // Far more than a pipe buffer holds, so every stage has to run concurrently.
+        let output = run("head -c 10000000 /dev/zero | cat | wc -c", &mut state)?;
+        assert_eq!(output.stdout.trim(), "10000000");
+        assert_eq!(state.last_status, 0);
+
+        let output = run("echo hello | tr a-z A-Z", &mut state)?;
+        assert_eq!(output.stdout, "HELLO\n");
+
+        let output = run("echo 'a|b' | cat", &mut state)?;
+        assert_eq!(output.stdout, "a|b\n");
+
+        run("echo hi | false", &mut state)?;
+        assert_eq!(state.last_status, 1);
+
+        let output = run("nonexistent_command_xyz | cat", &mut state)?;
+        assert_eq!(
+            output.stderr,
+            "nonexistent_command_xyz: command not found\n"
+        );
+
+        assert!("echo hi |".parse::<Command>().is_err());
+        assert!("| cat".parse::<Command>().is_err());
+
+        for (input, token) in [
+            ("echo hi > >", ">"),
+            ("echo hi >", "newline"),
+            ("echo hi > | cat", "|"),
+        ] {
+            assert_eq!(
+                input.parse::<Command>().unwrap_err().to_string(),
+                format!("syntax error near unexpected token `{}'", token)
+            );
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn shift_drops_positional_parameters() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.positional_params = vec![String::from("a"), String::from("b"), String::from("c")];
+
+        assert_eq!(run("shift 2; echo $# $1", &mut state)?.stdout, "1 c\n");
+        assert_eq!(state.last_status, 0);
+
+        run("shift 2", &mut state)?;
+        assert_eq!(state.last_status, 1);
+        assert_eq!(state.positional_params, vec![String::from("c")]);
+
+        assert_eq!(
+            run("shift x", &mut state)?.stderr,
+            "shift: x: numeric argument required\n"
+        );
+        run("shift", &mut state)?;
+        assert_eq!(state.positional_params, Vec::<String>::new());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn select_reads_choices_until_the_input_ends() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut prompter = CapturePrompter {
+            input: vec![String::from("2"), String::new(), String::from("foo")],
+            ..Default::default()
+        };
+        statement::run_line(
+            "select x in a b; do echo \"[$x] [$REPLY]\"; done",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(prompter.stdout, "[b] [2]\n[] [foo]\n");
+        assert_eq!(prompter.stderr, "1) a\n2) b\n#? #? 1) a\n2) b\n#? #? \n");
+        assert_eq!(state.last_status, 1);
+
+        state.assign("PS3", "pick: ");
+        state.positional_params = vec![String::from("one")];
+        let mut prompter = CapturePrompter {
+            input: vec![String::from("1"), String::from("1")],
+            ..Default::default()
+        };
+        statement::run_line(
+            "select x; do echo $x; break; echo skipped; done; echo after",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(prompter.stdout, "one\nafter\n");
+        assert_eq!(prompter.stderr, "1) one\npick: ");
+        assert_eq!(state.last_status, 0);
+
+        assert!(statement::parse("select x in a; do echo $x").is_err());
+        assert!(statement::parse("select 1x in a; do echo; done").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn process_substitution_reads_like_a_file() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        assert_eq!(run("cat <(echo hi)", &mut state)?.stdout, "hi\n");
+        assert_eq!(
+            run("cat <(echo a) <(echo 'b)')", &mut state)?.stdout,
+            "a\nb)\n"
+        );
+        assert_eq!(run("echo '<(echo hi)'", &mut state)?.stdout, "<(echo hi)\n");
+        assert!(state.process_substitutions.is_empty());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn local_variables_do_not_leak_to_the_caller() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.assign("x", "global");
+
+        run("inner() { echo inner $x; x=set-by-inner; }", &mut state)?;
+        run(
+            "outer() { local x=local y; echo outer $x; inner; echo outer $x; y=1; }",
+            &mut state,
+        )?;
+        assert_eq!(
+            run("outer", &mut state)?.stdout,
+            "outer local\ninner local\nouter set-by-inner\n"
+        );
+        assert_eq!(state.variables.get("x"), Some("global"));
+        assert_eq!(state.variables.get("y"), None);
+        assert!(state.local_scopes.is_empty());
+
+        // Without `local`, assignments in a function reach the caller.
+        run("set_z() { z=from-function; }; set_z", &mut state)?;
+        assert_eq!(state.variables.get("z"), Some("from-function"));
+
+        let output = run("local x=1", &mut state)?;
+        assert_eq!(output.stderr, "local: can only be used in a function\n");
+        assert_eq!(state.last_status, 1);
+        assert_eq!(state.variables.get("x"), Some("global"));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn functions_take_arguments_and_shadow_builtins() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+
+        run("greet() { echo hello $1; echo $#; }", &mut state)?;
+        assert_eq!(run("greet world", &mut state)?.stdout, "hello world\n1\n");
+        assert_eq!(state.positional_params, Vec::<String>::new());
+
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            run("cd() { echo no cd for $1; }", &mut state)?;
+            let output = run("cd /", &mut state)?;
+            assert_eq!(output.stdout, "no cd for /\n");
+            assert_eq!(std::env::current_dir()?, dir.path().canonicalize()?);
+
+            run("builtin cd /", &mut state)?;
+            assert_eq!(std::env::current_dir()?, Path::new("/"));
+            assert_eq!(state.variables.get("PWD"), Some("/"));
+
+            return Ok(());
+        })?;
+
+        let output = run("builtin greet", &mut state)?;
+        assert_eq!(output.stderr, "builtin: greet: not a shell builtin\n");
+        assert_eq!(state.last_status, 1);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn pipefail_reports_the_last_failing_stage() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        run("sh -c 'exit 3' | true", &mut state)?;
+        assert_eq!(state.last_status, 0);
+
+        run("set -o pipefail", &mut state)?;
+        run("sh -c 'exit 3' | true", &mut state)?;
+        assert_eq!(state.last_status, 3);
+        run("sh -c 'exit 3' | sh -c 'exit 4' | true", &mut state)?;
+        assert_eq!(state.last_status, 4);
+        run("false | true && echo passed", &mut state)?;
+        assert_eq!(state.last_status, 1);
+        run("echo hi | true", &mut state)?;
+        assert_eq!(state.last_status, 0);
+        assert_eq!(state.pipe_status, vec![0, 0]);
+
+        return Ok(());
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub struct Command {
-    kind: CommandKind,
-    redirection: Option<Redirection>,
-}
+    #[test]
+    fn pipestatus_records_every_stage() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-impl FromStr for Command {
-    type Err = anyhow::Error;
+        run("sh -c 'exit 3' | true", &mut state)?;
+        assert_eq!(state.pipe_status, vec![3, 0]);
+        let output = run("echo ${PIPESTATUS[0]} ${PIPESTATUS[1]} $?", &mut state)?;
+        assert_eq!(output.stdout, "3 0 0\n");
 
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let input_args = parse_args(input);
-        let redirection_start_index = input_args
-            .iter()
-            .position(|input_arg| return Redirection::is_redirection_arg(&input_arg));
+        run("echo hi | false", &mut state)?;
+        let output = run("echo ${PIPESTATUS[@]} $?", &mut state)?;
+        assert_eq!(output.stdout, "0 1 1\n");
 
-        match redirection_start_index {
-            Some(index) => {
-                let cmd = CommandKind::new(input_args[..index].to_vec())?;
-                let redirection = Redirection::new(input_args[index..].to_vec())?;
+        // A single command is a pipeline of one.
+        let output = run("echo ${PIPESTATUS[*]} ${PIPESTATUS[1]}", &mut state)?;
+        assert_eq!(output.stdout, "0\n");
 
-                return Ok(Command {
-                    kind: cmd,
-                    redirection: Some(redirection),
-                });
-            }
-            None => {
-                let cmd = CommandKind::new(input_args)?;
-                return Ok(Command {
-                    kind: cmd,
-                    redirection: None,
-                });
-            }
-        }
+        return Ok(());
     }
-}
 
-#[derive(Debug)]
-pub struct CommandOutput {
-    pub stdout: Option<String>,
-    pub stderr: Option<String>,
-}
+    #[test]
+    fn builtin_stages_run_in_a_subshell() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            let start = std::env::current_dir()?;
+            run(&format!("cd {}", start.display()), &mut state)?;
+
+            run("cd /usr | cat; x=1 | cat", &mut state)?;
+            assert_eq!(std::env::current_dir()?, start);
+            assert_eq!(state.variables.get("PWD"), start.to_str());
+            assert_eq!(state.variables.get("x"), None);
 
-impl Command {
-    pub fn run(
-        self,
-        prompter: &mut impl Prompter,
-        finder: &impl ExecutablePathFinder,
-        runner: &impl ExecutableRunner,
-    ) -> anyhow::Result<()> {
-        let Some(output) = (match self.kind {
-            CommandKind::Builtin(builtin_command) => {
-                match run_builtin_command(builtin_command, finder) {
-                    Ok(output) => Some(output),
-                    Err(e) => {
-                        println!("Command error");
-                        prompter.prompt(&e.to_string())?;
-                        None
-                    }
-                }
-            }
-            CommandKind::Unknown { cmd, args } => match run_unknown_command(runner, cmd, args) {
-                Ok(output) => Some(output),
-                Err(e) => {
-                    println!("Command error");
-                    prompter.prompt(&e.to_string())?;
-                    None
-                }
-            },
-        }) else {
             return Ok(());
-        };
+        })?;
+
+        return Ok(());
+    }
 
-        if let Some(redirection) = self.redirection {
-            redirection.run(&output)?;
+    #[test]
+    fn and_or_lists_depend_on_the_status() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-            match redirection.source {
-                redirection::Source::Stdout(_) if output.stderr.is_some() => {
-                    prompter.prompt(&output.stderr.unwrap_or("".to_string()))?
-                }
-                redirection::Source::Stderr(_) => {
-                    prompter.prompt(&output.stdout.unwrap_or("".to_string()))?
-                }
-                _ => {}
-            }
-        } else if let Some(prompt_output) = output.stderr.or(output.stdout) {
-            prompter.prompt(&prompt_output)?;
-        }
+        let output = run("false && echo no || echo yes; true || echo no", &mut state)?;
+        assert_eq!(output.stdout, "yes\n");
+        assert_eq!(state.last_status, 0);
+
+        let output = run("echo a && false && echo b", &mut state)?;
+        assert_eq!(output.stdout, "a\n");
+        assert_eq!(state.last_status, 1);
 
         return Ok(());
     }
-}
 
-fn run_builtin_command(
-    command: BuiltinCommand,
-    finder: &impl ExecutablePathFinder,
-) -> anyhow::Result<CommandOutput> {
-    match command {
-        BuiltinCommand::Exit { code } => {
-            std::process::exit(code);
-        }
-        BuiltinCommand::Echo { input } => {
-            return Ok(CommandOutput {
-                stdout: Some(format!("{}\n", input)),
-                stderr: None,
-            });
-        }
-        BuiltinCommand::Type(command) => match command {
-            TypeCommand::WellKnown { cmd } => {
-                return Ok(CommandOutput {
-                    stdout: Some(format!("{} is a shell builtin\n", cmd)),
-                    stderr: None,
-                })
-            }
-            TypeCommand::Unknown { cmd } => {
-                let env_path = std::env::var("PATH")?;
-                let result = finder.find_executable_path(&env_path, &cmd);
+    #[test]
+    fn command_substitution_runs_in_a_subshell() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        let output = run(
+            r#"echo "[$(echo a; echo b | cat)]" $(cd /; pwd) "$(greet() { echo hi; }; greet)""#,
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "[a\nb] / hi\n");
+        assert!(!state.functions.contains_key("greet"));
+
+        let dir = TempDir::new()?;
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            let output = run("x=$(cd /; pwd); echo $x; pwd", &mut state)?;
+            assert_eq!(output.stdout, format!("/\n{}\n", dir.path().display()));
+            assert_eq!(std::env::current_dir()?, dir.path().canonicalize()?);
+            return Ok(());
+        })?;
 
-                match result {
-                    Some(full_path) => {
-                        return Ok(CommandOutput {
-                            stdout: Some(format!("{} is {}\n", cmd, full_path)),
-                            stderr: None,
-                        });
-                    }
-                    None => {
-                        return Ok(CommandOutput {
-                            stdout: None,
-                            stderr: Some(format!("{}: not found\n", cmd)),
-                        });
-                    }
-                }
-            }
-        },
-        BuiltinCommand::Pwd => {
-            let pwd = std::env::current_dir()?;
-            let pwd = pwd
-                .into_os_string()
-                .into_string()
-                .expect("Failed to convert path");
+        let output = run(
+            "x=$(echo in; exit 5; echo never); echo after $x",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "after in\n");
+        assert_eq!(state.exiting, None);
 
-            return Ok(CommandOutput {
-                stdout: Some(format!("{}\n", pwd)),
-                stderr: None,
+        return Ok(());
+    }
+
+    #[test]
+    fn nested_substitutions_stop_at_the_depth_limit() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.variables.set("SHELL_EXPANSION_DEPTH", "3");
+        let nested = |depth: usize| -> String {
+            return (0..depth).fold(String::from("echo deep"), |command, _| {
+                return format!("echo $({})", command);
             });
-        }
-        BuiltinCommand::Cd { path } => {
-            let home_path =
-                std::env::home_dir().ok_or(anyhow!("Could not get the home directory"))?;
-            let home_path = home_path.to_str().expect("Could not convert the path");
+        };
 
-            let path = path.replace("~", home_path);
+        let output = run(&nested(3), &mut state)?;
+        assert_eq!(output.stdout, "deep\n");
 
-            let result = std::env::set_current_dir(&path);
-            if let Err(e) = result {
-                match e.kind() {
-                    std::io::ErrorKind::NotFound => {
-                        return Ok(CommandOutput {
-                            stdout: None,
-                            stderr: Some(format!("cd: {}: No such file or directory\n", path)),
-                        });
-                    }
-                    _ => return Err(anyhow!("Unknown error")),
-                }
-            };
+        let output = run(&nested(10), &mut state)?;
+        assert_eq!(output.stdout, "\n");
+        assert_eq!(output.stderr, "expansion too deep\n");
 
-            return Ok(CommandOutput {
-                stdout: None,
-                stderr: None,
-            });
-        }
+        return Ok(());
     }
-}
 
-fn run_unknown_command(
-    runner: &impl ExecutableRunner,
-    cmd: String,
-    args: Vec<String>,
-) -> anyhow::Result<CommandOutput> {
-    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
-    let args = args.as_slice();
+    #[test]
+    fn echo_keeps_quoted_spaces_and_collapses_unquoted_ones() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-    let output = runner.execute(&cmd, args)?;
-    return Ok(CommandOutput {
-        stdout: output.stdout,
-        stderr: output.stderr,
-    });
-}
+        let output = run(r#"echo "a   b""#, &mut state)?;
+        assert_eq!(output.stdout, "a   b\n");
 
-fn parse_args(args: &str) -> Vec<String> {
-    let mut current_arg = String::new();
-    let mut parsed_args: Vec<String> = vec![];
+        let output = run("echo a   b", &mut state)?;
+        assert_eq!(output.stdout, "a b\n");
 
-    let mut inside_single_quotes = false;
-    let mut inside_double_quotes = false;
+        let output = run(r#"echo 'a  '   "  b""#, &mut state)?;
+        assert_eq!(output.stdout, "a     b\n");
 
-    for (index, current_char) in args.chars().enumerate() {
-        let prev_char = if index > 0 {
-            args.chars().nth(index - 1)
-        } else {
-            None
-        };
+        return Ok(());
+    }
 
-        let next_char = args.chars().nth(index + 1);
-
-        match current_char {
-            '\'' => {
-                let is_previous_escape_char = prev_char == Some('\\');
-                if is_previous_escape_char {
-                    current_arg.push(current_char);
-                } else if inside_double_quotes {
-                    current_arg.push(current_char)
-                } else {
-                    inside_single_quotes = !inside_single_quotes;
-                }
-            }
-            '\\' => {
-                if inside_single_quotes {
-                    current_arg.push(current_char);
-                }
+    #[test]
+    fn substitution_strips_only_trailing_newlines() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-                if inside_double_quotes {
-                    if next_char == Some('"') {
-                        continue;
-                    }
+        run(r"x=$(printf 'a\n\n')", &mut state)?;
+        assert_eq!(state.variables.get("x"), Some("a"));
 
-                    if next_char == Some('$') {
-                        continue;
-                    }
+        run(r"x=$(printf ' a\n\nb \n\n\n')", &mut state)?;
+        assert_eq!(state.variables.get("x"), Some(" a\n\nb "));
+        let output = run(r#"echo "[$x]""#, &mut state)?;
+        assert_eq!(output.stdout, "[ a\n\nb ]\n");
 
-                    if prev_char == Some('\\') {
-                        continue;
-                    }
+        return Ok(());
+    }
 
-                    current_arg.push(current_char);
-                }
-            }
-            '"' => {
-                if inside_double_quotes && next_char.is_none() {
-                    continue;
-                }
+    #[test]
+    fn assignments() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+
+        run("a='x  y' b=$a c=\"$a\"z", &mut state)?;
+        assert_eq!(state.variables.get("b"), Some("x  y"));
+        assert_eq!(state.variables.get("c"), Some("x  yz"));
+        assert_eq!(state.last_status, 0);
+
+        // Before a command the value is only exported to that command.
+        let output = run("b=temporary sh -c 'echo $b'; echo $b", &mut state)?;
+        assert_eq!(output.stdout, "temporary\nx y\n");
+        let output = run(
+            "new=1 sh -c 'echo ${new:-unset}'; echo \"[$new]\"",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "1\n[]\n");
+        assert_eq!(state.variables.variable("new"), None);
+
+        run("RANDOM=7; x=$RANDOM; RANDOM=7; y=$RANDOM", &mut state)?;
+        assert_eq!(state.variables.get("x"), state.variables.get("y"));
+
+        // A bare assignment ends with the status of its last substitution.
+        let output = run(
+            "x=$(false); echo $?; x=$(exit 3)$(true); echo $?",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "1\n0\n");
+        let output = run("false; x=$?; echo $x $?; x=$(exit 3); echo $?", &mut state)?;
+        assert_eq!(output.stdout, "1 0\n3\n");
 
-                let is_previous_escape_char = prev_char == Some('\\');
-                if is_previous_escape_char {
-                    current_arg.push(current_char)
-                } else if inside_single_quotes {
-                    current_arg.push(current_char);
-                } else {
-                    inside_double_quotes = !inside_double_quotes
-                }
-            }
-            ' ' => {
-                let is_previous_escape_char = prev_char == Some('\\');
-                if inside_single_quotes {
-                    current_arg.push(current_char);
-                } else if inside_double_quotes {
-                    current_arg.push(current_char);
-                } else if is_previous_escape_char {
-                    current_arg.push(current_char);
-                } else if !current_arg.is_empty() {
-                    parsed_args.push(current_arg.clone());
-                    current_arg.clear();
-                }
-            }
-            _ => {
-                current_arg.push(current_char);
-            }
+        return Ok(());
+    }
+
+    /// Records which way each program was run instead of running it.
+    #[derive(Default)]
+    struct RecordingRunner {
+        runs: RefCell<Vec<String>>,
+        job_control: bool,
+    }
+
+    impl ExecutableRunner for RecordingRunner {
+        fn job_control(&self) -> bool {
+            return self.job_control;
+        }
+
+        fn execute(&self, invocation: &Invocation) -> anyhow::Result<ExecutableOutput> {
+            self.runs
+                .borrow_mut()
+                .push(format!("captured {}", invocation.name));
+            return Ok(ExecutableOutput {
+                stdout: None,
+                stderr: None,
+                status: 0,
+            });
+        }
+
+        fn execute_attached(
+            &self,
+            invocation: &Invocation,
+            _stdin: Stdio,
+            _stdout: Stdio,
+            _stderr: Stdio,
+        ) -> std::io::Result<Attached> {
+            self.runs
+                .borrow_mut()
+                .push(format!("inherited {}", invocation.name));
+            return Ok(Attached::Exited(0));
         }
     }
 
-    parsed_args.push(current_arg);
-    return parsed_args;
-}
+    #[test]
+    fn foreground_programs_inherit_the_terminal() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+        let runner = RecordingRunner::default();
+        let run = |input: &str, attached: bool, state: &mut ShellState| {
+            let mut prompter = CapturePrompter {
+                attached,
+                ..Default::default()
+            };
+            return statement::run_line(input, state, &mut prompter, &PathFinder::new(), &runner);
+        };
 
-#[cfg(test)]
-mod parse_args_tests {
-    use super::*;
+        run("vim notes", true, &mut state)?;
+        run(
+            &format!("ls > {}", dir.path().join("out").display()),
+            true,
+            &mut state,
+        )?;
+        run("top", false, &mut state)?;
+        assert_eq!(
+            runner.runs.take(),
+            vec!["inherited vim", "captured ls", "captured top"]
+        );
+
+        // Under job control a redirected program runs in the foreground as well.
+        let runner = RecordingRunner {
+            job_control: true,
+            ..Default::default()
+        };
+        let mut prompter = CapturePrompter {
+            attached: true,
+            ..Default::default()
+        };
+        let line = format!("ls > {}", dir.path().join("out").display());
+        statement::run_line(
+            &line,
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &runner,
+        )?;
+        assert_eq!(runner.runs.take(), vec!["inherited ls"]);
+
+        return Ok(());
+    }
+
+    /// Fails to start every program it runs.
+    struct FailingRunner;
+
+    impl ExecutableRunner for FailingRunner {
+        fn execute(&self, _invocation: &Invocation) -> anyhow::Result<ExecutableOutput> {
+            return Err(anyhow!("cannot start"));
+        }
+    }
 
     #[test]
-    fn single_arg() {
-        let args = r#"single"#;
+    fn command_errors_go_to_stderr() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut prompter = CapturePrompter::default();
+        statement::run_line(
+            "ls",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &FailingRunner,
+        )?;
+        assert_eq!(prompter.stdout, "");
+        assert_eq!(prompter.stderr, "cannot start\n");
+        assert_eq!(state.last_status, 1);
+
+        return Ok(());
+    }
 
-        let output = parse_args(args);
-        let expected = vec![r#"single"#.to_string()];
+    /// Stops each program it runs in the foreground, as Ctrl-Z would.
+    struct StoppingRunner;
+
+    impl ExecutableRunner for StoppingRunner {
+        fn execute_attached(
+            &self,
+            invocation: &Invocation,
+            _stdin: Stdio,
+            _stdout: Stdio,
+            _stderr: Stdio,
+        ) -> std::io::Result<Attached> {
+            let child = invocation.command().spawn()?;
+            // SAFETY: sending a signal has no memory safety requirements.
+            unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTSTP) };
+            return crate::executable::wait_attached(child);
+        }
+    }
 
-        assert_eq!(output, expected)
+    #[test]
+    fn stopped_foreground_programs_become_jobs() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut prompter = CapturePrompter {
+            attached: true,
+            ..Default::default()
+        };
+        statement::run_line(
+            "sleep 5",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &StoppingRunner,
+        )?;
+        assert_eq!(state.last_status, 148);
+        assert_eq!(prompter.stderr, "\n[1]+  Stopped                 sleep 5\n");
+
+        let output = run("jobs", &mut state)?;
+        assert_eq!(output.stdout, "[1]+  Stopped                 sleep 5\n");
+
+        run("kill -CONT %1", &mut state)?;
+        let output = run("jobs", &mut state)?;
+        assert_eq!(output.stdout, "[1]+  Running                 sleep 5 &\n");
+
+        run("kill -KILL %1", &mut state)?;
+        return Ok(());
     }
 
     #[test]
-    fn single_arg_single_quotes() {
-        let args = r#"'single'"#;
+    fn stopped_pipelines_and_redirected_commands_become_jobs() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let runner = Runner::new().with_job_control(true);
+        let run = |input: &str, state: &mut ShellState| {
+            let mut prompter = CapturePrompter {
+                attached: true,
+                ..Default::default()
+            };
+            statement::run_line(input, state, &mut prompter, &PathFinder::new(), &runner)?;
+            return anyhow::Ok(prompter);
+        };
 
-        let output = parse_args(args);
-        let expected = vec![r#"single"#.to_string()];
+        let output = run("sh -c 'kill -STOP $$' | cat", &mut state)?;
+        assert_eq!(state.last_status, 128 + libc::SIGSTOP);
+        assert_eq!(
+            output.stderr,
+            "\n[1]+  Stopped                 sh -c 'kill -STOP $$' | cat\n"
+        );
+
+        let output = run("fg", &mut state)?;
+        assert_eq!(output.stdout, "sh -c 'kill -STOP $$' | cat\n");
+        assert_eq!(state.last_status, 0);
+        assert_eq!(run("jobs", &mut state)?.stdout, "");
+
+        run("sh -c 'kill -STOP $$; exit 3' > /dev/null", &mut state)?;
+        assert_eq!(state.last_status, 128 + libc::SIGSTOP);
+        let output = run("bg %1", &mut state)?;
+        assert_eq!(
+            output.stdout,
+            "[1]+ sh -c 'kill -STOP $$; exit 3' > /dev/null &\n"
+        );
+        assert_eq!(
+            run("bg", &mut state)?.stderr,
+            "bg: job 1 already in background\n"
+        );
+
+        run("fg %sh", &mut state)?;
+        assert_eq!(state.last_status, 3);
+        assert_eq!(run("fg", &mut state)?.stderr, "fg: current: no such job\n");
+        assert_eq!(state.last_status, 1);
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn single_arg_escaped_single_quote() {
-        {
-            let args = r#"sing\'le"#;
+    fn exit_warns_about_stopped_jobs_once() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut prompter = CapturePrompter {
+            attached: true,
+            ..Default::default()
+        };
+        statement::run_line(
+            "sleep 5",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &StoppingRunner,
+        )?;
+
+        let output = run("exit", &mut state)?;
+        assert_eq!(output.stderr, "There are stopped jobs.\n");
+        assert_eq!(state.last_status, 1);
+        assert!(state.exit_warned);
+
+        // Any other command in between brings the warning back.
+        run("echo still here", &mut state)?;
+        assert!(!state.exit_warned);
+        let output = run("exit 3", &mut state)?;
+        assert_eq!(output.stderr, "There are stopped jobs.\n");
+
+        run("kill -KILL %1", &mut state)?;
+        return Ok(());
+    }
 
-            let output = parse_args(args);
-            let expected = vec![r#"sing'le"#.to_string()];
+    #[test]
+    fn end_of_input_warns_about_stopped_jobs_once() -> anyhow::Result<()> {
+        let mut shell = Shell::new(
+            CapturePrompter::default(),
+            PathFinder::new(),
+            StoppingRunner,
+        );
+        shell.prompter.attached = true;
+        shell.run_line("sleep 5")?;
+        shell.prompter.stderr.clear();
+
+        assert!(!shell.may_exit()?);
+        assert_eq!(shell.prompter.stderr, "There are stopped jobs.\n");
+        assert!(shell.may_exit()?);
+
+        shell.run_line("kill -KILL %1")?;
+        return Ok(());
+    }
 
-            assert_eq!(output, expected)
-        }
+    #[test]
+    fn signal_killed_commands_report_128_plus_the_signal() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-        {
-            let args = r#"\'single"#;
+        let output = run("sh -c 'kill -9 $$'; echo $?", &mut state)?;
+        assert_eq!(output.stdout, "137\n");
+
+        let output = run("sleep 5 & kill %1; wait %1; echo $?", &mut state)?;
+        assert_eq!(output.stdout, "143\n");
+        assert_eq!(state.jobs.iter().count(), 0);
+        return Ok(());
+    }
 
-            let output = parse_args(args);
-            let expected = vec![r#"'single"#.to_string()];
+    #[test]
+    fn timeout_stops_a_long_running_command() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-            assert_eq!(output, expected)
-        }
+        let start = std::time::Instant::now();
+        let output = run("timeout 0.2 sleep 10; echo $?", &mut state)?;
+        assert_eq!(output.stdout, "124\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
 
-        {
-            let args = r#"single\'"#;
+        let output = run("timeout 10 echo done | cat", &mut state)?;
+        assert_eq!(output.stdout, "done\n");
+        assert_eq!(state.last_status, 0);
 
-            let output = parse_args(args);
-            let expected = vec![r#"single'"#.to_string()];
+        let output = run("timeout soon echo done", &mut state)?;
+        assert_eq!(output.stderr, "timeout: invalid time interval 'soon'\n");
+        assert_eq!(state.last_status, 2);
 
-            assert_eq!(output, expected)
+        return Ok(());
+    }
+
+    #[test]
+    fn large_output_on_both_streams_does_not_deadlock() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        // Far more than a pipe holds, with stderr written in full before any stdout.
+        let script = "sh -c 'yes err | head -c 1000000 >&2; yes out | head -c 1000000'";
+
+        for line in [
+            script.to_string(),
+            format!("echo | {}", script),
+            format!("timeout 10 {}", script),
+        ] {
+            let output = run(&line, &mut state)?;
+            assert_eq!(output.stdout.len(), 1_000_000, "{}", line);
+            assert_eq!(output.stderr.len(), 1_000_000, "{}", line);
+            assert_eq!(state.last_status, 0, "{}", line);
         }
+
+        return Ok(());
     }
 
     #[test]
-    fn multiple_args_escaped_quote() {
-        let args = r#"f\'irst secon\'d"#;
+    fn read_joins_lines_ending_in_a_backslash_unless_raw() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        let mut read_line = |input: &[&str], command: &str| {
+            let mut prompter = CapturePrompter {
+                input: input.iter().map(|line| return line.to_string()).collect(),
+                ..Default::default()
+            };
+            statement::run_line(
+                command,
+                &mut state,
+                &mut prompter,
+                &PathFinder::new(),
+                &Runner::new(),
+            )?;
+            return anyhow::Ok(state.variables.get("line").unwrap_or_default().to_string());
+        };
 
-        let output = parse_args(args);
-        let expected = vec![r#"f'irst"#.to_string(), r#"secon'd"#.to_string()];
+        assert_eq!(read_line(&[r"C:\temp\", "dir"], "read line")?, r"C:tempdir");
+        assert_eq!(
+            read_line(&[r"C:\temp\", "dir"], "read -r line")?,
+            r"C:\temp\"
+        );
 
-        assert_eq!(output, expected);
+        return Ok(());
     }
 
     #[test]
-    fn single_arg_double_quotes() {
-        let args = r#""single""#;
+    fn read_times_out_without_input() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.variables.set("answer", "old");
+
+        let output = run(
+            "read -t 1 answer || echo \"timed out: $? [$answer]\"",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "timed out: 142 []\n");
+
+        let mut prompter = CapturePrompter {
+            input: vec![String::from("yes")],
+            ..Default::default()
+        };
+        statement::run_line(
+            "read -t 1 answer",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(state.last_status, 0);
+        assert_eq!(state.variables.get("answer"), Some("yes"));
+
+        return Ok(());
+    }
 
-        let output = parse_args(args);
-        let expected = vec![r#"single"#.to_string()];
+    #[test]
+    fn ifs_splits_substitutions_and_read_alike() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        run("IFS=,", &mut state)?;
+
+        let output = run(
+            "sh -c 'echo $#: $1/$3' x $(echo 1,2,3); set -- a b; echo \"$*\"",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "3: 1/3\na,b\n");
+
+        let mut prompter = CapturePrompter {
+            input: vec![String::from("1,2,3")],
+            ..Default::default()
+        };
+        statement::run_line(
+            "read a b c",
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(state.variables.get("a"), Some("1"));
+        assert_eq!(state.variables.get("b"), Some("2"));
+        assert_eq!(state.variables.get("c"), Some("3"));
+
+        state.variables.unset("IFS");
+        let output = run("sh -c 'echo $#' x $(echo '1,2 3')", &mut state)?;
+        assert_eq!(output.stdout, "2\n");
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn escaped_double_quotes() {
-        let args = r#"first\"second"#;
+    fn set_listing_reads_back_as_assignments() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        run(r#"value="it's  a \"test\" \$HOME""#, &mut state)?;
 
-        let output = parse_args(args);
-        let expected = vec![r#"first"second"#.to_string()];
+        let listing = run("set", &mut state)?.stdout;
+        let line = listing
+            .lines()
+            .find(|line| return line.starts_with("value="))
+            .unwrap_or_default();
+        assert_eq!(line, r#"value='it'\''s  a "test" $HOME'"#);
 
-        assert_eq!(output, expected);
+        let mut copy = ShellState::new();
+        run(line, &mut copy)?;
+        assert_eq!(copy.variables.get("value"), state.variables.get("value"));
+
+        return Ok(());
     }
 
     #[test]
-    fn escaped_double_quotes_inside_single_quotes() {
-        let args = r#"'sin\"gle'"#;
+    fn input_redirection_feeds_stdin() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let input = dir.path().join("in");
+        fs::write(&input, "hello world\nsecond\n")?;
+        let mut state = ShellState::new();
+        state.variables.set("IN", &input.display().to_string());
+
+        let output = run("cat < $IN", &mut state)?;
+        assert_eq!(output.stdout, "hello world\nsecond\n");
+
+        run("read first rest < $IN", &mut state)?;
+        assert_eq!(state.variables.get("first"), Some("hello"));
+        assert_eq!(state.variables.get("rest"), Some("world"));
+
+        let output = run("cat <$IN | wc -l; wc -l 0<$IN | cat", &mut state)?;
+        assert_eq!(
+            output.stdout.split_whitespace().collect::<Vec<_>>(),
+            ["2", "2"]
+        );
+
+        // A stage's own input takes the place of the pipe.
+        let output = run("echo piped | cat < $IN", &mut state)?;
+        assert_eq!(output.stdout, "hello world\nsecond\n");
+
+        let output = run("timeout 5 cat < $IN", &mut state)?;
+        assert_eq!(output.stdout, "hello world\nsecond\n");
+
+        let missing = dir.path().join("missing");
+        let output = run(&format!("cat < {}", missing.display()), &mut state)?;
+        assert_eq!(
+            output.stderr,
+            format!("{}: No such file or directory\n", missing.display())
+        );
+        assert_eq!(state.last_status, 1);
 
-        let output = parse_args(args);
-        let expected = vec![r#"sin\"gle"#.to_string()];
-
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn multiple_args() {
-        let args = r#"first second"#;
+    fn noclobber_refuses_or_asks_before_truncating() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "old\n")?;
+        let mut state = ShellState::new();
+        run("set -o noclobber", &mut state)?;
+
+        let output = run(&format!("echo new > {}", file.display()), &mut state)?;
+        assert_eq!(
+            output.stderr,
+            format!("{}: cannot overwrite existing file\n", file.display())
+        );
+        assert_eq!(state.last_status, 1);
+        assert_eq!(fs::read_to_string(&file)?, "old\n");
+
+        // The prompt only appears in an interactive shell.
+        state.interactive = true;
+        state.variables.set("SHELL_CLOBBER_PROMPT", "1");
+        for (answer, status, contents) in [("n", 1, "old\n"), ("y", 0, "new\n")] {
+            let mut prompter = CapturePrompter {
+                input: vec![answer.to_string()],
+                ..Default::default()
+            };
+            statement::run_line(
+                &format!("echo new > {}", file.display()),
+                &mut state,
+                &mut prompter,
+                &PathFinder::new(),
+                &Runner::new(),
+            )?;
+            assert_eq!(
+                prompter.stdout,
+                format!("overwrite '{}'? (y/n) ", file.display())
+            );
+            assert_eq!(state.last_status, status);
+            assert_eq!(fs::read_to_string(&file)?, contents);
+        }
 
-        let output = parse_args(args);
-        let expected = vec![r#"first"#.to_string(), r#"second"#.to_string()];
+        state.interactive = false;
+        run(&format!("echo forced >| {}", file.display()), &mut state)?;
+        assert_eq!(fs::read_to_string(&file)?, "forced\n");
+        run(
+            &format!("echo more >> {}; set +C", file.display()),
+            &mut state,
+        )?;
+        run(&format!("echo again > {}", file.display()), &mut state)?;
+        assert_eq!(fs::read_to_string(&file)?, "again\n");
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn double_quotes_inside_single_quotes() {
-        let args = r#"'first"second' '"first second'"#;
+    fn verbose_echoes_lines_as_read() -> anyhow::Result<()> {
+        let mut shell = Shell::new(CapturePrompter::default(), PathFinder::new(), Runner::new());
 
-        let output = parse_args(args);
-        let expected = vec![
-            r#"first"second"#.to_string(),
-            r#""first second"#.to_string(),
-        ];
+        let script = "name=world\nset -v\necho \"hi $name\"  $((1 + 1))\n\nset +v\necho quiet\n";
+        shell.run_script(script.as_bytes())?;
+        assert_eq!(shell.prompter.stdout, "hi world 2\nquiet\n");
+        assert_eq!(
+            shell.prompter.stderr,
+            "echo \"hi $name\"  $((1 + 1))\n\nset +v\n"
+        );
 
-        assert_eq!(output, expected);
+        return Ok(());
     }
 
     #[test]
-    fn multiple_args_double_quotes_whitespace() {
-        let args = r#""first  second"   "first""#;
+    fn export_n_hides_a_variable_from_children() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
 
-        let output = parse_args(args);
-        let expected = vec![r#"first  second"#.to_string(), r#"first"#.to_string()];
+        let output = run("export NAME=value; sh -c 'echo ${NAME:-unset}'", &mut state)?;
+        assert_eq!(output.stdout, "value\n");
 
-        assert_eq!(output, expected);
+        let output = run(
+            "export -n NAME; sh -c 'echo ${NAME:-unset}'; echo $NAME",
+            &mut state,
+        )?;
+        assert_eq!(output.stdout, "unset\nvalue\n");
+
+        return Ok(());
     }
 
     #[test]
-    fn single_quoted_backslash() {
-        let args = r#""/'f \21\'""#;
+    fn redirected_history_is_not_paged() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let file = dir.path().join("history.txt");
+        let mut state = ShellState::new();
+        state.variables.set("PAGER", "false");
+        for index in 0..500 {
+            state.history.push(&format!("echo {}", index));
+        }
 
-        let output = parse_args(args);
-        let expected = vec![r#"/'f \21\'"#.to_string()];
+        let output = run(&format!("history 2 > {}", file.display()), &mut state)?;
+        assert_eq!(output.stdout, "");
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            "  499  echo 498\n  500  echo 499\n"
+        );
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn single_quoted_backslash2() {
-        let args = r#""/'f  \78'""#;
+    fn source_runs_a_file_in_the_current_shell() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "# greeting\ngreet() { echo hi $1; }\n\ngreet $1\n")?;
+        let mut state = ShellState::new();
+
+        let output = run(&format!(". {} there", script.display()), &mut state)?;
+        assert_eq!(output.stdout, "hi there\n");
+        assert!(state.functions.contains_key("greet"));
+
+        let output = run("source /nonexistent_file", &mut state)?;
+        assert_eq!(
+            output.stderr,
+            "source: /nonexistent_file: No such file or directory\n"
+        );
+        assert_eq!(state.last_status, 1);
 
-        let output = parse_args(args);
-        let expected = vec![r#"/'f  \78'"#.to_string()];
+        return Ok(());
+    }
+
+    #[test]
+    fn bash_source_names_the_file_being_read() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let outer = dir.path().join("outer.sh");
+        let inner = dir.path().join("inner.sh");
+        fs::write(
+            &outer,
+            format!(
+                "echo \"outer: $BASH_SOURCE\"\nsource {}\necho \"back: ${{BASH_SOURCE[0]}}\"\n",
+                inner.display()
+            ),
+        )?;
+        fs::write(&inner, "echo \"inner: ${BASH_SOURCE[@]}\"\n")?;
+        let mut state = ShellState::new();
+
+        let output = run(&format!("source {}", outer.display()), &mut state)?;
+        let (outer, inner) = (outer.display(), inner.display());
+        assert_eq!(
+            output.stdout,
+            format!(
+                "outer: {}\ninner: {} {}\nback: {}\n",
+                outer, inner, outer, outer
+            )
+        );
+        assert!(state.sources.is_empty());
+        assert_eq!(run("echo \"[$BASH_SOURCE]\"", &mut state)?.stdout, "[]\n");
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn non_quoted_backslash_space() {
-        let args = r#"first\ \ \second"#;
+    fn exit_runs_the_exit_trap_then_the_logout_file() -> anyhow::Result<()> {
+        let home = TempDir::new()?;
+        fs::write(
+            home.path().join(".shell_logout"),
+            "echo \"bye $USER\"\nnonexistent_command_xyz\necho cleaned up\n",
+        )?;
+        let mut state = ShellState::new();
+        state
+            .variables
+            .set("HOME", &home.path().display().to_string());
+        state.variables.set("USER", "alice");
+        run("trap 'echo trap ran' EXIT", &mut state)?;
+        run("false", &mut state)?;
+
+        // Only an interactive session reads the logout file.
+        let mut prompter = CapturePrompter::default();
+        run_exit_hooks(
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(prompter.stdout, "trap ran\n");
+        assert!(state.traps.is_empty());
+
+        state.interactive = true;
+        run("trap 'echo trap ran' EXIT", &mut state)?;
+        run("false", &mut state)?;
+        let mut prompter = CapturePrompter::default();
+        run_exit_hooks(
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert_eq!(prompter.stdout, "trap ran\nbye alice\ncleaned up\n");
+        assert_eq!(
+            prompter.stderr,
+            "nonexistent_command_xyz: command not found\n"
+        );
+        assert_eq!(state.last_status, 1);
+
+        return Ok(());
+    }
 
-        let output = parse_args(args);
-        let expected = vec![r#"first  second"#.to_string()];
+    #[test]
+    fn waitjobs_waits_for_running_jobs_at_exit() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        run("shopt -s waitjobs; sleep 0.3 &", &mut state)?;
+
+        let start = std::time::Instant::now();
+        let mut prompter = CapturePrompter::default();
+        run_exit_hooks(
+            &mut state,
+            &mut prompter,
+            &PathFinder::new(),
+            &Runner::new(),
+        )?;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(state.jobs.iter().count(), 0);
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn double_quoted_backslash() {
-        let args = r#""test'world'\\n'example""#;
+    fn chld_trap_runs_when_a_background_job_finishes() -> anyhow::Result<()> {
+        let mut shell = Shell::new(CapturePrompter::default(), PathFinder::new(), Runner::new());
+
+        shell.run_line("trap 'echo \"child done, status $?\"' CHLD")?;
+        shell.run_line("true &")?;
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        shell.run_line("false")?;
+        shell.prompter.stdout.clear();
+
+        let notices = shell.reap_jobs()?;
+        assert!(notices.starts_with("[1]+  Done"), "{}", notices);
+        assert_eq!(shell.prompter.stdout, "child done, status 1\n");
+        assert_eq!(shell.state.last_status, 1);
 
-        let output = parse_args(args);
-        let expected = vec![r#"test'world'\n'example"#.to_string()];
+        // Nothing else has finished, so the trap does not run again.
+        assert_eq!(shell.reap_jobs()?, "");
+        assert_eq!(shell.prompter.stdout, "child done, status 1\n");
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn double_quoted_backslash2() {
-        let args = r#""mixed\"quote'test'\\""#;
-
-        let output = parse_args(args);
-        let expected = vec![r#"mixed"quote'test'\"#.to_string()];
+    fn lineno_tracks_lines_of_a_sourced_script() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let script = dir.path().join("script.sh");
+        fs::write(
+            &script,
+            "echo $LINENO\n\n# comment\necho \"line $LINENO\"\n",
+        )?;
+        let mut state = ShellState::new();
+        state.line_number = 7;
+
+        let output = run(&format!("source {}", script.display()), &mut state)?;
+        assert_eq!(output.stdout, "1\nline 4\n");
+        assert_eq!(state.line_number, 7);
 
-        assert_eq!(output, expected);
+        return Ok(());
     }
 
     #[test]
-    fn double_quoted_backslash3() {
-        let args = r#""example\"insidequotes"script\""#;
+    fn failed_builtins_set_the_status_and_continue() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "")?;
+        let mut state = ShellState::new();
+
+        let output = run("cd /nonexistent_dir", &mut state)?;
+        assert_eq!(
+            output.stderr,
+            "cd: /nonexistent_dir: No such file or directory\n"
+        );
+        assert_eq!(run("echo $?", &mut state)?.stdout, "1\n");
+
+        let output = run(&format!("cd {}", file.display()), &mut state)?;
+        assert_eq!(
+            output.stderr,
+            format!("cd: {}: Not a directory\n", file.display())
+        );
+        assert_eq!(state.last_status, 1);
+
+        let output = run("type", &mut state)?;
+        assert_eq!(output.stderr, "type: usage: type name\n");
+        assert_eq!(state.last_status, 2);
+
+        run("getopts", &mut state)?;
+        assert_eq!(state.last_status, 2);
+
+        assert_eq!(
+            run("echo still running", &mut state)?.stdout,
+            "still running\n"
+        );
+        assert_eq!(state.last_status, 0);
+
+        return Ok(());
+    }
+
+    fn fake_editor(dir: &TempDir, script: &str) -> anyhow::Result<String> {
+        let editor = dir.path().join("editor");
+        fs::write(&editor, format!("#!/bin/sh\n{}\n", script))?;
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755))?;
+        return Ok(editor.display().to_string());
+    }
 
-        let output = parse_args(args);
-        let expected = vec![r#"example"insidequotesscript""#.to_string()];
+    #[test]
+    fn fc_runs_the_edited_command() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+        let editor = fake_editor(&dir, r#"echo 'echo appended' >> "$1""#)?;
+        state.variables.set("EDITOR", &editor);
+        state.variables.unset("FCEDIT");
+
+        state.history.push("echo original");
+        state.history.push("fc");
+        let output = run("fc", &mut state)?;
+
+        assert_eq!(
+            output.stdout,
+            "echo original\noriginal\necho appended\nappended\n"
+        );
+        assert_eq!(
+            state.history.iter().collect::<Vec<_>>(),
+            vec!["echo original", "echo original", "echo appended"]
+        );
 
-        assert_eq!(output, expected)
+        return Ok(());
     }
 
     #[test]
-    fn stdout_redirect() {
-        let args = r#"'hello james' 1> /tmp/foo/foo.md"#;
+    fn fc_is_cancelled_when_the_editor_fails() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let mut state = ShellState::new();
+        let editor = fake_editor(&dir, "exit 3")?;
+        state.variables.set("EDITOR", &editor);
+        state.variables.unset("FCEDIT");
 
-        let output = parse_args(args);
-        let expected = vec![
-            r#"hello james"#.to_string(),
-            "1>".to_string(),
-            "/tmp/foo/foo.md".to_string(),
-        ];
+        state.history.push("echo original");
+        state.history.push("fc");
+        let output = run("fc", &mut state)?;
 
-        assert_eq!(output, expected)
+        assert_eq!(output.stdout, "");
+        assert_eq!(state.last_status, 3);
+
+        return Ok(());
     }
 }
@@ -0,0 +1,391 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use crate::{
+    builtins::BUILTINS,
+    command::CommandOutput,
+    executable::{is_executable, split_env_path},
+    expansion::Expander,
+    state::ShellState,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompletionRule {
+    Commands,
+    Directories,
+    Files,
+}
+
+/// Argument completion for specific commands; anything not listed completes files.
+const COMMAND_RULES: &[(&str, CompletionRule)] = &[("cd", CompletionRule::Directories)];
+
+const COMPGEN_USAGE: &str = "compgen: usage: compgen [-bcdf] [word]";
+
+/// Characters escaped with a backslash when inserting a completed name.
+const SPECIAL_CHARS: &str = " \t'\"\\$`&|;<>()*?[]{}!#";
+
+/// Returns the word under completion as typed: everything after the last unquoted,
+/// unescaped space.
+pub fn current_word(line: &str) -> &str {
+    let mut word_start = 0;
+    let mut inside_single_quotes = false;
+    let mut inside_double_quotes = false;
+    let mut chars = line.char_indices();
+
+    while let Some((index, current_char)) = chars.next() {
+        match current_char {
+            '\\' if !inside_single_quotes => {
+                chars.next();
+            }
+            '\'' if !inside_double_quotes => inside_single_quotes = !inside_single_quotes,
+            '"' if !inside_single_quotes => inside_double_quotes = !inside_double_quotes,
+            ' ' if !inside_single_quotes && !inside_double_quotes => word_start = index + 1,
+            _ => {}
+        }
+    }
+
+    return &line[word_start..];
+}
+
+/// The text a typed word stands for, with quotes and escapes removed.
+pub fn unquote(word: &str) -> String {
+    // Quote removal alone cannot fail.
+    return Expander::literal()
+        .expand_word(word)
+        .unwrap_or_default()
+        .concat();
+}
+
+/// Escapes a name so it reads back as a single word.
+pub fn quote(name: &str) -> String {
+    let mut quoted = String::with_capacity(name.len());
+    for current_char in name.chars() {
+        if SPECIAL_CHARS.contains(current_char) {
+            quoted.push('\\');
+        }
+        quoted.push(current_char);
+    }
+
+    return quoted;
+}
+
+pub fn rule_for(line: &str) -> CompletionRule {
+    let Some((cmd, _)) = line.trim_start().split_once(' ') else {
+        return CompletionRule::Commands;
+    };
+
+    return COMMAND_RULES
+        .iter()
+        .find(|(name, _)| *name == cmd)
+        .map(|(_, rule)| *rule)
+        .unwrap_or(CompletionRule::Files);
+}
+
+/// Candidates for the word under completion, sorted and unquoted. Directories end
+/// with `/`.
+pub fn complete(line: &str, cwd: &Path, env_path: &str) -> Vec<String> {
+    let word = unquote(current_word(line));
+    let word = word.as_str();
+
+    match rule_for(line) {
+        CompletionRule::Commands => return complete_command(word, env_path),
+        CompletionRule::Directories => return complete_path(word, cwd, true),
+        CompletionRule::Files => return complete_path(word, cwd, false),
+    }
+}
+
+/// `compgen [-bcdf] [word]`: prints the builtins (`-b`), commands (`-c`), directories
+/// (`-d`) or files (`-f`) starting with `word`, one per line, as Tab would offer them.
+/// Fails with status 1 when nothing matches.
+pub fn compgen(state: &ShellState, args: &[String]) -> CommandOutput {
+    let mut rules = vec![];
+    let mut index = 0;
+    while let Some(flags) = args.get(index).and_then(|arg| return arg.strip_prefix('-')) {
+        index += 1;
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'b' => rules.push(None),
+                'c' => rules.push(Some(CompletionRule::Commands)),
+                'd' => rules.push(Some(CompletionRule::Directories)),
+                'f' => rules.push(Some(CompletionRule::Files)),
+                _ => {
+                    return usage_error(&format!(
+                        "compgen: -{}: invalid option\n{}",
+                        flag, COMPGEN_USAGE
+                    ));
+                }
+            }
+        }
+    }
+    let prefix = match &args[index.min(args.len())..] {
+        [] => "",
+        [prefix] => prefix.as_str(),
+        _ => return usage_error(COMPGEN_USAGE),
+    };
+
+    let cwd = state.current_dir().unwrap_or_default();
+    let mut stdout = String::new();
+    for rule in rules {
+        let candidates = match rule {
+            None => complete_builtin(prefix),
+            Some(CompletionRule::Commands) => complete_command(prefix, &state.env_path()),
+            Some(CompletionRule::Directories) => complete_path(prefix, &cwd, true),
+            Some(CompletionRule::Files) => complete_path(prefix, &cwd, false),
+        };
+        for candidate in candidates {
+            // Listed names are not being typed, so directories keep no trailing `/`.
+            let candidate = match rule {
+                Some(CompletionRule::Directories) | Some(CompletionRule::Files) => {
+                    candidate.strip_suffix('/').unwrap_or(&candidate)
+                }
+                _ => &candidate,
+            };
+            stdout.push_str(&format!("{}\n", candidate));
+        }
+    }
+
+    let status = if stdout.is_empty() { 1 } else { 0 };
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: None,
+        status,
+    };
+}
+
+fn usage_error(message: &str) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}\n", message)),
+        status: 2,
+    };
+}
+
+fn complete_builtin(prefix: &str) -> Vec<String> {
+    return BUILTINS
+        .iter()
+        .filter(|builtin| builtin.name.starts_with(prefix))
+        .map(|builtin| builtin.name.to_string())
+        .collect();
+}
+
+fn complete_command(prefix: &str, env_path: &str) -> Vec<String> {
+    let mut candidates: BTreeSet<String> = complete_builtin(prefix).into_iter().collect();
+
+    for dir in split_env_path(env_path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) {
+                continue;
+            }
+
+            if is_executable(&entry.path()) {
+                candidates.insert(name);
+            }
+        }
+    }
+
+    return candidates.into_iter().collect();
+}
+
+fn complete_path(word: &str, cwd: &Path, directories_only: bool) -> Vec<String> {
+    let (dir_part, prefix) = match word.rfind('/') {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word),
+    };
+    let dir = cwd.join(if dir_part.is_empty() { "." } else { dir_part });
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let show_hidden = prefix.starts_with('.');
+    let mut candidates: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !show_hidden) {
+                return None;
+            }
+
+            let is_dir = entry.path().is_dir();
+            if directories_only && !is_dir {
+                return None;
+            }
+
+            let suffix = if is_dir { "/" } else { "" };
+            return Some(format!("{}{}{}", dir_part, name, suffix));
+        })
+        .collect();
+    candidates.sort();
+
+    return candidates;
+}
+
+#[cfg(test)]
+mod completion_tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use crate::test_support::args;
+
+    use super::*;
+
+    fn fixture() -> anyhow::Result<TempDir> {
+        let dir = TempDir::new()?;
+        fs::create_dir(dir.path().join("docs"))?;
+        fs::create_dir(dir.path().join("downloads"))?;
+        fs::write(dir.path().join("notes.txt"), "")?;
+        fs::write(dir.path().join("data.csv"), "")?;
+        return Ok(dir);
+    }
+
+    #[test]
+    fn cd_offers_only_directories() -> anyhow::Result<()> {
+        let dir = fixture()?;
+
+        let candidates = complete("cd ", dir.path(), "");
+        assert_eq!(
+            candidates,
+            vec!["docs/".to_string(), "downloads/".to_string()]
+        );
+
+        let candidates = complete("cd d", dir.path(), "");
+        assert_eq!(
+            candidates,
+            vec!["docs/".to_string(), "downloads/".to_string()]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn other_commands_offer_files() -> anyhow::Result<()> {
+        let dir = fixture()?;
+
+        let candidates = complete("cat d", dir.path(), "");
+        assert_eq!(
+            candidates,
+            vec![
+                "data.csv".to_string(),
+                "docs/".to_string(),
+                "downloads/".to_string()
+            ]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn nested_paths() -> anyhow::Result<()> {
+        let dir = fixture()?;
+        fs::write(dir.path().join("docs").join("readme.md"), "")?;
+
+        let candidates = complete("cat docs/r", dir.path(), "");
+        assert_eq!(candidates, vec!["docs/readme.md".to_string()]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn partial_filename() -> anyhow::Result<()> {
+        let dir = fixture()?;
+
+        let candidates = complete("cat no", dir.path(), "");
+        assert_eq!(candidates, vec!["notes.txt".to_string()]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn names_with_spaces() -> anyhow::Result<()> {
+        let dir = fixture()?;
+        fs::write(dir.path().join("my notes.txt"), "")?;
+
+        let candidates = complete("cat my", dir.path(), "");
+        assert_eq!(candidates, vec!["my notes.txt".to_string()]);
+
+        let candidates = complete(r#"cat my\ n"#, dir.path(), "");
+        assert_eq!(candidates, vec!["my notes.txt".to_string()]);
+
+        let candidates = complete(r#"cat "my n"#, dir.path(), "");
+        assert_eq!(candidates, vec!["my notes.txt".to_string()]);
+
+        assert_eq!(quote("my notes.txt"), r#"my\ notes.txt"#);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn hidden_files_need_a_dot_prefix() -> anyhow::Result<()> {
+        let dir = fixture()?;
+        fs::write(dir.path().join(".hidden"), "")?;
+
+        let candidates = complete("cat ", dir.path(), "");
+        assert!(!candidates.contains(&".hidden".to_string()));
+
+        let candidates = complete("cat .", dir.path(), "");
+        assert_eq!(candidates, vec![".hidden".to_string()]);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn first_word_offers_commands() -> anyhow::Result<()> {
+        let dir = fixture()?;
+
+        let candidates = complete("ech", dir.path(), "");
+        assert_eq!(candidates, vec!["echo".to_string()]);
+
+        return Ok(());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn compgen_lists_candidates() -> anyhow::Result<()> {
+        let dir = fixture()?;
+        let bin = TempDir::new()?;
+        use std::os::unix::fs::PermissionsExt;
+
+        let program = bin.path().join("echoer");
+        fs::write(&program, "#!/bin/sh\n")?;
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755))?;
+        fs::write(bin.path().join("echo-notes"), "")?;
+
+        let mut state = ShellState::default();
+        state
+            .variables
+            .set("PWD", &dir.path().display().to_string());
+        state
+            .variables
+            .set("PATH", &bin.path().display().to_string());
+
+        crate::test_support::in_dir(dir.path(), || {
+            let output = compgen(&state, &args(&["-b", "e"]));
+            assert_eq!(output.stdout, Some(String::from("echo\nexit\nexport\n")));
+
+            let output = compgen(&state, &args(&["-c", "ech"]));
+            assert_eq!(output.stdout, Some(String::from("echo\nechoer\n")));
+
+            let output = compgen(&state, &args(&["-f", "d"]));
+            assert_eq!(
+                output.stdout,
+                Some(String::from("data.csv\ndocs\ndownloads\n"))
+            );
+            let output = compgen(&state, &args(&["-d", "d"]));
+            assert_eq!(output.stdout, Some(String::from("docs\ndownloads\n")));
+
+            let output = compgen(&state, &args(&["-f", "missing"]));
+            assert_eq!((output.stdout, output.status), (None, 1));
+            assert_eq!(compgen(&state, &args(&["-x"])).status, 2);
+        });
+
+        return Ok(());
+    }
+}
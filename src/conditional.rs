@@ -0,0 +1,383 @@
+use std::{fs, path::Path};
+
+use anyhow::anyhow;
+
+use crate::{
+    executable::is_executable,
+    expansion::Expander,
+    glob::{self, GLOB_CHARS},
+    tokenizer::Token,
+};
+
+/// Characters with a special meaning in an extended regular expression.
+const REGEX_CHARS: &str = ".[]()*+?{}|^$";
+
+/// The expression of a `[[ ... ]]` command. Words are kept as written and expanded
+/// when it runs, without field splitting or pathname expansion.
+#[derive(Debug, PartialEq)]
+pub enum Condition {
+    /// A lone word: true when it is not empty.
+    Word(String),
+    /// `-z word`, `-f file`, ...
+    Unary {
+        operator: String,
+        operand: String,
+    },
+    /// `left == pattern`, `left =~ regex`, `left -lt right`, ...
+    Binary {
+        left: String,
+        operator: String,
+        right: String,
+    },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+const UNARY_OPERATORS: &[&str] = &["-z", "-n", "-e", "-f", "-d", "-s", "-x"];
+const BINARY_OPERATORS: &[&str] = &[
+    "==", "=", "!=", "=~", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge",
+];
+
+/// Parses the tokens between `[[` and `]]`. `!` binds tightest, then `&&`, then
+/// `||`; parentheses group.
+pub fn parse(tokens: &[Token]) -> anyhow::Result<Condition> {
+    let mut parser = Parser { tokens, index: 0 };
+    let condition = parser.parse_or()?;
+    if let Some(token) = tokens.get(parser.index) {
+        return Err(anyhow!(
+            "syntax error in conditional expression: unexpected token `{}'",
+            token
+        ));
+    }
+
+    return Ok(condition);
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn parse_or(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.parse_and()?;
+        while self.tokens.get(self.index) == Some(&Token::Or) {
+            self.index += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.parse_and()?));
+        }
+
+        return Ok(condition);
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.parse_not()?;
+        while self.tokens.get(self.index) == Some(&Token::And) {
+            self.index += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.parse_not()?));
+        }
+
+        return Ok(condition);
+    }
+
+    fn parse_not(&mut self) -> anyhow::Result<Condition> {
+        if self.peek_word() == Some("!") {
+            self.index += 1;
+            return Ok(Condition::Not(Box::new(self.parse_not()?)));
+        }
+
+        return self.parse_primary();
+    }
+
+    fn parse_primary(&mut self) -> anyhow::Result<Condition> {
+        let first = self.next_word()?;
+        if first == "(" {
+            let condition = self.parse_or()?;
+            if self.next_word()? != ")" {
+                return Err(anyhow!(
+                    "syntax error in conditional expression: expected `)'"
+                ));
+            }
+            return Ok(condition);
+        }
+
+        if let Some(operator) = self.peek_operator() {
+            self.index += 1;
+            let right = match operator.as_str() {
+                "=~" => self.next_regex()?,
+                _ => self.next_word()?,
+            };
+            return Ok(Condition::Binary {
+                left: first,
+                operator,
+                right,
+            });
+        }
+
+        if UNARY_OPERATORS.contains(&first.as_str()) {
+            if let Some(operand) = self.peek_word() {
+                let operand = operand.to_string();
+                self.index += 1;
+                return Ok(Condition::Unary {
+                    operator: first,
+                    operand,
+                });
+            }
+        }
+
+        return Ok(Condition::Word(first));
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.index) {
+            Some(Token::Word(word)) => return Some(word),
+            _ => return None,
+        }
+    }
+
+    /// The binary operator at the cursor. `<` and `>` reach here as redirection tokens.
+    fn peek_operator(&self) -> Option<String> {
+        match self.tokens.get(self.index) {
+            Some(Token::Word(word)) if BINARY_OPERATORS.contains(&word.as_str()) => {
+                return Some(word.clone());
+            }
+            Some(Token::Redirect { operator }) if operator == "<" || operator == ">" => {
+                return Some(operator.clone());
+            }
+            _ => return None,
+        }
+    }
+
+    fn next_word(&mut self) -> anyhow::Result<String> {
+        match self.tokens.get(self.index) {
+            Some(Token::Word(word)) => {
+                self.index += 1;
+                return Ok(word.clone());
+            }
+            Some(token) => {
+                return Err(anyhow!(
+                    "syntax error in conditional expression: unexpected token `{}'",
+                    token
+                ));
+            }
+            None => return Err(anyhow!("syntax error in conditional expression")),
+        }
+    }
+
+    /// The right side of `=~`, where an unquoted `|` is alternation rather than a
+    /// pipe: `a|b` is read as the words `a` and `b` around a pipe and joined again.
+    fn next_regex(&mut self) -> anyhow::Result<String> {
+        let mut regex = self.next_word()?;
+        while self.tokens.get(self.index) == Some(&Token::Pipe) {
+            self.index += 1;
+            regex.push('|');
+            regex.push_str(&self.next_word()?);
+        }
+
+        return Ok(regex);
+    }
+}
+
+/// Evaluates `condition`, expanding its words with `expander`.
+pub fn evaluate(condition: &Condition, expander: &Expander) -> anyhow::Result<bool> {
+    match condition {
+        Condition::Word(word) => return Ok(!expander.expand_assignment(word)?.is_empty()),
+        Condition::Unary { operator, operand } => {
+            let operand = expander.expand_assignment(operand)?;
+            return Ok(test_unary(operator, &operand));
+        }
+        Condition::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = expander.expand_assignment(left)?;
+            match operator.as_str() {
+                "==" | "=" | "!=" => {
+                    let pattern: Vec<char> = expander
+                        .expand_pattern(right, GLOB_CHARS)?
+                        .chars()
+                        .collect();
+                    let left: Vec<char> = left.chars().collect();
+                    return Ok(glob::matches(&pattern, &left) == (operator != "!="));
+                }
+                "=~" => {
+                    let regex = expander.expand_pattern(right, REGEX_CHARS)?;
+                    return regex_matches(&regex, &left);
+                }
+                _ => {
+                    let right = expander.expand_assignment(right)?;
+                    return compare(&left, operator, &right);
+                }
+            }
+        }
+        Condition::Not(condition) => return Ok(!evaluate(condition, expander)?),
+        Condition::And(first, second) => {
+            return Ok(evaluate(first, expander)? && evaluate(second, expander)?);
+        }
+        Condition::Or(first, second) => {
+            return Ok(evaluate(first, expander)? || evaluate(second, expander)?);
+        }
+    }
+}
+
+fn test_unary(operator: &str, operand: &str) -> bool {
+    let path = Path::new(operand);
+    match operator {
+        "-z" => return operand.is_empty(),
+        "-n" => return !operand.is_empty(),
+        "-e" => return path.exists(),
+        "-f" => return path.is_file(),
+        "-d" => return path.is_dir(),
+        "-s" => return fs::metadata(path).is_ok_and(|metadata| return metadata.len() > 0),
+        _ => return is_executable(path),
+    }
+}
+
+/// The string comparisons `<` and `>`, and the integer ones.
+fn compare(left: &str, operator: &str, right: &str) -> anyhow::Result<bool> {
+    match operator {
+        "<" => return Ok(left < right),
+        ">" => return Ok(left > right),
+        _ => {}
+    }
+
+    let integer = |word: &str| {
+        return word
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| return anyhow!("{}: integer expression expected", word));
+    };
+    let (left, right) = (integer(left)?, integer(right)?);
+    match operator {
+        "-eq" => return Ok(left == right),
+        "-ne" => return Ok(left != right),
+        "-lt" => return Ok(left < right),
+        "-le" => return Ok(left <= right),
+        "-gt" => return Ok(left > right),
+        _ => return Ok(left >= right),
+    }
+}
+
+/// Whether `regex`, a POSIX extended regular expression, matches anywhere in `text`.
+#[cfg(unix)]
+fn regex_matches(regex: &str, text: &str) -> anyhow::Result<bool> {
+    use std::{ffi::CString, mem::MaybeUninit, ptr};
+
+    let invalid = || return anyhow!("{}: invalid regular expression", regex);
+    let pattern = CString::new(regex).map_err(|_| return invalid())?;
+    let Ok(text) = CString::new(text) else {
+        return Ok(false);
+    };
+
+    let mut compiled = MaybeUninit::<libc::regex_t>::uninit();
+    // SAFETY: `regcomp` initializes `compiled` when it returns 0, and only then is it
+    // used and freed.
+    unsafe {
+        let flags = libc::REG_EXTENDED | libc::REG_NOSUB;
+        if libc::regcomp(compiled.as_mut_ptr(), pattern.as_ptr(), flags) != 0 {
+            return Err(invalid());
+        }
+        let matched = libc::regexec(compiled.as_ptr(), text.as_ptr(), 0, ptr::null_mut(), 0) == 0;
+        libc::regfree(compiled.as_mut_ptr());
+        return Ok(matched);
+    }
+}
+
+#[cfg(not(unix))]
+fn regex_matches(regex: &str, _text: &str) -> anyhow::Result<bool> {
+    return Err(anyhow!("{}: regular expressions are not supported", regex));
+}
+
+#[cfg(test)]
+mod conditional_tests {
+    use crate::{state::ShellState, tokenizer::tokenize};
+
+    use super::*;
+
+    fn holds(expression: &str, state: &ShellState) -> anyhow::Result<bool> {
+        let condition = parse(&tokenize(expression)?)?;
+        return evaluate(&condition, &Expander::new(state));
+    }
+
+    #[test]
+    fn glob_patterns_on_the_right() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.assign("x", "abc");
+        state.assign("p", "a*");
+
+        assert!(holds("$x == a*", &state)?);
+        assert!(holds("$x == $p", &state)?);
+        assert!(holds("$x != b*", &state)?);
+        assert!(holds("$x = ?b[a-c]", &state)?);
+        // Quoted pattern characters match literally.
+        assert!(!holds("$x == 'a*'", &state)?);
+        assert!(!holds("$x == \"$p\"", &state)?);
+        assert!(holds("'a*' == \"a*\"", &state)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn regex_matching() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.assign("s", "12345");
+
+        assert!(holds("$s =~ ^[0-9]+$", &state)?);
+        assert!(!holds("abc =~ ^[0-9]+$", &state)?);
+        assert!(holds("$s =~ 34", &state)?);
+        assert!(holds("cat =~ ^(dog|cat)$", &state)?);
+        assert!(holds("a.c =~ a'.'c", &state)?);
+        assert!(!holds("abc =~ a'.'c", &state)?);
+        assert!(holds("x =~ '('", &state).is_ok());
+        assert!(holds("x =~ (", &state).is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn operands_are_not_split_or_globbed() -> anyhow::Result<()> {
+        let mut state = ShellState::new();
+        state.assign("x", "a b");
+        state.assign("star", "*");
+        state.assign("empty", "");
+
+        assert!(holds("$x == 'a b'", &state)?);
+        assert!(holds("-n $x", &state)?);
+        assert!(holds("$star == '*'", &state)?);
+        assert!(!holds("$empty", &state)?);
+        assert!(holds("-z $unset", &state)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn string_ordering() -> anyhow::Result<()> {
+        let state = ShellState::new();
+
+        assert!(holds("apple < banana", &state)?);
+        assert!(!holds("apple > banana", &state)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn logical_operators() -> anyhow::Result<()> {
+        let state = ShellState::new();
+
+        assert!(holds("a == a && b == b", &state)?);
+        assert!(!holds("a == a && b == c", &state)?);
+        assert!(holds("a == b || b == b", &state)?);
+        assert!(holds("! a == b", &state)?);
+        assert!(holds("a == b || a == a && c == c", &state)?);
+        assert!(!holds("( a == b || a == a ) && c == d", &state)?);
+        assert!(holds("b > a && 2 -lt 10", &state)?);
+        assert!(holds("x -eq 1", &state).is_err());
+
+        assert!(parse(&tokenize("a ==")?).is_err());
+        assert!(parse(&tokenize("a b")?).is_err());
+        assert!(parse(&tokenize("")?).is_err());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::{Path, PathBuf};
+
+use crate::{command::CommandOutput, state::ShellState};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DirsOptions {
+    /// `-c`: clear the stack instead of printing it.
+    pub clear: bool,
+    /// `-l`: print full paths instead of abbreviating `$HOME` to `~`.
+    pub long: bool,
+    /// `-v`: one entry per line, prefixed with its index.
+    pub verbose: bool,
+    /// `-p`: one entry per line.
+    pub per_line: bool,
+}
+
+/// `pushd [dir | +N | -N]`: saves the current directory on the stack and changes to
+/// `dir`. Without `dir`, swaps the current directory with the top of the stack; `+N`
+/// and `-N` rotate the stack so that entry of the `dirs` listing comes first.
+pub fn pushd(state: &mut ShellState, dir: Option<&str>) -> CommandOutput {
+    let Ok(cwd) = state.current_dir() else {
+        return error("pushd: cannot read the current directory");
+    };
+
+    if let Some(spec) = dir.filter(|dir| is_stack_index(dir)) {
+        let mut entries = stack_entries(state, &cwd);
+        let Some(index) = stack_index(entries.len(), spec) else {
+            return error(&format!(
+                "pushd: {}: directory stack index out of range",
+                spec
+            ));
+        };
+
+        entries.rotate_left(index);
+        if state.change_dir(&entries[0]).is_err() {
+            return error(&format!(
+                "pushd: {}: No such file or directory",
+                entries[0].display()
+            ));
+        }
+        state.dir_stack = entries.into_iter().skip(1).rev().collect();
+        return print_dirs(state, &DirsOptions::default());
+    }
+
+    let target = match dir {
+        Some(dir) => expand_tilde(dir),
+        None => match state.dir_stack.pop() {
+            Some(top) => top,
+            None => return error("pushd: no other directory"),
+        },
+    };
+
+    if state.change_dir(&target).is_err() {
+        if dir.is_none() {
+            state.dir_stack.push(target.clone());
+        }
+        return error(&format!(
+            "pushd: {}: No such file or directory",
+            target.display()
+        ));
+    }
+
+    state.dir_stack.push(cwd);
+    return print_dirs(state, &DirsOptions::default());
+}
+
+/// `popd`: removes the top of the stack and changes to it.
+pub fn popd(state: &mut ShellState) -> CommandOutput {
+    let Some(top) = state.dir_stack.pop() else {
+        return error("popd: directory stack empty");
+    };
+
+    if state.change_dir(&top).is_err() {
+        return error(&format!(
+            "popd: {}: No such file or directory",
+            top.display()
+        ));
+    }
+
+    return print_dirs(state, &DirsOptions::default());
+}
+
+/// `dirs [-clpv]`: prints the current directory followed by the stack, most recent
+/// first, or clears the stack with `-c`.
+pub fn dirs(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let mut options = DirsOptions::default();
+    for arg in args {
+        let flags = arg.strip_prefix('-').unwrap_or_default();
+        if flags.is_empty() {
+            return usage_error(arg);
+        }
+
+        for flag in flags.chars() {
+            match flag {
+                'c' => options.clear = true,
+                'l' => options.long = true,
+                'p' => options.per_line = true,
+                'v' => options.verbose = true,
+                _ => return usage_error(arg),
+            }
+        }
+    }
+
+    if options.clear {
+        state.dir_stack.clear();
+        return CommandOutput {
+            stdout: None,
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    return print_dirs(state, &options);
+}
+
+fn print_dirs(state: &ShellState, options: &DirsOptions) -> CommandOutput {
+    let Ok(cwd) = state.current_dir() else {
+        return error("dirs: cannot read the current directory");
+    };
+    let home = std::env::home_dir();
+
+    return CommandOutput {
+        stdout: Some(format_dirs(
+            &cwd,
+            &state.dir_stack,
+            home.as_deref(),
+            options,
+        )),
+        stderr: None,
+        status: 0,
+    };
+}
+
+pub fn format_dirs(
+    cwd: &Path,
+    stack: &[PathBuf],
+    home: Option<&Path>,
+    options: &DirsOptions,
+) -> String {
+    let entries = std::iter::once(cwd)
+        .chain(stack.iter().rev().map(|dir| dir.as_path()))
+        .map(|dir| {
+            if options.long {
+                return dir.display().to_string();
+            }
+            return abbreviate_home(dir, home);
+        });
+
+    if options.verbose {
+        return entries
+            .enumerate()
+            .map(|(index, entry)| return format!("{:2}  {}\n", index, entry))
+            .collect();
+    }
+
+    if options.per_line {
+        return entries.map(|entry| return format!("{}\n", entry)).collect();
+    }
+
+    let entries: Vec<String> = entries.collect();
+    return format!("{}\n", entries.join(" "));
+}
+
+pub fn abbreviate_home(dir: &Path, home: Option<&Path>) -> String {
+    let relative = home.and_then(|home| dir.strip_prefix(home).ok());
+    match relative {
+        Some(relative) if relative.as_os_str().is_empty() => return String::from("~"),
+        Some(relative) => return format!("~/{}", relative.display()),
+        None => return dir.display().to_string(),
+    }
+}
+
+fn expand_tilde(dir: &str) -> PathBuf {
+    let home = std::env::home_dir();
+    match (dir.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            return home.join(rest.trim_start_matches('/'));
+        }
+        _ => return PathBuf::from(dir),
+    }
+}
+
+/// `~N`, `~+N` and `~-N`: entry `N` of the `dirs` listing, counted from the start
+/// (`+`) or the end (`-`). `None` when `word` is not such a reference, an error when
+/// the index is out of range.
+pub fn stack_reference(state: &ShellState, word: &str) -> Option<Result<PathBuf, String>> {
+    let spec = word.strip_prefix('~').filter(|spec| is_stack_index(spec))?;
+    let cwd = state.current_dir().ok()?;
+    let entries = stack_entries(state, &cwd);
+
+    match stack_index(entries.len(), spec) {
+        Some(index) => return Some(Ok(entries[index].clone())),
+        None => return Some(Err(format!("{}: directory stack index out of range", word))),
+    }
+}
+
+/// The `dirs` listing: the current directory, then the stack from the top.
+fn stack_entries(state: &ShellState, cwd: &Path) -> Vec<PathBuf> {
+    return std::iter::once(cwd.to_path_buf())
+        .chain(state.dir_stack.iter().rev().cloned())
+        .collect();
+}
+
+/// `N`, `+N` or `-N`.
+fn is_stack_index(spec: &str) -> bool {
+    let digits = spec.strip_prefix(['+', '-']).unwrap_or(spec);
+    return !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit());
+}
+
+fn stack_index(len: usize, spec: &str) -> Option<usize> {
+    if let Some(from_end) = spec.strip_prefix('-') {
+        return len.checked_sub(from_end.parse::<usize>().ok()? + 1);
+    }
+
+    let index = spec.trim_start_matches('+').parse::<usize>().ok()?;
+    return (index < len).then_some(index);
+}
+
+fn usage_error(arg: &str) -> CommandOutput {
+    return error(&format!(
+        "dirs: {}: invalid option\ndirs: usage: dirs [-clpv]",
+        arg
+    ));
+}
+
+fn error(message: &str) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}\n", message)),
+        status: 1,
+    };
+}
+
+#[cfg(test)]
+mod dirs_tests {
+    use super::*;
+
+    fn seeded_stack() -> Vec<PathBuf> {
+        return vec![PathBuf::from("/tmp"), PathBuf::from("/home/user/projects")];
+    }
+
+    fn format(options: &DirsOptions) -> String {
+        let home = Path::new("/home/user");
+        return format_dirs(
+            Path::new("/home/user/src"),
+            &seeded_stack(),
+            Some(home),
+            options,
+        );
+    }
+
+    #[test]
+    fn stack_indices() {
+        assert_eq!(stack_index(3, "0"), Some(0));
+        assert_eq!(stack_index(3, "+2"), Some(2));
+        assert_eq!(stack_index(3, "-0"), Some(2));
+        assert_eq!(stack_index(3, "-2"), Some(0));
+        assert_eq!(stack_index(3, "3"), None);
+        assert_eq!(stack_index(3, "-3"), None);
+        assert!(!is_stack_index("+"));
+        assert!(!is_stack_index("1a"));
+    }
+
+    #[test]
+    fn pushd_rotates_to_a_stack_index() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        std::fs::create_dir(&first)?;
+        std::fs::create_dir(&second)?;
+        let mut state = ShellState::default();
+
+        crate::test_support::in_dir(dir.path(), || {
+            pushd(&mut state, first.to_str());
+            pushd(&mut state, second.to_str());
+
+            // second, first, start -> start, second, first
+            assert_eq!(pushd(&mut state, Some("+2")).status, 0);
+            assert_eq!(state.variables.get("PWD"), dir.path().to_str());
+            assert_eq!(state.dir_stack, vec![first.clone(), second.clone()]);
+
+            assert_eq!(pushd(&mut state, Some("-5")).status, 1);
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn abbreviates_home_by_default() {
+        let output = format(&DirsOptions::default());
+        assert_eq!(output, "~/src ~/projects /tmp\n");
+    }
+
+    #[test]
+    fn long_prints_full_paths() {
+        let options = DirsOptions {
+            long: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format(&options),
+            "/home/user/src /home/user/projects /tmp\n"
+        );
+    }
+
+    #[test]
+    fn verbose_prints_indices() {
+        let options = DirsOptions {
+            verbose: true,
+            ..Default::default()
+        };
+        assert_eq!(format(&options), " 0  ~/src\n 1  ~/projects\n 2  /tmp\n");
+
+        let options = DirsOptions {
+            verbose: true,
+            long: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format(&options),
+            " 0  /home/user/src\n 1  /home/user/projects\n 2  /tmp\n"
+        );
+    }
+
+    #[test]
+    fn per_line_prints_one_entry_per_line() {
+        let options = DirsOptions {
+            per_line: true,
+            ..Default::default()
+        };
+        assert_eq!(format(&options), "~/src\n~/projects\n/tmp\n");
+    }
+
+    #[test]
+    fn clear_empties_the_stack() {
+        let mut state = ShellState {
+            dir_stack: seeded_stack(),
+            ..Default::default()
+        };
+
+        let output = dirs(&mut state, &["-c".to_string()]);
+        assert_eq!(output.stdout, None);
+        assert_eq!(output.status, 0);
+        assert!(state.dir_stack.is_empty());
+    }
+
+    #[test]
+    fn invalid_option() {
+        let mut state = ShellState {
+            dir_stack: seeded_stack(),
+            ..Default::default()
+        };
+
+        let output = dirs(&mut state, &["-x".to_string()]);
+        assert_eq!(output.status, 1);
+        assert_eq!(state.dir_stack, seeded_stack());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::command::CommandOutput;
+
+/// Builds the exact text `echo` writes. Leading words made only of `-n`, `-e` and `-E`
+/// letters are flags: `-n` drops the trailing newline, `-e` interprets backslash
+/// escapes and `-E` turns that back off.
+pub fn echo(args: &[String]) -> CommandOutput {
+    return build(args, "neE", false);
+}
+
+/// `echo` under `shopt -s xpg_echo`: backslash escapes are interpreted unless `-E`
+/// turns them off.
+pub fn xpg_echo(args: &[String]) -> CommandOutput {
+    return build(args, "neE", true);
+}
+
+/// `echo` in POSIX mode: only `-n` is a flag, so `-e` and `-E` are printed like any
+/// other word and backslashes are never interpreted.
+pub fn posix_echo(args: &[String]) -> CommandOutput {
+    return build(args, "n", false);
+}
+
+/// What the leading flags of an `echo` asked for.
+struct Options {
+    trailing_newline: bool,
+    interpret_escapes: bool,
+}
+
+/// Reads flags from the front of `args` until the first word that is not made only
+/// of `known_flags` letters, which is printed along with everything after it. Flags
+/// may be combined and repeated; a later `-e` or `-E` wins. A lone `-` is a word.
+fn scan_options<'a>(
+    args: &'a [String],
+    known_flags: &str,
+    interpret_escapes: bool,
+) -> (Options, &'a [String]) {
+    let mut options = Options {
+        trailing_newline: true,
+        interpret_escapes,
+    };
+
+    let mut words = args;
+    while let [word, rest @ ..] = words {
+        let Some(flags) = word.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|flag| known_flags.contains(flag)) {
+            break;
+        }
+
+        for flag in flags.chars() {
+            match flag {
+                'n' => options.trailing_newline = false,
+                'e' => options.interpret_escapes = true,
+                _ => options.interpret_escapes = false,
+            }
+        }
+        words = rest;
+    }
+
+    return (options, words);
+}
+
+fn build(args: &[String], known_flags: &str, interpret_escapes: bool) -> CommandOutput {
+    let (options, words) = scan_options(args, known_flags, interpret_escapes);
+    let mut trailing_newline = options.trailing_newline;
+
+    let mut stdout = String::new();
+    for (index, word) in words.iter().enumerate() {
+        if index > 0 {
+            stdout.push(' ');
+        }
+
+        if !options.interpret_escapes {
+            stdout.push_str(word);
+            continue;
+        }
+
+        if !push_escaped(&mut stdout, word) {
+            // `\c` ends the output, including the trailing newline.
+            trailing_newline = false;
+            break;
+        }
+    }
+
+    if trailing_newline {
+        stdout.push('\n');
+    }
+
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// Appends `word` with backslash escapes interpreted. Returns `false` when a `\c`
+/// asks for the rest of the output to be dropped.
+fn push_escaped(output: &mut String, word: &str) -> bool {
+    let mut chars = word.chars().peekable();
+
+    while let Some(current_char) = chars.next() {
+        if current_char != '\\' {
+            output.push(current_char);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            output.push('\\');
+            break;
+        };
+
+        match escape {
+            '\\' => output.push('\\'),
+            'a' => output.push('\x07'),
+            'b' => output.push('\x08'),
+            'c' => return false,
+            'e' | 'E' => output.push('\x1b'),
+            'f' => output.push('\x0c'),
+            'n' => output.push('\n'),
+            'r' => output.push('\r'),
+            't' => output.push('\t'),
+            'v' => output.push('\x0b'),
+            '0' => {
+                let value = take_digits(&mut chars, 8, 3);
+                output.push(char::from(value as u8));
+            }
+            'x' if chars.peek().is_some_and(|next| next.is_ascii_hexdigit()) => {
+                let value = take_digits(&mut chars, 16, 2);
+                output.push(char::from(value as u8));
+            }
+            _ => {
+                output.push('\\');
+                output.push(escape);
+            }
+        }
+    }
+
+    return true;
+}
+
+fn take_digits(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    radix: u32,
+    max_digits: usize,
+) -> u32 {
+    let mut value = 0;
+    for _ in 0..max_digits {
+        let Some(digit) = chars.peek().and_then(|next| next.to_digit(radix)) else {
+            break;
+        };
+        value = value * radix + digit;
+        chars.next();
+    }
+
+    return value;
+}
+
+#[cfg(test)]
+mod echo_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    fn stdout(args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return echo(&args).stdout.unwrap_or_default();
+    }
+
+    #[test]
+    fn plain_words() {
+        assert_eq!(stdout(&["hello", "world"]), "hello world\n");
+        assert_eq!(stdout(&[]), "\n");
+    }
+
+    #[test]
+    fn xpg_echo_interprets_escapes_by_default() {
+        assert_eq!(stdout(&["a\\tb"]), "a\\tb\n");
+        assert_eq!(
+            xpg_echo(&args(&["a\\tb"])).stdout,
+            Some(String::from("a\tb\n"))
+        );
+        assert_eq!(
+            xpg_echo(&args(&["-E", "a\\tb"])).stdout,
+            Some(String::from("a\\tb\n"))
+        );
+    }
+
+    #[test]
+    fn no_trailing_newline() {
+        assert_eq!(stdout(&["-n", "hi"]), "hi");
+        assert_eq!(stdout(&["-n"]), "");
+        assert_eq!(echo(&["-n".to_string()]).stdout, None);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(stdout(&["-e", r"a\tb\n"]), "a\tb\n\n");
+        assert_eq!(stdout(&["-ne", r"a\x41\0101"]), "aAA");
+        assert_eq!(stdout(&[r"a\tb"]), "a\\tb\n");
+        assert_eq!(stdout(&["-eE", r"a\tb"]), "a\\tb\n");
+    }
+
+    #[test]
+    fn escape_c_stops_output() {
+        assert_eq!(stdout(&["-e", r"ab\ccd"]), "ab");
+        assert_eq!(stdout(&["-e", "one", r"tw\co", "three"]), "one tw");
+        assert_eq!(stdout(&["-ne", r"ab\ccd"]), "ab");
+        assert_eq!(stdout(&[r"ab\ccd"]), "ab\\ccd\n");
+    }
+
+    #[test]
+    fn non_flags_are_printed() {
+        assert_eq!(stdout(&["-x", "hi"]), "-x hi\n");
+        assert_eq!(stdout(&["-", "hi"]), "- hi\n");
+        assert_eq!(stdout(&["hi", "-n"]), "hi -n\n");
+    }
+
+    #[test]
+    fn option_scanning_matches_bash() {
+        for (args, expected) in [
+            (&["-E", "-n", "-", "hi"][..], "- hi"),
+            (&["-n", "-e", "-E", "x"], "x"),
+            (&["-nEe", r"a\tb"], "a\tb"),
+            (&["-ee", "-nn", "x"], "x"),
+            (&["--", "hi"], "-- hi\n"),
+            (&["-n-e", "hi"], "-n-e hi\n"),
+            (&["-e", "-x", r"a\tb"], "-x a\tb\n"),
+            (&["", "-n", "x"], " -n x\n"),
+            (&["-N", "x"], "-N x\n"),
+            (&["-"], "-\n"),
+            (&["-e"], "\n"),
+            (&["-en", r"\x41", "-n"], "A -n"),
+            (&["-E", r"a\tb"], "a\\tb\n"),
+        ] {
+            assert_eq!(stdout(args), expected, "echo {:?}", args);
+        }
+    }
+
+    #[test]
+    fn posix_echo_only_knows_dash_n() {
+        let posix_stdout = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            return posix_echo(&args).stdout.unwrap_or_default();
+        };
+
+        assert_eq!(posix_stdout(&["-e", r"a\tb"]), "-e a\\tb\n");
+        assert_eq!(posix_stdout(&["-ne", "hi"]), "-ne hi\n");
+        assert_eq!(posix_stdout(&["-n", "hi"]), "hi");
+    }
+}
@@ -0,0 +1,306 @@
+use std::io::{BufRead, Write};
+
+use crate::{completion, prompt::LineTooLong};
+
+const TAB: u8 = b'\t';
+const ENTER: u8 = b'\r';
+const NEWLINE: u8 = b'\n';
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x7f;
+const CTRL_H: u8 = 0x08;
+const ESCAPE: u8 = 0x1b;
+const BELL: &str = "\x07";
+
+/// Reads a single line from a terminal in raw mode, echoing input and handling Tab
+/// completion. `complete` returns the candidates for the word under the cursor.
+/// Returns `None` at end of input, or on Ctrl-D with an empty line. `prompt_width` is
+/// the column input starts at, used to redraw the line without reprinting the prompt.
+/// Input past `max_length` bytes is dropped, and the line fails with `LineTooLong`.
+pub fn read_line(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    prompt: &str,
+    prompt_width: usize,
+    max_length: usize,
+    complete: &dyn Fn(&str) -> Vec<String>,
+) -> anyhow::Result<Option<String>> {
+    let mut line: Vec<u8> = vec![];
+    // Set after a Tab that could not make progress; the next Tab lists the candidates.
+    let mut listing_pending = false;
+    let mut too_long = false;
+
+    loop {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            break;
+        }
+        let key = byte[0];
+
+        match key {
+            ENTER | NEWLINE => {
+                writeln!(writer)?;
+                break;
+            }
+            CTRL_D if line.is_empty() => {
+                writeln!(writer)?;
+                writer.flush()?;
+                return Ok(None);
+            }
+            CTRL_C => {
+                writeln!(writer, "^C")?;
+                line.clear();
+                break;
+            }
+            BACKSPACE | CTRL_H => {
+                if pop_char(&mut line) {
+                    write!(writer, "\x08 \x08")?;
+                }
+            }
+            TAB => {
+                let text = String::from_utf8_lossy(&line).to_string();
+                let candidates = complete(&text);
+                let word = completion::current_word(&text);
+                let prefix = completion::unquote(word);
+                let line_start = &text[..text.len() - word.len()];
+
+                let replacement = match candidates.as_slice() {
+                    [] => None,
+                    [candidate] if candidate.ends_with('/') => Some(completion::quote(candidate)),
+                    [candidate] => Some(format!("{} ", completion::quote(candidate))),
+                    _ => {
+                        let common_prefix = longest_common_prefix(&candidates);
+                        if common_prefix.len() > prefix.len() {
+                            Some(completion::quote(&common_prefix))
+                        } else {
+                            None
+                        }
+                    }
+                };
+
+                match replacement {
+                    Some(replacement) => {
+                        let new_text = format!("{}{}", line_start, replacement);
+                        match new_text.strip_prefix(&text) {
+                            Some(appended) => write!(writer, "{}", appended)?,
+                            None => {
+                                write!(writer, "\r")?;
+                                if prompt_width > 0 {
+                                    write!(writer, "\x1b[{}C", prompt_width)?;
+                                }
+                                write!(writer, "{}\x1b[K", new_text)?;
+                            }
+                        }
+                        line = new_text.into_bytes();
+                    }
+                    None if listing_pending => {
+                        write!(writer, "\n{}\n{}{}", candidates.join("  "), prompt, text)?;
+                    }
+                    None => {
+                        write!(writer, "{}", BELL)?;
+                        listing_pending = candidates.len() > 1;
+                        writer.flush()?;
+                        continue;
+                    }
+                }
+            }
+            ESCAPE => {
+                // Ignore arrow keys and other escape sequences: `ESC [ <final byte>`.
+                let mut sequence = [0u8; 2];
+                reader.read_exact(&mut sequence)?;
+            }
+            _ if key < 0x20 => {}
+            _ if line.len() >= max_length => too_long = true,
+            _ => {
+                line.push(key);
+                writer.write_all(&byte)?;
+            }
+        }
+
+        writer.flush()?;
+        listing_pending = false;
+    }
+
+    writer.flush()?;
+    if too_long {
+        return Err(LineTooLong.into());
+    }
+    return Ok(Some(String::from_utf8_lossy(&line).to_string()));
+}
+
+/// Removes the last UTF-8 character. Returns whether anything was removed.
+fn pop_char(line: &mut Vec<u8>) -> bool {
+    while let Some(byte) = line.pop() {
+        if byte & 0b1100_0000 != 0b1000_0000 {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+fn longest_common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+
+    let mut prefix_len = first.len();
+    for candidate in &candidates[1..] {
+        prefix_len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((index, a), _)| index + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(prefix_len);
+    }
+
+    return first[..prefix_len].to_string();
+}
+
+#[cfg(test)]
+mod editor_tests {
+    use super::*;
+
+    fn run(input: &str, candidates: &[&str]) -> (String, String) {
+        let mut reader = input.as_bytes();
+        let mut writer: Vec<u8> = vec![];
+        let candidates: Vec<String> = candidates.iter().map(|c| c.to_string()).collect();
+        let complete = |line: &str| -> Vec<String> {
+            let word = completion::unquote(completion::current_word(line));
+            return candidates
+                .iter()
+                .filter(|candidate| candidate.starts_with(&word))
+                .cloned()
+                .collect();
+        };
+
+        let line = read_line(&mut reader, &mut writer, "$ ", 2, 1024, &complete)
+            .unwrap()
+            .unwrap_or_default();
+        return (line, String::from_utf8(writer).unwrap());
+    }
+
+    #[test]
+    fn single_candidate_is_completed() {
+        let (line, _) = run("ech\t\r", &["echo", "exit"]);
+        assert_eq!(line, "echo ");
+    }
+
+    #[test]
+    fn common_prefix_is_completed() {
+        let (line, _) = run("cd d\t\r", &["docs", "downloads"]);
+        assert_eq!(line, "cd do");
+
+        let (line, _) = run("do\t\r", &["docs", "dots"]);
+        assert_eq!(line, "do");
+
+        let (line, _) = run("x\t\r", &["xyz_a", "xyz_b"]);
+        assert_eq!(line, "xyz_");
+    }
+
+    #[test]
+    fn second_tab_lists_candidates() {
+        let (line, output) = run("cd do\t\t\r", &["docs", "downloads"]);
+        assert_eq!(line, "cd do");
+        assert!(output.contains("\x07"));
+        assert!(output.contains("\ndocs  downloads\n$ cd do"));
+    }
+
+    #[test]
+    fn directories_and_quoted_names() {
+        let (line, _) = run("cd do\t\r", &["docs/"]);
+        assert_eq!(line, "cd docs/");
+
+        let (line, _) = run("cat my\t\r", &["my notes.txt"]);
+        assert_eq!(line, r#"cat my\ notes.txt "#);
+
+        let (line, output) = run("cat \"my\t\r", &["my notes.txt"]);
+        assert_eq!(line, r#"cat my\ notes.txt "#);
+        assert!(output.contains("\r\x1b[2Ccat my\\ notes.txt \x1b[K"));
+    }
+
+    #[test]
+    fn redraw_skips_the_visible_width_of_the_prompt() {
+        let complete = |_: &str| -> Vec<String> { return vec![String::from("my notes.txt")] };
+        let mut writer: Vec<u8> = vec![];
+        let prompt = "\x1b[32m>\x1b[0m ";
+
+        read_line(
+            &mut "\"my\t\r".as_bytes(),
+            &mut writer,
+            prompt,
+            2,
+            1024,
+            &complete,
+        )
+        .unwrap();
+        let output = String::from_utf8(writer).unwrap();
+        assert!(
+            output.contains("\r\x1b[2Cmy\\ notes.txt \x1b[K"),
+            "{:?}",
+            output
+        );
+    }
+
+    #[test]
+    fn input_past_the_limit_fails_the_line() {
+        let complete = |_: &str| -> Vec<String> { return vec![] };
+        let mut writer: Vec<u8> = vec![];
+
+        let result = read_line(
+            &mut "abcdef\r".as_bytes(),
+            &mut writer,
+            "$ ",
+            2,
+            4,
+            &complete,
+        );
+        assert!(result.unwrap_err().is::<LineTooLong>());
+        assert_eq!(String::from_utf8(writer).unwrap(), "abcd\n");
+
+        let line = read_line(&mut "abcd\r".as_bytes(), &mut vec![], "$ ", 2, 4, &complete);
+        assert_eq!(line.unwrap(), Some(String::from("abcd")));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let complete = |_: &str| -> Vec<String> { return vec![] };
+        let mut writer: Vec<u8> = vec![];
+
+        let line = read_line(&mut "".as_bytes(), &mut writer, "$ ", 2, 1024, &complete).unwrap();
+        assert_eq!(line, None);
+
+        let line = read_line(
+            &mut "\x04".as_bytes(),
+            &mut writer,
+            "$ ",
+            2,
+            1024,
+            &complete,
+        )
+        .unwrap();
+        assert_eq!(line, None);
+
+        let line = read_line(
+            &mut "ls\x04\r".as_bytes(),
+            &mut writer,
+            "$ ",
+            2,
+            1024,
+            &complete,
+        )
+        .unwrap();
+        assert_eq!(line, Some("ls".to_string()));
+    }
+
+    #[test]
+    fn backspace_removes_characters() {
+        let (line, _) = run("echoo\x7f\r", &[]);
+        assert_eq!(line, "echo");
+    }
+}
@@ -1,20 +1,78 @@
-use std::path::PathBuf;
+#[cfg(unix)]
+use std::os::unix::{
+    fs::PermissionsExt,
+    process::{CommandExt, ExitStatusExt},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus, Stdio},
+};
+
+#[cfg(unix)]
+use crate::terminal;
 
 #[derive(Debug)]
 pub struct ExecutableOutput {
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    pub status: i32,
+}
+
+/// How a program run on the shell's terminal came back to it.
+#[derive(Debug)]
+pub enum Attached {
+    Exited(i32),
+    /// Stopped by `signal`, e.g. `SIGTSTP` from Ctrl-Z. The program can be resumed.
+    Stopped {
+        child: Child,
+        signal: i32,
+    },
+}
+
+/// A program to start: the name it was invoked as (passed as `argv[0]`), the path it
+/// resolved to, its arguments and the exported shell variables it sees.
+pub struct Invocation<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    pub args: &'a [&'a str],
+    pub env: &'a [(String, String)],
+}
+
+impl Invocation<'_> {
+    pub fn command(&self) -> std::process::Command {
+        let mut command = std::process::Command::new(self.path);
+        #[cfg(unix)]
+        command.arg0(self.name);
+        command
+            .args(self.args)
+            .env_clear()
+            .envs(self.env.iter().map(|(name, value)| return (name, value)));
+
+        return command;
+    }
+}
+
+/// The shell's `$?` for a program that ended with `status`: its exit code, or
+/// `128 + signal` when a signal killed it, as `sh -c 'kill -9 $$'` giving 137.
+pub fn status_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = status.signal() {
+        return 128 + signal;
+    }
+    return status.code().unwrap_or(1);
 }
 
 pub trait ExecutableRunner {
-    fn execute(&self, exec_name: &str, args: &[&str]) -> anyhow::Result<ExecutableOutput> {
-        let result = std::process::Command::new(exec_name).args(args).output();
+    fn execute(&self, invocation: &Invocation) -> anyhow::Result<ExecutableOutput> {
+        let result = invocation.command().output();
 
         match result {
             Ok(result) => {
                 let mut output = ExecutableOutput {
                     stdout: None,
                     stderr: None,
+                    status: status_code(result.status),
                 };
 
                 let stderr = String::from_utf8_lossy(&result.stderr).to_string();
@@ -31,27 +89,181 @@ pub trait ExecutableRunner {
             }
             Err(_) => {
                 return Ok(ExecutableOutput {
-                    stderr: Some(format!("{}: command not found\n", exec_name)),
+                    stderr: Some(format!("{}: command not found\n", invocation.name)),
                     stdout: None,
+                    status: 127,
                 })
             }
         }
     }
+
+    /// Runs the program in the foreground until it exits or is stopped, connected to
+    /// the given handles: the shell's own stdin, stdout and stderr (e.g. for an editor)
+    /// unless redirected. Under job control it runs in its own process group, which
+    /// has the terminal meanwhile.
+    fn execute_attached(
+        &self,
+        invocation: &Invocation,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> std::io::Result<Attached> {
+        let child = self.spawn_job(invocation, stdin, stdout, stderr, None)?;
+        #[cfg(unix)]
+        let _foreground = match self.job_control() {
+            true => terminal::Foreground::give(child.id()),
+            false => None,
+        };
+        return wait_attached(child);
+    }
+
+    /// Whether foreground programs get their own process group and the terminal.
+    fn job_control(&self) -> bool {
+        return false;
+    }
+
+    /// Starts the program without waiting for it, connected to the given handles.
+    fn spawn(
+        &self,
+        invocation: &Invocation,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> std::io::Result<Child> {
+        return invocation
+            .command()
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn();
+    }
+
+    /// Starts a process of a foreground job without waiting for it. Under job control
+    /// it joins the process group `group`, or leads a new one when `None`.
+    fn spawn_job(
+        &self,
+        invocation: &Invocation,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+        group: Option<u32>,
+    ) -> std::io::Result<Child> {
+        let mut command = invocation.command();
+        command.stdin(stdin).stdout(stdout).stderr(stderr);
+        return spawn_foreground(command, self.job_control(), group);
+    }
+}
+
+/// Starts a process of a foreground job. With `job_control` it joins the process
+/// group `group` (`setpgid`), or leads a new one as the job's first process, and
+/// takes the terminal when the shell has it to give.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn spawn_foreground(
+    mut command: std::process::Command,
+    job_control: bool,
+    group: Option<u32>,
+) -> io::Result<Child> {
+    #[cfg(unix)]
+    let group = group.unwrap_or(0) as libc::pid_t;
+    #[cfg(unix)]
+    if job_control {
+        let take_terminal = terminal::has_foreground();
+        // The process does both itself before `exec`, so it cannot read from the
+        // terminal, or be missed by Ctrl-C, before the shell has handed it over.
+        // SAFETY: the closure only makes calls that are safe between `fork` and `exec`.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setpgid(0, group) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // Failing here leaves it to the shell's `Foreground::give`.
+                if take_terminal {
+                    let _ = terminal::set_foreground(libc::getpgrp());
+                }
+                return Ok(());
+            });
+        }
+    }
+    let child = command.spawn()?;
+    #[cfg(unix)]
+    if job_control {
+        // The shell sets the group as well, so it is in place for `Foreground::give`
+        // whichever of the two runs first.
+        let pid = child.id() as libc::pid_t;
+        // SAFETY: the child has not been waited for, so its pid still names it.
+        unsafe {
+            libc::setpgid(pid, if group == 0 { pid } else { group });
+        }
+    }
+    return Ok(child);
+}
+
+/// Waits for the processes of a foreground job in order. Once one is stopped, e.g. by
+/// Ctrl-Z, so is the job: the processes after it come back stopped too, without
+/// being waited for, to be resumed along with it.
+pub fn wait_job(children: Vec<Child>) -> io::Result<Vec<Attached>> {
+    let mut results = vec![];
+    let mut stop_signal = None;
+    for child in children {
+        let result = match stop_signal {
+            Some(signal) => Attached::Stopped { child, signal },
+            None => wait_attached(child)?,
+        };
+        if let Attached::Stopped { signal, .. } = result {
+            stop_signal = Some(signal);
+        }
+        results.push(result);
+    }
+
+    return Ok(results);
+}
+
+/// Waits for a program on the shell's terminal to exit, or to be stopped.
+#[cfg(unix)]
+pub fn wait_attached(child: Child) -> io::Result<Attached> {
+    let mut status = 0;
+    loop {
+        // SAFETY: `status` is valid to write to, and the child has not been reaped, so
+        // its pid still names it.
+        if unsafe { libc::waitpid(child.id() as libc::pid_t, &mut status, libc::WUNTRACED) } != -1 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    if libc::WIFSTOPPED(status) {
+        let signal = libc::WSTOPSIG(status);
+        return Ok(Attached::Stopped { child, signal });
+    }
+    return Ok(Attached::Exited(status_code(ExitStatus::from_raw(status))));
+}
+
+#[cfg(not(unix))]
+pub fn wait_attached(mut child: Child) -> io::Result<Attached> {
+    let status: ExitStatus = child.wait()?;
+    return Ok(Attached::Exited(status_code(status)));
 }
 
 pub trait ExecutablePathFinder {
     fn find_executable_path(&self, env_path: &str, name: &str) -> Option<String> {
-        let env_paths = env_path.split(":");
-
-        for env_path in env_paths {
-            let full_path: PathBuf = [env_path, name].iter().collect();
-            if full_path.exists() {
-                return Some(
-                    full_path
-                        .into_os_string()
-                        .into_string()
-                        .expect("Failed to convert path"),
-                );
+        for dir in split_env_path(env_path) {
+            if !dir.is_dir() {
+                continue;
+            }
+
+            for candidate_name in candidate_names(name) {
+                let full_path = dir.join(candidate_name);
+                if is_executable(&full_path) {
+                    return Some(
+                        full_path
+                            .into_os_string()
+                            .into_string()
+                            .expect("Failed to convert path"),
+                    );
+                }
             }
         }
 
@@ -59,6 +271,59 @@ pub trait ExecutablePathFinder {
     }
 }
 
+/// Splits `PATH` with the platform separator (`:` on Unix, `;` on Windows). An empty
+/// entry (`::`, or a leading/trailing separator) means the current directory.
+pub fn split_env_path(env_path: &str) -> Vec<PathBuf> {
+    return std::env::split_paths(env_path)
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                return PathBuf::from(".");
+            }
+            return dir;
+        })
+        .collect();
+}
+
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    return path
+        .metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+}
+
+#[cfg(windows)]
+pub fn is_executable(path: &Path) -> bool {
+    return path.is_file();
+}
+
+#[cfg(not(windows))]
+fn candidate_names(name: &str) -> Vec<String> {
+    return vec![name.to_string()];
+}
+
+/// On Windows a bare command name also resolves with each `PATHEXT` extension.
+#[cfg(windows)]
+fn candidate_names(name: &str) -> Vec<String> {
+    let pathext = std::env::var("PATHEXT").unwrap_or(String::from(".COM;.EXE;.BAT;.CMD"));
+    return with_extensions(name, &pathext);
+}
+
+#[cfg(windows)]
+fn with_extensions(name: &str, pathext: &str) -> Vec<String> {
+    let mut names = vec![];
+    if Path::new(name).extension().is_some() {
+        names.push(name.to_string());
+    }
+
+    for extension in pathext.split(';').filter(|extension| !extension.is_empty()) {
+        names.push(format!("{}{}", name, extension));
+    }
+
+    return names;
+}
+
+#[derive(Default)]
 pub struct PathFinder {}
 
 impl ExecutablePathFinder for PathFinder {}
@@ -69,12 +334,177 @@ impl PathFinder {
     }
 }
 
-pub struct Runner {}
+#[derive(Default)]
+pub struct Runner {
+    job_control: bool,
+}
 
-impl ExecutableRunner for Runner {}
+impl ExecutableRunner for Runner {
+    fn job_control(&self) -> bool {
+        return self.job_control;
+    }
+}
 
 impl Runner {
     pub fn new() -> Self {
-        return Self {};
+        return Self { job_control: false };
+    }
+
+    /// Runs foreground programs as jobs in their own process groups, for an
+    /// interactive shell on a terminal.
+    pub fn with_job_control(mut self, job_control: bool) -> Self {
+        self.job_control = job_control;
+        return self;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod executable_tests {
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+    use tempfile::TempDir;
+
+    use crate::test_support::in_dir;
+
+    use super::{spawn_foreground, split_env_path, ExecutablePathFinder, Invocation, PathFinder};
+
+    fn create_executable(path: &Path) -> anyhow::Result<()> {
+        fs::write(path, "#!/bin/sh\n")?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        return Ok(());
+    }
+
+    #[test]
+    fn empty_entry_is_current_directory() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        create_executable(&dir.path().join("local_tool"))?;
+
+        let finder = PathFinder::new();
+        for env_path in ["/nonexistent::/usr/bin", ":/nonexistent", "/nonexistent:"] {
+            let result = in_dir(dir.path(), || {
+                return finder.find_executable_path(env_path, "local_tool");
+            });
+            assert_eq!(result, Some("./local_tool".to_string()), "{}", env_path);
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn dot_entry_is_current_directory() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        create_executable(&dir.path().join("local_tool"))?;
+
+        let finder = PathFinder::new();
+        let result = in_dir(dir.path(), || {
+            return finder.find_executable_path("/nonexistent:.", "local_tool");
+        });
+        assert_eq!(result, Some("./local_tool".to_string()));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn colon_separator() {
+        assert_eq!(
+            split_env_path("/usr/bin::/bin"),
+            vec![
+                Path::new("/usr/bin").to_path_buf(),
+                Path::new(".").to_path_buf(),
+                Path::new("/bin").to_path_buf()
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_file_entries_and_non_executables() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let file_entry = dir.path().join("not_a_dir");
+        fs::write(&file_entry, "")?;
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin)?;
+        fs::write(bin.join("plain"), "")?;
+        create_executable(&bin.join("tool"))?;
+
+        let env_path = format!("{}:{}", file_entry.display(), bin.display());
+        let finder = PathFinder::new();
+
+        assert_eq!(
+            finder.find_executable_path(&env_path, "tool"),
+            Some(bin.join("tool").display().to_string())
+        );
+        assert_eq!(finder.find_executable_path(&env_path, "plain"), None);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn foreground_jobs_lead_their_own_process_group() -> anyhow::Result<()> {
+        let invocation = Invocation {
+            name: "sleep",
+            path: "sleep",
+            args: &["5"],
+            env: &[(String::from("PATH"), String::from("/bin:/usr/bin"))],
+        };
+        // SAFETY: `getpgid` only reads the process group of a process.
+        let group = |pid: u32| return unsafe { libc::getpgid(pid as libc::pid_t) };
+
+        let mut job = spawn_foreground(invocation.command(), true, None)?;
+        let mut stage = spawn_foreground(invocation.command(), true, Some(job.id()))?;
+        let mut plain = spawn_foreground(invocation.command(), false, None)?;
+        let (job_group, stage_group, plain_group) =
+            (group(job.id()), group(stage.id()), group(plain.id()));
+        for child in [&mut job, &mut stage, &mut plain] {
+            child.kill()?;
+            child.wait()?;
+        }
+
+        assert_eq!(job_group, job.id() as libc::pid_t);
+        assert_eq!(stage_group, job_group);
+        // SAFETY: `getpgrp` has no requirements.
+        assert_eq!(plain_group, unsafe { libc::getpgrp() });
+
+        return Ok(());
+    }
+}
+
+#[cfg(all(test, windows))]
+mod windows_executable_tests {
+    use std::{fs, path::PathBuf};
+
+    use tempfile::TempDir;
+
+    use super::{split_env_path, with_extensions, ExecutablePathFinder, PathFinder};
+
+    #[test]
+    fn semicolon_separator() {
+        assert_eq!(
+            split_env_path(r"C:\Windows;;C:\Tools"),
+            vec![
+                PathBuf::from(r"C:\Windows"),
+                PathBuf::from("."),
+                PathBuf::from(r"C:\Tools")
+            ]
+        );
+    }
+
+    #[test]
+    fn pathext_extensions() -> anyhow::Result<()> {
+        assert_eq!(
+            with_extensions("tool", ".EXE;.BAT"),
+            vec!["tool.EXE".to_string(), "tool.BAT".to_string()]
+        );
+        assert_eq!(
+            with_extensions("tool.exe", ".EXE"),
+            vec!["tool.exe".to_string(), "tool.exe.EXE".to_string()]
+        );
+
+        let dir = TempDir::new()?;
+        fs::write(dir.path().join("tool.bat"), "")?;
+        let finder = PathFinder::new();
+        let result = finder.find_executable_path(&dir.path().display().to_string(), "tool");
+        assert!(result.is_some());
+
+        return Ok(());
     }
 }
@@ -0,0 +1,936 @@
+use std::cell::RefCell;
+
+use anyhow::anyhow;
+
+use crate::{
+    arithmetic::{self, Variables},
+    brace::{expand_braces, too_long},
+    glob::{glob, GlobOptions, GLOB_CHARS},
+    state::{ShellState, DEFAULT_IFS},
+    substitution,
+};
+
+/// Upper bound on the fields a single command's words may expand to, so something
+/// like `{1..100000000}` fails instead of exhausting memory.
+pub const DEFAULT_FIELD_LIMIT: usize = 1_000_000;
+
+/// Runs the command of a `$(command)` and returns its output.
+pub type Substitute<'a> = &'a dyn Fn(&str) -> anyhow::Result<String>;
+
+/// Turns a raw word (as written on the command line, quotes included) into the
+/// fields passed to a command: quotes are removed, and when a shell state is
+/// available, braces and `$` parameters are expanded, unquoted results are split on
+/// whitespace and unquoted glob patterns are matched against file names.
+pub struct Expander<'a> {
+    state: Option<&'a ShellState>,
+    field_limit: usize,
+    substitute: Option<Substitute<'a>>,
+    /// Runs the command of a `<(command)` and returns the path to read its output from.
+    process_substitute: Option<Substitute<'a>>,
+    /// Variables assigned by `$((name = value))`, in order. The state is only
+    /// borrowed here, so the caller applies them once expansion is done.
+    assignments: RefCell<Vec<(String, String)>>,
+}
+
+/// The shell's variables as seen during expansion, with the assignments made so far
+/// on top.
+struct Assigned<'e, 'a> {
+    state: &'a ShellState,
+    assignments: &'e RefCell<Vec<(String, String)>>,
+}
+
+impl Variables for Assigned<'_, '_> {
+    fn get(&self, name: &str) -> Option<String> {
+        return assigned(self.assignments, name)
+            .or_else(|| return self.state.variables.get(name).map(String::from));
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        self.assignments
+            .borrow_mut()
+            .push((name.to_string(), value.to_string()));
+    }
+}
+
+struct Field {
+    text: String,
+    /// Set when the field has unquoted glob characters; quoted ones are escaped.
+    pattern: Option<String>,
+}
+
+#[derive(Default)]
+struct Fields {
+    fields: Vec<Field>,
+    current: String,
+    pattern: String,
+    has_glob: bool,
+    has_current: bool,
+    /// Keeps unquoted expansions whole, as in the value of an assignment.
+    no_split: bool,
+    /// The characters unquoted expansions are split on.
+    ifs: String,
+    /// The characters with a special meaning in `pattern`; quoted ones are escaped.
+    specials: &'static str,
+    /// The last field was just ended by `IFS` whitespace, which a following
+    /// non-whitespace separator belongs to.
+    ended_by_blank: bool,
+}
+
+impl Fields {
+    /// Pushes a quoted character, which never acts as a glob character.
+    fn push(&mut self, value: char) {
+        self.ended_by_blank = false;
+        self.current.push(value);
+        if self.specials.contains(value) || value == '\\' {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(value);
+        self.has_current = true;
+    }
+
+    fn push_unquoted(&mut self, value: char) {
+        self.ended_by_blank = false;
+        self.current.push(value);
+        self.pattern.push(value);
+        self.has_glob |= self.specials.contains(value);
+        self.has_current = true;
+    }
+
+    fn push_str(&mut self, value: &str) {
+        for current_char in value.chars() {
+            self.push(current_char);
+        }
+        self.has_current = true;
+    }
+
+    fn push_split(&mut self, value: &str) {
+        if self.no_split {
+            // Still unquoted: kept whole, but any pattern characters keep their meaning.
+            for current_char in value.chars() {
+                self.push_unquoted(current_char);
+            }
+            return;
+        }
+
+        for current_char in value.chars() {
+            if !self.ifs.contains(current_char) {
+                self.push_unquoted(current_char);
+                continue;
+            }
+
+            if is_ifs_whitespace(current_char) {
+                if self.has_current {
+                    self.finish();
+                    self.ended_by_blank = true;
+                }
+                continue;
+            }
+
+            // Any other separator ends a field even when it is empty, as in `a,,b`.
+            if self.has_current {
+                self.finish();
+            } else if !self.ended_by_blank {
+                self.fields.push(Field {
+                    text: String::new(),
+                    pattern: None,
+                });
+            }
+            self.ended_by_blank = false;
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.has_current {
+            let pattern = std::mem::take(&mut self.pattern);
+            self.fields.push(Field {
+                text: std::mem::take(&mut self.current),
+                pattern: self.has_glob.then_some(pattern),
+            });
+            self.has_current = false;
+        }
+        self.pattern.clear();
+        self.has_glob = false;
+    }
+
+    fn into_fields(mut self) -> Vec<Field> {
+        self.finish();
+        return self.fields;
+    }
+}
+
+/// Backslash-escapes `specials` and backslashes in `text`.
+fn escape(text: &str, specials: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for current_char in text.chars() {
+        if specials.contains(current_char) || current_char == '\\' {
+            output.push('\\');
+        }
+        output.push(current_char);
+    }
+
+    return output;
+}
+
+/// `IFS` whitespace runs together into one separator; other `IFS` characters each
+/// separate a field of their own.
+fn is_ifs_whitespace(value: char) -> bool {
+    return matches!(value, ' ' | '\t' | '\n');
+}
+
+fn is_name_start(value: char) -> bool {
+    return value.is_ascii_alphabetic() || value == '_';
+}
+
+fn is_name_char(value: char) -> bool {
+    return value.is_ascii_alphanumeric() || value == '_';
+}
+
+enum Parameter {
+    Value(String),
+    /// `$@`: every positional parameter as its own field when quoted.
+    AllSeparate(Vec<String>),
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(state: &'a ShellState) -> Self {
+        return Self {
+            state: Some(state),
+            field_limit: DEFAULT_FIELD_LIMIT,
+            substitute: None,
+            process_substitute: None,
+            assignments: RefCell::new(vec![]),
+        };
+    }
+
+    /// Quote removal only; `$`, braces and glob characters are kept literally.
+    pub fn literal() -> Self {
+        return Self {
+            state: None,
+            field_limit: DEFAULT_FIELD_LIMIT,
+            substitute: None,
+            process_substitute: None,
+            assignments: RefCell::new(vec![]),
+        };
+    }
+
+    pub fn with_field_limit(mut self, field_limit: usize) -> Self {
+        self.field_limit = field_limit;
+        return self;
+    }
+
+    /// Enables `$(command)`; without it the text is kept literally.
+    pub fn with_substitution(mut self, substitute: Substitute<'a>) -> Self {
+        self.substitute = Some(substitute);
+        return self;
+    }
+
+    /// Enables `<(command)`; without it the text is kept literally.
+    pub fn with_process_substitution(mut self, substitute: Substitute<'a>) -> Self {
+        self.process_substitute = Some(substitute);
+        return self;
+    }
+
+    /// Takes the variables assigned by arithmetic expansion so far, in order.
+    pub fn take_assignments(&self) -> Vec<(String, String)> {
+        return self.assignments.take();
+    }
+
+    /// Fully expands a command's words. Fails with `argument list too long` once
+    /// they produce more fields than the limit.
+    pub fn expand_words(&self, words: &[String]) -> anyhow::Result<Vec<String>> {
+        let mut fields = vec![];
+        for word in words {
+            fields.extend(self.expand(word, self.field_limit - fields.len())?);
+        }
+
+        return Ok(fields);
+    }
+
+    /// Expands a redirection target, which must stay a single word.
+    pub fn expand_single(&self, word: &str) -> anyhow::Result<String> {
+        let fields = self.expand(word, self.field_limit)?;
+        let [field] = fields.try_into().map_err(|_| {
+            return anyhow!("{}: ambiguous redirect", word);
+        })?;
+
+        return Ok(field);
+    }
+
+    fn expand(&self, word: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+        if self.state.is_none() {
+            return self.expand_word(word);
+        }
+
+        // Brace expansion is a bash extension; POSIX mode keeps `{a,b}` as written.
+        let words = match self.state {
+            Some(state) if state.options.is_set("posix") => vec![word.to_string()],
+            _ => expand_braces(word, limit)?,
+        };
+
+        let is_set = |name| {
+            return self
+                .state
+                .is_some_and(|state| return state.options.is_set(name));
+        };
+        let glob_options = GlobOptions {
+            nocase: is_set("nocaseglob"),
+            dotglob: is_set("dotglob"),
+        };
+        let mut output = vec![];
+        for word in words {
+            for field in self.split_fields(&word)? {
+                let matches = field
+                    .pattern
+                    .map(|pattern| return glob(&pattern, glob_options));
+                match matches {
+                    Some(matches) if !matches.is_empty() => output.extend(matches),
+                    // Unmatched patterns are kept as written unless `shopt -s failglob`
+                    // or `nullglob` say otherwise.
+                    Some(_) if is_set("failglob") => {
+                        return Err(anyhow!("no match: {}", field.text));
+                    }
+                    Some(_) if is_set("nullglob") => {}
+                    _ => output.push(field.text),
+                }
+
+                if output.len() > limit {
+                    return Err(too_long());
+                }
+            }
+        }
+
+        return Ok(output);
+    }
+
+    /// Quote removal, parameter expansion and field splitting; no braces or globs.
+    pub fn expand_word(&self, word: &str) -> anyhow::Result<Vec<String>> {
+        return Ok(self
+            .split_fields(word)?
+            .into_iter()
+            .map(|field| return field.text)
+            .collect());
+    }
+
+    /// Expands the value of a `NAME=value` assignment: quote removal and parameter
+    /// expansion only, so the value is never split or matched against file names.
+    pub fn expand_assignment(&self, value: &str) -> anyhow::Result<String> {
+        let fields = Fields {
+            no_split: true,
+            ..self.fields()
+        };
+        let fields: Vec<String> = self
+            .split_fields_into(value, fields)?
+            .into_iter()
+            .map(|field| return field.text)
+            .collect();
+
+        return Ok(fields.join(" "));
+    }
+
+    /// Expands the right side of `==` or `=~` in `[[ ... ]]`. Like an assignment value
+    /// it is never split, but quoted characters among `specials` are backslash-escaped
+    /// so they match literally.
+    pub fn expand_pattern(&self, word: &str, specials: &'static str) -> anyhow::Result<String> {
+        let fields = Fields {
+            no_split: true,
+            specials,
+            ..self.fields()
+        };
+        let patterns: Vec<String> = self
+            .split_fields_into(word, fields)?
+            .into_iter()
+            .map(|field| {
+                return field
+                    .pattern
+                    .unwrap_or_else(|| return escape(&field.text, specials));
+            })
+            .collect();
+
+        return Ok(patterns.join(" "));
+    }
+
+    fn split_fields(&self, word: &str) -> anyhow::Result<Vec<Field>> {
+        return self.split_fields_into(word, self.fields());
+    }
+
+    fn fields(&self) -> Fields {
+        let ifs = self.state.map_or(DEFAULT_IFS, |state| return state.ifs());
+        return Fields {
+            ifs: ifs.to_string(),
+            specials: GLOB_CHARS,
+            ..Default::default()
+        };
+    }
+
+    fn split_fields_into(&self, word: &str, mut fields: Fields) -> anyhow::Result<Vec<Field>> {
+        let chars: Vec<char> = word.chars().collect();
+        // Most words come out about as long as they went in; reserving up front keeps
+        // a very long argument from being regrown over and over.
+        fields.current.reserve(word.len());
+        fields.pattern.reserve(word.len());
+        let mut index = 0;
+
+        while index < chars.len() {
+            let current_char = chars[index];
+            match current_char {
+                '\\' => {
+                    if let Some(&next_char) = chars.get(index + 1) {
+                        fields.push(next_char);
+                    }
+                    index += 2;
+                }
+                '\'' => {
+                    fields.has_current = true;
+                    index += 1;
+                    while index < chars.len() && chars[index] != '\'' {
+                        fields.push(chars[index]);
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                '"' => {
+                    index = self.expand_double_quoted(&chars, index + 1, &mut fields)?;
+                }
+                '<' if chars.get(index + 1) == Some(&'(') => {
+                    let mut rest = chars[index + 2..].iter().copied();
+                    match (
+                        substitution::take_command(&mut rest),
+                        self.process_substitute,
+                    ) {
+                        (Some(command), Some(substitute)) => {
+                            fields.push_str(&substitute(&command)?);
+                            index += 3 + command.chars().count();
+                        }
+                        _ => {
+                            fields.push_unquoted(current_char);
+                            index += 1;
+                        }
+                    }
+                }
+                '$' => match self.parse_parameter(&chars, index + 1)? {
+                    Some((parameter, next_index)) => {
+                        match parameter {
+                            Parameter::Value(value) => fields.push_split(&value),
+                            Parameter::AllSeparate(values) => {
+                                for value in values {
+                                    fields.push_split(&value);
+                                    fields.finish();
+                                }
+                            }
+                        }
+                        index = next_index;
+                    }
+                    None => {
+                        fields.push_unquoted(current_char);
+                        index += 1;
+                    }
+                },
+                _ => {
+                    fields.push_unquoted(current_char);
+                    index += 1;
+                }
+            }
+        }
+
+        return Ok(fields.into_fields());
+    }
+
+    /// Expands the inside of a double-quoted section starting at `index` (just past
+    /// the opening quote). Returns the index just past the closing quote.
+    fn expand_double_quoted(
+        &self,
+        chars: &[char],
+        mut index: usize,
+        fields: &mut Fields,
+    ) -> anyhow::Result<usize> {
+        let mut mark_quoted = true;
+
+        while index < chars.len() && chars[index] != '"' {
+            let current_char = chars[index];
+            match current_char {
+                '\\' => match chars.get(index + 1) {
+                    Some(&next_char) if matches!(next_char, '"' | '\\' | '$' | '`' | '\n') => {
+                        fields.push(next_char);
+                        index += 2;
+                    }
+                    _ => {
+                        fields.push(current_char);
+                        index += 1;
+                    }
+                },
+                '$' => match self.parse_parameter(chars, index + 1)? {
+                    Some((parameter, next_index)) => {
+                        match parameter {
+                            Parameter::Value(value) => fields.push_str(&value),
+                            Parameter::AllSeparate(values) => {
+                                for (value_index, value) in values.iter().enumerate() {
+                                    if value_index > 0 {
+                                        fields.finish();
+                                    }
+                                    fields.push_str(value);
+                                }
+                                // `"$@"` with no positional parameters produces no field at all.
+                                if values.is_empty() {
+                                    mark_quoted = false;
+                                }
+                            }
+                        }
+                        index = next_index;
+                    }
+                    None => {
+                        fields.push(current_char);
+                        index += 1;
+                    }
+                },
+                _ => {
+                    fields.push(current_char);
+                    index += 1;
+                }
+            }
+        }
+
+        if mark_quoted {
+            fields.has_current = true;
+        }
+
+        return Ok(index + 1);
+    }
+
+    /// Parses the parameter following a `$` at `index`. Returns `None` when the `$`
+    /// should be treated literally.
+    fn parse_parameter(
+        &self,
+        chars: &[char],
+        index: usize,
+    ) -> anyhow::Result<Option<(Parameter, usize)>> {
+        let (Some(state), Some(&next_char)) = (self.state, chars.get(index)) else {
+            return Ok(None);
+        };
+
+        if next_char == '(' {
+            let mut rest = chars[index + 1..].iter().copied();
+            let Some(command) = substitution::take_command(&mut rest) else {
+                return Ok(None);
+            };
+            let close_index = index + 1 + command.chars().count();
+            let value = match (arithmetic_expression(&command), self.substitute) {
+                (Some(expression), _) => self.evaluate(state, expression)?.to_string(),
+                (None, Some(substitute)) => substitute(&command)?,
+                (None, None) => return Ok(None),
+            };
+            return Ok(Some((Parameter::Value(value), close_index + 1)));
+        }
+
+        if next_char == '{' {
+            let Some(offset) = chars[index..].iter().position(|&c| return c == '}') else {
+                return Ok(None);
+            };
+            let close_index = index + offset;
+            let name: String = chars[index + 1..close_index].iter().collect();
+            return Ok(Some((self.lookup(state, &name), close_index + 1)));
+        }
+
+        if is_name_start(next_char) {
+            let end_index = chars[index..]
+                .iter()
+                .position(|&c| return !is_name_char(c))
+                .map(|offset| return index + offset)
+                .unwrap_or(chars.len());
+            let name: String = chars[index..end_index].iter().collect();
+            return Ok(Some((self.lookup(state, &name), end_index)));
+        }
+
+        if next_char.is_ascii_digit() || matches!(next_char, '?' | '#' | '@' | '*' | '$' | '!') {
+            return Ok(Some((lookup(state, &next_char.to_string()), index + 1)));
+        }
+
+        return Ok(None);
+    }
+}
+
+impl Expander<'_> {
+    /// Evaluates the inside of `$((...))` once its own parameters are expanded.
+    fn evaluate(&self, state: &ShellState, expression: &str) -> anyhow::Result<i64> {
+        let expression = self.expand_assignment(expression)?;
+        let mut variables = Assigned {
+            state,
+            assignments: &self.assignments,
+        };
+        return arithmetic::evaluate(&expression, &mut variables);
+    }
+
+    fn lookup(&self, state: &ShellState, name: &str) -> Parameter {
+        match assigned(&self.assignments, name) {
+            Some(value) => return Parameter::Value(value),
+            None => return lookup(state, name),
+        }
+    }
+}
+
+/// The latest value arithmetic expansion gave `name`, if any.
+fn assigned(assignments: &RefCell<Vec<(String, String)>>, name: &str) -> Option<String> {
+    return assignments
+        .borrow()
+        .iter()
+        .rev()
+        .find(|(assigned, _)| return assigned == name)
+        .map(|(_, value)| return value.clone());
+}
+
+/// The expression of `$((expression))`, given what is between the outer `$(` and
+/// `)`: it must be a single parenthesized group.
+fn arithmetic_expression(command: &str) -> Option<&str> {
+    let inner = command.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+    for current_char in inner.chars() {
+        match current_char {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    return Some(inner);
+}
+
+fn lookup(state: &ShellState, name: &str) -> Parameter {
+    match name {
+        "?" => return Parameter::Value(state.last_status.to_string()),
+        "$" => return Parameter::Value(std::process::id().to_string()),
+        "!" => {
+            let pid = state.jobs.last_pid();
+            return Parameter::Value(pid.map(|pid| return pid.to_string()).unwrap_or_default());
+        }
+        "#" => return Parameter::Value(state.positional_params.len().to_string()),
+        "@" => return Parameter::AllSeparate(state.positional_params.clone()),
+        "*" => return Parameter::Value(state.positional_params.join(&join_separator(state))),
+        "RANDOM" => return Parameter::Value(state.random.next().to_string()),
+        "SECONDS" => return Parameter::Value(state.seconds.get().to_string()),
+        "LINENO" => return Parameter::Value(state.line_number.to_string()),
+        _ => {}
+    }
+
+    if let Some((name, subscript)) = name
+        .strip_suffix(']')
+        .and_then(|name| return name.split_once('['))
+    {
+        return lookup_element(state, name, subscript);
+    }
+
+    if name == "PIPESTATUS" || name == "BASH_SOURCE" {
+        return lookup_element(state, name, "0");
+    }
+
+    if let Ok(position) = name.parse::<usize>() {
+        let value = match position {
+            0 => String::from("shell"),
+            _ => state
+                .positional_params
+                .get(position - 1)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        return Parameter::Value(value);
+    }
+
+    let value = state.variables.get(name).unwrap_or_default().to_string();
+    return Parameter::Value(value);
+}
+
+/// `$*` joins the values with the first character of `IFS`.
+fn join_separator(state: &ShellState) -> String {
+    return state
+        .ifs()
+        .chars()
+        .next()
+        .map(String::from)
+        .unwrap_or_default();
+}
+
+/// `${NAME[N]}`, `${NAME[@]}` and `${NAME[*]}`. `PIPESTATUS` and `BASH_SOURCE` (the
+/// file being read first, then the ones that sourced it) are the only arrays; any
+/// other variable behaves as an array of one element.
+fn lookup_element(state: &ShellState, name: &str, subscript: &str) -> Parameter {
+    let elements: Vec<String> = match name {
+        "PIPESTATUS" => state
+            .pipe_status
+            .iter()
+            .map(|status| return status.to_string())
+            .collect(),
+        "BASH_SOURCE" => state.sources.iter().rev().cloned().collect(),
+        _ => state
+            .variables
+            .get(name)
+            .map(|value| return vec![value.to_string()])
+            .unwrap_or_default(),
+    };
+
+    match subscript {
+        "@" => return Parameter::AllSeparate(elements),
+        "*" => return Parameter::Value(elements.join(&join_separator(state))),
+        index => {
+            let value = index
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| return elements.get(index).cloned())
+                .unwrap_or_default();
+            return Parameter::Value(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod expansion_tests {
+    use crate::test_support::in_dir;
+
+    use super::*;
+
+    fn state() -> ShellState {
+        let mut state = ShellState::default();
+        state.variables.set("NAME", "world");
+        state.variables.set("SPACED", "a  b");
+        state.positional_params = vec!["first".to_string(), "second arg".to_string()];
+        return state;
+    }
+
+    #[test]
+    fn literal_keeps_dollar() {
+        let output = Expander::literal().expand_word(r#""$NAME""#).unwrap();
+        assert_eq!(output, vec!["$NAME".to_string()]);
+    }
+
+    #[test]
+    fn variable() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#"hello_$NAME"#).unwrap();
+        assert_eq!(output, vec!["hello_world".to_string()]);
+    }
+
+    #[test]
+    fn braced_variable() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#"${NAME}wide"#).unwrap();
+        assert_eq!(output, vec!["worldwide".to_string()]);
+    }
+
+    #[test]
+    fn single_quotes_are_not_expanded() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#"'$NAME'"#).unwrap();
+        assert_eq!(output, vec!["$NAME".to_string()]);
+    }
+
+    #[test]
+    fn unquoted_expansion_is_split() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#"$SPACED"#).unwrap();
+        assert_eq!(output, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn quoted_expansion_is_not_split() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#""$SPACED""#).unwrap();
+        assert_eq!(output, vec!["a  b".to_string()]);
+    }
+
+    #[test]
+    fn unset_unquoted_expansion_removes_the_word() {
+        let state = state();
+        let output = Expander::new(&state).expand_word(r#"$UNSET"#).unwrap();
+        assert_eq!(output, Vec::<String>::new());
+
+        let output = Expander::new(&state).expand_word(r#""$UNSET""#).unwrap();
+        assert_eq!(output, vec!["".to_string()]);
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let state = state();
+        let expander = Expander::new(&state);
+
+        assert_eq!(
+            expander.expand_word("$1").unwrap(),
+            vec!["first".to_string()]
+        );
+        assert_eq!(expander.expand_word("$#").unwrap(), vec!["2".to_string()]);
+        assert_eq!(
+            expander.expand_word(r#""$@""#).unwrap(),
+            vec!["first".to_string(), "second arg".to_string()]
+        );
+        assert_eq!(
+            expander.expand_word(r#""$*""#).unwrap(),
+            vec!["first second arg".to_string()]
+        );
+    }
+
+    #[test]
+    fn quoted_all_params_without_params_is_empty() {
+        let state = ShellState::default();
+        let output = Expander::new(&state).expand_word(r#""$@""#).unwrap();
+        assert_eq!(output, Vec::<String>::new());
+    }
+
+    #[test]
+    fn random_changes_on_every_expansion() {
+        let state = state();
+        let expander = Expander::new(&state);
+
+        let values: Vec<u32> = (0..20)
+            .flat_map(|_| return expander.expand_words(&words(&["$RANDOM", r#""${RANDOM}""#])))
+            .flatten()
+            .map(|value| return value.parse().unwrap())
+            .collect();
+        assert_eq!(values.len(), 40);
+        assert!(values.iter().all(|value| return *value <= 32767));
+        assert!(values.windows(2).any(|pair| return pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn seconds_count_up_from_the_last_value() {
+        let mut state = state();
+        state.seconds.set(5);
+        assert_eq!(
+            Expander::new(&state).expand_word("$SECONDS").unwrap(),
+            vec!["5"]
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        assert_eq!(
+            Expander::new(&state).expand_word("$SECONDS").unwrap(),
+            vec!["6"]
+        );
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        return words.iter().map(|word| return word.to_string()).collect();
+    }
+
+    #[test]
+    fn braces_and_parameters() -> anyhow::Result<()> {
+        let state = state();
+        let expander = Expander::new(&state);
+
+        let output = expander.expand_words(&words(&["${NAME}{1,2}", "'{a,b}'"]))?;
+        assert_eq!(output, words(&["world1", "world2", "{a,b}"]));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn arithmetic_expansion() -> anyhow::Result<()> {
+        let mut state = state();
+        state.variables.set("x", "5");
+        let expander = Expander::new(&state);
+
+        let output =
+            expander.expand_words(&words(&["$(( 1 + 2 * 3 ))", "\"$((x * 2))\"", "n$((x-6))"]))?;
+        assert_eq!(output, words(&["7", "10", "n-1"]));
+
+        // Assignments are seen by the rest of the expansion and handed back after.
+        let output = expander.expand_words(&words(&["$((y = x + 1))", "$y"]))?;
+        assert_eq!(output, words(&["6", "6"]));
+        assert_eq!(
+            expander.take_assignments(),
+            vec![(String::from("y"), String::from("6"))]
+        );
+
+        assert!(expander.expand_words(&words(&["$((1 / 0))"])).is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn posix_mode_has_no_brace_expansion() -> anyhow::Result<()> {
+        let mut state = state();
+        state.options.set("posix", true);
+        let expander = Expander::new(&state);
+
+        let output = expander.expand_words(&words(&["${NAME}{1,2}", "{1..3}"]))?;
+        assert_eq!(output, words(&["world{1,2}", "{1..3}"]));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn splitting_follows_ifs() -> anyhow::Result<()> {
+        let mut state = state();
+        state.variables.set("LIST", " a, ,b,,c ");
+        state.variables.set("IFS", ", ");
+        let expander = Expander::new(&state);
+        assert_eq!(
+            expander.expand_words(&words(&["$LIST"]))?,
+            words(&["a", "", "b", "", "c"])
+        );
+        assert_eq!(
+            expander.expand_words(&words(&["\"$*\""]))?,
+            words(&["first,second arg"])
+        );
+
+        state.variables.set("IFS", "");
+        let expander = Expander::new(&state);
+        assert_eq!(
+            expander.expand_words(&words(&["$SPACED"]))?,
+            words(&["a  b"])
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn unquoted_globs_match_files() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("a.txt"), "")?;
+        std::fs::write(dir.path().join("b.txt"), "")?;
+
+        let state = state();
+        let expander = Expander::new(&state);
+        let output = in_dir(dir.path(), || {
+            return expander.expand_words(&words(&["*.txt", "'*.txt'", "*.csv"]));
+        })?;
+        assert_eq!(output, words(&["a.txt", "b.txt", "*.txt", "*.csv"]));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn unmatched_globs_follow_nullglob_and_failglob() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let patterns = words(&["echo", "*.nonexistent"]);
+
+        let mut state = state();
+        let expand = |state: &ShellState| {
+            return in_dir(dir.path(), || {
+                return Expander::new(state).expand_words(&patterns);
+            });
+        };
+        assert_eq!(expand(&state)?, words(&["echo", "*.nonexistent"]));
+
+        state.options.set_shopt("nullglob", true);
+        assert_eq!(expand(&state)?, words(&["echo"]));
+
+        state.options.set_shopt("failglob", true);
+        let error = expand(&state).unwrap_err();
+        assert_eq!(error.to_string(), "no match: *.nonexistent");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn oversized_expansion_is_an_error() {
+        let state = state();
+
+        let error = Expander::new(&state)
+            .expand_words(&words(&["{1..100000000}"]))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "argument list too long");
+
+        let error = Expander::new(&state)
+            .with_field_limit(5)
+            .expand_words(&words(&["{1..3}", "{1..3}"]))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "argument list too long");
+    }
+}
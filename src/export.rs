@@ -0,0 +1,142 @@
+use crate::{
+    command::CommandOutput,
+    state::ShellState,
+    variables::{is_name, split_assignment},
+};
+
+/// `export [-n] [-p] [name[=value] ...]`: marks variables for the environment of child
+/// processes, assigning them first when a value is given. `-n` takes the mark away
+/// and keeps the variable; without names lists the exported variables.
+pub fn export(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let mut unexport = false;
+    let mut names = args;
+    while let Some(flags) = names.first().and_then(|arg| return arg.strip_prefix('-')) {
+        names = &names[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'n' => unexport = true,
+                'p' => {}
+                _ => {
+                    return CommandOutput {
+                        stdout: None,
+                        stderr: Some(format!(
+                            "export: -{}: invalid option\nexport: usage: export [-n] [-p] [name[=value] ...]\n",
+                            flag
+                        )),
+                        status: 2,
+                    };
+                }
+            }
+        }
+    }
+
+    if names.is_empty() {
+        let stdout: String = state
+            .variables
+            .exported()
+            .iter()
+            .map(|(name, value)| return format!("declare -x {}=\"{}\"\n", name, escape(value)))
+            .collect();
+        return CommandOutput {
+            stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let mut stderr = String::new();
+    for arg in names {
+        let (name, value) = match split_assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None if is_name(arg) => (arg.as_str(), None),
+            None => {
+                stderr.push_str(&format!("export: `{}': not a valid identifier\n", arg));
+                continue;
+            }
+        };
+
+        if let Some(value) = value {
+            state.assign(name, value);
+        }
+        if unexport {
+            state.variables.unexport(name);
+        } else {
+            state.variables.export(name);
+        }
+    }
+
+    return CommandOutput {
+        stdout: None,
+        status: if stderr.is_empty() { 0 } else { 1 },
+        stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+    };
+}
+
+/// Escapes a value for the inside of double quotes.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for current_char in value.chars() {
+        if matches!(current_char, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(current_char);
+    }
+
+    return escaped;
+}
+
+#[cfg(test)]
+mod export_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn exports_and_lists_variables() {
+        let mut state = ShellState::default();
+        state.variables.set("LOCAL", "1");
+
+        assert_eq!(
+            export(&mut state, &args(&["LOCAL", "NEW=a \"b\""])).status,
+            0
+        );
+        assert_eq!(
+            export(&mut state, &[]).stdout,
+            Some(String::from(
+                "declare -x LOCAL=\"1\"\ndeclare -x NEW=\"a \\\"b\\\"\"\n"
+            ))
+        );
+
+        let output = export(&mut state, &args(&["1x=2", "OK"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("export: `1x=2': not a valid identifier\n"))
+        );
+        assert_eq!(output.status, 1);
+        assert_eq!(export(&mut state, &args(&["-z"])).status, 2);
+    }
+
+    #[test]
+    fn dash_n_keeps_the_variable() {
+        let mut state = ShellState::default();
+        export(&mut state, &args(&["NAME=value"]));
+
+        assert_eq!(export(&mut state, &args(&["-n", "NAME"])).status, 0);
+        assert_eq!(state.variables.get("NAME"), Some("value"));
+        assert!(state.variables.exported().is_empty());
+    }
+
+    #[test]
+    fn listing_quotes_spaces_and_quotes() {
+        let mut state = ShellState::default();
+        export(&mut state, &args(&["Q=it's a `b` $c"]));
+
+        assert_eq!(
+            export(&mut state, &[]).stdout,
+            Some(String::from("declare -x Q=\"it's a \\`b\\` \\$c\"\n"))
+        );
+    }
+}
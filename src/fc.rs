@@ -0,0 +1,76 @@
+use std::{fs, io::Write, process::Stdio};
+
+use anyhow::anyhow;
+
+use crate::{
+    executable::{Attached, ExecutablePathFinder, ExecutableRunner, Invocation},
+    state::ShellState,
+    temp,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum EditOutcome {
+    Edited(String),
+    /// The editor exited with a non-zero status; nothing should run.
+    Cancelled(i32),
+}
+
+/// The editor `fc` opens: `$FCEDIT`, then `$EDITOR`, then `vi`.
+pub fn editor(state: &ShellState) -> String {
+    return ["FCEDIT", "EDITOR"]
+        .iter()
+        .filter_map(|name| state.variables.get(name))
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or("vi")
+        .to_string();
+}
+
+/// Writes `command` to a temporary file, opens it in the editor attached to the
+/// terminal and returns the saved contents.
+pub fn edit(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    command: &str,
+) -> anyhow::Result<EditOutcome> {
+    let mut file = temp::file("fc-", ".sh")?;
+    writeln!(file, "{}", command)?;
+    file.flush()?;
+
+    // `EDITOR` may carry arguments, e.g. `code --wait`.
+    let editor = editor(state);
+    let mut words = editor.split_whitespace();
+    let name = words.next().ok_or(anyhow!("fc: no editor set"))?;
+    let path = file.path().display().to_string();
+    let args: Vec<&str> = words.chain([path.as_str()]).collect();
+
+    let exec_path = state.command_path(finder, name);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name,
+        path: &exec_path,
+        args: &args,
+        env: &env,
+    };
+    let attached = runner
+        .execute_attached(
+            &invocation,
+            Stdio::inherit(),
+            Stdio::inherit(),
+            Stdio::inherit(),
+        )
+        .map_err(|_| return anyhow!("fc: {}: command not found", name))?;
+    let status = match attached {
+        Attached::Exited(status) => status,
+        // A stopped editor is left as a job, and the edit is not run.
+        Attached::Stopped { child, signal } => {
+            state.jobs.add_stopped(vec![child], &editor);
+            128 + signal
+        }
+    };
+    if status != 0 {
+        return Ok(EditOutcome::Cancelled(status));
+    }
+
+    return Ok(EditOutcome::Edited(fs::read_to_string(file.path())?));
+}
@@ -0,0 +1,212 @@
+use crate::{command::CommandOutput, state::ShellState};
+
+/// Runs a single `getopts optstring name [args]` step over `args`, or over the
+/// positional parameters when no `args` are given. Mirrors bash: `OPTIND` holds the
+/// index of the next argument to inspect, `OPTARG` the option argument, and the
+/// status is non-zero once the options are exhausted.
+pub fn getopts(
+    state: &mut ShellState,
+    optstring: &str,
+    name: &str,
+    args: &[String],
+) -> CommandOutput {
+    let params = if args.is_empty() {
+        state.positional_params.clone()
+    } else {
+        args.to_vec()
+    };
+    let silent = optstring.starts_with(':');
+    let optstring = optstring.trim_start_matches(':');
+
+    let optind = state
+        .variables
+        .get("OPTIND")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let (cursor_optind, cursor_char_index) = state.getopts_cursor;
+    let mut char_index = if cursor_optind == optind {
+        cursor_char_index
+    } else {
+        0
+    };
+
+    let Some(arg) = params.get(optind - 1) else {
+        return finish(state, name, optind);
+    };
+
+    if char_index == 0 {
+        if arg == "--" {
+            return finish(state, name, optind + 1);
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            return finish(state, name, optind);
+        }
+        char_index = 1;
+    }
+
+    let chars: Vec<char> = arg.chars().collect();
+    let Some(&option) = chars.get(char_index) else {
+        return finish(state, name, optind + 1);
+    };
+    char_index += 1;
+    let at_word_end = char_index >= chars.len();
+
+    let (next_optind, next_char_index) = if at_word_end {
+        (optind + 1, 0)
+    } else {
+        (optind, char_index)
+    };
+
+    let spec_index = match option {
+        ':' => None,
+        _ => optstring.find(option),
+    };
+
+    let mut stderr = None;
+    let (next_optind, next_char_index) = match spec_index {
+        None => {
+            state.variables.set(name, "?");
+            if silent {
+                state.variables.set("OPTARG", &option.to_string());
+            } else {
+                state.variables.unset("OPTARG");
+                stderr = Some(format!("getopts: illegal option -- {}\n", option));
+            }
+
+            (next_optind, next_char_index)
+        }
+        Some(spec_index) if optstring[spec_index + 1..].starts_with(':') => {
+            if !at_word_end {
+                let value: String = chars[char_index..].iter().collect();
+                state.variables.set(name, &option.to_string());
+                state.variables.set("OPTARG", &value);
+                (optind + 1, 0)
+            } else if let Some(value) = params.get(optind) {
+                state.variables.set(name, &option.to_string());
+                state.variables.set("OPTARG", value);
+                (optind + 2, 0)
+            } else {
+                if silent {
+                    state.variables.set(name, ":");
+                    state.variables.set("OPTARG", &option.to_string());
+                } else {
+                    state.variables.set(name, "?");
+                    state.variables.unset("OPTARG");
+                    stderr = Some(format!(
+                        "getopts: option requires an argument -- {}\n",
+                        option
+                    ));
+                }
+                (optind + 1, 0)
+            }
+        }
+        Some(_) => {
+            state.variables.set(name, &option.to_string());
+            state.variables.unset("OPTARG");
+            (next_optind, next_char_index)
+        }
+    };
+
+    state.variables.set("OPTIND", &next_optind.to_string());
+    state.getopts_cursor = (next_optind, next_char_index);
+
+    return CommandOutput {
+        stdout: None,
+        stderr,
+        status: 0,
+    };
+}
+
+fn finish(state: &mut ShellState, name: &str, optind: usize) -> CommandOutput {
+    state.variables.set(name, "?");
+    state.variables.set("OPTIND", &optind.to_string());
+    state.getopts_cursor = (optind, 0);
+
+    return CommandOutput {
+        stdout: None,
+        stderr: None,
+        status: 1,
+    };
+}
+
+#[cfg(test)]
+mod getopts_tests {
+    use super::*;
+
+    fn state_with_params(params: &[&str]) -> ShellState {
+        let mut state = ShellState::default();
+        state.variables.set("OPTIND", "1");
+        state.positional_params = params.iter().map(|param| param.to_string()).collect();
+        return state;
+    }
+
+    #[test]
+    fn successive_calls() {
+        let mut state = state_with_params(&["-a", "-b", "val", "rest"]);
+
+        let output = getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(output.status, 0);
+        assert_eq!(state.variables.get("opt"), Some("a"));
+        assert_eq!(state.variables.get("OPTARG"), None);
+        assert_eq!(state.variables.get("OPTIND"), Some("2"));
+
+        let output = getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(output.status, 0);
+        assert_eq!(state.variables.get("opt"), Some("b"));
+        assert_eq!(state.variables.get("OPTARG"), Some("val"));
+        assert_eq!(state.variables.get("OPTIND"), Some("4"));
+
+        let output = getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(output.status, 1);
+        assert_eq!(state.variables.get("opt"), Some("?"));
+        assert_eq!(state.variables.get("OPTIND"), Some("4"));
+    }
+
+    #[test]
+    fn grouped_options_and_attached_argument() {
+        let mut state = state_with_params(&["-cbval"]);
+
+        getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(state.variables.get("opt"), Some("c"));
+        assert_eq!(state.variables.get("OPTIND"), Some("1"));
+
+        getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(state.variables.get("opt"), Some("b"));
+        assert_eq!(state.variables.get("OPTARG"), Some("val"));
+        assert_eq!(state.variables.get("OPTIND"), Some("2"));
+    }
+
+    #[test]
+    fn illegal_option() {
+        let mut state = state_with_params(&["-x"]);
+
+        let output = getopts(&mut state, "ab:c", "opt", &[]);
+        assert_eq!(output.status, 0);
+        assert_eq!(state.variables.get("opt"), Some("?"));
+        assert_eq!(
+            output.stderr,
+            Some("getopts: illegal option -- x\n".to_string())
+        );
+    }
+
+    #[test]
+    fn silent_missing_argument() {
+        let mut state = state_with_params(&["-b"]);
+
+        let output = getopts(&mut state, ":ab:c", "opt", &[]);
+        assert_eq!(output.status, 0);
+        assert_eq!(output.stderr, None);
+        assert_eq!(state.variables.get("opt"), Some(":"));
+        assert_eq!(state.variables.get("OPTARG"), Some("b"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let mut state = state_with_params(&["--", "-a"]);
+
+        let output = getopts(&mut state, "a", "opt", &[]);
+        assert_eq!(output.status, 1);
+        assert_eq!(state.variables.get("OPTIND"), Some("2"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{fs, path::Path};
+
+/// Characters with a special meaning in a pathname pattern.
+pub const GLOB_CHARS: &str = "*?[";
+
+/// The `shopt` settings that change how `glob` matches names.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GlobOptions {
+    /// `nocaseglob`: letters match regardless of case.
+    pub nocase: bool,
+    /// `dotglob`: names starting with `.` match without an explicit leading dot.
+    pub dotglob: bool,
+}
+
+/// Expands a pathname pattern against the file system. Backslash-escaped characters
+/// match literally, and names starting with `.` only match an explicit leading dot
+/// unless `dotglob` is set.
+/// Returns the sorted matches; empty when nothing matches.
+pub fn glob(pattern: &str, options: GlobOptions) -> Vec<String> {
+    let fold = |text: &str| -> Vec<char> {
+        match options.nocase {
+            true => return text.to_lowercase().chars().collect(),
+            false => return text.chars().collect(),
+        }
+    };
+
+    let (mut prefixes, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![String::from("/")], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let components: Vec<&str> = rest.split('/').collect();
+    for (index, component) in components.iter().enumerate() {
+        let is_last = index == components.len() - 1;
+        let mut next_prefixes = vec![];
+
+        for prefix in &prefixes {
+            if !has_magic(component) {
+                let path = format!("{}{}", prefix, unescape(component));
+                if !is_last {
+                    next_prefixes.push(format!("{}/", path));
+                } else if Path::new(&path).symlink_metadata().is_ok() {
+                    next_prefixes.push(path);
+                }
+                continue;
+            }
+
+            let dir = if prefix.is_empty() { "." } else { prefix };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+
+            let pattern = fold(component);
+            let explicit_dot = component.starts_with('.') || component.starts_with("\\.");
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // `read_dir` never lists `.` and `..`, so `dotglob` cannot match them.
+                if name.starts_with('.') && !explicit_dot && !options.dotglob {
+                    continue;
+                }
+
+                if !matches(&pattern, &fold(&name)) {
+                    continue;
+                }
+
+                let path = format!("{}{}", prefix, name);
+                if is_last {
+                    next_prefixes.push(path);
+                } else if entry.path().is_dir() {
+                    next_prefixes.push(format!("{}/", path));
+                }
+            }
+        }
+
+        prefixes = next_prefixes;
+    }
+
+    prefixes.sort();
+    return prefixes;
+}
+
+/// Whether `pattern` contains an unescaped glob character.
+fn has_magic(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '\\' => {
+                chars.next();
+            }
+            _ if GLOB_CHARS.contains(current_char) => return true,
+            _ => {}
+        }
+    }
+
+    return false;
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut output = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '\\' => output.extend(chars.next()),
+            _ => output.push(current_char),
+        }
+    }
+
+    return output;
+}
+
+/// Matches a whole name against a pattern with `*`, `?`, `[...]` and `\` escapes.
+pub fn matches(pattern: &[char], name: &[char]) -> bool {
+    let Some((&first, rest)) = pattern.split_first() else {
+        return name.is_empty();
+    };
+
+    match first {
+        '*' => return (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        '?' => return !name.is_empty() && matches(rest, &name[1..]),
+        '[' => {
+            if let Some((matched, rest)) = match_bracket(rest, name.first()) {
+                return matched && matches(rest, &name[1..]);
+            }
+        }
+        '\\' => {
+            if let Some((&escaped, rest)) = rest.split_first() {
+                return name.first() == Some(&escaped) && matches(rest, &name[1..]);
+            }
+        }
+        _ => {}
+    }
+
+    return name.first() == Some(&first) && matches(rest, &name[1..]);
+}
+
+/// Matches one character against a bracket expression whose `[` has already been
+/// consumed. Returns whether it matched and the pattern after the closing `]`, or
+/// `None` when the bracket is never closed (and is therefore a literal `[`).
+fn match_bracket<'a>(pattern: &'a [char], value: Option<&char>) -> Option<(bool, &'a [char])> {
+    let (negated, mut pattern) = match pattern.split_first() {
+        Some(('!' | '^', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let (&current_char, rest) = pattern.split_first()?;
+        if current_char == ']' && !first {
+            pattern = rest;
+            break;
+        }
+        first = false;
+
+        let (low, rest) = match (current_char, rest.split_first()) {
+            ('\\', Some((&escaped, rest))) => (escaped, rest),
+            _ => (current_char, rest),
+        };
+
+        match rest {
+            ['-', high, rest @ ..] if *high != ']' => {
+                matched |= value.is_some_and(|value| (low..=*high).contains(value));
+                pattern = rest;
+            }
+            _ => {
+                matched |= value == Some(&low);
+                pattern = rest;
+            }
+        }
+    }
+
+    return Some((value.is_some() && matched != negated, pattern));
+}
+
+#[cfg(test)]
+mod glob_tests {
+    use tempfile::TempDir;
+
+    use crate::test_support::in_dir;
+
+    use super::*;
+
+    fn is_match(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        return matches(&pattern, &name);
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(is_match("*.txt", "notes.txt"));
+        assert!(!is_match("*.txt", "notes.csv"));
+        assert!(is_match("n?tes*", "notes.txt"));
+        assert!(is_match("*", ""));
+        assert!(is_match(r"\*", "*"));
+        assert!(!is_match(r"\*", "a"));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(is_match("[abc]", "b"));
+        assert!(is_match("[a-c]x", "cx"));
+        assert!(!is_match("[!a-c]", "b"));
+        assert!(is_match("[^a-c]", "d"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("[", "["));
+    }
+
+    #[test]
+    fn matches_files() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::create_dir(dir.path().join("docs"))?;
+        fs::write(dir.path().join("docs").join("a.md"), "")?;
+        fs::write(dir.path().join("b.txt"), "")?;
+        fs::write(dir.path().join("a.txt"), "")?;
+        fs::write(dir.path().join(".hidden.txt"), "")?;
+
+        in_dir(dir.path(), || {
+            assert_eq!(
+                glob("*.txt", GlobOptions::default()),
+                vec!["a.txt", "b.txt"]
+            );
+            assert_eq!(glob(".*.txt", GlobOptions::default()), vec![".hidden.txt"]);
+            assert_eq!(glob("*/*.md", GlobOptions::default()), vec!["docs/a.md"]);
+            assert_eq!(glob("docs/?.md", GlobOptions::default()), vec!["docs/a.md"]);
+            assert_eq!(glob("*.csv", GlobOptions::default()), Vec::<String>::new());
+        });
+
+        let absolute = format!("{}/*.txt", dir.path().display());
+        assert_eq!(glob(&absolute, GlobOptions::default()).len(), 2);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn nocase_matches_any_case() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::write(dir.path().join("File.TXT"), "")?;
+        fs::write(dir.path().join("notes.txt"), "")?;
+        fs::write(dir.path().join("other.csv"), "")?;
+
+        let nocase = GlobOptions {
+            nocase: true,
+            ..Default::default()
+        };
+        in_dir(dir.path(), || {
+            assert_eq!(glob("*.txt", GlobOptions::default()), vec!["notes.txt"]);
+            assert_eq!(glob("*.TXT", GlobOptions::default()), vec!["File.TXT"]);
+            assert_eq!(glob("*.TXT", nocase), vec!["File.TXT", "notes.txt"]);
+            assert_eq!(glob("f[i]le.*", nocase), vec!["File.TXT"]);
+            assert_eq!(glob("*.CSV", nocase), vec!["other.csv"]);
+        });
+
+        return Ok(());
+    }
+
+    #[test]
+    fn dotglob_matches_dotfiles() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        fs::write(dir.path().join(".profile"), "")?;
+        fs::write(dir.path().join("notes"), "")?;
+
+        let dotglob = GlobOptions {
+            dotglob: true,
+            ..Default::default()
+        };
+        in_dir(dir.path(), || {
+            assert_eq!(glob("*", GlobOptions::default()), vec!["notes"]);
+            assert_eq!(glob("*", dotglob), vec![".profile", "notes"]);
+            assert_eq!(glob("?profile", dotglob), vec![".profile"]);
+            assert_eq!(glob(".*", dotglob), vec![".profile"]);
+        });
+
+        return Ok(());
+    }
+}
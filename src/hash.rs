@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, path::Path};
+
+use crate::executable::{is_executable, ExecutablePathFinder};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashedCommand {
+    pub path: String,
+    pub hits: usize,
+}
+
+/// Remembers where commands were found in `PATH` so later lookups skip the search.
+#[derive(Debug, Default, Clone)]
+pub struct HashTable {
+    entries: BTreeMap<String, HashedCommand>,
+}
+
+impl HashTable {
+    pub fn new() -> Self {
+        return Self {
+            entries: BTreeMap::new(),
+        };
+    }
+
+    /// The hashed path for `name`, if it still points at an executable.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self
+            .entries
+            .get(name)
+            .map(|command| command.path.as_str())
+            .filter(|path| is_executable(Path::new(path)));
+    }
+
+    /// Finds `name`, preferring the hashed path and falling back to a fresh `PATH`
+    /// search when it no longer exists. Every successful lookup counts as a hit.
+    pub fn resolve(
+        &mut self,
+        finder: &impl ExecutablePathFinder,
+        env_path: &str,
+        name: &str,
+    ) -> Option<String> {
+        if self.get(name).is_none() {
+            let path = finder.find_executable_path(env_path, name)?;
+            self.insert(name, &path);
+        }
+
+        let command = self.entries.get_mut(name)?;
+        command.hits += 1;
+        return Some(command.path.clone());
+    }
+
+    pub fn insert(&mut self, name: &str, path: &str) {
+        let command = HashedCommand {
+            path: path.to_string(),
+            hits: 0,
+        };
+        self.entries.insert(name.to_string(), command);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        return self.entries.remove(name).is_some();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &HashedCommand)> {
+        return self.entries.iter();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod hash_tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use tempfile::TempDir;
+
+    use crate::executable::PathFinder;
+
+    use super::*;
+
+    #[test]
+    fn resolve_hashes_and_counts_hits() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let tool = dir.path().join("tool");
+        fs::write(&tool, "#!/bin/sh\n")?;
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755))?;
+
+        let finder = PathFinder::new();
+        let env_path = dir.path().display().to_string();
+        let mut table = HashTable::new();
+        assert_eq!(table.get("tool"), None);
+
+        let expected = tool.display().to_string();
+        assert_eq!(
+            table.resolve(&finder, &env_path, "tool"),
+            Some(expected.clone())
+        );
+        assert_eq!(table.resolve(&finder, "", "tool"), Some(expected.clone()));
+        assert_eq!(table.get("tool"), Some(expected.as_str()));
+        assert_eq!(
+            table.iter().next().map(|(_, command)| command.hits),
+            Some(2)
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn stale_entries_are_searched_again() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let tool = dir.path().join("tool");
+        fs::write(&tool, "#!/bin/sh\n")?;
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755))?;
+
+        let finder = PathFinder::new();
+        let env_path = dir.path().display().to_string();
+        let mut table = HashTable::new();
+        table.insert("tool", "/nonexistent/tool");
+
+        assert_eq!(table.get("tool"), None);
+        assert_eq!(
+            table.resolve(&finder, &env_path, "tool"),
+            Some(tool.display().to_string())
+        );
+
+        return Ok(());
+    }
+}
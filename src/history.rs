@@ -0,0 +1,419 @@
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+
+use crate::{
+    command::CommandOutput,
+    state::ShellState,
+    tokenizer::{tokenize, Token},
+};
+
+/// Command lines entered at the interactive prompt, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    line: String,
+    /// When the line was entered, shown when `HISTTIMEFORMAT` is set.
+    time: SystemTime,
+}
+
+impl History {
+    pub fn new() -> Self {
+        return Self { entries: vec![] };
+    }
+
+    pub fn push(&mut self, line: &str) {
+        self.push_at(line, SystemTime::now());
+    }
+
+    pub fn push_at(&mut self, line: &str, time: SystemTime) {
+        self.entries.push(Entry {
+            line: line.to_string(),
+            time,
+        });
+    }
+
+    /// Adds an interactive line unless `histcontrol` (the colon-separated
+    /// `HISTCONTROL`) leaves it out: `ignorespace` skips lines starting with a blank,
+    /// `ignoredups` a repeat of the previous entry and `ignoreboth` either.
+    pub fn record(&mut self, line: &str, histcontrol: &str) {
+        let (mut ignore_space, mut ignore_dups) = (false, false);
+        for setting in histcontrol.split(':') {
+            match setting {
+                "ignorespace" => ignore_space = true,
+                "ignoredups" => ignore_dups = true,
+                "ignoreboth" => (ignore_space, ignore_dups) = (true, true),
+                _ => {}
+            }
+        }
+
+        if ignore_space && line.starts_with([' ', '\t']) {
+            return;
+        }
+        if ignore_dups && self.last() == Some(line) {
+            return;
+        }
+        self.push(line);
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        return self.entries.pop().map(|entry| entry.line);
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        return self.entries.last().map(|entry| entry.line.as_str());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        return self.entries.iter().map(|entry| &entry.line);
+    }
+
+    /// Every entry with the time it was entered.
+    pub fn timed(&self) -> impl Iterator<Item = (&str, SystemTime)> {
+        return self
+            .entries
+            .iter()
+            .map(|entry| return (entry.line.as_str(), entry.time));
+    }
+}
+
+/// Replaces history references in an interactive line before it is parsed:
+///
+/// - `!!` the previous command, `!N` entry `N`, `!-N` the `N`th entry back;
+/// - `!$` the last word of the previous command, `!^` its first argument, `!:N` its
+///   `N`th word and `!*` all of its arguments;
+/// - the same word designators after an event, as in `!!:2` or `!3:$`.
+///
+/// Nothing is expanded inside single quotes, after a backslash, or when `!` is
+/// followed by a blank, `=` or `(`. `None` when the line has no references.
+pub fn expand(line: &str, history: &History) -> anyhow::Result<Option<String>> {
+    let mut expanded = String::with_capacity(line.len());
+    let mut changed = false;
+    let mut inside_single_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '\\' if !inside_single_quotes => {
+                expanded.push(current_char);
+                expanded.extend(chars.next());
+                continue;
+            }
+            '\'' => inside_single_quotes = !inside_single_quotes,
+            '!' if !inside_single_quotes => {
+                let is_reference = chars
+                    .peek()
+                    .is_some_and(|next| !matches!(next, ' ' | '\t' | '=' | '(' | '"'));
+                if is_reference {
+                    let (reference, text) = take_reference(&mut chars);
+                    expanded.push_str(&resolve(&reference, &text, history)?);
+                    changed = true;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        expanded.push(current_char);
+    }
+
+    return Ok(changed.then_some(expanded));
+}
+
+/// Which entry a reference points at, and which of its words.
+struct Reference {
+    /// `None` is the previous command.
+    event: Option<isize>,
+    words: Option<String>,
+}
+
+/// Reads what follows a `!`, returning the reference and its text for errors.
+fn take_reference(
+    chars: &mut std::iter::Peekable<impl Iterator<Item = char>>,
+) -> (Reference, String) {
+    let mut text = String::from("!");
+    let mut event = None;
+
+    match chars.peek() {
+        Some('!') => {
+            text.extend(chars.next());
+        }
+        Some('$' | '^' | '*') => {
+            let designator = chars.next().unwrap_or_default();
+            text.push(designator);
+            let reference = Reference {
+                event: None,
+                words: Some(designator.to_string()),
+            };
+            return (reference, text);
+        }
+        Some(next) if next.is_ascii_digit() || *next == '-' => {
+            let mut number = String::new();
+            number.extend(chars.next());
+            while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                number.push(digit);
+            }
+            text.push_str(&number);
+            event = Some(number.parse().unwrap_or(0));
+        }
+        _ => {}
+    }
+
+    let mut words = None;
+    if chars.next_if_eq(&':').is_some() {
+        text.push(':');
+        let mut designator = String::new();
+        while let Some(next) = chars.next_if(|c| c.is_ascii_digit() || matches!(c, '$' | '^' | '*'))
+        {
+            designator.push(next);
+        }
+        text.push_str(&designator);
+        words = Some(designator);
+    }
+
+    return (Reference { event, words }, text);
+}
+
+fn resolve(reference: &Reference, text: &str, history: &History) -> anyhow::Result<String> {
+    let index = match reference.event {
+        None => history.len().checked_sub(1),
+        Some(number) if number < 0 => history.len().checked_sub(number.unsigned_abs()),
+        Some(number) => (number as usize).checked_sub(1),
+    };
+    let entry = index
+        .and_then(|index| return history.entries.get(index))
+        .map(|entry| return &entry.line)
+        .ok_or(anyhow!("{}: event not found", text))?;
+
+    let Some(designator) = &reference.words else {
+        return Ok(entry.clone());
+    };
+
+    let words: Vec<String> = match tokenize(entry) {
+        Ok(tokens) => tokens.iter().map(Token::to_string).collect(),
+        Err(_) => entry.split_whitespace().map(str::to_string).collect(),
+    };
+    let word = match designator.as_str() {
+        "$" => words.last(),
+        "^" => words.get(1),
+        "*" => return Ok(words.get(1..).unwrap_or_default().join(" ")),
+        number => number
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| return words.get(n)),
+    };
+
+    return word.cloned().ok_or(anyhow!("{}: bad word specifier", text));
+}
+
+/// `history [n]`: the last `n` entries (all by default), numbered from 1. When
+/// `HISTTIMEFORMAT` is set, each command is preceded by when it was entered, in that
+/// `strftime` format.
+pub fn history(state: &ShellState, args: &[String]) -> CommandOutput {
+    let count = match args.first() {
+        None => state.history.len(),
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                return CommandOutput {
+                    stdout: None,
+                    stderr: Some(format!("history: {}: numeric argument required\n", count)),
+                    status: 1,
+                };
+            }
+        },
+    };
+
+    let time_format = state
+        .variables
+        .get("HISTTIMEFORMAT")
+        .filter(|format| !format.is_empty());
+    let skip = state.history.len().saturating_sub(count);
+    let stdout: String = state
+        .history
+        .timed()
+        .enumerate()
+        .skip(skip)
+        .map(|(index, (line, time))| {
+            let time = time_format
+                .map(|format| return format_time(format, time))
+                .unwrap_or_default();
+            return format!("{:>5}  {}{}\n", index + 1, time, line);
+        })
+        .collect();
+
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// Formats `time`, in the local timezone, with `strftime`.
+#[cfg(unix)]
+fn format_time(format: &str, time: SystemTime) -> String {
+    let Ok(format) = std::ffi::CString::new(format) else {
+        return String::new();
+    };
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| return since.as_secs()) as libc::time_t;
+
+    let mut buffer = [0u8; 256];
+    // SAFETY: `localtime_r` fills `fields`; `strftime` writes at most `buffer.len()`
+    // bytes into `buffer` and returns how many, 0 when the result did not fit.
+    let length = unsafe {
+        let mut fields: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&seconds, &mut fields).is_null() {
+            return String::new();
+        }
+        libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            format.as_ptr(),
+            &fields,
+        )
+    };
+
+    return String::from_utf8_lossy(&buffer[..length]).to_string();
+}
+
+#[cfg(not(unix))]
+fn format_time(_format: &str, _time: SystemTime) -> String {
+    return String::new();
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    #[test]
+    fn lists_numbered_entries() {
+        let mut state = ShellState::default();
+        for line in ["echo one", "echo two", "history"] {
+            state.history.push(line);
+        }
+
+        let output = history(&state, &[]);
+        assert_eq!(
+            output.stdout,
+            Some(String::from(
+                "    1  echo one\n    2  echo two\n    3  history\n"
+            ))
+        );
+
+        let output = history(&state, &[String::from("2")]);
+        assert_eq!(
+            output.stdout,
+            Some(String::from("    2  echo two\n    3  history\n"))
+        );
+
+        assert_eq!(history(&state, &[String::from("x")]).status, 1);
+    }
+
+    #[test]
+    fn histtimeformat_prefixes_entries_with_their_time() {
+        let mut state = ShellState::default();
+        let entered = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        state.history.push_at("echo one", entered);
+        state.history.push_at("history", entered);
+
+        state.variables.set("HISTTIMEFORMAT", "%s %Y %% ");
+        assert_eq!(
+            history(&state, &[]).stdout,
+            Some(String::from(
+                "    1  1700000000 2023 % echo one\n    2  1700000000 2023 % history\n"
+            ))
+        );
+
+        state.variables.set("HISTTIMEFORMAT", "");
+        assert_eq!(
+            history(&state, &[String::from("1")]).stdout,
+            Some(String::from("    2  history\n"))
+        );
+    }
+
+    fn record_all(histcontrol: &str) -> Vec<String> {
+        let mut history = History::new();
+        for line in ["echo a", "echo a", " secret", "echo b", "echo a"] {
+            history.record(line, histcontrol);
+        }
+        return history.iter().cloned().collect();
+    }
+
+    #[test]
+    fn histcontrol_modes() {
+        assert_eq!(
+            record_all(""),
+            vec!["echo a", "echo a", " secret", "echo b", "echo a"]
+        );
+        assert_eq!(
+            record_all("ignorespace"),
+            vec!["echo a", "echo a", "echo b", "echo a"]
+        );
+        assert_eq!(
+            record_all("ignoredups"),
+            vec!["echo a", " secret", "echo b", "echo a"]
+        );
+        assert_eq!(record_all("ignoreboth"), vec!["echo a", "echo b", "echo a"]);
+        assert_eq!(
+            record_all("ignorespace:ignoredups"),
+            record_all("ignoreboth")
+        );
+    }
+
+    fn recorded(lines: &[&str]) -> History {
+        let mut history = History::new();
+        for line in lines {
+            history.push(line);
+        }
+        return history;
+    }
+
+    #[test]
+    fn word_designators_of_the_previous_command() -> anyhow::Result<()> {
+        let history = recorded(&["echo one", "cp 'a b' dir/ > log"]);
+        let expand = |line: &str| return expand(line, &history);
+
+        assert_eq!(expand("ls !$")?, Some(String::from("ls log")));
+        assert_eq!(expand("ls !^")?, Some(String::from("ls 'a b'")));
+        assert_eq!(expand("ls !:2")?, Some(String::from("ls dir/")));
+        assert_eq!(
+            expand("echo !*")?,
+            Some(String::from("echo 'a b' dir/ > log"))
+        );
+        assert_eq!(expand("!1:$ !!:0")?, Some(String::from("one cp")));
+        assert!(expand("ls !:9").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn events() -> anyhow::Result<()> {
+        let history = recorded(&["echo one", "echo two"]);
+        let expand = |line: &str| return expand(line, &history);
+
+        assert_eq!(expand("!!")?, Some(String::from("echo two")));
+        assert_eq!(
+            expand("!1 && !-1")?,
+            Some(String::from("echo one && echo two"))
+        );
+        assert_eq!(expand("!3").unwrap_err().to_string(), "!3: event not found");
+
+        assert_eq!(expand("echo hi")?, None);
+        assert_eq!(expand("echo '!!' \\!! ! a!=b")?, None);
+
+        return Ok(());
+    }
+}
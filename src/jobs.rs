@@ -0,0 +1,656 @@
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::{
+    io,
+    process::{Child, ExitStatus},
+};
+
+use crate::{
+    command::CommandOutput,
+    executable::{self, Attached},
+    state::ShellState,
+};
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    /// The process id of the job's first process, which leads its process group
+    /// under job control.
+    pub pid: u32,
+    /// The command line as typed, without the trailing `&`.
+    pub command: String,
+    /// Set while the job is stopped, e.g. by Ctrl-Z.
+    pub stopped: bool,
+    /// The job's processes, one per stage of a pipeline.
+    children: Vec<Child>,
+}
+
+impl Job {
+    /// Sends `SIGCONT` to each of the job's processes, which resumes a stopped job.
+    pub fn resume(&self) {
+        #[cfg(unix)]
+        for child in &self.children {
+            // SAFETY: sending a signal has no memory safety requirements.
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGCONT);
+            }
+        }
+    }
+
+    fn into_children(self) -> Vec<Child> {
+        return self.children;
+    }
+
+    fn state(&self) -> &'static str {
+        return if self.stopped { "Stopped" } else { "Running" };
+    }
+
+    /// How the command is shown in listings: running jobs end with ` &`.
+    fn shown_command(&self) -> String {
+        match self.stopped {
+            true => return self.command.clone(),
+            false => return format!("{} &", self.command),
+        }
+    }
+}
+
+/// Commands started in the background with `&`, and ones stopped in the foreground.
+#[derive(Debug, Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    /// The process id of the last job started, kept after it finishes (`$!`).
+    last_pid: Option<u32>,
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        return Self {
+            jobs: vec![],
+            last_pid: None,
+        };
+    }
+
+    /// An empty table for a subshell, which still knows the last job started.
+    pub fn subshell(&self) -> Self {
+        return Self {
+            jobs: vec![],
+            last_pid: self.last_pid,
+        };
+    }
+
+    pub fn last_pid(&self) -> Option<u32> {
+        return self.last_pid;
+    }
+
+    /// Adds a started job and returns its job number, one more than the highest in use.
+    pub fn add(&mut self, child: Child, command: &str) -> usize {
+        self.last_pid = Some(child.id());
+        let id = self.next_id();
+        self.push(id, vec![child], command, false);
+        return id;
+    }
+
+    /// Adds a foreground command or pipeline that was stopped, with the processes it
+    /// has left, and returns its job number.
+    pub fn add_stopped(&mut self, children: Vec<Child>, command: &str) -> usize {
+        let id = self.next_id();
+        self.push(id, children, command, true);
+        return id;
+    }
+
+    /// Waits for job `id` to finish or stop, and returns the status of its last
+    /// process, or 128 plus the signal that stopped it. A finished job leaves the
+    /// table; a stopped one is put back under its number, as the current job. `None`
+    /// when there is no such job.
+    pub fn wait_for(&mut self, id: usize) -> io::Result<Option<i32>> {
+        let Some(job) = self.remove(id) else {
+            return Ok(None);
+        };
+        let command = job.command.clone();
+
+        let mut status = 0;
+        let mut stopped = vec![];
+        for result in executable::wait_job(job.into_children())? {
+            match result {
+                Attached::Exited(exited) => status = exited,
+                Attached::Stopped { child, signal } => {
+                    status = 128 + signal;
+                    stopped.push(child);
+                }
+            }
+        }
+        if !stopped.is_empty() {
+            self.push(id, stopped, &command, true);
+        }
+
+        return Ok(Some(status));
+    }
+
+    fn next_id(&self) -> usize {
+        return self.jobs.iter().map(|job| return job.id).max().unwrap_or(0) + 1;
+    }
+
+    fn push(&mut self, id: usize, children: Vec<Child>, command: &str, stopped: bool) {
+        let Some(pid) = children.first().map(|child| return child.id()) else {
+            return;
+        };
+        self.jobs.push(Job {
+            id,
+            pid,
+            command: command.to_string(),
+            stopped,
+            children,
+        });
+    }
+
+    /// Records that a job was stopped or resumed.
+    pub fn set_stopped(&mut self, id: usize, stopped: bool) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| return job.id == id) {
+            job.stopped = stopped;
+        }
+    }
+
+    /// The `[N]+  Stopped    command` line printed when a job stops.
+    pub fn stopped_notice(&self, id: usize) -> Option<String> {
+        let job = self.get(id)?;
+        let is_current = self
+            .current()
+            .is_some_and(|current| return current.id == id);
+        return Some(format_job(job, is_current, job.state()) + &format!("{}\n", job.command));
+    }
+
+    pub fn has_stopped(&self) -> bool {
+        return self.jobs.iter().any(|job| return job.stopped);
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        return self.jobs.iter().find(|job| return job.id == id);
+    }
+
+    /// The most recently started job, marked `+` in listings.
+    pub fn current(&self) -> Option<&Job> {
+        return self.jobs.last();
+    }
+
+    /// The job started before the current one, marked `-` in listings.
+    pub fn previous(&self) -> Option<&Job> {
+        return self.jobs.iter().rev().nth(1);
+    }
+
+    /// Resolves a job spec such as `%2`, `%+` or `%sleep`.
+    pub fn find(&self, spec: &str) -> Option<&Job> {
+        match parse_job_spec(spec)? {
+            JobSpec::Current => return self.current(),
+            JobSpec::Previous => return self.previous(),
+            JobSpec::Number(id) => return self.get(id),
+            JobSpec::Prefix(prefix) => {
+                return self
+                    .jobs
+                    .iter()
+                    .rev()
+                    .find(|job| return job.command.starts_with(prefix));
+            }
+            JobSpec::Containing(text) => {
+                return self
+                    .jobs
+                    .iter()
+                    .rev()
+                    .find(|job| return job.command.contains(text));
+            }
+        }
+    }
+
+    /// Takes a job out of the table; its process keeps running.
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| return job.id == id)?;
+        return Some(self.jobs.remove(index));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        return self.jobs.iter();
+    }
+
+    /// Removes and returns the jobs whose processes have all exited, with the status
+    /// of the last one, or `None` if it couldn't be waited for.
+    pub fn reap(&mut self) -> Vec<(Job, Option<ExitStatus>)> {
+        let mut finished = vec![];
+        let mut index = 0;
+        while index < self.jobs.len() {
+            let mut status = Some(None);
+            for child in &mut self.jobs[index].children {
+                status = match (status, child.try_wait()) {
+                    (None, _) | (_, Ok(None)) => None,
+                    (Some(_), Ok(Some(exit))) => Some(Some(exit)),
+                    (Some(_), Err(_)) => Some(None),
+                };
+            }
+            match status {
+                Some(status) => finished.push((self.jobs.remove(index), status)),
+                None => index += 1,
+            }
+        }
+
+        return finished;
+    }
+
+    /// Reaps finished jobs and describes each one, e.g. `[1]+  Done    sleep 1`.
+    pub fn finished_notices(&mut self) -> Vec<String> {
+        let current_id = self.current().map(|job| return job.id);
+        return self
+            .reap()
+            .into_iter()
+            .map(|(job, status)| {
+                let status = describe_status(status);
+                return format_job(&job, current_id == Some(job.id), &status)
+                    + &format!("{}\n", job.command);
+            })
+            .collect();
+    }
+
+    /// `jobs`: one `[N]+  Running    command &` line per job.
+    pub fn list(&self) -> String {
+        let current_id = self.current().map(|job| return job.id);
+        return self
+            .jobs
+            .iter()
+            .map(|job| {
+                return format_job(job, current_id == Some(job.id), job.state())
+                    + &format!("{}\n", job.shown_command());
+            })
+            .collect();
+    }
+
+    /// `jobs -l`: the listing with each job's process id after its number.
+    pub fn list_long(&self) -> String {
+        let current_id = self.current().map(|job| return job.id);
+        return self
+            .jobs
+            .iter()
+            .map(|job| {
+                let marker = if current_id == Some(job.id) { '+' } else { '-' };
+                return format!(
+                    "[{}]{} {:>5} {:<24}{}\n",
+                    job.id,
+                    marker,
+                    job.pid,
+                    job.state(),
+                    job.shown_command()
+                );
+            })
+            .collect();
+    }
+}
+
+/// The ways a job can be named after `%`.
+#[derive(Debug, PartialEq)]
+pub enum JobSpec<'a> {
+    /// `%%`, `%+` or a lone `%`: the most recent job.
+    Current,
+    /// `%-`: the job before the current one.
+    Previous,
+    /// `%N`: job number `N`.
+    Number(usize),
+    /// `%string`: the most recent job whose command starts with `string`.
+    Prefix(&'a str),
+    /// `%?string`: the most recent job whose command contains `string`.
+    Containing(&'a str),
+}
+
+/// Reads a job spec, or `None` when `spec` does not start with `%`.
+pub fn parse_job_spec(spec: &str) -> Option<JobSpec<'_>> {
+    match spec.strip_prefix('%')? {
+        "" | "%" | "+" => return Some(JobSpec::Current),
+        "-" => return Some(JobSpec::Previous),
+        rest => {
+            if let Some(text) = rest.strip_prefix('?') {
+                return Some(JobSpec::Containing(text));
+            }
+            match rest.parse() {
+                Ok(id) => return Some(JobSpec::Number(id)),
+                Err(_) => return Some(JobSpec::Prefix(rest)),
+            }
+        }
+    }
+}
+
+/// `jobs [-l]`: lists the running jobs, with their process ids under `-l`.
+pub fn jobs(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let mut long = false;
+    for arg in args {
+        match arg.as_str() {
+            "-l" => long = true,
+            _ => {
+                return CommandOutput {
+                    stdout: None,
+                    stderr: Some(format!(
+                        "jobs: {}: invalid option\njobs: usage: jobs [-l]\n",
+                        arg
+                    )),
+                    status: 2,
+                };
+            }
+        }
+    }
+
+    let listing = match long {
+        true => state.jobs.list_long(),
+        false => state.jobs.list(),
+    };
+    return CommandOutput {
+        stdout: Some(listing).filter(|listing| return !listing.is_empty()),
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// `disown [-a] [jobspec ...]`: forgets jobs so the shell no longer reports or waits
+/// for them. Without arguments the current job; `-a` every job.
+pub fn disown(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    if args.iter().any(|arg| return arg == "-a") {
+        let ids: Vec<usize> = state.jobs.iter().map(|job| return job.id).collect();
+        for id in ids {
+            state.jobs.remove(id);
+        }
+        return CommandOutput {
+            stdout: None,
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let specs = match args {
+        [] => vec![String::from("%+")],
+        specs => specs.to_vec(),
+    };
+    let mut stderr = String::new();
+    for spec in &specs {
+        match state.jobs.find(spec).map(|job| return job.id) {
+            Some(id) => {
+                state.jobs.remove(id);
+            }
+            None if args.is_empty() => stderr.push_str("disown: current: no such job\n"),
+            None => stderr.push_str(&format!("disown: {}: no such job\n", spec)),
+        }
+    }
+
+    return CommandOutput {
+        stdout: None,
+        status: if stderr.is_empty() { 0 } else { 1 },
+        stderr: Some(stderr).filter(|stderr| return !stderr.is_empty()),
+    };
+}
+
+/// `bg [jobspec ...]`: resumes stopped jobs in the background, the current job when
+/// none is given, and prints each as `[N]+ command &`.
+pub fn bg(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let specs = match args {
+        [] => vec![String::from("%+")],
+        specs => specs.to_vec(),
+    };
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut status = 0;
+    for spec in &specs {
+        let Some(job) = state.jobs.find(spec) else {
+            match args.is_empty() {
+                true => stderr.push_str("bg: current: no such job\n"),
+                false => stderr.push_str(&format!("bg: {}: no such job\n", spec)),
+            }
+            status = 1;
+            continue;
+        };
+        if !job.stopped {
+            stderr.push_str(&format!("bg: job {} already in background\n", job.id));
+            continue;
+        }
+
+        job.resume();
+        let is_current = state
+            .jobs
+            .current()
+            .is_some_and(|current| return current.id == job.id);
+        let marker = if is_current { '+' } else { '-' };
+        stdout.push_str(&format!("[{}]{} {} &\n", job.id, marker, job.command));
+        let id = job.id;
+        state.jobs.set_stopped(id, false);
+    }
+
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| return !stdout.is_empty()),
+        stderr: Some(stderr).filter(|stderr| return !stderr.is_empty()),
+        status,
+    };
+}
+
+/// `wait [jobspec or pid ...]`: waits for each job given to finish and returns the
+/// status of the last one. Without arguments it waits for every job and returns 0.
+pub fn wait(state: &mut ShellState, args: &[String]) -> anyhow::Result<CommandOutput> {
+    if args.is_empty() {
+        let ids: Vec<usize> = state.jobs.iter().map(|job| return job.id).collect();
+        for id in ids {
+            state.jobs.wait_for(id)?;
+        }
+        return Ok(CommandOutput {
+            stdout: None,
+            stderr: None,
+            status: 0,
+        });
+    }
+
+    let mut stderr = String::new();
+    let mut status = 0;
+    for arg in args {
+        let pid = arg.parse::<u32>().ok();
+        let job = match pid {
+            Some(pid) => state.jobs.iter().find(|job| return job.pid == pid),
+            None => state.jobs.find(arg),
+        };
+        match job.map(|job| return job.id) {
+            Some(id) => status = state.jobs.wait_for(id)?.unwrap_or(127),
+            None => {
+                match pid {
+                    Some(pid) => stderr
+                        .push_str(&format!("wait: pid {} is not a child of this shell\n", pid)),
+                    None => stderr.push_str(&format!("wait: {}: no such job\n", arg)),
+                }
+                status = 127;
+            }
+        }
+    }
+
+    return Ok(CommandOutput {
+        stdout: None,
+        stderr: Some(stderr).filter(|stderr| return !stderr.is_empty()),
+        status,
+    });
+}
+
+/// A finished job's status as `jobs` shows it: `Done`, `Exit N`, or what killed it,
+/// e.g. `Killed` or `Terminated`.
+fn describe_status(status: Option<ExitStatus>) -> String {
+    let Some(status) = status else {
+        return String::from("Exit 1");
+    };
+    #[cfg(unix)]
+    if let Some(signal) = status.signal() {
+        return match signal {
+            libc::SIGHUP => String::from("Hangup"),
+            libc::SIGINT => String::from("Interrupt"),
+            libc::SIGKILL => String::from("Killed"),
+            libc::SIGTERM => String::from("Terminated"),
+            _ => match crate::signals::signal_name(signal) {
+                Some(name) => format!("SIG{}", name),
+                None => format!("Signal {}", signal),
+            },
+        };
+    }
+    return match status.code() {
+        Some(0) => String::from("Done"),
+        code => format!("Exit {}", code.unwrap_or(1)),
+    };
+}
+
+/// The `[N]+  Status` prefix shared by `jobs` and completion notices.
+pub fn format_job(job: &Job, is_current: bool, status: &str) -> String {
+    let marker = if is_current { '+' } else { '-' };
+    return format!("[{}]{}  {:<24}", job.id, marker, status);
+}
+
+#[cfg(all(test, unix))]
+mod jobs_tests {
+    use std::process::Command;
+
+    use crate::test_support::args;
+
+    use super::*;
+
+    fn spawn(seconds: &str) -> Child {
+        return Command::new("sleep").arg(seconds).spawn().unwrap();
+    }
+
+    #[test]
+    fn job_numbers_and_specs() {
+        let mut table = JobTable::new();
+        assert_eq!(table.add(spawn("5"), "sleep 5"), 1);
+        assert_eq!(table.add(spawn("5"), "sleep 5"), 2);
+
+        assert_eq!(table.find("%1").map(|job| return job.id), Some(1));
+        assert_eq!(table.find("%+").map(|job| return job.id), Some(2));
+        assert_eq!(table.find("%-").map(|job| return job.id), Some(1));
+        assert!(table.find("%3").is_none());
+        assert!(table.find("3").is_none());
+
+        assert_eq!(
+            table.list(),
+            "[1]-  Running                 sleep 5 &\n[2]+  Running                 sleep 5 &\n"
+        );
+
+        for child in table
+            .jobs
+            .iter_mut()
+            .flat_map(|job| return &mut job.children)
+        {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+        assert_eq!(
+            table.finished_notices().concat(),
+            "[1]-  Killed                  sleep 5\n[2]+  Killed                  sleep 5\n"
+        );
+        assert_eq!(table.iter().count(), 0);
+    }
+
+    #[test]
+    fn disowned_jobs_are_not_listed() {
+        let mut state = ShellState::default();
+        // Disowned jobs are left running, so they are kept short.
+        for _ in 0..3 {
+            state.jobs.add(spawn("1"), "sleep 1");
+        }
+
+        assert_eq!(disown(&mut state, &args(&["%1"])).status, 0);
+        assert_eq!(disown(&mut state, &[]).status, 0);
+        assert_eq!(
+            state.jobs.list(),
+            "[2]+  Running                 sleep 1 &\n"
+        );
+
+        let output = disown(&mut state, &args(&["%7"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("disown: %7: no such job\n"))
+        );
+        assert_eq!(output.status, 1);
+
+        assert_eq!(disown(&mut state, &args(&["-a"])).status, 0);
+        assert_eq!(state.jobs.list(), "");
+        assert_eq!(
+            disown(&mut state, &[]).stderr,
+            Some(String::from("disown: current: no such job\n"))
+        );
+    }
+
+    #[test]
+    fn wait_for_jobs_by_spec_or_pid() -> anyhow::Result<()> {
+        let exiting = |status: &str| {
+            return Command::new("sh")
+                .args(["-c", &format!("exit {}", status)])
+                .spawn()
+                .unwrap();
+        };
+        let mut state = ShellState::default();
+        state.jobs.add(exiting("3"), "sh -c 'exit 3'");
+        state.jobs.add(exiting("4"), "sh -c 'exit 4'");
+        let pid = state.jobs.last_pid().unwrap_or_default().to_string();
+
+        assert_eq!(wait(&mut state, &args(&["%?3"]))?.status, 3);
+        assert_eq!(wait(&mut state, &args(&[&pid]))?.status, 4);
+        assert_eq!(state.jobs.iter().count(), 0);
+
+        let output = wait(&mut state, &args(&["%1", "1"]))?;
+        assert_eq!(
+            output.stderr,
+            Some(String::from(
+                "wait: %1: no such job\nwait: pid 1 is not a child of this shell\n"
+            ))
+        );
+        assert_eq!(output.status, 127);
+
+        state.jobs.add(exiting("5"), "sh -c 'exit 5'");
+        state.jobs.add(spawn("0"), "sleep 0");
+        assert_eq!(wait(&mut state, &[])?.status, 0);
+        assert_eq!(state.jobs.iter().count(), 0);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn job_spec_forms() {
+        assert_eq!(parse_job_spec("%"), Some(JobSpec::Current));
+        assert_eq!(parse_job_spec("%%"), Some(JobSpec::Current));
+        assert_eq!(parse_job_spec("%+"), Some(JobSpec::Current));
+        assert_eq!(parse_job_spec("%-"), Some(JobSpec::Previous));
+        assert_eq!(parse_job_spec("%12"), Some(JobSpec::Number(12)));
+        assert_eq!(parse_job_spec("%sle"), Some(JobSpec::Prefix("sle")));
+        assert_eq!(parse_job_spec("%?ep 3"), Some(JobSpec::Containing("ep 3")));
+        assert_eq!(parse_job_spec("12"), None);
+
+        let mut table = JobTable::new();
+        table.add(spawn("3"), "sleep 3");
+        table.add(spawn("4"), "sleep 4");
+        table.add(Command::new("sleep").arg("5").spawn().unwrap(), "sleep 5");
+
+        let id = |spec: &str| return table.find(spec).map(|job| return job.id);
+        assert_eq!(id("%+"), Some(3));
+        assert_eq!(id("%-"), Some(2));
+        assert_eq!(id("%1"), Some(1));
+        assert_eq!(id("%sleep"), Some(3));
+        assert_eq!(id("%?3"), Some(1));
+        assert_eq!(id("%cat"), None);
+        assert_eq!(id("%?x"), None);
+
+        let pids: Vec<u32> = table.iter().map(|job| return job.pid).collect();
+        let mut state = ShellState {
+            jobs: table,
+            ..Default::default()
+        };
+        assert_eq!(
+            jobs(&mut state, &[String::from("-l")]).stdout,
+            Some(format!(
+                "[1]- {:>5} Running                 sleep 3 &\n[2]- {:>5} Running                 sleep 4 &\n[3]+ {:>5} Running                 sleep 5 &\n",
+                pids[0], pids[1], pids[2]
+            ))
+        );
+        assert_eq!(jobs(&mut state, &[String::from("-x")]).status, 2);
+
+        for child in state
+            .jobs
+            .jobs
+            .iter_mut()
+            .flat_map(|job| return &mut job.children)
+        {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+    }
+}
@@ -0,0 +1,49 @@
+#![allow(deprecated)]
+#![allow(dead_code)]
+#![allow(clippy::needless_return)]
+
+mod arithmetic;
+mod brace;
+mod builtins;
+mod cd;
+pub mod command;
+mod completion;
+mod conditional;
+mod dirs;
+mod echo;
+mod editor;
+pub mod executable;
+mod expansion;
+mod export;
+mod fc;
+mod getopts;
+mod glob;
+mod hash;
+pub mod history;
+mod jobs;
+mod local;
+mod nohup;
+pub mod options;
+mod pager;
+pub mod prompt;
+mod random;
+mod read;
+mod redirection;
+mod set;
+pub mod shell;
+mod shopt;
+mod signals;
+pub mod state;
+mod statement;
+mod substitution;
+pub mod syntax;
+mod temp;
+pub mod terminal;
+#[cfg(test)]
+mod test_support;
+mod timeout;
+pub mod tokenizer;
+mod unset;
+mod variables;
+
+pub use shell::Shell;
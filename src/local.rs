@@ -0,0 +1,96 @@
+use crate::{
+    command::CommandOutput,
+    state::ShellState,
+    variables::{is_name, split_assignment, Variable},
+};
+
+/// The variables one function call made local, with the values they shadow. These
+/// are put back when the call returns, so the caller never sees the local ones.
+#[derive(Debug, Default, Clone)]
+pub struct Scope {
+    shadowed: Vec<(String, Option<Variable>)>,
+}
+
+impl Scope {
+    fn declares(&self, name: &str) -> bool {
+        return self
+            .shadowed
+            .iter()
+            .any(|(shadowed, _)| return shadowed == name);
+    }
+}
+
+/// Starts the scope of a function call.
+pub fn enter(state: &mut ShellState) {
+    state.local_scopes.push(Scope::default());
+}
+
+/// Ends the innermost function call's scope, restoring what its locals shadowed.
+pub fn leave(state: &mut ShellState) {
+    let Some(scope) = state.local_scopes.pop() else {
+        return;
+    };
+    for (name, variable) in scope.shadowed.into_iter().rev() {
+        state.variables.restore(&name, variable);
+    }
+}
+
+/// `local [name[=value] ...]`: makes variables local to the running function. A name
+/// without a value starts out unset. Without names, lists the function's locals.
+pub fn local(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let Some(scope) = state.local_scopes.last() else {
+        return CommandOutput {
+            stdout: None,
+            stderr: Some(String::from("local: can only be used in a function\n")),
+            status: 1,
+        };
+    };
+
+    if args.is_empty() {
+        let stdout: String = scope
+            .shadowed
+            .iter()
+            .filter_map(|(name, _)| {
+                let value = state.variables.get(name)?;
+                return Some(format!("{}={}\n", name, value));
+            })
+            .collect();
+        return CommandOutput {
+            stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let mut stderr = String::new();
+    for arg in args {
+        let (name, value) = match split_assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None if is_name(arg) => (arg.as_str(), None),
+            None => {
+                stderr.push_str(&format!("local: `{}': not a valid identifier\n", arg));
+                continue;
+            }
+        };
+
+        let Some(scope) = state.local_scopes.last_mut() else {
+            break;
+        };
+        if !scope.declares(name) {
+            let shadowed = state.variables.variable(name).cloned();
+            scope.shadowed.push((name.to_string(), shadowed));
+            if value.is_none() {
+                state.variables.unset(name);
+            }
+        }
+        if let Some(value) = value {
+            state.assign(name, value);
+        }
+    }
+
+    return CommandOutput {
+        stdout: None,
+        status: if stderr.is_empty() { 0 } else { 1 },
+        stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+    };
+}
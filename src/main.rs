@@ -1,31 +1,215 @@
-#![allow(deprecated)]
-#![allow(dead_code)]
+#![allow(clippy::needless_return)]
 
-use std::io;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, IsTerminal},
+};
 
-use command::Command;
-use executable::{PathFinder, Runner};
-use prompt::{ConsolePrompter, Prompter};
-
-mod command;
-mod executable;
-mod prompt;
-mod redirection;
+use shell_starter_rust::{
+    command,
+    executable::{PathFinder, Runner},
+    history,
+    options::{self, Mode, Options},
+    prompt::{self, ConsolePrompter, PromptInfo, Prompter},
+    syntax, terminal, tokenizer, Shell,
+};
 
 fn main() -> anyhow::Result<()> {
-    let reader = io::stdin().lock();
-    let writer = io::stdout();
-    let mut prompter = ConsolePrompter::new(reader, writer);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match options::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let debug_parse =
+        options.debug_parse || std::env::var("SHELL_DEBUG").is_ok_and(|value| value == "1");
+    let crlf = std::env::var("SHELL_CRLF").is_ok_and(|value| value == "1");
+    let caret_notation = std::env::var("SHELL_CARET_NOTATION").is_ok_and(|value| value == "1");
+    let max_line_length = std::env::var("SHELL_MAX_LINE_LENGTH")
+        .ok()
+        .and_then(|value| return value.parse().ok())
+        .unwrap_or(prompt::DEFAULT_MAX_LINE_LENGTH);
 
-    let finder = PathFinder::new();
-    let runner = Runner::new();
+    if options.no_exec && options.mode != Mode::Version {
+        check_syntax(&options.mode);
+    }
+
+    match &options.mode {
+        Mode::Version => {
+            println!("{}", options::version());
+            return Ok(());
+        }
+        Mode::Command(command) => {
+            let prompter = ConsolePrompter::new(io::stdin().lock(), stdout(), io::stderr())
+                .with_crlf(crlf)
+                .with_caret_notation(caret_notation)
+                .with_attached(true)
+                .with_max_line_length(max_line_length);
+            let mut shell = new_shell(prompter, &options, debug_parse, false);
+            for line in command.lines() {
+                shell.run_line(line)?;
+                if let Some(status) = shell.exit_status() {
+                    shell.prompter.flush()?;
+                    std::process::exit(status);
+                }
+            }
+            shell.run_exit_hooks()?;
+            std::process::exit(shell.state.last_status);
+        }
+        Mode::Script(path) => {
+            let script = match File::open(path) {
+                Ok(script) => script,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), command::io_error_message(&e));
+                    std::process::exit(127);
+                }
+            };
+            let prompter = ConsolePrompter::new(io::stdin().lock(), stdout(), io::stderr())
+                .with_crlf(crlf)
+                .with_caret_notation(caret_notation)
+                .with_attached(true)
+                .with_max_line_length(max_line_length);
+            let mut shell = new_shell(prompter, &options, debug_parse, false);
+            shell.state.sources.push(path.display().to_string());
+            shell.run_script(BufReader::new(script))?;
+            if let Some(status) = shell.exit_status() {
+                shell.prompter.flush()?;
+                std::process::exit(status);
+            }
+            shell.run_exit_hooks()?;
+            std::process::exit(shell.state.last_status);
+        }
+        Mode::Stdin => {}
+    }
+
+    // Without a terminal (e.g. `echo 'echo hi' | shell`) commands are read line by line
+    // until the input ends, with no prompt.
+    let interactive = io::stdin().is_terminal();
+    if cfg!(unix) && interactive {
+        terminal::restore_on_panic();
+        terminal::keep_running_on_stop();
+    }
+    let prompter = ConsolePrompter::new(io::stdin().lock(), stdout(), io::stderr())
+        .with_line_editing(cfg!(unix) && interactive)
+        .with_crlf(crlf)
+        .with_caret_notation(caret_notation)
+        .with_attached(true)
+        .with_max_line_length(max_line_length);
+    let mut shell = new_shell(prompter, &options, debug_parse, cfg!(unix) && interactive);
+    shell.state.interactive = interactive;
+
+    if interactive && !options.quiet {
+        shell
+            .prompter
+            .prompt(&format!("{}\n", options::version()))?;
+    }
 
     loop {
-        prompter.prompt("$ ")?;
+        let notices = shell.reap_jobs()?;
+        // `exit` has already run the exit hooks.
+        if let Some(status) = shell.exit_status() {
+            shell.prompter.flush()?;
+            std::process::exit(status);
+        }
+        let state = &mut shell.state;
+        if interactive {
+            shell.prompter.prompt(&notices)?;
+            let ps1 = state.variables.get("PS1").unwrap_or(prompt::DEFAULT_PS1);
+            let ps1 = prompt::render_ps1(ps1, &PromptInfo::current(state));
+            shell.prompter.prompt_command(&ps1)?;
+        }
+
+        let input = match shell.prompter.read() {
+            Ok(Some(input)) => input,
+            Ok(None) => {
+                if !shell.may_exit()? {
+                    continue;
+                }
+                shell.run_exit_hooks()?;
+                std::process::exit(shell.state.last_status);
+            }
+            Err(e) if e.is::<prompt::LineTooLong>() => {
+                shell.prompter.error(&format!("{}\n", e))?;
+                state.last_status = 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let input = if interactive {
+            match history::expand(&input, &state.history) {
+                Ok(Some(expanded)) => {
+                    let echoed = tokenizer::echoed(&expanded);
+                    shell.prompter.prompt(&format!("{}\n", echoed))?;
+                    expanded
+                }
+                Ok(None) => input,
+                Err(e) => {
+                    shell.prompter.error(&format!("{}\n", e))?;
+                    state.last_status = 1;
+                    continue;
+                }
+            }
+        } else {
+            input
+        };
+
+        if interactive && !input.is_empty() {
+            let histcontrol = state.variables.get("HISTCONTROL").unwrap_or_default();
+            state.history.record(&input, histcontrol);
+        }
+        shell.run_line(&input)?;
+    }
+}
+
+/// The shell's stdout. Output is buffered and written out before input is read, so a
+/// run of small writes costs one system call rather than one each.
+fn stdout() -> BufWriter<io::Stdout> {
+    return BufWriter::new(io::stdout());
+}
+
+/// A shell for the real system, set up from the command-line options. Only an
+/// interactive shell runs foreground programs as jobs.
+fn new_shell<P: Prompter>(
+    prompter: P,
+    options: &Options,
+    debug_parse: bool,
+    job_control: bool,
+) -> Shell<P, PathFinder, Runner> {
+    let runner = Runner::new().with_job_control(job_control);
+    let mut shell = Shell::new(prompter, PathFinder::new(), runner).with_debug_parse(debug_parse);
+    shell.state.positional_params = options.positional_params.clone();
+    shell.state.options.set("posix", options.posix);
+    return shell;
+}
 
-        let input = prompter.read()?;
+/// `-n`: reads all of the input and reports its first syntax error with status 2, or
+/// exits with 0 when everything parses. Nothing is run.
+fn check_syntax(mode: &Mode) -> ! {
+    let (source, script) = match mode {
+        Mode::Command(command) => (Some(String::from("-c")), Ok(command.clone())),
+        Mode::Script(path) => (
+            Some(path.display().to_string()),
+            std::fs::read_to_string(path),
+        ),
+        Mode::Stdin | Mode::Version => (None, io::read_to_string(io::stdin())),
+    };
+    let script = match script {
+        Ok(script) => script,
+        Err(e) => {
+            let source = source.unwrap_or_else(|| return String::from("stdin"));
+            eprintln!("{}: {}", source, command::io_error_message(&e));
+            std::process::exit(127);
+        }
+    };
 
-        let command = input.parse::<Command>()?;
-        command.run(&mut prompter, &finder, &runner)?;
+    match (syntax::check(&script), source) {
+        (Ok(()), _) => std::process::exit(0),
+        (Err(e), Some(source)) => eprintln!("{}: {}", source, e),
+        (Err(e), None) => eprintln!("{}", e),
     }
+    std::process::exit(2);
 }
@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use crate::redirection::{OutputMode, Redirection, Source, Target};
+
+/// Where `nohup` sends output that would otherwise go to the terminal.
+pub const OUTPUT_FILE: &str = "nohup.out";
+
+/// Adds the redirections `nohup` makes so a program outlives its terminal: stdout on
+/// a terminal is appended to `nohup.out`, and stderr on a terminal follows stdout.
+/// Also returns the notice to print about it, if any.
+pub fn redirections(
+    mut redirections: Vec<Redirection>,
+    stdout_is_terminal: bool,
+    stderr_is_terminal: bool,
+) -> (Vec<Redirection>, Option<String>) {
+    let is_redirected = |source: Source| {
+        return redirections
+            .iter()
+            .any(|redirection| return redirection.source == source);
+    };
+    let stdout_is_terminal = stdout_is_terminal && !is_redirected(Source::Stdout);
+    let stderr_is_terminal = stderr_is_terminal && !is_redirected(Source::Stderr);
+
+    let mut notice = None;
+    if stdout_is_terminal {
+        redirections.push(Redirection {
+            source: Source::Stdout,
+            target: Target::File {
+                path: PathBuf::from(OUTPUT_FILE),
+                mode: OutputMode::Append,
+            },
+        });
+        notice = Some(format!("nohup: appending output to '{}'\n", OUTPUT_FILE));
+    }
+    if stderr_is_terminal {
+        redirections.push(Redirection {
+            source: Source::Stderr,
+            target: Target::Duplicate(Source::Stdout),
+        });
+        notice.get_or_insert_with(|| return String::from("nohup: redirecting stderr to stdout\n"));
+    }
+
+    return (redirections, notice);
+}
+
+/// Keeps `SIGHUP` ignored while alive. Programs started meanwhile inherit this, so
+/// they survive the terminal, or the shell, hanging up.
+pub struct HangupIgnored {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+#[cfg(unix)]
+pub fn ignore_hangup() -> HangupIgnored {
+    // SAFETY: swapping the disposition of a signal has no memory safety requirements.
+    let previous = unsafe { libc::signal(libc::SIGHUP, libc::SIG_IGN) };
+    return HangupIgnored { previous };
+}
+
+#[cfg(not(unix))]
+pub fn ignore_hangup() -> HangupIgnored {
+    return HangupIgnored {};
+}
+
+impl Drop for HangupIgnored {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores the disposition saved by `ignore_hangup`.
+        unsafe {
+            libc::signal(libc::SIGHUP, self.previous);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod nohup_tests {
+    use std::{fs, process::Command};
+
+    use tempfile::TempDir;
+
+    use crate::{command::CommandOutput, redirection, test_support::in_dir};
+
+    use super::*;
+
+    #[test]
+    fn terminal_output_is_appended_to_nohup_out() -> anyhow::Result<()> {
+        let dir = TempDir::new()?;
+        let output = CommandOutput {
+            stdout: Some(String::from("out\n")),
+            stderr: Some(String::from("err\n")),
+            status: 0,
+        };
+
+        let (added, notice) = redirections(vec![], true, true);
+        assert_eq!(
+            notice,
+            Some(String::from("nohup: appending output to 'nohup.out'\n"))
+        );
+        in_dir(dir.path(), || -> anyhow::Result<()> {
+            fs::write(OUTPUT_FILE, "earlier\n")?;
+            let terminal_output = redirection::apply(&added, &output)?;
+            assert_eq!(terminal_output.stdout, None);
+            assert_eq!(terminal_output.stderr, None);
+            return Ok(());
+        })?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join(OUTPUT_FILE))?,
+            "earlier\nout\nerr\n"
+        );
+
+        // Already redirected, or not on a terminal: nothing to add.
+        let file = dir.path().join("file");
+        let redirected = Redirection::new(vec![String::from(">"), file.display().to_string()])?;
+        assert_eq!(redirections(vec![redirected], true, false).0.len(), 1);
+        assert_eq!(redirections(vec![], false, false), (vec![], None));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn programs_started_meanwhile_ignore_sighup() -> anyhow::Result<()> {
+        let script = "kill -HUP $$; echo survived";
+        let ignored = ignore_hangup();
+        let output = Command::new("sh").args(["-c", script]).output()?;
+        drop(ignored);
+        assert_eq!(String::from_utf8(output.stdout)?, "survived\n");
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+/// Where the shell reads its commands from.
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// Standard input, prompting when it is a terminal.
+    Stdin,
+    /// `-c 'commands'`.
+    Command(String),
+    /// `shell script.sh`.
+    Script(PathBuf),
+    /// `--version`: print the version and exit without reading commands.
+    Version,
+}
+
+/// The command-line arguments the shell was started with.
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub mode: Mode,
+    pub debug_parse: bool,
+    /// `--quiet`: no startup banner.
+    pub quiet: bool,
+    /// `--posix`: start with `set -o posix`.
+    pub posix: bool,
+    /// `-n`: check the commands for syntax errors without running them.
+    pub no_exec: bool,
+    /// Arguments after the command string or script, seen as `$1`, `$2`, ...
+    pub positional_params: Vec<String>,
+}
+
+/// `shell-starter-rust 0.1.0`, for `--version` and the startup banner.
+pub fn version() -> String {
+    return format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+}
+
+/// Parses the arguments after the program name. Options come first; the first other
+/// word is the script to run.
+pub fn parse(args: &[String]) -> anyhow::Result<Options> {
+    let mut options = Options {
+        mode: Mode::Stdin,
+        debug_parse: false,
+        quiet: false,
+        posix: false,
+        no_exec: false,
+        positional_params: vec![],
+    };
+
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        index += 1;
+        match arg.as_str() {
+            "--version" => {
+                options.mode = Mode::Version;
+                return Ok(options);
+            }
+            "--quiet" => options.quiet = true,
+            "--posix" => options.posix = true,
+            "--debug-parse" => options.debug_parse = true,
+            "-n" => options.no_exec = true,
+            "-c" => {
+                let command = args
+                    .get(index)
+                    .ok_or(anyhow!("-c: option requires an argument"))?;
+                options.mode = Mode::Command(command.to_string());
+                // As in other shells, the word after the command string is `$0`.
+                options.positional_params = args.iter().skip(index + 2).cloned().collect();
+                return Ok(options);
+            }
+            "--" => break,
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(anyhow!("{}: invalid option", option));
+            }
+            _ => {
+                index -= 1;
+                break;
+            }
+        }
+    }
+
+    if let Some(script) = args.get(index) {
+        options.mode = Mode::Script(PathBuf::from(script));
+        options.positional_params = args[index + 1..].to_vec();
+    }
+
+    return Ok(options);
+}
+
+#[cfg(test)]
+mod options_tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> anyhow::Result<Options> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return parse(&args);
+    }
+
+    #[test]
+    fn modes() -> anyhow::Result<()> {
+        assert_eq!(parse_args(&[])?.mode, Mode::Stdin);
+        assert_eq!(parse_args(&["--quiet", "--version"])?.mode, Mode::Version);
+
+        let options = parse_args(&["-c", "echo $1", "name", "arg"])?;
+        assert_eq!(options.mode, Mode::Command(String::from("echo $1")));
+        assert_eq!(options.positional_params, vec![String::from("arg")]);
+
+        let options = parse_args(&["--posix", "--debug-parse", "script.sh", "-x"])?;
+        assert_eq!(options.mode, Mode::Script(PathBuf::from("script.sh")));
+        assert_eq!(options.positional_params, vec![String::from("-x")]);
+        assert!(options.debug_parse);
+        assert!(options.posix);
+
+        let options = parse_args(&["-n", "-c", "echo"])?;
+        assert_eq!(options.mode, Mode::Command(String::from("echo")));
+        assert!(options.no_exec);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn invalid_options() {
+        assert!(parse_args(&["-c"]).is_err());
+        assert!(parse_args(&["--nope"]).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    process::Stdio,
+};
+
+use crate::{
+    executable::{ExecutablePathFinder, ExecutableRunner, Invocation},
+    state::ShellState,
+    terminal,
+};
+
+/// Whether builtin output should go through `$PAGER`: only on a terminal, when it
+/// does not fit on one screen, and unless `SHELL_PAGER=0` turns paging off.
+pub fn should_page(
+    state: &ShellState,
+    output: &str,
+    is_terminal: bool,
+    height: Option<usize>,
+) -> bool {
+    if !is_terminal || state.variables.get("SHELL_PAGER") == Some("0") {
+        return false;
+    }
+
+    return height.is_some_and(|height| output.lines().count() >= height);
+}
+
+/// Pages `output` when the shell's stdout is a terminal it overflows. Returns `false`
+/// when the output still has to be printed.
+pub fn page(
+    state: &mut ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+    output: &str,
+) -> bool {
+    if !should_page(
+        state,
+        output,
+        io::stdout().is_terminal(),
+        terminal::height(),
+    ) {
+        return false;
+    }
+
+    let pager = state
+        .variables
+        .get("PAGER")
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or("less")
+        .to_string();
+    let mut words = pager.split_whitespace();
+    let Some(name) = words.next() else {
+        return false;
+    };
+    let args: Vec<&str> = words.collect();
+
+    let path = state.command_path(finder, name);
+    let env = state.variables.exported();
+    let invocation = Invocation {
+        name,
+        path: &path,
+        args: &args,
+        env: &env,
+    };
+    let Ok(mut child) = runner.spawn(
+        &invocation,
+        Stdio::piped(),
+        Stdio::inherit(),
+        Stdio::inherit(),
+    ) else {
+        return false;
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may quit before reading everything.
+        let _ = stdin.write_all(output.as_bytes());
+    }
+    let _ = child.wait();
+
+    return true;
+}
+
+#[cfg(test)]
+mod pager_tests {
+    use super::*;
+
+    #[test]
+    fn pages_long_terminal_output_only() {
+        let mut state = ShellState::default();
+        let long = "line\n".repeat(50);
+
+        assert!(should_page(&state, &long, true, Some(24)));
+        assert!(!should_page(&state, "short\n", true, Some(24)));
+        assert!(!should_page(&state, &long, false, Some(24)));
+        assert!(!should_page(&state, &long, true, None));
+
+        state.variables.set("SHELL_PAGER", "0");
+        assert!(!should_page(&state, &long, true, Some(24)));
+    }
+}
@@ -1,33 +1,808 @@
-use std::io;
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, IsTerminal, Read},
+    time::Duration,
+};
+
+use crate::{completion, dirs::abbreviate_home, editor, state::ShellState, terminal::RawMode};
 
 pub trait Prompter {
-    fn read(&mut self) -> anyhow::Result<String>;
+    /// Reads the next line of input, or `None` once the input has ended.
+    fn read(&mut self) -> anyhow::Result<Option<String>>;
+    /// Like `read`, but fails with `ReadTimedOut` when no input arrives in `timeout`.
+    /// Prompters that cannot wait with a deadline just read.
+    fn read_timeout(&mut self, _timeout: Duration) -> anyhow::Result<Option<String>> {
+        return self.read();
+    }
     fn prompt(&mut self, prompt: &str) -> anyhow::Result<()>;
+    /// Writes the command prompt, whose visible width may differ from its length.
+    fn prompt_command(&mut self, prompt: &RenderedPrompt) -> anyhow::Result<()> {
+        return self.prompt(&prompt.text);
+    }
+    fn error(&mut self, message: &str) -> anyhow::Result<()>;
+    /// Writes a program's output as the bytes it wrote. Prompters that only keep text
+    /// show invalid UTF-8 as replacement characters.
+    fn write_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
+        return self.prompt(&String::from_utf8_lossy(output));
+    }
+    /// Like `write_bytes`, for what a program wrote to its stderr.
+    fn error_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
+        return self.error(&String::from_utf8_lossy(output));
+    }
+    /// Writes out any output still held back. Called before something else writes to
+    /// the same place, like a program given the terminal, or the shell exiting.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        return Ok(());
+    }
+    /// Whether output goes straight to the shell's own stdout and stderr, so a
+    /// foreground program can be handed them (and the terminal) instead of having
+    /// its output captured.
+    fn is_attached(&self) -> bool {
+        return false;
+    }
 }
 
-pub struct ConsolePrompter<R: io::BufRead, W: io::Write> {
+/// The longest input line read when `SHELL_MAX_LINE_LENGTH` is not set: 1 MiB.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 1 << 20;
+
+/// An input line was longer than the prompter accepts; it was skipped.
+#[derive(Debug, thiserror::Error)]
+#[error("input line too long")]
+pub struct LineTooLong;
+
+/// No input arrived before a timed read's deadline.
+#[derive(Debug, thiserror::Error)]
+#[error("timed out waiting for input")]
+pub struct ReadTimedOut;
+
+pub struct ConsolePrompter<R: io::BufRead, W: io::Write, E: io::Write> {
     reader: R,
     writer: W,
+    error_writer: E,
+    line_editing: bool,
+    /// The last text written before reading, redrawn after listing completions.
+    last_prompt: String,
+    /// Columns taken by the last line of `last_prompt`.
+    last_prompt_width: usize,
+    /// Write `\r\n` for every bare `\n` (`SHELL_CRLF=1`).
+    crlf: bool,
+    /// Show control characters other than newline and tab as `^X`
+    /// (`SHELL_CARET_NOTATION=1`).
+    caret_notation: bool,
+    /// `writer` and `error_writer` are the process's stdout and stderr.
+    attached: bool,
+    /// Longer lines fail with `LineTooLong` instead of being read into memory.
+    max_line_length: usize,
 }
 
-impl<R: io::BufRead, W: io::Write> Prompter for ConsolePrompter<R, W> {
-    fn read(&mut self) -> anyhow::Result<String> {
-        let mut input = String::new();
-        self.reader.read_line(&mut input)?;
+impl<R: io::BufRead, W: io::Write, E: io::Write> Prompter for ConsolePrompter<R, W, E> {
+    fn read(&mut self) -> anyhow::Result<Option<String>> {
+        // The prompt, and whatever came before it, must be visible before input.
+        self.writer.flush()?;
+        if self.line_editing {
+            let _raw_mode = RawMode::enable()?;
+            let cwd = std::env::current_dir()?;
+            let env_path = std::env::var("PATH").unwrap_or_default();
+            let complete = |line: &str| return completion::complete(line, &cwd, &env_path);
 
-        return Ok(input.trim().to_string());
+            let input = editor::read_line(
+                &mut self.reader,
+                &mut self.writer,
+                &self.last_prompt,
+                self.last_prompt_width,
+                self.max_line_length,
+                &complete,
+            )?;
+            return Ok(input.map(|input| input.trim_end().to_string()));
+        }
+
+        let mut input = vec![];
+        let limit = self.max_line_length as u64 + 1;
+        if (&mut self.reader)
+            .take(limit)
+            .read_until(b'\n', &mut input)?
+            == 0
+        {
+            return Ok(None);
+        }
+        if !input.ends_with(b"\n") && input.len() > self.max_line_length {
+            skip_line(&mut self.reader)?;
+            return Err(LineTooLong.into());
+        }
+
+        let input = String::from_utf8(input).map_err(|_| {
+            return io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            );
+        })?;
+        return Ok(Some(input.trim_end().to_string()));
+    }
+
+    fn read_timeout(&mut self, timeout: Duration) -> anyhow::Result<Option<String>> {
+        // Only a terminal is waited on: from a pipe or file the line may already be
+        // held in the reader's buffer, where polling the descriptor cannot see it.
+        if self.attached && io::stdin().is_terminal() {
+            self.writer.flush()?;
+            if !stdin_ready(timeout)? {
+                return Err(ReadTimedOut.into());
+            }
+        }
+
+        return self.read();
     }
 
     fn prompt(&mut self, prompt: &str) -> anyhow::Result<()> {
-        write!(self.writer, "{}", prompt)?;
+        write!(self.writer, "{}", self.translate(prompt))?;
+        self.remember_prompt(prompt);
+
+        return Ok(());
+    }
+
+    fn prompt_command(&mut self, prompt: &RenderedPrompt) -> anyhow::Result<()> {
+        // The prompt's own escape sequences (colors, titles) are meant for the terminal.
+        write!(
+            self.writer,
+            "{}",
+            self.line_endings(Cow::Borrowed(&prompt.text))
+        )?;
+        self.remember_prompt(&prompt.text);
+        self.last_prompt_width = prompt.visible_width;
+
+        return Ok(());
+    }
+
+    fn error(&mut self, message: &str) -> anyhow::Result<()> {
+        // Keeps errors in order with the output written before them.
+        self.writer.flush()?;
+        write!(self.error_writer, "{}", self.translate(message))?;
+        self.error_writer.flush()?;
+
+        return Ok(());
+    }
+
+    fn write_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
+        write_lines(&mut self.writer, output, self.crlf)?;
+        self.remember_prompt(&String::from_utf8_lossy(output));
+
+        return Ok(());
+    }
+
+    fn error_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
         self.writer.flush()?;
+        write_lines(&mut self.error_writer, output, self.crlf)?;
+        self.error_writer.flush()?;
 
         return Ok(());
     }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        return Ok(());
+    }
+
+    fn is_attached(&self) -> bool {
+        // A program writing to the terminal itself would skip the `\r\n` translation.
+        return self.attached && !self.crlf;
+    }
+}
+
+impl<R: io::BufRead, W: io::Write, E: io::Write> ConsolePrompter<R, W, E> {
+    pub fn new(reader: R, writer: W, error_writer: E) -> Self {
+        return ConsolePrompter {
+            reader,
+            writer,
+            error_writer,
+            line_editing: false,
+            last_prompt: String::new(),
+            last_prompt_width: 0,
+            crlf: false,
+            caret_notation: false,
+            attached: false,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+        };
+    }
+
+    /// Reads input through the raw-mode line editor (Tab completion) instead of
+    /// plain buffered lines. Only meaningful when stdin is a terminal.
+    pub fn with_line_editing(mut self, line_editing: bool) -> Self {
+        self.line_editing = line_editing;
+        return self;
+    }
+
+    /// Ends every line written to the terminal with `\r\n`. Redirected output is
+    /// written elsewhere and stays byte-exact.
+    pub fn with_crlf(mut self, crlf: bool) -> Self {
+        self.crlf = crlf;
+        return self;
+    }
+
+    /// Writes control characters in the shell's own output as `^X` (`^[` for escape,
+    /// `^?` for delete), so stray bytes cannot change the terminal's state. Redirected
+    /// output is written elsewhere and stays byte-exact.
+    pub fn with_caret_notation(mut self, caret_notation: bool) -> Self {
+        self.caret_notation = caret_notation;
+        return self;
+    }
+
+    /// Marks the writers as the process's own stdout and stderr, which programs run
+    /// in the foreground then inherit.
+    pub fn with_attached(mut self, attached: bool) -> Self {
+        self.attached = attached;
+        return self;
+    }
+
+    /// Caps how many bytes a line of input may have.
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        return self;
+    }
+
+    /// Keeps the text just written, to redraw it after listing completions.
+    fn remember_prompt(&mut self, prompt: &str) {
+        self.last_prompt = prompt.to_string();
+        self.last_prompt_width = match prompt.rsplit_once('\n') {
+            Some((_, last_line)) => last_line.chars().count(),
+            None => prompt.chars().count(),
+        };
+    }
+
+    fn translate<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let text = match self.caret_notation {
+            true => caret_notation(text),
+            false => Cow::Borrowed(text),
+        };
+        return self.line_endings(text);
+    }
+
+    fn line_endings<'a>(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+        if !self.crlf || !text.contains('\n') {
+            return text;
+        }
+
+        let mut translated = String::with_capacity(text.len());
+        let mut previous = None;
+        for current_char in text.chars() {
+            if current_char == '\n' && previous != Some('\r') {
+                translated.push('\r');
+            }
+            translated.push(current_char);
+            previous = Some(current_char);
+        }
+
+        return Cow::Owned(translated);
+    }
+}
+
+/// Writes `output` as is, but for ending its lines with `\r\n` when `crlf` is set.
+fn write_lines(writer: &mut impl io::Write, output: &[u8], crlf: bool) -> io::Result<()> {
+    for line in output.split_inclusive(|byte| return *byte == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(text) if crlf && !text.ends_with(b"\r") => {
+                writer.write_all(text)?;
+                writer.write_all(b"\r\n")?;
+            }
+            _ => writer.write_all(line)?,
+        }
+    }
+
+    return Ok(());
+}
+
+/// `text` with each control character but newline and tab written as `^` and the
+/// character 64 places on (`\r` is `^M`, escape `^[`), and delete as `^?`.
+fn caret_notation(text: &str) -> Cow<'_, str> {
+    let is_shown = |c: char| return c.is_ascii_control() && c != '\n' && c != '\t';
+    if !text.contains(is_shown) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut shown = String::with_capacity(text.len());
+    for current_char in text.chars() {
+        match current_char {
+            '\x7f' => shown.push_str("^?"),
+            c if is_shown(c) => {
+                shown.push('^');
+                shown.push((c as u8 + 64) as char);
+            }
+            c => shown.push(c),
+        }
+    }
+
+    return Cow::Owned(shown);
+}
+
+/// Waits up to `timeout` for the shell's stdin to have input, or to reach its end.
+#[cfg(unix)]
+fn stdin_ready(timeout: Duration) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let milliseconds = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: `poll_fd` is a valid, initialized array of one entry.
+    let ready = unsafe { libc::poll(&mut poll_fd, 1, milliseconds) };
+    if ready < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    return Ok(ready > 0);
+}
+
+#[cfg(not(unix))]
+fn stdin_ready(_timeout: Duration) -> io::Result<bool> {
+    return Ok(true);
+}
+
+/// Discards the rest of the current line, through its newline, without keeping it.
+fn skip_line(reader: &mut impl io::BufRead) -> io::Result<()> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        match buffer.iter().position(|byte| return *byte == b'\n') {
+            Some(index) => {
+                reader.consume(index + 1);
+                return Ok(());
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+/// The prompt shown when `PS1` is not set.
+pub const DEFAULT_PS1: &str = "$ ";
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Wall-clock time in the local timezone, as far as `PS1` needs it.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalTime {
+    /// 0 (January) to 11.
+    pub month: usize,
+    pub day: u32,
+    /// 0 (Sunday) to 6.
+    pub weekday: usize,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl LocalTime {
+    #[cfg(unix)]
+    pub fn now() -> Self {
+        let mut time: libc::tm = unsafe { std::mem::zeroed() };
+        let seconds = unsafe { libc::time(std::ptr::null_mut()) };
+        if unsafe { libc::localtime_r(&seconds, &mut time) }.is_null() {
+            return Self::default();
+        }
+
+        return Self {
+            month: time.tm_mon as usize,
+            day: time.tm_mday as u32,
+            weekday: time.tm_wday as usize,
+            hour: time.tm_hour as u32,
+            minute: time.tm_min as u32,
+            second: time.tm_sec as u32,
+        };
+    }
+
+    #[cfg(not(unix))]
+    pub fn now() -> Self {
+        return Self::default();
+    }
 }
 
-impl<R: io::BufRead, W: io::Write> ConsolePrompter<R, W> {
-    pub fn new(reader: R, writer: W) -> Self {
-        return ConsolePrompter { reader, writer };
+/// Everything `PS1` escapes expand to, gathered once per prompt.
+#[derive(Debug, Default)]
+pub struct PromptInfo {
+    /// The full hostname; `\h` shows it up to the first `.`.
+    pub hostname: String,
+    pub user: String,
+    /// The working directory with `$HOME` abbreviated to `~`.
+    pub cwd: String,
+    pub jobs: usize,
+    pub is_root: bool,
+    pub time: LocalTime,
+}
+
+impl PromptInfo {
+    pub fn current(state: &ShellState) -> Self {
+        let home = state.variables.get("HOME").map(std::path::Path::new);
+        let cwd = state
+            .current_dir()
+            .map(|cwd| return abbreviate_home(&cwd, home))
+            .unwrap_or_default();
+
+        return Self {
+            hostname: hostname(),
+            user: state.variables.get("USER").unwrap_or_default().to_string(),
+            cwd,
+            jobs: state.jobs.iter().count(),
+            is_root: is_root(),
+            time: LocalTime::now(),
+        };
+    }
+}
+
+/// A rendered `PS1`, ready to print.
+#[derive(Debug, PartialEq)]
+pub struct RenderedPrompt {
+    pub text: String,
+    /// Columns taken by the last line of `text`, not counting the sequences that were
+    /// marked as non-printing with `\[ ... \]`.
+    pub visible_width: usize,
+}
+
+impl RenderedPrompt {
+    fn push(&mut self, text: &str, is_visible: bool) {
+        self.text.push_str(text);
+        if !is_visible {
+            return;
+        }
+
+        match text.rsplit_once('\n') {
+            Some((_, last_line)) => self.visible_width = last_line.chars().count(),
+            None => self.visible_width += text.chars().count(),
+        }
+    }
+}
+
+/// Expands the backslash escapes of a `PS1` value. Unknown escapes are kept as written.
+pub fn render_ps1(ps1: &str, info: &PromptInfo) -> RenderedPrompt {
+    let mut rendered = RenderedPrompt {
+        text: String::new(),
+        visible_width: 0,
+    };
+    let mut is_visible = true;
+    let mut chars = ps1.chars();
+
+    while let Some(current_char) = chars.next() {
+        if current_char != '\\' {
+            rendered.push(current_char.encode_utf8(&mut [0; 4]), is_visible);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            rendered.push("\\", is_visible);
+            break;
+        };
+
+        let time = &info.time;
+        let expansion = match escape {
+            'u' => info.user.clone(),
+            'h' => info
+                .hostname
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            'H' => info.hostname.clone(),
+            'w' => info.cwd.clone(),
+            'W' => match info.cwd.as_str() {
+                "/" => String::from("/"),
+                cwd => cwd.rsplit('/').next().unwrap_or(cwd).to_string(),
+            },
+            'j' => info.jobs.to_string(),
+            't' => format!("{:02}:{:02}:{:02}", time.hour, time.minute, time.second),
+            'T' => format!(
+                "{:02}:{:02}:{:02}",
+                (time.hour + 11) % 12 + 1,
+                time.minute,
+                time.second
+            ),
+            'd' => format!(
+                "{} {} {:02}",
+                WEEKDAYS[time.weekday % 7],
+                MONTHS[time.month % 12],
+                time.day
+            ),
+            'n' => String::from("\n"),
+            'e' => String::from("\x1b"),
+            '$' => String::from(if info.is_root { "#" } else { "$" }),
+            '\\' => String::from("\\"),
+            '[' => {
+                is_visible = false;
+                continue;
+            }
+            ']' => {
+                is_visible = true;
+                continue;
+            }
+            _ => format!("\\{}", escape),
+        };
+        rendered.push(&expansion, is_visible);
+    }
+
+    return rendered;
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } == 0 {
+        let length = buffer
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(buffer.len());
+        return String::from_utf8_lossy(&buffer[..length]).to_string();
+    }
+
+    return std::fs::read_to_string("/etc/hostname")
+        .map(|hostname| return hostname.trim().to_string())
+        .unwrap_or_default();
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    return std::env::var("COMPUTERNAME").unwrap_or_default();
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    return unsafe { libc::geteuid() } == 0;
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    return false;
+}
+
+#[cfg(test)]
+mod prompt_tests {
+    use super::*;
+
+    fn info() -> PromptInfo {
+        return PromptInfo {
+            hostname: String::from("box.example.com"),
+            user: String::from("alice"),
+            cwd: String::from("~/src/shell"),
+            jobs: 2,
+            is_root: false,
+            time: LocalTime {
+                month: 4,
+                day: 5,
+                weekday: 2,
+                hour: 15,
+                minute: 4,
+                second: 9,
+            },
+        };
+    }
+
+    fn render(ps1: &str, info: &PromptInfo) -> String {
+        return render_ps1(ps1, info).text;
+    }
+
+    #[test]
+    fn crlf_translation() -> anyhow::Result<()> {
+        let mut stdout: Vec<u8> = vec![];
+        let mut stderr: Vec<u8> = vec![];
+        {
+            let mut prompter =
+                ConsolePrompter::new(io::empty(), &mut stdout, &mut stderr).with_crlf(true);
+            prompter.prompt("a\nb\r\n")?;
+            prompter.error("oops\n")?;
+        }
+        assert_eq!(stdout, b"a\r\nb\r\n");
+        assert_eq!(stderr, b"oops\r\n");
+
+        let mut stdout: Vec<u8> = vec![];
+        ConsolePrompter::new(io::empty(), &mut stdout, io::sink()).prompt("a\n")?;
+        assert_eq!(stdout, b"a\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn over_long_lines_are_skipped() -> anyhow::Result<()> {
+        let input = format!("short\n{}\nnext\n{}", "x".repeat(100), "y".repeat(11));
+        let mut prompter =
+            ConsolePrompter::new(input.as_bytes(), io::sink(), io::sink()).with_max_line_length(10);
+
+        assert_eq!(prompter.read()?, Some(String::from("short")));
+        let error = prompter.read().unwrap_err();
+        assert!(error.is::<LineTooLong>());
+        assert_eq!(error.to_string(), "input line too long");
+        assert_eq!(prompter.read()?, Some(String::from("next")));
+        assert!(prompter.read().unwrap_err().is::<LineTooLong>());
+        assert_eq!(prompter.read()?, None);
+
+        let exact = format!("{}\n", "z".repeat(10));
+        let mut prompter =
+            ConsolePrompter::new(exact.as_bytes(), io::sink(), io::sink()).with_max_line_length(10);
+        assert_eq!(prompter.read()?, Some("z".repeat(10)));
+
+        return Ok(());
+    }
+
+    /// Both streams of a terminal: whatever reaches either, in the order it arrives.
+    #[derive(Clone, Default)]
+    struct Terminal(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl io::Write for Terminal {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buffer);
+            return Ok(buffer.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn output_outside_a_utf8_locale_is_passed_through() -> anyhow::Result<()> {
+        use crate::{
+            executable::{PathFinder, Runner},
+            Shell,
+        };
+
+        let terminal = Terminal::default();
+        let prompter = ConsolePrompter::new(io::empty(), terminal.clone(), terminal.clone());
+        let mut shell = Shell::new(prompter, PathFinder::new(), Runner::new());
+
+        shell.state.variables.set("LANG", "en_US.ISO-8859-1");
+        shell.run_line(r"printf 'caf\351\n' | cat")?;
+        assert_eq!(*terminal.0.borrow(), b"caf\xe9\n");
+
+        shell.state.variables.set("LANG", "C");
+        for line in [
+            r"printf '\377\n'",
+            r"printf '\377\n' >&2",
+            r"timeout 5 printf '\377\n'",
+            r"timeout 5 printf '\377\n' | cat",
+            r"cat <(printf '\377\n')",
+            r"echo $(printf '\377' >&2)",
+        ] {
+            terminal.0.borrow_mut().clear();
+            shell.run_line(line)?;
+            assert_eq!(*terminal.0.borrow(), b"\xff\n", "{}", line);
+        }
+
+        let dir = tempfile::TempDir::new()?;
+        let file = dir.path().join("out");
+        terminal.0.borrow_mut().clear();
+        shell.run_line(&format!(r"printf '\377' > {}", file.display()))?;
+        assert_eq!(std::fs::read(&file)?, b"\xff");
+
+        terminal.0.borrow_mut().clear();
+        shell.state.variables.set("LANG", "en_US.UTF-8");
+        shell.run_line(r"printf 'caf\351\n' | cat")?;
+        assert_eq!(*terminal.0.borrow(), "caf\u{fffd}\n".as_bytes());
+
+        let mut stdout: Vec<u8> = vec![];
+        ConsolePrompter::new(io::empty(), &mut stdout, io::sink())
+            .with_crlf(true)
+            .write_bytes(b"\xff\na\r\n")?;
+        assert_eq!(stdout, b"\xff\r\na\r\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn caret_notation_is_only_for_the_terminal() -> anyhow::Result<()> {
+        use crate::{
+            executable::{PathFinder, Runner},
+            Shell,
+        };
+
+        let dir = tempfile::TempDir::new()?;
+        let file = dir.path().join("out");
+        let terminal = Terminal::default();
+        let prompter = ConsolePrompter::new(io::empty(), terminal.clone(), terminal.clone())
+            .with_caret_notation(true);
+        let mut shell = Shell::new(prompter, PathFinder::new(), Runner::new());
+
+        shell.run_line(r"echo -e 'a\rb\x1b[31m\x7f\tc'")?;
+        shell.run_line(&format!(r"echo -e 'a\rb' > {}", file.display()))?;
+        assert_eq!(*terminal.0.borrow(), b"a^Mb^[[31m^?\tc\n");
+        assert_eq!(std::fs::read(&file)?, b"a\rb\n");
+
+        terminal.0.borrow_mut().clear();
+        let prompt = render_ps1(r"\[\e[1m\]$ ", &info());
+        shell.prompter.prompt_command(&prompt)?;
+        assert_eq!(*terminal.0.borrow(), b"\x1b[1m$ ");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn buffered_output_stays_in_order() -> anyhow::Result<()> {
+        let terminal = Terminal::default();
+        let shown = || return String::from_utf8_lossy(&terminal.0.borrow()).to_string();
+        let mut prompter = ConsolePrompter::new(
+            "input\n".as_bytes(),
+            io::BufWriter::new(terminal.clone()),
+            terminal.clone(),
+        );
+
+        prompter.prompt("a\n")?;
+        prompter.prompt("b\n")?;
+        assert_eq!(shown(), "");
+        prompter.error("oops\n")?;
+        assert_eq!(shown(), "a\nb\noops\n");
+
+        prompter.prompt("c\n$ ")?;
+        assert_eq!(prompter.read()?, Some(String::from("input")));
+        assert_eq!(shown(), "a\nb\noops\nc\n$ ");
+
+        prompter.prompt("d\n")?;
+        prompter.flush()?;
+        assert_eq!(shown(), "a\nb\noops\nc\n$ d\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn hostname_escapes() {
+        assert_eq!(render(r"\h", &info()), "box");
+        assert_eq!(render(r"\H", &info()), "box.example.com");
+    }
+
+    #[test]
+    fn time_escapes() {
+        assert_eq!(render(r"\t", &info()), "15:04:09");
+        assert_eq!(render(r"\T", &info()), "03:04:09");
+
+        let midnight = PromptInfo {
+            time: LocalTime::default(),
+            ..info()
+        };
+        assert_eq!(render(r"\T", &midnight), "12:00:00");
+    }
+
+    #[test]
+    fn date_escape() {
+        assert_eq!(render(r"\d", &info()), "Tue May 05");
+    }
+
+    #[test]
+    fn newline_escape() {
+        assert_eq!(render(r"\w\n\$ ", &info()), "~/src/shell\n$ ");
+    }
+
+    #[test]
+    fn jobs_escape() {
+        assert_eq!(render(r"[\j]", &info()), "[2]");
+    }
+
+    #[test]
+    fn user_and_directory_escapes() {
+        assert_eq!(render(r"\u@\h:\W\$ ", &info()), "alice@box:shell$ ");
+
+        let root = PromptInfo {
+            is_root: true,
+            cwd: String::from("/"),
+            ..info()
+        };
+        assert_eq!(render(r"\W\$", &root), "/#");
+    }
+
+    #[test]
+    fn non_printing_sequences_have_no_width() {
+        let rendered = render_ps1(r"\[\e[1;32m\]\u\[\e[0m\]\$ ", &info());
+        assert_eq!(rendered.text, "\x1b[1;32malice\x1b[0m$ ");
+        assert_eq!(rendered.visible_width, "alice$ ".len());
+
+        let rendered = render_ps1(r"\w\n\[\e[31m\]> ", &info());
+        assert_eq!(rendered.text, "~/src/shell\n\x1b[31m> ");
+        assert_eq!(rendered.visible_width, 2);
+    }
+
+    #[test]
+    fn unknown_escapes_pass_through() {
+        assert_eq!(render(r"\q \\ end\", &info()), r"\q \ end\");
     }
 }
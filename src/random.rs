@@ -0,0 +1,60 @@
+use std::{
+    cell::Cell,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The generator behind `$RANDOM`. Expansion only borrows the shell state, so the
+/// generator state lives in a `Cell`.
+#[derive(Debug)]
+pub struct Random {
+    state: Cell<u32>,
+}
+
+impl Default for Random {
+    /// Seeded from the clock and the process id, so every shell gets its own sequence.
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| return elapsed.subsec_nanos() ^ elapsed.as_secs() as u32)
+            .unwrap_or_default();
+
+        return Self {
+            state: Cell::new(nanos ^ std::process::id()),
+        };
+    }
+}
+
+impl Random {
+    /// Restarts the sequence; the same seed always produces the same values.
+    pub fn seed(&self, seed: u32) {
+        self.state.set(seed);
+    }
+
+    /// The next value, between 0 and 32767.
+    pub fn next(&self) -> u16 {
+        let state = self
+            .state
+            .get()
+            .wrapping_mul(1_103_515_245)
+            .wrapping_add(12_345);
+        self.state.set(state);
+
+        return ((state >> 16) & 0x7fff) as u16;
+    }
+}
+
+#[cfg(test)]
+mod random_tests {
+    use super::*;
+
+    #[test]
+    fn seeding_repeats_the_sequence() {
+        let random = Random::default();
+        random.seed(42);
+        let first: Vec<u16> = (0..5).map(|_| return random.next()).collect();
+        random.seed(42);
+        let second: Vec<u16> = (0..5).map(|_| return random.next()).collect();
+
+        assert_eq!(first, second);
+    }
+}
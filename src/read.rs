@@ -0,0 +1,312 @@
+use std::{
+    io::{self, BufRead},
+    time::Duration,
+};
+
+use crate::{command::CommandOutput, state::ShellState, variables::is_name};
+
+const USAGE: &str = "read: usage: read [-r] [-t timeout] [name ...]";
+
+/// The status of a `read` that timed out: 128 plus `SIGALRM`, as in bash.
+pub const TIMED_OUT_STATUS: i32 = 142;
+
+/// The options given to `read` and the names it assigns to.
+struct Options {
+    raw: bool,
+    timeout: Option<Duration>,
+    names: Vec<String>,
+}
+
+/// `read [-r] [-t timeout] [name ...]`: splits a line of input on `IFS` and assigns
+/// the fields to the names in order, the last name taking the rest of the line.
+/// Without names the whole line goes to `REPLY`. Unless `-r` is given a backslash
+/// keeps the next character from splitting and is removed, and one at the end of a
+/// line joins the next (see `continued`). `line` is `None` once the input has ended,
+/// which empties the names and fails.
+pub fn read(state: &mut ShellState, args: &[String], line: Option<&str>) -> CommandOutput {
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(output) => return output,
+    };
+
+    let fields = match line {
+        Some(line) => split(
+            &unescape(line, options.raw),
+            state.ifs(),
+            options.names.len(),
+        ),
+        None => vec![],
+    };
+    for (index, name) in options.names.iter().enumerate() {
+        let value = fields.get(index).cloned().unwrap_or_default();
+        state.assign(name, &value);
+    }
+
+    return CommandOutput {
+        stdout: None,
+        stderr: None,
+        status: if line.is_some() { 0 } else { 1 },
+    };
+}
+
+/// Without `-r`, a line ending in an unescaped backslash goes on to the next line of
+/// input. Returns the line without that backslash, for the next one to be added to.
+pub fn continued(args: &[String], line: &str) -> Option<String> {
+    if parse_options(args).ok()?.raw {
+        return None;
+    }
+
+    let backslashes = line.len() - line.trim_end_matches('\\').len();
+    if backslashes.is_multiple_of(2) {
+        return None;
+    }
+    return Some(line[..line.len() - 1].to_string());
+}
+
+/// The next line of `reader`, without its newline. `None` once the input has ended.
+pub fn line_from(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    return Ok(Some(line.to_string()));
+}
+
+/// How long `read` waits for its line with `-t`, in seconds with an optional
+/// fraction. `None` without the option, or when the arguments are invalid, which
+/// `read` then reports.
+pub fn timeout(args: &[String]) -> Option<Duration> {
+    return parse_options(args).ok()?.timeout;
+}
+
+/// `read -t` when no line came in time: the names are emptied, as at the end of the
+/// input, and the status is above 128.
+pub fn timed_out(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let output = read(state, args, None);
+    return CommandOutput {
+        status: TIMED_OUT_STATUS,
+        ..output
+    };
+}
+
+fn parse_options(args: &[String]) -> Result<Options, CommandOutput> {
+    let mut raw = false;
+    let mut timeout = None;
+    let mut names = args;
+    while let Some(flags) = names.first().and_then(|arg| return arg.strip_prefix('-')) {
+        names = &names[1..];
+        if flags == "-" {
+            break;
+        }
+        for (index, flag) in flags.char_indices() {
+            match flag {
+                'r' => raw = true,
+                't' => {
+                    // The value follows in the same word (`-t5`) or the next one.
+                    let value = match &flags[index + 1..] {
+                        "" => {
+                            let Some((value, rest)) = names.split_first() else {
+                                return Err(failure(
+                                    &format!("read: -t: option requires an argument\n{}", USAGE),
+                                    2,
+                                ));
+                            };
+                            names = rest;
+                            value.as_str()
+                        }
+                        value => value,
+                    };
+                    let seconds = value.parse::<f64>().ok().filter(|seconds| {
+                        return seconds.is_finite() && *seconds >= 0.0 && !value.starts_with('+');
+                    });
+                    let Some(seconds) = seconds else {
+                        return Err(failure(
+                            &format!("read: {}: invalid timeout specification", value),
+                            1,
+                        ));
+                    };
+                    timeout = Some(Duration::from_secs_f64(seconds));
+                    break;
+                }
+                _ => {
+                    return Err(failure(
+                        &format!("read: -{}: invalid option\n{}", flag, USAGE),
+                        2,
+                    ));
+                }
+            }
+        }
+    }
+
+    let names = match names {
+        [] => vec![String::from("REPLY")],
+        names => names.to_vec(),
+    };
+    if let Some(name) = names.iter().find(|name| !is_name(name)) {
+        return Err(failure(
+            &format!("read: `{}': not a valid identifier", name),
+            1,
+        ));
+    }
+
+    return Ok(Options {
+        raw,
+        timeout,
+        names,
+    });
+}
+
+fn failure(message: &str, status: i32) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}\n", message)),
+        status,
+    };
+}
+
+/// Each character of the line and whether a backslash escaped it.
+fn unescape(line: &str, raw: bool) -> Vec<(char, bool)> {
+    if raw {
+        return line.chars().map(|c| return (c, false)).collect();
+    }
+
+    let mut chars = line.chars();
+    let mut unescaped = vec![];
+    while let Some(current_char) = chars.next() {
+        match current_char {
+            '\\' => unescaped.extend(chars.next().map(|next| return (next, true))),
+            _ => unescaped.push((current_char, false)),
+        }
+    }
+
+    return unescaped;
+}
+
+/// Splits into at most `count` fields, the last keeping the rest of the line apart
+/// from surrounding `IFS` whitespace.
+fn split(chars: &[(char, bool)], ifs: &str, count: usize) -> Vec<String> {
+    let is_separator = |(c, escaped): (char, bool)| return !escaped && ifs.contains(c);
+    let is_blank = |(c, escaped): (char, bool)| {
+        return is_separator((c, escaped)) && matches!(c, ' ' | '\t' | '\n');
+    };
+
+    let mut fields = vec![];
+    let mut index = 0;
+    let skip_blanks = |index: &mut usize| {
+        while *index < chars.len() && is_blank(chars[*index]) {
+            *index += 1;
+        }
+    };
+
+    skip_blanks(&mut index);
+    while index < chars.len() && fields.len() + 1 < count {
+        let start = index;
+        while index < chars.len() && !is_separator(chars[index]) {
+            index += 1;
+        }
+        fields.push(chars[start..index].iter().map(|(c, _)| return *c).collect());
+
+        // One separator ends the field, along with the blanks around it.
+        skip_blanks(&mut index);
+        if index < chars.len() && !is_blank(chars[index]) && is_separator(chars[index]) {
+            index += 1;
+            skip_blanks(&mut index);
+        }
+    }
+
+    let mut end = chars.len();
+    while end > index && is_blank(chars[end - 1]) {
+        end -= 1;
+    }
+    if index < end {
+        fields.push(chars[index..end].iter().map(|(c, _)| return *c).collect());
+    }
+
+    return fields;
+}
+
+#[cfg(test)]
+mod read_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn splits_on_ifs_with_the_rest_in_the_last_name() {
+        let mut state = ShellState::default();
+
+        read(&mut state, &args(&["a", "b"]), Some("  one  two three  "));
+        assert_eq!(state.variables.get("a"), Some("one"));
+        assert_eq!(state.variables.get("b"), Some("two three"));
+
+        state.variables.set("IFS", ",");
+        read(&mut state, &args(&["a", "b", "c"]), Some("1,2,3"));
+        assert_eq!(state.variables.get("a"), Some("1"));
+        assert_eq!(state.variables.get("b"), Some("2"));
+        assert_eq!(state.variables.get("c"), Some("3"));
+
+        read(&mut state, &args(&["a", "b", "c"]), Some("1,,3 4"));
+        assert_eq!(state.variables.get("b"), Some(""));
+        assert_eq!(state.variables.get("c"), Some("3 4"));
+    }
+
+    #[test]
+    fn backslashes_and_end_of_input() {
+        let mut state = ShellState::default();
+
+        read(&mut state, &args(&["a", "b"]), Some(r"x\ y z"));
+        assert_eq!(state.variables.get("a"), Some("x y"));
+        read(&mut state, &args(&["-r", "a", "b"]), Some(r"x\ y z"));
+        assert_eq!(state.variables.get("a"), Some(r"x\"));
+
+        read(&mut state, &[], Some("whole line"));
+        assert_eq!(state.variables.get("REPLY"), Some("whole line"));
+
+        let output = read(&mut state, &args(&["a"]), None);
+        assert_eq!(output.status, 1);
+        assert_eq!(state.variables.get("a"), Some(""));
+
+        assert_eq!(read(&mut state, &args(&["-x"]), Some("")).status, 2);
+        assert_eq!(read(&mut state, &args(&["1a"]), Some("")).status, 1);
+    }
+
+    #[test]
+    fn trailing_backslash_continues_the_line() {
+        assert_eq!(
+            continued(&args(&["a"]), r"C:\dir\"),
+            Some(String::from(r"C:\dir"))
+        );
+        assert_eq!(continued(&args(&["a"]), r"ends in \\"), None);
+        assert_eq!(continued(&args(&["a"]), "plain"), None);
+        assert_eq!(continued(&args(&["-r", "a"]), r"C:\dir\"), None);
+    }
+
+    #[test]
+    fn timeout_option() {
+        let mut state = ShellState::default();
+
+        assert_eq!(
+            timeout(&args(&["-t", "2", "a"])),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            timeout(&args(&["-rt0.5", "a"])),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(timeout(&args(&["-r", "a"])), None);
+        assert_eq!(timeout(&args(&["-t", "soon"])), None);
+
+        read(&mut state, &args(&["-t", "1", "a", "b"]), Some("x y"));
+        assert_eq!(state.variables.get("b"), Some("y"));
+
+        let output = read(&mut state, &args(&["-t", "-1"]), Some(""));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("read: -1: invalid timeout specification\n"))
+        );
+        assert_eq!(output.status, 1);
+        assert_eq!(read(&mut state, &args(&["-t"]), Some("")).status, 2);
+    }
+}
@@ -1,135 +1,383 @@
 use anyhow::anyhow;
 use std::{
     fs::{File, OpenOptions},
-    io::Write,
-    path::PathBuf,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Stdio,
 };
 
-use crate::command::CommandOutput;
+use crate::{
+    command::{io_error_message, CommandOutput},
+    prompt::Prompter,
+    state::ShellState,
+};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OutputMode {
     Append,
     Override,
+    /// `>|`: truncates the file even when `noclobber` is set.
+    Clobber,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Source {
-    Stdout(OutputMode),
-    Stderr(OutputMode),
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    File {
+        path: PathBuf,
+        mode: OutputMode,
+    },
+    /// `N>&M`: the source now points wherever `M` points.
+    Duplicate(Source),
+    /// `N>&-`: output written to the source is discarded.
+    Close,
+    /// `< file`: the command reads its standard input from the file.
+    Input(PathBuf),
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Redirection {
     pub source: Source,
-    pub target: PathBuf,
+    pub target: Target,
 }
 
 const STDOUT_OVERRIDE: &[&str] = &[">", "1>"];
 const STDOUT_APPEND: &[&str] = &[">>", "1>>"];
+const STDOUT_CLOBBER: &[&str] = &[">|", "1>|"];
 const STDERR_OVERRIDE: &[&str] = &["2>"];
 const STDERR_APPEND: &[&str] = &["2>>"];
+const STDERR_CLOBBER: &[&str] = &["2>|"];
+const ALL_OVERRIDE: &[&str] = &["&>"];
+const ALL_APPEND: &[&str] = &["&>>"];
+const STDIN_INPUT: &[&str] = &["<", "0<"];
+
+/// Where a stream ends up once all redirections of a command are applied.
+#[derive(Debug, Clone, Copy)]
+enum Destination {
+    Stdout,
+    Stderr,
+    File(usize),
+    Closed,
+}
 
 impl Redirection {
+    /// Parses the redirections written as an operator and its target. `&>word` and
+    /// `&>>word` send stdout and stderr to the same file, so they come back as the
+    /// stdout redirection followed by `2>&1`.
+    pub fn parse(mut args: Vec<String>) -> anyhow::Result<Vec<Self>> {
+        let stdout_operator = match args.first().map(|arg| return arg.as_str()) {
+            Some(operator) if ALL_OVERRIDE.contains(&operator) => ">",
+            Some(operator) if ALL_APPEND.contains(&operator) => ">>",
+            _ => return Ok(vec![Self::new(args)?]),
+        };
+
+        args[0] = stdout_operator.to_string();
+        return Ok(vec![
+            Self::new(args)?,
+            Self::new(vec![String::from("2>&1")])?,
+        ]);
+    }
+
     pub fn new(args: Vec<String>) -> anyhow::Result<Self> {
-        let Some(output_source) = args
-            .get(0)
-            .and_then(|raw_source| match raw_source.as_str() {
-                s if STDOUT_OVERRIDE.contains(&s) => Some(Source::Stdout(OutputMode::Override)),
-                s if STDOUT_APPEND.contains(&s) => Some(Source::Stdout(OutputMode::Append)),
-                s if STDERR_OVERRIDE.contains(&s) => Some(Source::Stderr(OutputMode::Override)),
-                s if STDERR_APPEND.contains(&s) => Some(Source::Stderr(OutputMode::Append)),
-                _ => None,
-            })
-        else {
+        let Some(raw_source) = args.first() else {
+            return Err(anyhow!(
+                "Failed to create redirection: could not parse the output source"
+            ));
+        };
+
+        if let Some(redirection) = Self::parse_duplicate(raw_source)? {
+            return Ok(redirection);
+        }
+
+        let Some((source, mode)) = (match raw_source.as_str() {
+            s if STDIN_INPUT.contains(&s) => Some((Source::Stdin, None)),
+            s if STDOUT_OVERRIDE.contains(&s) => Some((Source::Stdout, Some(OutputMode::Override))),
+            s if STDOUT_APPEND.contains(&s) => Some((Source::Stdout, Some(OutputMode::Append))),
+            s if STDERR_OVERRIDE.contains(&s) => Some((Source::Stderr, Some(OutputMode::Override))),
+            s if STDERR_APPEND.contains(&s) => Some((Source::Stderr, Some(OutputMode::Append))),
+            s if STDOUT_CLOBBER.contains(&s) => Some((Source::Stdout, Some(OutputMode::Clobber))),
+            s if STDERR_CLOBBER.contains(&s) => Some((Source::Stderr, Some(OutputMode::Clobber))),
+            _ => None,
+        }) else {
             return Err(anyhow!(
                 "Failed to create redirection: could not parse the output source"
             ));
         };
-        let Some(target) = args.get(1) else {
-            return Err(anyhow!("Failed to create redirection: target not found"));
+        // The target must be exactly one word; bash reports a missing one as an
+        // unexpected end of line.
+        let target = match &args[1..] {
+            [] => return Err(anyhow!("syntax error near unexpected token `newline'")),
+            [target] if Self::is_redirection_arg(target) => {
+                return Err(anyhow!("syntax error near unexpected token `{}'", target));
+            }
+            [target] => target,
+            [_, extra, ..] => {
+                return Err(anyhow!("syntax error near unexpected token `{}'", extra));
+            }
+        };
+
+        let path = PathBuf::from(target);
+        let target = match mode {
+            Some(mode) => Target::File { path, mode },
+            None => Target::Input(path),
+        };
+        return Ok(Self { source, target });
+    }
+
+    /// Parses `N>&M`, `N>&-` and the bare `>&M` shorthand for `1>&M`.
+    fn parse_duplicate(arg: &str) -> anyhow::Result<Option<Self>> {
+        let Some((raw_source, raw_target)) = arg.split_once(">&") else {
+            return Ok(None);
+        };
+
+        let source = match raw_source {
+            "" | "1" => Source::Stdout,
+            "2" => Source::Stderr,
+            _ => return Ok(None),
+        };
+        let target = match raw_target {
+            "1" => Target::Duplicate(Source::Stdout),
+            "2" => Target::Duplicate(Source::Stderr),
+            "-" => Target::Close,
+            fd if !fd.is_empty() && fd.chars().all(|c| c.is_ascii_digit()) => {
+                return Err(anyhow!("{}: Bad file descriptor", fd));
+            }
+            _ => return Ok(None),
         };
 
-        return Ok(Self {
-            source: output_source,
-            target: PathBuf::from(target),
-        });
+        return Ok(Some(Self { source, target }));
+    }
+
+    /// Number of words this redirection consumed on the command line.
+    pub fn word_count(&self) -> usize {
+        match self.target {
+            Target::File { .. } | Target::Input(_) => return 2,
+            Target::Duplicate(_) | Target::Close => return 1,
+        }
     }
 
     pub fn run(&self, command_output: &CommandOutput) -> anyhow::Result<()> {
-        let path = PathBuf::from(&self.target);
-
-        match &self.source {
-            Source::Stdout(output_mode) => match output_mode {
-                OutputMode::Append => {
-                    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
-
-                    file.write(
-                        command_output
-                            .stdout
-                            .clone()
-                            .unwrap_or("".to_string())
-                            .as_bytes(),
-                    )?;
-
-                    return Ok(());
-                }
-                OutputMode::Override => {
-                    let mut file = File::create(path)?;
-
-                    file.write(
-                        command_output
-                            .stdout
-                            .clone()
-                            .unwrap_or("".to_string())
-                            .as_bytes(),
-                    )?;
-
-                    return Ok(());
-                }
-            },
-            Source::Stderr(output_mode) => match output_mode {
-                OutputMode::Append => {
-                    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
-
-                    file.write(
-                        command_output
-                            .stderr
-                            .clone()
-                            .unwrap_or("".to_string())
-                            .as_bytes(),
-                    )?;
-
-                    return Ok(());
-                }
-                OutputMode::Override => {
-                    let mut file = File::create(path)?;
-
-                    file.write(
-                        command_output
-                            .stderr
-                            .clone()
-                            .unwrap_or("".to_string())
-                            .as_bytes(),
-                    )?;
-
-                    return Ok(());
+        apply(std::slice::from_ref(self), command_output)?;
+
+        return Ok(());
+    }
+
+    pub fn is_redirection_arg(arg: &str) -> bool {
+        let is_duplicate = matches!(Self::parse_duplicate(arg), Ok(Some(_)) | Err(_));
+
+        return is_duplicate
+            || [
+                STDOUT_APPEND,
+                STDOUT_OVERRIDE,
+                STDOUT_CLOBBER,
+                STDERR_APPEND,
+                STDERR_OVERRIDE,
+                STDERR_CLOBBER,
+                ALL_OVERRIDE,
+                ALL_APPEND,
+                STDIN_INPUT,
+            ]
+            .concat()
+            .contains(&arg);
+    }
+}
+
+/// With `noclobber` set, `>` may not truncate an existing regular file; `>|` still
+/// does. An interactive shell with `SHELL_CLOBBER_PROMPT` set asks before refusing.
+pub fn check_clobber(
+    redirections: &[Redirection],
+    state: &ShellState,
+    prompter: &mut impl Prompter,
+) -> anyhow::Result<()> {
+    if !state.options.is_set("noclobber") {
+        return Ok(());
+    }
+    let asks = state.interactive
+        && state
+            .variables
+            .get("SHELL_CLOBBER_PROMPT")
+            .is_some_and(|value| !value.is_empty() && value != "0");
+
+    for redirection in redirections {
+        let Target::File {
+            path,
+            mode: OutputMode::Override,
+        } = &redirection.target
+        else {
+            continue;
+        };
+        if !path.metadata().is_ok_and(|metadata| metadata.is_file()) {
+            continue;
+        }
+
+        if asks {
+            prompter.prompt(&format!("overwrite '{}'? (y/n) ", path.display()))?;
+            let answer = prompter.read()?.unwrap_or_default();
+            if matches!(answer.trim(), "y" | "Y" | "yes") {
+                continue;
+            }
+        }
+        return Err(anyhow!(
+            "{}: cannot overwrite existing file",
+            path.display()
+        ));
+    }
+
+    return Ok(());
+}
+
+/// Applies the redirections in order and writes the redirected parts of the output.
+/// Returns whatever is left for the terminal. A target that cannot be opened or
+/// written to fails with a `path: reason` error.
+pub fn apply(
+    redirections: &[Redirection],
+    command_output: &CommandOutput,
+) -> anyhow::Result<CommandOutput> {
+    let stdout = command_output.stdout.as_deref().unwrap_or_default();
+    let stderr = command_output.stderr.as_deref().unwrap_or_default();
+    let (stdout, stderr) = apply_bytes(redirections, stdout.as_bytes(), stderr.as_bytes())?;
+
+    // Pieces of text put back together are text again.
+    let text = |bytes: Vec<u8>| {
+        return Some(String::from_utf8_lossy(&bytes).to_string()).filter(|text| !text.is_empty());
+    };
+    return Ok(CommandOutput {
+        stdout: text(stdout),
+        stderr: text(stderr),
+        status: command_output.status,
+    });
+}
+
+/// `apply` for output kept as the bytes a program wrote, which reach files unchanged.
+/// Returns the stdout and stderr left for the terminal.
+pub fn apply_bytes(
+    redirections: &[Redirection],
+    stdout: &[u8],
+    stderr: &[u8],
+) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let (mut files, stdout_destination, stderr_destination) = resolve(redirections)?;
+
+    let mut terminal = (vec![], vec![]);
+    write(stdout_destination, stdout, &mut files, &mut terminal)?;
+    write(stderr_destination, stderr, &mut files, &mut terminal)?;
+
+    return Ok(terminal);
+}
+
+/// The file a command reads its standard input from, when it has a `< file`
+/// redirection. Every such file is opened, in order, and the last one is used.
+pub fn input(redirections: &[Redirection]) -> anyhow::Result<Option<File>> {
+    let mut input = None;
+    for redirection in redirections {
+        if let Target::Input(path) = &redirection.target {
+            let file = File::open(path)
+                .map_err(|e| return anyhow!("{}: {}", path.display(), io_error_message(&e)))?;
+            input = Some(file);
+        }
+    }
+
+    return Ok(input);
+}
+
+/// The stdout and stderr handles for a process that writes directly instead of
+/// having its output captured (e.g. a background job). With `piped`, output left on
+/// stdout goes into a new pipe for the next pipeline stage.
+pub fn stdio(redirections: &[Redirection], piped: bool) -> anyhow::Result<(Stdio, Stdio)> {
+    let (files, stdout, stderr) = resolve(redirections)?;
+
+    let to_stdio = |destination: Destination| -> anyhow::Result<Stdio> {
+        match destination {
+            Destination::Stdout => return Ok(Stdio::from(io::stdout())),
+            Destination::Stderr => return Ok(Stdio::from(io::stderr())),
+            Destination::File(index) => return Ok(Stdio::from(files[index].file.try_clone()?)),
+            Destination::Closed => return Ok(Stdio::null()),
+        }
+    };
+
+    let stdout = match stdout {
+        Destination::Stdout if piped => Stdio::piped(),
+        _ => to_stdio(stdout)?,
+    };
+    return Ok((stdout, to_stdio(stderr)?));
+}
+
+/// A redirection target opened for writing.
+struct OpenFile<'a> {
+    path: &'a Path,
+    file: File,
+}
+
+impl OpenFile<'_> {
+    fn write_all(&mut self, content: &[u8]) -> anyhow::Result<()> {
+        return self
+            .file
+            .write_all(content)
+            .map_err(|e| return anyhow!("{}: {}", self.path.display(), io_error_message(&e)));
+    }
+}
+
+/// Opens the redirection targets in order and works out where each stream ends up.
+fn resolve(
+    redirections: &[Redirection],
+) -> anyhow::Result<(Vec<OpenFile<'_>>, Destination, Destination)> {
+    let mut files: Vec<OpenFile> = vec![];
+    let mut stdout = Destination::Stdout;
+    let mut stderr = Destination::Stderr;
+
+    for redirection in redirections {
+        let destination = match &redirection.target {
+            // Input is opened by `input`; it has no say over where output goes.
+            Target::Input(_) | Target::Duplicate(Source::Stdin) => continue,
+            Target::File { path, mode } => {
+                let file = match mode {
+                    OutputMode::Append => OpenOptions::new().append(true).create(true).open(path),
+                    OutputMode::Override | OutputMode::Clobber => File::create(path),
                 }
-            },
+                .map_err(|e| return anyhow!("{}: {}", path.display(), io_error_message(&e)))?;
+                files.push(OpenFile { path, file });
+                Destination::File(files.len() - 1)
+            }
+            Target::Duplicate(Source::Stdout) => stdout,
+            Target::Duplicate(Source::Stderr) => stderr,
+            Target::Close => Destination::Closed,
+        };
+
+        match redirection.source {
+            Source::Stdin => {}
+            Source::Stdout => stdout = destination,
+            Source::Stderr => stderr = destination,
         }
     }
 
-    pub fn is_redirection_arg(arg: &str) -> bool {
-        return [
-            STDOUT_APPEND,
-            STDOUT_OVERRIDE,
-            STDERR_APPEND,
-            STDERR_OVERRIDE,
-        ]
-        .concat()
-        .iter()
-        .any(|&redirection_arg| return redirection_arg == arg);
+    return Ok((files, stdout, stderr));
+}
+
+/// Writes one stream's output to where it ends up; `terminal` collects the stdout
+/// and stderr left for the terminal.
+fn write(
+    destination: Destination,
+    content: &[u8],
+    files: &mut [OpenFile],
+    terminal: &mut (Vec<u8>, Vec<u8>),
+) -> anyhow::Result<()> {
+    if content.is_empty() {
+        return Ok(());
+    }
+
+    match destination {
+        Destination::Stdout => terminal.0.extend_from_slice(content),
+        Destination::Stderr => terminal.1.extend_from_slice(content),
+        Destination::File(index) => files[index].write_all(content)?,
+        Destination::Closed => {}
     }
+
+    return Ok(());
 }
 
 #[cfg(test)]
@@ -143,7 +391,7 @@ mod redirection_tests {
         redirection::{STDOUT_APPEND, STDOUT_OVERRIDE},
     };
 
-    use super::Redirection;
+    use super::{apply, Redirection, Source, Target};
 
     #[test]
     fn test_stdout_override() -> anyhow::Result<()> {
@@ -158,6 +406,7 @@ mod redirection_tests {
         let command_output = CommandOutput {
             stdout: Some(expected_content.to_string()),
             stderr: None,
+            status: 0,
         };
 
         let redirection = Redirection::new(vec![
@@ -185,6 +434,7 @@ mod redirection_tests {
         let command_output = CommandOutput {
             stdout: Some(additional_content.to_string()),
             stderr: None,
+            status: 0,
         };
 
         let redirection = Redirection::new(vec![
@@ -201,4 +451,140 @@ mod redirection_tests {
 
         return Ok(());
     }
+
+    #[test]
+    fn test_parse_duplicate() -> anyhow::Result<()> {
+        assert_eq!(
+            Redirection::new(vec![">&2".to_string()])?,
+            Redirection {
+                source: Source::Stdout,
+                target: Target::Duplicate(Source::Stderr),
+            }
+        );
+        assert_eq!(
+            Redirection::new(vec!["2>&1".to_string()])?,
+            Redirection {
+                source: Source::Stderr,
+                target: Target::Duplicate(Source::Stdout),
+            }
+        );
+        assert_eq!(
+            Redirection::new(vec!["2>&-".to_string()])?,
+            Redirection {
+                source: Source::Stderr,
+                target: Target::Close,
+            }
+        );
+        assert!(Redirection::is_redirection_arg(">&2"));
+        assert!(!Redirection::is_redirection_arg("a>&2"));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_target_must_be_one_word() {
+        let error = |args: &[&str]| {
+            let args = args.iter().map(|arg| return arg.to_string()).collect();
+            return Redirection::new(args).unwrap_err().to_string();
+        };
+
+        assert_eq!(error(&[">", ">"]), "syntax error near unexpected token `>'");
+        assert_eq!(
+            error(&["2>>", "&>"]),
+            "syntax error near unexpected token `&>'"
+        );
+        assert_eq!(
+            error(&[">"]),
+            "syntax error near unexpected token `newline'"
+        );
+        assert_eq!(
+            error(&[">", "out", "extra"]),
+            "syntax error near unexpected token `extra'"
+        );
+    }
+
+    #[test]
+    fn test_stdout_to_stderr() -> anyhow::Result<()> {
+        let command_output = CommandOutput {
+            stdout: Some("hi\n".to_string()),
+            stderr: None,
+            status: 0,
+        };
+
+        let redirection = Redirection::new(vec![">&2".to_string()])?;
+        let terminal_output = apply(&[redirection], &command_output)?;
+
+        assert_eq!(terminal_output.stdout, None);
+        assert_eq!(terminal_output.stderr, Some("hi\n".to_string()));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_redirection_order() -> anyhow::Result<()> {
+        let file = NamedTempFile::new()?;
+        let path = file.path().to_string_lossy().to_string();
+
+        let command_output = CommandOutput {
+            stdout: Some("out\n".to_string()),
+            stderr: Some("err\n".to_string()),
+            status: 0,
+        };
+
+        let redirections = vec![
+            Redirection::new(vec![">".to_string(), path.clone()])?,
+            Redirection::new(vec!["2>&1".to_string()])?,
+        ];
+        let terminal_output = apply(&redirections, &command_output)?;
+        assert_eq!(terminal_output.stdout, None);
+        assert_eq!(terminal_output.stderr, None);
+        assert_eq!(fs::read_to_string(&path)?, "out\nerr\n");
+
+        let redirections = vec![
+            Redirection::new(vec!["2>&1".to_string()])?,
+            Redirection::new(vec![">".to_string(), path.clone()])?,
+        ];
+        let terminal_output = apply(&redirections, &command_output)?;
+        assert_eq!(terminal_output.stdout, Some("err\n".to_string()));
+        assert_eq!(fs::read_to_string(&path)?, "out\n");
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_stdout_and_stderr_together() -> anyhow::Result<()> {
+        let file = NamedTempFile::new()?;
+        let path = file.path().to_string_lossy().to_string();
+        fs::write(&path, "old\n")?;
+
+        let command_output = CommandOutput {
+            stdout: Some("out\n".to_string()),
+            stderr: Some("err\n".to_string()),
+            status: 0,
+        };
+        let run = |operator: &str| -> anyhow::Result<CommandOutput> {
+            let redirections = Redirection::parse(vec![operator.to_string(), path.clone()])?;
+            return apply(&redirections, &command_output);
+        };
+
+        let terminal_output = run("&>")?;
+        assert_eq!(terminal_output.stdout, None);
+        assert_eq!(terminal_output.stderr, None);
+        assert_eq!(fs::read_to_string(&path)?, "out\nerr\n");
+
+        run("&>>")?;
+        run("&>>")?;
+        assert_eq!(fs::read_to_string(&path)?, "out\nerr\nout\nerr\nout\nerr\n");
+
+        run("&>")?;
+        assert_eq!(fs::read_to_string(&path)?, "out\nerr\n");
+
+        assert!(Redirection::is_redirection_arg("&>>"));
+        assert_eq!(
+            Redirection::parse(vec![">".to_string(), path.clone()])?.len(),
+            1
+        );
+
+        return Ok(());
+    }
 }
@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+
+use crate::{command::CommandOutput, shopt::SHOPT_OPTIONS, state::ShellState, tokenizer::quote};
+
+/// An option `set -o` knows: its long name and single-letter flag, if it has one.
+struct ShellOption {
+    name: &'static str,
+    flag: Option<char>,
+}
+
+const SHELL_OPTIONS: &[ShellOption] = &[
+    // When `cd` cannot find a directory, suggests the closest name next to it.
+    ShellOption {
+        name: "cdsuggest",
+        flag: None,
+    },
+    ShellOption {
+        name: "noclobber",
+        flag: Some('C'),
+    },
+    // Makes `cd` resolve symbolic links, so `$PWD` is always the physical path.
+    ShellOption {
+        name: "physical",
+        flag: Some('P'),
+    },
+    // A pipeline fails with the status of its last failing stage, not only its last.
+    ShellOption {
+        name: "pipefail",
+        flag: None,
+    },
+    // Turns off bash extensions for portable scripts: no brace expansion, and `echo`
+    // takes only `-n`, printing `-e`/`-E` and backslashes as written.
+    ShellOption {
+        name: "posix",
+        flag: None,
+    },
+    // Echoes each input line to stderr as it is read, before any expansion.
+    ShellOption {
+        name: "verbose",
+        flag: Some('v'),
+    },
+];
+
+const USAGE: &str = "set: usage: set [-CPv] [-o option-name] [--] [-] [arg ...]";
+
+/// The options turned on with `set -o name` or their single-letter flags, and with
+/// `shopt -s name`.
+#[derive(Debug, Default, Clone)]
+pub struct ShellOptions {
+    enabled: BTreeSet<&'static str>,
+}
+
+impl ShellOptions {
+    pub fn is_set(&self, name: &str) -> bool {
+        return self.enabled.contains(name);
+    }
+
+    /// Turns an option on or off. Returns `false` when there is no such option.
+    pub fn set(&mut self, name: &str, on: bool) -> bool {
+        let Some(option) = SHELL_OPTIONS.iter().find(|option| option.name == name) else {
+            return false;
+        };
+
+        self.turn(option.name, on);
+        return true;
+    }
+
+    /// Turns a `shopt` option on or off. Returns `false` when there is no such option.
+    pub fn set_shopt(&mut self, name: &str, on: bool) -> bool {
+        let Some(name) = SHOPT_OPTIONS.iter().find(|option| **option == name) else {
+            return false;
+        };
+
+        self.turn(name, on);
+        return true;
+    }
+
+    fn turn(&mut self, name: &'static str, on: bool) {
+        if on {
+            self.enabled.insert(name);
+        } else {
+            self.enabled.remove(name);
+        }
+    }
+}
+
+/// `set [-CPv] [-o name] [+CPv] [+o name] [--] [arg ...]`: turns options on (`-`) or off
+/// (`+`) and makes the remaining arguments the positional parameters. Without
+/// arguments lists the shell variables; `-o`/`+o` without a name list the options.
+pub fn set(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    if args.is_empty() {
+        return success(list_variables(state));
+    }
+
+    let mut index = 0;
+    let mut ends_options = false;
+    while let Some(arg) = args.get(index) {
+        let (on, flags) = match arg.split_at_checked(1) {
+            Some(("-", flags)) => (true, flags),
+            Some(("+", flags)) => (false, flags),
+            _ => break,
+        };
+        index += 1;
+        if flags.is_empty() || arg == "--" {
+            ends_options = true;
+            break;
+        }
+
+        for flag in flags.chars() {
+            if flag == 'o' {
+                let Some(name) = args.get(index) else {
+                    return success(list_options(state, on));
+                };
+                index += 1;
+                if !state.options.set(name, on) {
+                    return failure(&format!("set: {}: invalid option name\n", name));
+                }
+                continue;
+            }
+
+            match SHELL_OPTIONS
+                .iter()
+                .find(|option| option.flag == Some(flag))
+            {
+                Some(option) => {
+                    state.options.set(option.name, on);
+                }
+                None => {
+                    let sign = if on { '-' } else { '+' };
+                    return failure(&format!(
+                        "set: {}{}: invalid option\n{}\n",
+                        sign, flag, USAGE
+                    ));
+                }
+            }
+        }
+    }
+
+    if ends_options || index < args.len() {
+        state.positional_params = args[index..].to_vec();
+    }
+    return success(String::new());
+}
+
+/// `set -o` prints a table of the options; `set +o` prints the `set` commands that
+/// recreate the current settings.
+fn list_options(state: &ShellState, on: bool) -> String {
+    return SHELL_OPTIONS
+        .iter()
+        .map(|option| {
+            let is_set = state.options.is_set(option.name);
+            match (on, is_set) {
+                (true, true) => return format!("{:<15}\ton\n", option.name),
+                (true, false) => return format!("{:<15}\toff\n", option.name),
+                (false, true) => return format!("set -o {}\n", option.name),
+                (false, false) => return format!("set +o {}\n", option.name),
+            }
+        })
+        .collect();
+}
+
+fn list_variables(state: &ShellState) -> String {
+    return state
+        .variables
+        .iter()
+        .map(|(name, value)| return format!("{}={}\n", name, quote(value)))
+        .collect();
+}
+
+fn success(stdout: String) -> CommandOutput {
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: None,
+        status: 0,
+    };
+}
+
+fn failure(message: &str) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(message.to_string()),
+        status: 2,
+    };
+}
+
+#[cfg(test)]
+mod set_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn options_are_turned_on_and_off() {
+        let mut state = ShellState::default();
+
+        assert_eq!(set(&mut state, &args(&["-o", "noclobber"])).status, 0);
+        assert!(state.options.is_set("noclobber"));
+        assert_eq!(
+            set(&mut state, &args(&["-o"])).stdout,
+            Some(String::from(
+                "cdsuggest      \toff\nnoclobber      \ton\nphysical       \toff\npipefail       \toff\nposix          \toff\nverbose        \toff\n"
+            ))
+        );
+
+        assert_eq!(set(&mut state, &args(&["+C"])).status, 0);
+        assert!(!state.options.is_set("noclobber"));
+        assert_eq!(
+            set(&mut state, &args(&["+o"])).stdout,
+            Some(String::from(
+                "set +o cdsuggest\nset +o noclobber\nset +o physical\nset +o pipefail\nset +o posix\nset +o verbose\n"
+            ))
+        );
+
+        let output = set(&mut state, &args(&["-o", "nope"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("set: nope: invalid option name\n"))
+        );
+        assert_eq!(output.status, 2);
+        assert_eq!(set(&mut state, &args(&["-Z"])).status, 2);
+    }
+
+    #[test]
+    fn remaining_arguments_become_positional_parameters() {
+        let mut state = ShellState::default();
+
+        set(&mut state, &args(&["-C", "a", "b"]));
+        assert_eq!(state.positional_params, args(&["a", "b"]));
+        assert!(state.options.is_set("noclobber"));
+
+        set(&mut state, &args(&["--", "-x"]));
+        assert_eq!(state.positional_params, args(&["-x"]));
+
+        set(&mut state, &args(&["--"]));
+        assert!(state.positional_params.is_empty());
+    }
+
+    #[test]
+    fn lists_variables_quoted() {
+        let mut state = ShellState::default();
+        state.variables.set("PLAIN", "/usr/bin:/bin");
+        state.variables.set("SPACED", "a b");
+        state.variables.set("QUOTE", "it's");
+
+        assert_eq!(
+            set(&mut state, &[]).stdout,
+            Some(String::from(
+                "PLAIN=/usr/bin:/bin\nQUOTE='it'\\''s'\nSPACED='a b'\n"
+            ))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::BufRead;
+
+use crate::{
+    command,
+    executable::{ExecutablePathFinder, ExecutableRunner},
+    prompt::Prompter,
+    state::ShellState,
+    statement,
+};
+
+/// A shell session: its state (variables, history, jobs, working directory, last
+/// status) together with where its output goes and how it finds and starts programs.
+/// The binary drives one of these; other programs can embed it the same way.
+pub struct Shell<P: Prompter, F: ExecutablePathFinder, R: ExecutableRunner> {
+    pub state: ShellState,
+    pub prompter: P,
+    finder: F,
+    runner: R,
+    /// Write how each line was parsed to stderr before running it.
+    debug_parse: bool,
+}
+
+impl<P: Prompter, F: ExecutablePathFinder, R: ExecutableRunner> Shell<P, F, R> {
+    pub fn new(prompter: P, finder: F, runner: R) -> Self {
+        return Self {
+            state: ShellState::new(),
+            prompter,
+            finder,
+            runner,
+            debug_parse: false,
+        };
+    }
+
+    pub fn with_debug_parse(mut self, debug_parse: bool) -> Self {
+        self.debug_parse = debug_parse;
+        return self;
+    }
+
+    /// Parses and runs one line of input and returns its exit status. Syntax errors
+    /// are reported with status 2. After `exit`, `exit_status` holds the status to
+    /// end the session with.
+    pub fn run_line(&mut self, input: &str) -> anyhow::Result<i32> {
+        self.state.line_number += 1;
+        if self.state.options.is_set("verbose") {
+            self.prompter.error(&format!("{}\n", input))?;
+        }
+        if input.is_empty() {
+            return Ok(self.state.last_status);
+        }
+
+        let statements = match statement::parse(input) {
+            Ok(statements) => statements,
+            Err(e) => {
+                self.prompter.error(&format!("{}\n", e))?;
+                self.state.last_status = 2;
+                return Ok(self.state.last_status);
+            }
+        };
+        if self.debug_parse {
+            self.prompter
+                .error(&command::debug_parse(input, &statements))?;
+        }
+
+        let result = statement::run(
+            statements,
+            &mut self.state,
+            &mut self.prompter,
+            &self.finder,
+            &self.runner,
+        )?;
+        return Ok(result.status);
+    }
+
+    /// The status `exit` asked the session to end with, once it has run. The shell
+    /// does not end the process itself; that is left to the program embedding it.
+    pub fn exit_status(&self) -> Option<i32> {
+        return self.state.exiting;
+    }
+
+    /// Reaps the background jobs that have finished and returns their notices. The
+    /// `CHLD` trap, if one is set, runs once for each of them.
+    pub fn reap_jobs(&mut self) -> anyhow::Result<String> {
+        let notices = self.state.jobs.finished_notices();
+        if let Some(action) = self.state.traps.get("CHLD").cloned() {
+            for _ in &notices {
+                self.run_trap(&action)?;
+            }
+        }
+
+        return Ok(notices.concat());
+    }
+
+    /// Runs a trap's action. `$?` is left as it was before the trap ran.
+    fn run_trap(&mut self, action: &str) -> anyhow::Result<()> {
+        let statements = match statement::parse(action) {
+            Ok(statements) => statements,
+            Err(e) => return self.prompter.error(&format!("{}\n", e)),
+        };
+
+        let last_status = self.state.last_status;
+        statement::run(
+            statements,
+            &mut self.state,
+            &mut self.prompter,
+            &self.finder,
+            &self.runner,
+        )?;
+        self.state.last_status = last_status;
+        return Ok(());
+    }
+
+    /// Whether the session may end at the end of its input, as `exit` checks. The
+    /// first time there are stopped jobs it warns about them and returns `false`.
+    pub fn may_exit(&mut self) -> anyhow::Result<bool> {
+        let exit_warned = std::mem::take(&mut self.state.exit_warned);
+        return command::may_exit(exit_warned, &mut self.state, &mut self.prompter);
+    }
+
+    /// Runs the `EXIT` trap and, in an interactive session, `~/.shell_logout`. Called
+    /// as the shell exits at the end of its input; `exit` runs them itself.
+    pub fn run_exit_hooks(&mut self) -> anyhow::Result<()> {
+        return command::run_exit_hooks(
+            &mut self.state,
+            &mut self.prompter,
+            &self.finder,
+            &self.runner,
+        );
+    }
+
+    /// Runs every line of `reader` in turn, up to an `exit`, and returns the status
+    /// of the last one.
+    pub fn run_script(&mut self, reader: impl BufRead) -> anyhow::Result<i32> {
+        for line in reader.lines() {
+            self.run_line(line?.trim_end())?;
+            if self.exit_status().is_some() {
+                break;
+            }
+        }
+
+        return Ok(self.state.last_status);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{command::CommandOutput, state::ShellState};
+
+/// The options `shopt` knows. They share the shell's option set with the `set -o`
+/// ones but are only changed through `shopt`.
+pub const SHOPT_OPTIONS: &[&str] = &[
+    // Glob patterns match names starting with `.` without an explicit leading dot.
+    "dotglob",
+    // A glob pattern that matches no file is an error, and the command is not run.
+    "failglob",
+    // Glob patterns match file names regardless of case.
+    "nocaseglob",
+    // A glob pattern that matches no file expands to nothing instead of itself.
+    "nullglob",
+    // The shell waits for its running background jobs before it exits.
+    "waitjobs",
+    // `echo` interprets backslash escapes without `-e`.
+    "xpg_echo",
+];
+
+const USAGE: &str = "shopt: usage: shopt [-psu] [optname ...]";
+
+/// `shopt [-psu] [optname ...]`: `-s` turns the named options on and `-u` turns them
+/// off. Without either, prints each named option, or every option, with whether it is
+/// on; the status is then 1 when any named option is off. `-p` prints them as the
+/// `shopt` commands that recreate the settings.
+pub fn shopt(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let mut change = None;
+    let mut reusable = false;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        let Some(flags) = arg
+            .strip_prefix('-')
+            .filter(|flags| return !flags.is_empty())
+        else {
+            break;
+        };
+        index += 1;
+        if flags == "-" {
+            break;
+        }
+
+        for flag in flags.chars() {
+            match flag {
+                's' => change = Some(true),
+                'u' => change = Some(false),
+                'p' => reusable = true,
+                _ => {
+                    return CommandOutput {
+                        stdout: None,
+                        stderr: Some(format!("shopt: -{}: invalid option\n{}\n", flag, USAGE)),
+                        status: 2,
+                    };
+                }
+            }
+        }
+    }
+
+    let names = &args[index..];
+    if let Some(name) = names.iter().find(|name| {
+        return !SHOPT_OPTIONS.contains(&name.as_str());
+    }) {
+        return CommandOutput {
+            stdout: None,
+            stderr: Some(format!("shopt: {}: invalid shell option name\n", name)),
+            status: 1,
+        };
+    }
+
+    if let (Some(on), false) = (change, names.is_empty()) {
+        for name in names {
+            state.options.set_shopt(name, on);
+        }
+        return CommandOutput {
+            stdout: None,
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    // With `-s` or `-u` and no names, only the options that are on, or off, are listed.
+    let listed: Vec<&str> = match names.is_empty() {
+        true => SHOPT_OPTIONS
+            .iter()
+            .copied()
+            .filter(|name| return change.is_none_or(|on| return state.options.is_set(name) == on))
+            .collect(),
+        false => names.iter().map(|name| return name.as_str()).collect(),
+    };
+    let mut stdout = String::new();
+    for name in &listed {
+        let is_set = state.options.is_set(name);
+        match (reusable, is_set) {
+            (true, true) => stdout.push_str(&format!("shopt -s {}\n", name)),
+            (true, false) => stdout.push_str(&format!("shopt -u {}\n", name)),
+            (false, true) => stdout.push_str(&format!("{:<15}\ton\n", name)),
+            (false, false) => stdout.push_str(&format!("{:<15}\toff\n", name)),
+        }
+    }
+
+    let all_set = names.iter().all(|name| return state.options.is_set(name));
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| !stdout.is_empty()),
+        stderr: None,
+        status: if all_set { 0 } else { 1 },
+    };
+}
+
+#[cfg(test)]
+mod shopt_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn sets_unsets_and_queries_an_option() {
+        let mut state = ShellState::default();
+
+        let output = shopt(&mut state, &args(&["nullglob"]));
+        assert_eq!(output.stdout, Some(String::from("nullglob       \toff\n")));
+        assert_eq!(output.status, 1);
+
+        assert_eq!(shopt(&mut state, &args(&["-s", "nullglob"])).status, 0);
+        assert!(state.options.is_set("nullglob"));
+        let output = shopt(&mut state, &args(&["nullglob"]));
+        assert_eq!(output.stdout, Some(String::from("nullglob       \ton\n")));
+        assert_eq!(output.status, 0);
+        assert_eq!(
+            shopt(&mut state, &args(&["-p"])).stdout,
+            Some(String::from(
+                "shopt -u dotglob\nshopt -u failglob\nshopt -u nocaseglob\nshopt -s nullglob\nshopt -u waitjobs\nshopt -u xpg_echo\n"
+            ))
+        );
+        assert_eq!(
+            shopt(&mut state, &args(&["-s"])).stdout,
+            Some(String::from("nullglob       \ton\n"))
+        );
+
+        assert_eq!(shopt(&mut state, &args(&["-u", "nullglob"])).status, 0);
+        assert!(!state.options.is_set("nullglob"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut state = ShellState::default();
+
+        let output = shopt(&mut state, &args(&["nope"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("shopt: nope: invalid shell option name\n"))
+        );
+        assert_eq!(output.status, 1);
+
+        // `set -o` options are not `shopt` ones.
+        assert_eq!(shopt(&mut state, &args(&["-s", "noclobber"])).status, 1);
+        assert!(!state.options.is_set("noclobber"));
+
+        assert_eq!(shopt(&mut state, &args(&["-x"])).status, 2);
+    }
+}
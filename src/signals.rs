@@ -0,0 +1,417 @@
+use crate::{command::CommandOutput, state::ShellState};
+
+/// Signal names (without the `SIG` prefix) and their numbers on this platform.
+#[cfg(unix)]
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+#[cfg(not(unix))]
+pub const SIGNALS: &[(&str, i32)] = &[];
+
+const KILL_USAGE: &str =
+    "kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]";
+
+/// Translates a signal name (`TERM`, `SIGTERM`, case-insensitive) or number to its
+/// number. `0` is accepted as the "check the process exists" signal.
+pub fn signal_number(spec: &str) -> Option<i32> {
+    if let Ok(number) = spec.parse::<i32>() {
+        let is_known = number == 0 || SIGNALS.iter().any(|(_, value)| return *value == number);
+        return is_known.then_some(number);
+    }
+
+    let name = spec.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    return SIGNALS
+        .iter()
+        .find(|(signal_name, _)| return *signal_name == name)
+        .map(|(_, number)| return *number);
+}
+
+pub fn signal_name(number: i32) -> Option<&'static str> {
+    return SIGNALS
+        .iter()
+        .find(|(_, value)| return *value == number)
+        .map(|(name, _)| return *name);
+}
+
+/// `kill -l` without arguments: every signal as ` N) SIGNAME`, five per line.
+pub fn list_signals() -> String {
+    let mut signals = SIGNALS.to_vec();
+    signals.sort_by_key(|(_, number)| return *number);
+
+    let mut output = String::new();
+    for (index, (name, number)) in signals.iter().enumerate() {
+        output.push_str(&format!("{:2}) SIG{}", number, name));
+        let is_line_end = (index + 1) % 5 == 0 || index + 1 == signals.len();
+        output.push(if is_line_end { '\n' } else { '\t' });
+    }
+
+    return output;
+}
+
+/// `kill [-s sigspec | -n signum | -sigspec] pid | jobspec ...` and `kill -l [sigspec]`.
+pub fn kill(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let mut signal = signal_number("TERM").unwrap_or(15);
+    let mut targets = args;
+
+    match args {
+        [] => return failure(KILL_USAGE, 2),
+        [flag, rest @ ..] if flag == "-l" || flag == "-L" => return list(rest),
+        [flag, spec, rest @ ..] if flag == "-s" || flag == "-n" => {
+            let Some(number) = signal_number(spec) else {
+                return invalid_signal(spec);
+            };
+            signal = number;
+            targets = rest;
+        }
+        [flag, rest @ ..] if flag == "--" => targets = rest,
+        [flag, rest @ ..] if flag.starts_with('-') && flag.len() > 1 => {
+            let spec = &flag[1..];
+            let Some(number) = signal_number(spec) else {
+                return invalid_signal(spec);
+            };
+            signal = number;
+            targets = rest;
+        }
+        _ => {}
+    }
+
+    if targets.is_empty() {
+        return failure(KILL_USAGE, 2);
+    }
+
+    // Jobs sent `SIGCONT` run again; the stop signals stop them.
+    let stops = match signal_name(signal) {
+        Some("CONT") => Some(false),
+        Some("STOP" | "TSTP" | "TTIN" | "TTOU") => Some(true),
+        _ => None,
+    };
+
+    let mut stderr = String::new();
+    for target in targets {
+        let mut job_id = None;
+        let pid = if target.starts_with('%') {
+            match state.jobs.find(target) {
+                Some(job) => {
+                    job_id = Some(job.id);
+                    job.pid as i32
+                }
+                None => {
+                    stderr.push_str(&format!("kill: {}: no such job\n", target));
+                    continue;
+                }
+            }
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => pid,
+                Err(_) => {
+                    stderr.push_str(&format!(
+                        "kill: {}: arguments must be process or job IDs\n",
+                        target
+                    ));
+                    continue;
+                }
+            }
+        };
+
+        if let Err(e) = send_signal(pid, signal) {
+            let message = match e.raw_os_error() {
+                Some(libc::ESRCH) => String::from("No such process"),
+                Some(libc::EPERM) => String::from("Operation not permitted"),
+                _ => e.to_string(),
+            };
+            stderr.push_str(&format!("kill: ({}) - {}\n", pid, message));
+            continue;
+        }
+        if let (Some(id), Some(stopped)) = (job_id, stops) {
+            state.jobs.set_stopped(id, stopped);
+        }
+    }
+
+    let status = if stderr.is_empty() { 0 } else { 1 };
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(stderr).filter(|stderr| return !stderr.is_empty()),
+        status,
+    };
+}
+
+/// `trap [-lp] [[action] sigspec ...]`: sets the command run when a signal arrives,
+/// or with `EXIT` (`0`) when the shell exits. An action of `-`, or a lone sigspec,
+/// resets the signals; `-p` or no arguments list the traps. Of the signals only `CHLD`
+/// handlers are run for now, as finished background jobs are reaped.
+pub fn trap(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let args = match args {
+        [flag, rest @ ..] if flag == "--" => rest,
+        _ => args,
+    };
+
+    let (action, specs) = match args {
+        [] => return list_traps(state, &[]),
+        [flag, rest @ ..] if flag == "-p" => return list_traps(state, rest),
+        [flag, ..] if flag == "-l" => return list(&[]),
+        [flag, ..] if flag.starts_with('-') && flag.len() > 1 && flag != "-" => {
+            return failure(
+                &format!(
+                    "trap: {}: invalid option\ntrap: usage: trap [-lp] [[action] sigspec ...]",
+                    flag
+                ),
+                2,
+            );
+        }
+        [spec] => ("-", std::slice::from_ref(spec)),
+        [action, specs @ ..] => (action.as_str(), specs),
+    };
+
+    let mut stderr = String::new();
+    for spec in specs {
+        let Some(name) = trap_name(spec) else {
+            stderr.push_str(&format!("trap: {}: invalid signal specification\n", spec));
+            continue;
+        };
+        match action {
+            "-" => state.traps.remove(name),
+            _ => state.traps.insert(name.to_string(), action.to_string()),
+        };
+    }
+
+    let status = if stderr.is_empty() { 0 } else { 1 };
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(stderr).filter(|stderr| return !stderr.is_empty()),
+        status,
+    };
+}
+
+/// The name a trap is kept under: the signal's, or `EXIT`.
+fn trap_name(spec: &str) -> Option<&'static str> {
+    if spec.eq_ignore_ascii_case("EXIT") {
+        return Some("EXIT");
+    }
+
+    match signal_number(spec)? {
+        0 => return Some("EXIT"),
+        number => return signal_name(number),
+    }
+}
+
+/// The traps for `specs`, or all of them, as the `trap` commands that set them.
+fn list_traps(state: &ShellState, specs: &[String]) -> CommandOutput {
+    let mut names = vec![];
+    for spec in specs {
+        match trap_name(spec) {
+            Some(name) => names.push(name),
+            None => {
+                return failure(&format!("trap: {}: invalid signal specification", spec), 1);
+            }
+        }
+    }
+
+    let stdout: String = state
+        .traps
+        .iter()
+        .filter(|(name, _)| return names.is_empty() || names.contains(&name.as_str()))
+        .map(|(name, action)| {
+            let prefix = if name == "EXIT" { "" } else { "SIG" };
+            return format!(
+                "trap -- '{}' {}{}\n",
+                action.replace('\'', r"'\''"),
+                prefix,
+                name
+            );
+        })
+        .collect();
+    return CommandOutput {
+        stdout: Some(stdout).filter(|stdout| return !stdout.is_empty()),
+        stderr: None,
+        status: 0,
+    };
+}
+
+/// `kill -l [sigspec ...]`: names for numbers and numbers for names.
+fn list(specs: &[String]) -> CommandOutput {
+    if specs.is_empty() {
+        return CommandOutput {
+            stdout: Some(list_signals()),
+            stderr: None,
+            status: 0,
+        };
+    }
+
+    let mut stdout = String::new();
+    for spec in specs {
+        let translated = match spec.parse::<i32>() {
+            // Exit statuses of signalled processes (128 + N) list as the signal too.
+            Ok(number) => signal_name(if number > 128 { number - 128 } else { number })
+                .map(|name| return name.to_string()),
+            Err(_) => signal_number(spec).map(|number| return number.to_string()),
+        };
+        let Some(translated) = translated else {
+            return invalid_signal(spec);
+        };
+        stdout.push_str(&format!("{}\n", translated));
+    }
+
+    return CommandOutput {
+        stdout: Some(stdout),
+        stderr: None,
+        status: 0,
+    };
+}
+
+#[cfg(unix)]
+fn send_signal(pid: i32, signal: i32) -> std::io::Result<()> {
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: i32, _signal: i32) -> std::io::Result<()> {
+    return Err(std::io::Error::from(std::io::ErrorKind::Unsupported));
+}
+
+fn invalid_signal(spec: &str) -> CommandOutput {
+    return failure(&format!("kill: {}: invalid signal specification", spec), 1);
+}
+
+fn failure(message: &str, status: i32) -> CommandOutput {
+    return CommandOutput {
+        stdout: None,
+        stderr: Some(format!("{}\n", message)),
+        status,
+    };
+}
+
+#[cfg(all(test, unix))]
+mod signals_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn name_to_number() {
+        assert_eq!(signal_number("KILL"), Some(9));
+        assert_eq!(signal_number("SIGTERM"), Some(15));
+        assert_eq!(signal_number("term"), Some(15));
+        assert_eq!(signal_number("9"), Some(9));
+        assert_eq!(signal_number("0"), Some(0));
+        assert_eq!(signal_number("NOPE"), None);
+        assert_eq!(signal_number("999"), None);
+        assert_eq!(signal_name(2), Some("INT"));
+    }
+
+    #[test]
+    fn list_output() {
+        let listing = list_signals();
+        assert!(listing.starts_with(" 1) SIGHUP\t 2) SIGINT\t 3) SIGQUIT"));
+        assert!(listing.contains(" 9) SIGKILL"));
+        assert!(listing.ends_with('\n'));
+
+        let mut state = ShellState::default();
+        let output = kill(&mut state, &args(&["-l", "9", "TERM", "130"]));
+        assert_eq!(output.stdout, Some("KILL\n15\nINT\n".to_string()));
+    }
+
+    #[test]
+    fn errors() {
+        let mut state = ShellState::default();
+
+        let output = kill(&mut state, &args(&["-FOO", "1"]));
+        assert_eq!(
+            output.stderr,
+            Some("kill: FOO: invalid signal specification\n".to_string())
+        );
+        assert_eq!(output.status, 1);
+
+        let output = kill(&mut state, &args(&["%1"]));
+        assert_eq!(output.stderr, Some("kill: %1: no such job\n".to_string()));
+
+        let output = kill(&mut state, &args(&["abc"]));
+        assert_eq!(
+            output.stderr,
+            Some("kill: abc: arguments must be process or job IDs\n".to_string())
+        );
+
+        assert_eq!(kill(&mut state, &[]).status, 2);
+    }
+
+    #[test]
+    fn signals_a_process() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let mut state = ShellState::default();
+
+        let output = kill(&mut state, &args(&["-9", &child.id().to_string()]));
+        assert_eq!(output.status, 0);
+        assert_eq!(child.wait().unwrap().code(), None);
+    }
+
+    #[test]
+    fn traps_are_set_listed_and_reset() {
+        let mut state = ShellState::default();
+
+        let output = trap(&mut state, &args(&["echo 'done'", "SIGCHLD", "int"]));
+        assert_eq!(output.status, 0);
+        assert_eq!(
+            trap(&mut state, &[]).stdout,
+            Some(String::from(
+                "trap -- 'echo '\\''done'\\''' SIGCHLD\ntrap -- 'echo '\\''done'\\''' SIGINT\n"
+            ))
+        );
+        assert_eq!(
+            trap(&mut state, &args(&["-p", "INT"])).stdout,
+            Some(String::from("trap -- 'echo '\\''done'\\''' SIGINT\n"))
+        );
+
+        assert_eq!(trap(&mut state, &args(&["echo bye", "0"])).status, 0);
+        assert_eq!(
+            trap(&mut state, &args(&["-p", "exit"])).stdout,
+            Some(String::from("trap -- 'echo bye' EXIT\n"))
+        );
+        assert_eq!(trap(&mut state, &args(&["-", "EXIT", "CHLD"])).status, 0);
+        assert_eq!(trap(&mut state, &args(&["INT"])).status, 0);
+        assert!(state.traps.is_empty());
+
+        let output = trap(&mut state, &args(&["true", "NOPE"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from("trap: NOPE: invalid signal specification\n"))
+        );
+        assert_eq!(output.status, 1);
+        assert_eq!(trap(&mut state, &args(&["-x"])).status, 2);
+    }
+}
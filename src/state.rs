@@ -0,0 +1,351 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Component, Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    executable::ExecutablePathFinder, hash::HashTable, history::History, jobs::JobTable,
+    local::Scope, random::Random, set::ShellOptions, substitution::ProcessSubstitution,
+    variables::Variables,
+};
+
+/// The field separators when `IFS` is not set.
+pub const DEFAULT_IFS: &str = " \t\n";
+
+#[derive(Debug, Default)]
+pub struct ShellState {
+    pub variables: Variables,
+    pub positional_params: Vec<String>,
+    pub last_status: i32,
+    /// Exit status of each stage of the last pipeline, read through `PIPESTATUS`.
+    pub pipe_status: Vec<i32>,
+    /// `OPTIND` value and character offset inside a grouped option word (e.g. `-ab`)
+    /// where the next `getopts` call resumes.
+    pub getopts_cursor: (usize, usize),
+    pub hash_table: HashTable,
+    /// Directories saved by `pushd`, most recent last.
+    pub dir_stack: Vec<PathBuf>,
+    pub jobs: JobTable,
+    pub history: History,
+    /// Function bodies by name, as written in their definitions.
+    pub functions: BTreeMap<String, String>,
+    /// Generates `$RANDOM`.
+    pub random: Random,
+    pub seconds: Seconds,
+    /// The input line being run, read through `LINENO`.
+    pub line_number: usize,
+    /// How many command substitutions this shell is nested in.
+    pub substitution_depth: usize,
+    /// The status of the last command substitution run while expanding the current
+    /// command. A command of only assignments ends with it.
+    pub substitution_status: Option<i32>,
+    pub options: ShellOptions,
+    /// Set when commands are read from a terminal.
+    pub interactive: bool,
+    /// How many loops the running command is inside of.
+    pub loop_depth: usize,
+    /// Set by `break`; the commands left in the loop body are skipped.
+    pub breaking: bool,
+    /// Set by an `exit` refused because of stopped jobs. Only an `exit` right after
+    /// it leaves them behind.
+    pub exit_warned: bool,
+    /// Set by `exit` to the status the shell exits with. Nothing more runs once it
+    /// is set; whoever drives the shell ends it.
+    pub exiting: Option<i32>,
+    /// The pipes of the `<(command)` words of the running commands, kept open until
+    /// the command using them is done.
+    pub process_substitutions: Vec<ProcessSubstitution>,
+    /// One scope per running function call, innermost last.
+    pub local_scopes: Vec<Scope>,
+    /// Commands set with `trap`, by signal name without the `SIG` prefix.
+    pub traps: BTreeMap<String, String>,
+    /// The script being run and the files being sourced from it, innermost last.
+    /// Read through `BASH_SOURCE`.
+    pub sources: Vec<String>,
+}
+
+impl ShellState {
+    pub fn new() -> Self {
+        let mut variables = Variables::from_env();
+        variables.set("OPTIND", "1");
+
+        return Self {
+            variables,
+            positional_params: vec![],
+            last_status: 0,
+            pipe_status: vec![],
+            getopts_cursor: (1, 0),
+            hash_table: HashTable::new(),
+            dir_stack: vec![],
+            jobs: JobTable::new(),
+            history: History::new(),
+            functions: BTreeMap::new(),
+            random: Random::default(),
+            seconds: Seconds::default(),
+            line_number: 0,
+            substitution_depth: 0,
+            substitution_status: None,
+            options: ShellOptions::default(),
+            interactive: false,
+            loop_depth: 0,
+            breaking: false,
+            exit_warned: false,
+            exiting: None,
+            process_substitutions: vec![],
+            local_scopes: vec![],
+            traps: BTreeMap::new(),
+            sources: vec![],
+        };
+    }
+
+    /// `name=value`. `RANDOM` and `SECONDS` restart their sequences instead of
+    /// storing the value.
+    pub fn assign(&mut self, name: &str, value: &str) {
+        match name {
+            "RANDOM" => self.random.seed(value.trim().parse().unwrap_or(0)),
+            "SECONDS" => self.seconds.set(value.trim().parse().unwrap_or(0)),
+            _ => self.variables.set(name, value),
+        }
+    }
+
+    /// Runs `run` on a subshell of this shell, in this process. The working directory
+    /// it leaves is put back afterwards, even when `run` fails; whatever else it
+    /// changes goes with the subshell's state.
+    pub fn in_subshell<T>(
+        &self,
+        run: impl FnOnce(&mut ShellState) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut subshell = self.subshell();
+        let working_dir = std::env::current_dir()?;
+        let result = run(&mut subshell);
+        std::env::set_current_dir(&working_dir)?;
+        return result;
+    }
+
+    /// A copy of the shell for running a command substitution: variables, functions
+    /// and parameters are shared, but nothing it changes reaches this shell.
+    pub fn subshell(&self) -> Self {
+        return Self {
+            variables: self.variables.clone(),
+            positional_params: self.positional_params.clone(),
+            last_status: self.last_status,
+            pipe_status: self.pipe_status.clone(),
+            getopts_cursor: self.getopts_cursor,
+            hash_table: self.hash_table.clone(),
+            dir_stack: self.dir_stack.clone(),
+            jobs: self.jobs.subshell(),
+            history: History::new(),
+            functions: self.functions.clone(),
+            random: Random::default(),
+            seconds: self.seconds.clone(),
+            line_number: self.line_number,
+            substitution_depth: self.substitution_depth,
+            substitution_status: None,
+            options: self.options.clone(),
+            interactive: false,
+            loop_depth: 0,
+            breaking: false,
+            exit_warned: false,
+            exiting: None,
+            process_substitutions: vec![],
+            local_scopes: self.local_scopes.clone(),
+            traps: BTreeMap::new(),
+            sources: self.sources.clone(),
+        };
+    }
+}
+
+/// Backs `$SECONDS`: the whole seconds since the shell started, or since the value
+/// was last set.
+#[derive(Debug, Clone)]
+pub struct Seconds {
+    start: Instant,
+    base: u64,
+}
+
+impl Default for Seconds {
+    fn default() -> Self {
+        return Self {
+            start: Instant::now(),
+            base: 0,
+        };
+    }
+}
+
+impl Seconds {
+    pub fn get(&self) -> u64 {
+        return self.base + self.start.elapsed().as_secs();
+    }
+
+    /// Restarts the count from `value`.
+    pub fn set(&mut self, value: u64) {
+        self.start = Instant::now();
+        self.base = value;
+    }
+}
+
+impl ShellState {
+    /// The characters in `IFS`, which word splitting, `$*` and `read` all go by.
+    /// Unset means space, tab and newline; empty means nothing is split.
+    pub fn ifs(&self) -> &str {
+        return self.variables.get("IFS").unwrap_or(DEFAULT_IFS);
+    }
+
+    /// Whether the locale's character encoding is UTF-8, going by `LC_ALL`, then
+    /// `LC_CTYPE`, then `LANG`. With none of them set, UTF-8 is assumed.
+    pub fn uses_utf8(&self) -> bool {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|name| return self.variables.get(name))
+            .find(|value| return !value.is_empty());
+        let Some(locale) = locale else {
+            return true;
+        };
+
+        let encoding = locale
+            .split_once('.')
+            .map_or("", |(_, encoding)| return encoding);
+        let encoding = encoding.split('@').next().unwrap_or_default();
+        return encoding.eq_ignore_ascii_case("UTF-8") || encoding.eq_ignore_ascii_case("utf8");
+    }
+
+    /// The search path commands are looked up in.
+    pub fn env_path(&self) -> String {
+        return self.variables.get("PATH").unwrap_or_default().to_string();
+    }
+
+    /// The path to run `name` from. Names with a slash are run as given; everything
+    /// else goes through the hash table.
+    pub fn command_path(&mut self, finder: &impl ExecutablePathFinder, name: &str) -> String {
+        if name.contains('/') {
+            return name.to_string();
+        }
+
+        let env_path = self.env_path();
+        return self
+            .hash_table
+            .resolve(finder, &env_path, name)
+            .unwrap_or(name.to_string());
+    }
+
+    /// The logical working directory: `$PWD` when it names the current directory
+    /// (possibly through symlinks), otherwise the physical one.
+    pub fn current_dir(&self) -> io::Result<PathBuf> {
+        let physical = std::env::current_dir()?;
+        if let Some(pwd) = self.variables.get("PWD").map(Path::new) {
+            if pwd.is_absolute() && is_same_file(pwd, &physical) {
+                return Ok(pwd.to_path_buf());
+            }
+        }
+
+        return Ok(physical);
+    }
+
+    /// Changes the working directory, keeping `PWD` as the logical path (symlinks are
+    /// not resolved and `..` drops the previous component) and `OLDPWD` as the previous one.
+    /// Under `set -P` the target is resolved first and `PWD` is the physical path.
+    pub fn change_dir(&mut self, path: &Path) -> io::Result<()> {
+        let old_pwd = self.current_dir()?;
+        let pwd = if self.options.is_set("physical") {
+            let physical = path.canonicalize()?;
+            std::env::set_current_dir(&physical)?;
+            physical
+        } else {
+            let logical = logical_path(&old_pwd, path);
+            match std::env::set_current_dir(&logical) {
+                Ok(()) => logical,
+                Err(_) => {
+                    std::env::set_current_dir(path)?;
+                    std::env::current_dir()?
+                }
+            }
+        };
+
+        self.variables.set("OLDPWD", &old_pwd.display().to_string());
+        self.variables.set("PWD", &pwd.display().to_string());
+        self.variables.export("OLDPWD");
+        self.variables.export("PWD");
+        return Ok(());
+    }
+}
+
+fn logical_path(base: &Path, path: &Path) -> PathBuf {
+    let mut logical = if path.is_absolute() {
+        PathBuf::new()
+    } else {
+        base.to_path_buf()
+    };
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                logical.pop();
+            }
+            _ => logical.push(component),
+        }
+    }
+
+    return logical;
+}
+
+#[cfg(unix)]
+fn is_same_file(first: &Path, second: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let (Ok(first), Ok(second)) = (first.metadata(), second.metadata()) else {
+        return false;
+    };
+    return first.dev() == second.dev() && first.ino() == second.ino();
+}
+
+#[cfg(not(unix))]
+fn is_same_file(first: &Path, second: &Path) -> bool {
+    return match (first.canonicalize(), second.canonicalize()) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    };
+}
+
+#[cfg(test)]
+mod state_tests {
+    use std::path::Path;
+
+    use super::{logical_path, ShellState};
+
+    #[test]
+    fn utf8_locales() {
+        let mut state = ShellState::default();
+        assert!(state.uses_utf8());
+
+        for (locale, utf8) in [
+            ("en_US.UTF-8", true),
+            ("C.utf8", true),
+            ("de_DE.UTF-8@euro", true),
+            ("en_US.ISO-8859-1", false),
+            ("C", false),
+        ] {
+            state.variables.set("LANG", locale);
+            assert_eq!(state.uses_utf8(), utf8, "{}", locale);
+        }
+
+        state.variables.set("LC_ALL", "en_US.UTF-8");
+        assert!(state.uses_utf8());
+    }
+
+    #[test]
+    fn logical_paths() {
+        let base = Path::new("/home/user/link");
+        assert_eq!(logical_path(base, Path::new("..")), Path::new("/home/user"));
+        assert_eq!(
+            logical_path(base, Path::new("./docs/../src")),
+            Path::new("/home/user/link/src")
+        );
+        assert_eq!(
+            logical_path(base, Path::new("/tmp/./x")),
+            Path::new("/tmp/x")
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use anyhow::anyhow;
+
+use crate::{
+    command::{self, unexpected_token, Command, CommandResult},
+    conditional::{self, Condition},
+    executable::{ExecutablePathFinder, ExecutableRunner},
+    local,
+    prompt::Prompter,
+    state::ShellState,
+    tokenizer::{self, tokenize, Token},
+    variables::is_name,
+};
+
+/// One `;`-separated part of an input line.
+#[derive(Debug, PartialEq)]
+pub enum Statement {
+    Command(Command),
+    /// `name() { body; }`: the body is kept as written and parsed on every call.
+    Function {
+        name: String,
+        body: String,
+    },
+    /// `first && second`: `second` runs only if `first` succeeds.
+    And(Box<Statement>, Box<Statement>),
+    /// `first || second`: `second` runs only if `first` fails.
+    Or(Box<Statement>, Box<Statement>),
+    /// `[[ expression ]]`: succeeds when the expression holds.
+    Conditional(Condition),
+    /// `select name [in words]; do body; done`: a numbered menu of the words (the
+    /// positional parameters without `in`), running the body for each choice read.
+    Select {
+        name: String,
+        words: Option<Vec<String>>,
+        body: String,
+    },
+}
+
+/// Splits a line into statements on unquoted `;` and `&`, recognising function
+/// definitions and `&&`/`||` lists. A command before `&` runs in the background.
+pub fn parse(input: &str) -> anyhow::Result<Vec<Statement>> {
+    let tokens = tokenize(input)?;
+    let mut statements = vec![];
+    let mut rest = tokens.as_slice();
+
+    while !rest.is_empty() {
+        let (statement, end) = parse_list(rest)?;
+        statements.push(statement);
+
+        // The `&` already belongs to the statement, and separates it from the next.
+        if ends_in_background(&rest[..end]) {
+            match rest.get(end) {
+                Some(next @ Token::Semicolon) => return Err(unexpected_token(next)),
+                _ => rest = &rest[end..],
+            }
+            continue;
+        }
+
+        match rest.get(end) {
+            None | Some(Token::Semicolon) => {}
+            Some(next) => return Err(unexpected_token(next)),
+        }
+        // Skip the separator, if any.
+        rest = rest.get(end + 1..).unwrap_or_default();
+    }
+
+    return Ok(statements);
+}
+
+/// Parses `first && second || third ...`, returning the statement and the number of
+/// tokens it used. `&&` and `||` have equal precedence and group to the left.
+fn parse_list(tokens: &[Token]) -> anyhow::Result<(Statement, usize)> {
+    let (mut statement, mut end) = parse_single(tokens)?;
+
+    while let Some(operator @ (Token::And | Token::Or)) = tokens.get(end) {
+        if ends_in_background(&tokens[..end]) {
+            return Err(unexpected_token(operator));
+        }
+        let (next, used) = parse_single(&tokens[end + 1..])?;
+        statement = match operator {
+            Token::And => Statement::And(Box::new(statement), Box::new(next)),
+            _ => Statement::Or(Box::new(statement), Box::new(next)),
+        };
+        end += 1 + used;
+    }
+
+    return Ok((statement, end));
+}
+
+/// Parses a function definition or a pipeline, returning it and the number of tokens
+/// it used.
+fn parse_single(tokens: &[Token]) -> anyhow::Result<(Statement, usize)> {
+    match tokens.first() {
+        None => return Err(anyhow!("syntax error: unexpected end of file")),
+        Some(token @ (Token::Semicolon | Token::And | Token::Or)) => {
+            return Err(unexpected_token(token));
+        }
+        Some(_) => {}
+    }
+
+    if let Some((name, body_start)) = function_header(tokens) {
+        let body_end = closing_brace(tokens, body_start)?;
+        let body = tokenizer::reserialize(&tokens[body_start..body_end]);
+
+        return Ok((Statement::Function { name, body }, body_end + 1));
+    }
+
+    if tokens.first() == Some(&Token::Word(String::from("[["))) {
+        let end = tokens
+            .iter()
+            .position(|token| return *token == Token::Word(String::from("]]")))
+            .ok_or(anyhow!("syntax error: unexpected end of file"))?;
+        let condition = conditional::parse(&tokens[1..end])?;
+        return Ok((Statement::Conditional(condition), end + 1));
+    }
+
+    if tokens.first() == Some(&Token::Word(String::from("select"))) {
+        return parse_select(tokens);
+    }
+
+    let end = match tokens.iter().position(|token| {
+        return matches!(
+            token,
+            Token::Semicolon | Token::And | Token::Or | Token::Background
+        );
+    }) {
+        // The `&` stays with the command, marking it for the background.
+        Some(index) if tokens[index] == Token::Background => index + 1,
+        Some(index) => index,
+        None => tokens.len(),
+    };
+    return Ok((
+        Statement::Command(Command::from_tokens(&tokens[..end])?),
+        end,
+    ));
+}
+
+fn ends_in_background(tokens: &[Token]) -> bool {
+    return tokens.last() == Some(&Token::Background);
+}
+
+/// Runs the statements in order and returns how the last one finished.
+pub fn run(
+    statements: Vec<Statement>,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<CommandResult> {
+    let mut result = CommandResult {
+        status: state.last_status,
+    };
+    for statement in statements {
+        result = run_statement(statement, state, prompter, finder, runner)?;
+        if state.breaking || state.exiting.is_some() {
+            break;
+        }
+    }
+
+    return Ok(result);
+}
+
+fn run_statement(
+    statement: Statement,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<CommandResult> {
+    match statement {
+        Statement::Command(command) => {
+            // Commands run inside this one (by a function, say) close only their own.
+            let open = state.process_substitutions.len();
+            let result = command.run(state, prompter, finder, runner);
+            state.process_substitutions.truncate(open);
+            return result;
+        }
+        Statement::Function { name, body } => {
+            state.functions.insert(name, body);
+            state.last_status = 0;
+        }
+        Statement::And(first, second) => {
+            let result = run_statement(*first, state, prompter, finder, runner)?;
+            if result.status != 0 || state.breaking || state.exiting.is_some() {
+                return Ok(result);
+            }
+            return run_statement(*second, state, prompter, finder, runner);
+        }
+        Statement::Or(first, second) => {
+            let result = run_statement(*first, state, prompter, finder, runner)?;
+            if result.status == 0 || state.breaking || state.exiting.is_some() {
+                return Ok(result);
+            }
+            return run_statement(*second, state, prompter, finder, runner);
+        }
+        Statement::Conditional(condition) => {
+            let result = command::with_expander(state, prompter, finder, runner, |expander| {
+                return conditional::evaluate(&condition, expander);
+            });
+            state.last_status = match result {
+                Ok(holds) => i32::from(!holds),
+                Err(e) => {
+                    prompter.error(&format!("{}\n", e))?;
+                    2
+                }
+            };
+        }
+        Statement::Select { name, words, body } => {
+            run_select(&name, words, &body, state, prompter, finder, runner)?;
+        }
+    }
+
+    return Ok(CommandResult {
+        status: state.last_status,
+    });
+}
+
+/// Shows the menu, then reads choices until the input ends or the body runs
+/// `break`. An empty line shows the menu again. Each choice is kept in `REPLY`, and
+/// `name` is set to the word it numbers, or emptied when it numbers none.
+fn run_select(
+    name: &str,
+    words: Option<Vec<String>>,
+    body: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<()> {
+    let words = match words {
+        Some(words) => match command::expand_words(&words, state, prompter, finder, runner) {
+            Ok(words) => words,
+            Err(e) => {
+                prompter.error(&format!("{}\n", e))?;
+                state.last_status = 1;
+                return Ok(());
+            }
+        },
+        None => state.positional_params.clone(),
+    };
+    state.last_status = 0;
+    if words.is_empty() {
+        return Ok(());
+    }
+
+    let width = words.len().to_string().len();
+    let menu: String = words
+        .iter()
+        .enumerate()
+        .map(|(index, word)| return format!("{:>width$}) {}\n", index + 1, word))
+        .collect();
+
+    let mut show_menu = true;
+    loop {
+        if show_menu {
+            prompter.error(&menu)?;
+        }
+        let ps3 = state.variables.get("PS3").unwrap_or("#? ").to_string();
+        prompter.error(&ps3)?;
+
+        let Some(line) = prompter.read()? else {
+            prompter.error("\n")?;
+            state.last_status = 1;
+            return Ok(());
+        };
+        show_menu = line.trim().is_empty();
+        if show_menu {
+            continue;
+        }
+
+        let choice = line
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| return words.get(number.checked_sub(1)?))
+            .cloned()
+            .unwrap_or_default();
+        state.assign("REPLY", &line);
+        state.assign(name, &choice);
+
+        state.loop_depth += 1;
+        let result = run_line(body, state, prompter, finder, runner);
+        state.loop_depth -= 1;
+        result?;
+        if state.breaking {
+            state.breaking = false;
+            return Ok(());
+        }
+        if state.exiting.is_some() {
+            return Ok(());
+        }
+    }
+}
+
+/// Parses and runs a line; syntax errors are reported with status 2.
+pub fn run_line(
+    input: &str,
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<CommandResult> {
+    match parse(input) {
+        Ok(statements) => return run(statements, state, prompter, finder, runner),
+        Err(e) => {
+            prompter.error(&format!("{}\n", e))?;
+            state.last_status = 2;
+            return Ok(CommandResult { status: 2 });
+        }
+    }
+}
+
+/// Runs a function body with `args` as the positional parameters.
+pub fn call_function(
+    body: &str,
+    args: &[String],
+    state: &mut ShellState,
+    prompter: &mut impl Prompter,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<CommandResult> {
+    let caller_params = std::mem::replace(&mut state.positional_params, args.to_vec());
+    local::enter(state);
+    let result = run_line(body, state, prompter, finder, runner);
+    local::leave(state);
+    state.positional_params = caller_params;
+
+    return result;
+}
+
+/// Parses `select name [in words ...]; do body; done`, returning the statement and
+/// the number of tokens it used. Like a function body, the loop body is kept as
+/// written.
+fn parse_select(tokens: &[Token]) -> anyhow::Result<(Statement, usize)> {
+    let name = match tokens.get(1) {
+        Some(Token::Word(name)) if is_name(name) => name.clone(),
+        Some(token) => return Err(unexpected_token(token)),
+        None => return Err(anyhow!("syntax error: unexpected end of file")),
+    };
+
+    let mut index = 2;
+    let mut words = None;
+    if tokens.get(index) == Some(&Token::Word(String::from("in"))) {
+        index += 1;
+        let mut listed = vec![];
+        while let Some(Token::Word(word)) = tokens.get(index) {
+            listed.push(word.clone());
+            index += 1;
+        }
+        words = Some(listed);
+    }
+    if tokens.get(index) == Some(&Token::Semicolon) {
+        index += 1;
+    }
+    match tokens.get(index) {
+        Some(Token::Word(word)) if word == "do" => index += 1,
+        Some(token) => return Err(unexpected_token(token)),
+        None => return Err(anyhow!("syntax error: unexpected end of file")),
+    }
+
+    let done = closing_done(tokens, index)?;
+    let body = tokenizer::reserialize(&tokens[index..done]);
+
+    return Ok((Statement::Select { name, words, body }, done + 1));
+}
+
+/// Finds the `done` closing a loop body that starts at `start`, skipping over the
+/// bodies of nested loops. Only a `done` in command position counts.
+fn closing_done(tokens: &[Token], start: usize) -> anyhow::Result<usize> {
+    let mut depth = 0;
+    let mut command_position = true;
+
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        let Token::Word(word) = token else {
+            command_position = !matches!(token, Token::Redirect { .. });
+            continue;
+        };
+
+        match word.as_str() {
+            "done" if command_position && depth == 0 => return Ok(index),
+            "done" if command_position => depth -= 1,
+            "select" if command_position => depth += 1,
+            _ => {}
+        }
+        command_position = matches!(word.as_str(), "do" | "{" | "}");
+    }
+
+    return Err(anyhow!("syntax error: unexpected end of file"));
+}
+
+/// Matches `name() {`, `name () {` and `function name {`, returning the name and the
+/// index of the first body token.
+fn function_header(tokens: &[Token]) -> Option<(String, usize)> {
+    let word = |index: usize| -> Option<&str> {
+        match tokens.get(index) {
+            Some(Token::Word(word)) => return Some(word.as_str()),
+            _ => return None,
+        }
+    };
+
+    let (name, brace_index) = match (word(0)?, word(1)) {
+        ("function", Some(name)) => {
+            let name = name.strip_suffix("()").unwrap_or(name);
+            match word(2) {
+                Some("()") => (name, 3),
+                _ => (name, 2),
+            }
+        }
+        (name, Some("()")) => (name, 2),
+        (name, _) => (name.strip_suffix("()")?, 1),
+    };
+
+    if !is_function_name(name) || word(brace_index)? != "{" {
+        return None;
+    }
+
+    return Some((name.to_string(), brace_index + 1));
+}
+
+fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    return (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+}
+
+/// Finds the `}` closing a body that starts at `start`. Braces only count in command
+/// position or after a nested function header, so `echo }` does not close anything.
+fn closing_brace(tokens: &[Token], start: usize) -> anyhow::Result<usize> {
+    let mut depth = 0;
+    let mut command_position = true;
+    let mut previous = "";
+
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        let Token::Word(word) = token else {
+            command_position = !matches!(token, Token::Redirect { .. });
+            previous = "";
+            continue;
+        };
+
+        match word.as_str() {
+            "}" if command_position && depth == 0 => return Ok(index),
+            "}" if command_position => depth -= 1,
+            "{" if command_position => depth += 1,
+            _ => {}
+        }
+        command_position =
+            word == "{" || word == "}" || word.ends_with("()") || previous == "function";
+        previous = word;
+    }
+
+    return Err(anyhow!("syntax error: unexpected end of file"));
+}
+
+#[cfg(test)]
+mod statement_tests {
+    use super::*;
+
+    fn functions(input: &str) -> Vec<(String, String)> {
+        return parse(input)
+            .unwrap()
+            .into_iter()
+            .filter_map(|statement| match statement {
+                Statement::Function { name, body } => return Some((name, body)),
+                _ => return None,
+            })
+            .collect();
+    }
+
+    #[test]
+    fn separators() -> anyhow::Result<()> {
+        assert_eq!(parse("echo a; echo b;")?.len(), 2);
+        assert_eq!(parse("echo 'a;b'")?.len(), 1);
+        assert!(parse("; echo a").is_err());
+        assert!(parse("echo a;; echo b").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn ampersand_separates_and_backgrounds() -> anyhow::Result<()> {
+        let statements = parse("sleep 1 & echo done")?;
+        assert_eq!(
+            statements,
+            vec![
+                Statement::Command("sleep 1 &".parse()?),
+                Statement::Command("echo done".parse()?),
+            ]
+        );
+        assert_ne!(statements[1], Statement::Command("echo done &".parse()?));
+
+        assert_eq!(parse("a & b & c")?.len(), 3);
+        assert_eq!(parse("a &")?.len(), 1);
+        assert_eq!(parse("echo 'a & b' \\& c")?.len(), 1);
+        assert_eq!(parse("a && b & c")?.len(), 2);
+        assert!(parse("a & ; b").is_err());
+        assert!(parse("a & && b").is_err());
+        assert!(parse("& a").is_err());
+        assert!(parse("a & &").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn and_or_lists() -> anyhow::Result<()> {
+        let statements = parse("a && b || c; d")?;
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(
+            &statements[0],
+            Statement::Or(first, _) if matches!(**first, Statement::And(_, _))
+        ));
+
+        assert!(parse("a &&").is_err());
+        assert!(parse("|| a").is_err());
+        assert!(parse("a && ; b").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn function_definitions() {
+        let greet = (String::from("greet"), String::from("echo hi ;"));
+        assert_eq!(functions("greet() { echo hi; }"), vec![greet.clone()]);
+        assert_eq!(functions("greet () { echo hi; }"), vec![greet.clone()]);
+        assert_eq!(
+            functions("function greet { echo hi; }"),
+            vec![greet.clone()]
+        );
+        assert_eq!(functions("function greet() { echo hi; }"), vec![greet]);
+
+        assert_eq!(
+            functions("outer() { inner() { echo }; }; }; echo done"),
+            vec![(
+                String::from("outer"),
+                String::from("inner() { echo } ; } ;")
+            )]
+        );
+    }
+
+    #[test]
+    fn unterminated_function() {
+        assert!(parse("greet() { echo hi }").is_err());
+        assert!(parse("greet() { echo hi; } extra").is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::anyhow;
+
+use crate::{
+    executable::{ExecutablePathFinder, ExecutableRunner},
+    prompt::Prompter,
+    state::ShellState,
+    statement,
+};
+
+/// How many command substitutions may be nested inside each other before expansion
+/// fails. `SHELL_EXPANSION_DEPTH` overrides it.
+pub const DEFAULT_EXPANSION_DEPTH: usize = 32;
+
+/// What a command substitution wrote, and the status it ended with.
+pub struct Output {
+    pub stdout: String,
+    pub stderr: Vec<u8>,
+    pub status: i32,
+}
+
+/// Collects everything a substituted command writes, as the bytes it wrote.
+#[derive(Default)]
+struct Capture {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Prompter for Capture {
+    fn read(&mut self) -> anyhow::Result<Option<String>> {
+        return Ok(None);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> anyhow::Result<()> {
+        return self.write_bytes(prompt.as_bytes());
+    }
+
+    fn error(&mut self, message: &str) -> anyhow::Result<()> {
+        return self.error_bytes(message.as_bytes());
+    }
+
+    fn write_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
+        self.stdout.extend_from_slice(output);
+        return Ok(());
+    }
+
+    fn error_bytes(&mut self, output: &[u8]) -> anyhow::Result<()> {
+        self.stderr.extend_from_slice(output);
+        return Ok(());
+    }
+}
+
+/// Runs the command of a `$(command)` in a subshell and captures its output, with
+/// trailing newlines removed. The output becomes a shell word, which is text, so
+/// bytes that are not UTF-8 are replaced.
+pub fn run(
+    command: &str,
+    state: &ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<Output> {
+    let (output, status) = capture(command, state, finder, runner)?;
+    return Ok(Output {
+        stdout: String::from_utf8_lossy(&output.stdout)
+            .trim_end_matches('\n')
+            .to_string(),
+        stderr: output.stderr,
+        status,
+    });
+}
+
+/// Runs the command of a `<(command)` in a subshell and returns a pipe its output
+/// can be read from, along with what it wrote to stderr. The command runs to
+/// completion first; a thread then feeds the pipe as the reader takes it.
+pub fn run_process(
+    command: &str,
+    state: &ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<(ProcessSubstitution, Vec<u8>)> {
+    let (output, _) = capture(command, state, finder, runner)?;
+    return Ok((ProcessSubstitution::new(output.stdout)?, output.stderr));
+}
+
+/// Runs `command` in a subshell, returning what it wrote and the status of the last
+/// command it ran.
+fn capture(
+    command: &str,
+    state: &ShellState,
+    finder: &impl ExecutablePathFinder,
+    runner: &impl ExecutableRunner,
+) -> anyhow::Result<(Capture, i32)> {
+    let max_depth = state
+        .variables
+        .get("SHELL_EXPANSION_DEPTH")
+        .and_then(|depth| return depth.parse().ok())
+        .unwrap_or(DEFAULT_EXPANSION_DEPTH);
+    if state.substitution_depth >= max_depth {
+        return Err(anyhow!("expansion too deep"));
+    }
+
+    let mut capture = Capture::default();
+    // An `exit` in the subshell only stops it, as the state it sets is dropped.
+    let status = state.in_subshell(|subshell| {
+        subshell.substitution_depth += 1;
+        statement::run_line(command, subshell, &mut capture, finder, runner)?;
+        return Ok(subshell.exiting.unwrap_or(subshell.last_status));
+    })?;
+
+    return Ok((capture, status));
+}
+
+/// The read end of the pipe behind a `<(command)`. Programs started while it is
+/// open inherit it and reach it through `path`; dropping it closes the shell's copy.
+#[derive(Debug)]
+pub struct ProcessSubstitution {
+    #[cfg(unix)]
+    read_end: std::os::fd::OwnedFd,
+}
+
+impl ProcessSubstitution {
+    #[cfg(unix)]
+    fn new(output: Vec<u8>) -> anyhow::Result<Self> {
+        use std::{
+            fs::File,
+            io::Write,
+            os::fd::{AsRawFd, FromRawFd, OwnedFd},
+            thread,
+        };
+
+        let mut fds = [0; 2];
+        // SAFETY: `pipe` fills `fds` with two new descriptors, owned from here on.
+        let (read_end, write_end) = unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+        };
+        // Only the read end may be inherited: a program holding the write end would
+        // never see the output end.
+        // SAFETY: `write_end` is an open descriptor owned above.
+        unsafe {
+            libc::fcntl(write_end.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+
+        let mut writer = File::from(write_end);
+        // Ends early with a broken pipe once every reader has closed the pipe.
+        thread::spawn(move || {
+            let _ = writer.write_all(&output);
+        });
+
+        return Ok(Self { read_end });
+    }
+
+    #[cfg(not(unix))]
+    fn new(_output: Vec<u8>) -> anyhow::Result<Self> {
+        return Err(anyhow!("process substitution is not supported"));
+    }
+
+    /// The path programs open to read the output.
+    #[cfg(unix)]
+    pub fn path(&self) -> String {
+        use std::os::fd::AsRawFd;
+
+        return format!("/dev/fd/{}", self.read_end.as_raw_fd());
+    }
+
+    #[cfg(not(unix))]
+    pub fn path(&self) -> String {
+        return String::new();
+    }
+}
+
+/// Reads the command of a `$(...)` whose `$(` was just consumed, through the
+/// matching `)`, and returns it. Quotes and nested substitutions are skipped over, so
+/// a `)` inside them does not end the command. `None` when the input ends first.
+pub fn take_command(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut command = String::new();
+    let mut depth = 0;
+    let mut inside_single_quotes = false;
+    let mut inside_double_quotes = false;
+    let mut previous = None;
+
+    while let Some(current_char) = chars.next() {
+        let is_quoted = inside_single_quotes || inside_double_quotes;
+        match current_char {
+            '\\' if !inside_single_quotes => {
+                command.push(current_char);
+                command.push(chars.next()?);
+                previous = None;
+                continue;
+            }
+            '\'' if !inside_double_quotes => inside_single_quotes = !inside_single_quotes,
+            '"' if !inside_single_quotes => inside_double_quotes = !inside_double_quotes,
+            '(' if !inside_single_quotes && previous == Some('$') => {
+                command.push(current_char);
+                command.push_str(&take_command(chars)?);
+                command.push(')');
+                previous = None;
+                continue;
+            }
+            '(' if !is_quoted => depth += 1,
+            ')' if !is_quoted && depth == 0 => return Some(command),
+            ')' if !is_quoted => depth -= 1,
+            _ => {}
+        }
+        command.push(current_char);
+        previous = Some(current_char);
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod substitution_tests {
+    use super::*;
+
+    #[test]
+    fn take_command_finds_the_matching_paren() {
+        let take = |input: &str| return take_command(&mut input.chars());
+
+        assert_eq!(take("echo hi) rest"), Some(String::from("echo hi")));
+        assert_eq!(
+            take("echo ')' \")\" \\)) x"),
+            Some(String::from("echo ')' \")\" \\)"))
+        );
+        assert_eq!(
+            take("echo \"$(echo \")\")\") x"),
+            Some(String::from("echo \"$(echo \")\")\""))
+        );
+        assert_eq!(take("(a) b) c"), Some(String::from("(a) b")));
+        assert_eq!(take("echo hi"), None);
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::anyhow;
+
+use crate::{
+    statement,
+    tokenizer::{tokenize, Token},
+};
+
+/// Checks a script without running any of it, for `-n`. Every line must parse, and
+/// compound commands (`if ... fi`, `while`/`until`/`for`/`select ... done`,
+/// `case ... esac`, `{ ... }` and `( ... )`) must be closed in order, possibly on later
+/// lines. Errors start with the number of the line they were found on.
+pub fn check(script: &str) -> anyhow::Result<()> {
+    let mut open = vec![];
+    let mut last_line = 0;
+
+    for (index, line) in script.lines().enumerate() {
+        last_line = index + 1;
+        let at_line = |e: anyhow::Error| return anyhow!("line {}: {}", index + 1, e);
+
+        statement::parse(line).map_err(at_line)?;
+        check_keywords(&tokenize(line)?, &mut open).map_err(at_line)?;
+    }
+
+    if let Some(expected) = open.last() {
+        return Err(anyhow!(
+            "line {}: syntax error: unexpected end of file (expected '{}')",
+            last_line,
+            expected
+        ));
+    }
+    return Ok(());
+}
+
+/// Follows the reserved words at the start of each command on one line. `open` holds
+/// the word that closes each compound command still open, innermost last.
+fn check_keywords(tokens: &[Token], open: &mut Vec<&'static str>) -> anyhow::Result<()> {
+    let mut starts_command = true;
+
+    for token in tokens {
+        let Token::Word(word) = token else {
+            // Every operator is followed by a new command.
+            starts_command = true;
+            continue;
+        };
+        if !starts_command {
+            close_parens(word, open)?;
+            // The body of `name() {` starts a command as well.
+            if word == "{" {
+                open.push("}");
+            }
+            starts_command = word == "{";
+            continue;
+        }
+
+        // A subshell's parentheses are part of the words next to them, as in
+        // `(cd /tmp; ls)`.
+        let rest = word.trim_start_matches('(');
+        for _ in rest.len()..word.len() {
+            open.push(")");
+        }
+        let keyword = rest.trim_end_matches(')');
+
+        let innermost = open.last().copied();
+        match keyword {
+            "if" => open.push("fi"),
+            "while" | "until" | "for" | "select" => open.push("done"),
+            "case" => open.push("esac"),
+            "{" => open.push("}"),
+            "then" | "elif" | "else" if innermost == Some("fi") => {}
+            "do" if innermost == Some("done") => {}
+            closer @ ("fi" | "done" | "esac" | "}") if innermost == Some(closer) => {
+                open.pop();
+            }
+            keyword @ ("then" | "elif" | "else" | "do" | "fi" | "done" | "esac" | "}") => {
+                return Err(anyhow!("syntax error near unexpected token `{}'", keyword));
+            }
+            _ => {}
+        }
+        close_parens(rest, open)?;
+
+        // The word after these starts a command too, as in `if ! true` or `then echo`.
+        starts_command = matches!(
+            keyword,
+            "" | "if" | "then" | "elif" | "else" | "while" | "until" | "do" | "!" | "{"
+        );
+    }
+
+    return Ok(());
+}
+
+/// Closes the subshells ended by the `)`s in `word` that it did not open itself.
+/// Inside `case` they end patterns instead, as in `a) echo a;;`.
+fn close_parens(word: &str, open: &mut Vec<&'static str>) -> anyhow::Result<()> {
+    for _ in 0..unmatched_closing_parens(word) {
+        match open.last().copied() {
+            Some(")") => {
+                open.pop();
+            }
+            Some("esac") => {}
+            _ if open.contains(&")") => {
+                return Err(anyhow!("syntax error near unexpected token `)'"));
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(());
+}
+
+/// The unquoted `)`s in a word without a `(` before them, e.g. 1 in `$(date))`.
+fn unmatched_closing_parens(word: &str) -> usize {
+    let mut depth = 0;
+    let mut unmatched = 0;
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(current_char) = chars.next() {
+        match (quote, current_char) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(current_char),
+            (None, '(') => depth += 1,
+            (None, ')') if depth > 0 => depth -= 1,
+            (None, ')') => unmatched += 1,
+            _ => {}
+        }
+    }
+
+    return unmatched;
+}
+
+#[cfg(test)]
+mod syntax_tests {
+    use super::*;
+
+    fn error(script: &str) -> String {
+        return check(script).unwrap_err().to_string();
+    }
+
+    #[test]
+    fn balanced_compound_commands() -> anyhow::Result<()> {
+        check("if true; then\n  echo yes\nelse echo no; fi")?;
+        check("while true; do if false; then echo; fi; done")?;
+        check("f() { if true; then echo fi; fi; }\necho done")?;
+        check("")?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn groups_and_subshells() -> anyhow::Result<()> {
+        check("{ echo a; echo b; }")?;
+        check("(cd /tmp; ls)")?;
+        check("( echo $(date) )\n(if true; then echo; fi)")?;
+        check("f() { echo ')'; }")?;
+
+        return Ok(());
+    }
+
+    #[test]
+    fn unbalanced_constructs_name_what_is_missing() {
+        for (script, expected) in [
+            ("if true; then echo", "fi"),
+            ("while true; do echo", "done"),
+            ("for x in a; do", "done"),
+            ("case x in", "esac"),
+            ("{ echo a", "}"),
+            ("(echo a", ")"),
+            ("(if true; then echo; fi", ")"),
+        ] {
+            assert_eq!(
+                error(script),
+                format!(
+                    "line 1: syntax error: unexpected end of file (expected '{}')",
+                    expected
+                ),
+                "{}",
+                script
+            );
+        }
+        assert_eq!(
+            error("{ echo a"),
+            "line 1: syntax error: unexpected end of file (expected '}')"
+        );
+        assert_eq!(
+            error("(echo a\necho b"),
+            "line 2: syntax error: unexpected end of file (expected ')')"
+        );
+        assert_eq!(
+            error("echo a; }"),
+            "line 1: syntax error near unexpected token `}'"
+        );
+        assert_eq!(
+            error("(if true; then echo)"),
+            "line 1: syntax error near unexpected token `)'"
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_the_error() {
+        assert_eq!(
+            error("echo a\nif true; then\n  echo b"),
+            "line 3: syntax error: unexpected end of file (expected 'fi')"
+        );
+        assert_eq!(
+            error("while true; do\nfi"),
+            "line 2: syntax error near unexpected token `fi'"
+        );
+        assert_eq!(
+            error("echo ok\nthen"),
+            "line 2: syntax error near unexpected token `then'"
+        );
+        assert_eq!(
+            error("echo 'open"),
+            "line 1: unexpected EOF while looking for matching `''"
+        );
+        assert_eq!(
+            error("echo a &&"),
+            "line 1: syntax error: unexpected end of file"
+        );
+    }
+}
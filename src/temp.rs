@@ -0,0 +1,34 @@
+use std::io;
+
+use tempfile::NamedTempFile;
+
+/// Creates an empty file named `<prefix><random><suffix>` in the temporary directory,
+/// for a path to hand to another program. It is created exclusively with mode 0600, so
+/// no other user can swap or read it, and removed when dropped.
+pub fn file(prefix: &str, suffix: &str) -> io::Result<NamedTempFile> {
+    return tempfile::Builder::new()
+        .prefix(prefix)
+        .suffix(suffix)
+        .tempfile();
+}
+
+#[cfg(all(test, unix))]
+mod temp_tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn files_are_private_and_removed_on_drop() -> anyhow::Result<()> {
+        let file = file("test-", ".sh")?;
+        let path = file.path().to_path_buf();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("test-") && name.ends_with(".sh"));
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+
+        drop(file);
+        assert!(!path.exists());
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,260 @@
+use std::io;
+#[cfg(unix)]
+use std::{mem::MaybeUninit, sync::Mutex};
+
+/// Reads and changes the settings of a terminal. `Stdin` is the real one; tests use
+/// an in-memory stand-in.
+#[cfg(unix)]
+pub trait TermiosBackend {
+    fn get(&self) -> io::Result<libc::termios>;
+    fn set(&self, termios: &libc::termios) -> io::Result<()>;
+}
+
+/// The terminal on stdin.
+#[cfg(unix)]
+pub struct Stdin;
+
+#[cfg(unix)]
+impl TermiosBackend for Stdin {
+    fn get(&self) -> io::Result<libc::termios> {
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(unsafe { termios.assume_init() });
+    }
+
+    fn set(&self, termios: &libc::termios) -> io::Result<()> {
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        return Ok(());
+    }
+}
+
+/// The settings stdin had before raw mode, while it is on. The panic hook puts them
+/// back before the message is printed, which also covers builds that abort on panic.
+#[cfg(unix)]
+static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Puts a terminal into raw mode for as long as the value lives. Dropping it, on
+/// any path out of the line editor including errors and panics, restores the
+/// settings it started with.
+#[cfg(unix)]
+pub struct RawMode<B: TermiosBackend = Stdin> {
+    backend: B,
+    original: libc::termios,
+    /// Whether `original` was saved for the panic hook.
+    registered: bool,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let mut raw_mode = Self::enable_with(Stdin)?;
+        *ORIGINAL.lock().unwrap_or_else(|e| return e.into_inner()) = Some(raw_mode.original);
+        raw_mode.registered = true;
+        return Ok(raw_mode);
+    }
+}
+
+#[cfg(unix)]
+impl<B: TermiosBackend> RawMode<B> {
+    pub fn enable_with(backend: B) -> io::Result<Self> {
+        let original = backend.get()?;
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        backend.set(&raw)?;
+
+        return Ok(Self {
+            backend,
+            original,
+            registered: false,
+        });
+    }
+}
+
+#[cfg(unix)]
+impl<B: TermiosBackend> Drop for RawMode<B> {
+    fn drop(&mut self) {
+        let _ = self.backend.set(&self.original);
+        if self.registered {
+            *ORIGINAL.lock().unwrap_or_else(|e| return e.into_inner()) = None;
+        }
+    }
+}
+
+/// Makes a panic restore the terminal before anything is printed, so the message
+/// and the user's shell afterwards are not left in raw mode.
+#[cfg(unix)]
+pub fn restore_on_panic() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let original = ORIGINAL
+            .lock()
+            .ok()
+            .and_then(|mut original| return original.take());
+        if let Some(original) = original {
+            let _ = Stdin.set(&original);
+        }
+        previous(info);
+    }));
+}
+
+/// Keeps an interactive shell running when Ctrl-Z sends `SIGTSTP`, so only the
+/// foreground program stops. A handler that does nothing is used rather than ignoring
+/// the signal, as programs the shell starts get the default action back when they
+/// start, where an ignored signal would stay ignored.
+#[cfg(unix)]
+pub fn keep_running_on_stop() {
+    extern "C" fn on_stop(_signal: libc::c_int) {}
+
+    // SAFETY: the handler does nothing, so it is safe to run at any point.
+    unsafe {
+        libc::signal(libc::SIGTSTP, on_stop as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn keep_running_on_stop() {}
+
+/// Makes a job's process group the terminal's foreground group for as long as the
+/// value lives, so Ctrl-C and Ctrl-Z reach the job instead of the shell. Dropping it
+/// gives the terminal back to the shell's group.
+#[cfg(unix)]
+pub struct Foreground {
+    shell_group: libc::pid_t,
+}
+
+#[cfg(unix)]
+impl Foreground {
+    /// Hands the terminal on stdin to `group`. `None` when the shell's group does not
+    /// have the terminal to give, e.g. when stdin is not one. The job may have taken
+    /// it already, from its first process before `exec` (see `spawn_foreground`).
+    pub fn give(group: u32) -> Option<Self> {
+        let group = group as libc::pid_t;
+        // SAFETY: these calls only read the process groups of the shell and terminal.
+        let (shell_group, foreground) =
+            unsafe { (libc::getpgrp(), libc::tcgetpgrp(libc::STDIN_FILENO)) };
+        if foreground != shell_group && foreground != group {
+            return None;
+        }
+        set_foreground(group).ok()?;
+        return Some(Self { shell_group });
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Foreground {
+    fn drop(&mut self) {
+        let _ = set_foreground(self.shell_group);
+    }
+}
+
+/// Makes `group` the foreground process group of the terminal on stdin. Unless the
+/// caller is in that group already, the change raises `SIGTTOU`, which would stop
+/// it; the signal is blocked meanwhile. Only calls that are safe between `fork` and
+/// `exec` are made, so a job's first process can take the terminal itself.
+#[cfg(unix)]
+pub fn set_foreground(group: libc::pid_t) -> io::Result<()> {
+    // SAFETY: the signal sets are initialized by `sigemptyset` before use.
+    unsafe {
+        let mut block = MaybeUninit::<libc::sigset_t>::uninit();
+        let mut previous = MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(block.as_mut_ptr());
+        libc::sigaddset(block.as_mut_ptr(), libc::SIGTTOU);
+        libc::sigprocmask(libc::SIG_BLOCK, block.as_ptr(), previous.as_mut_ptr());
+        let result = match libc::tcsetpgrp(libc::STDIN_FILENO, group) {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+        libc::sigprocmask(libc::SIG_SETMASK, previous.as_ptr(), std::ptr::null_mut());
+        return result;
+    }
+}
+
+/// Whether the shell's process group has the terminal on stdin to hand to a job.
+#[cfg(unix)]
+pub fn has_foreground() -> bool {
+    // SAFETY: these calls only read the process groups of the shell and terminal.
+    return unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() };
+}
+
+/// Raw mode relies on termios; elsewhere the line editor is not available.
+#[cfg(not(unix))]
+pub struct RawMode {}
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        return Err(io::Error::from(io::ErrorKind::Unsupported));
+    }
+}
+
+#[cfg(not(unix))]
+pub fn restore_on_panic() {}
+
+/// Number of rows of the terminal on stdout, if it is one.
+#[cfg(unix)]
+pub fn height() -> Option<usize> {
+    let mut size = MaybeUninit::<libc::winsize>::uninit();
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    let rows = unsafe { size.assume_init() }.ws_row as usize;
+    return (rows > 0).then_some(rows);
+}
+
+#[cfg(not(unix))]
+pub fn height() -> Option<usize> {
+    return None;
+}
+
+#[cfg(all(test, unix))]
+mod terminal_tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// A terminal that only keeps its settings in memory.
+    struct FakeTerminal<'a> {
+        settings: &'a RefCell<libc::termios>,
+    }
+
+    impl TermiosBackend for FakeTerminal<'_> {
+        fn get(&self) -> io::Result<libc::termios> {
+            return Ok(*self.settings.borrow());
+        }
+
+        fn set(&self, termios: &libc::termios) -> io::Result<()> {
+            *self.settings.borrow_mut() = *termios;
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn dropping_raw_mode_restores_the_saved_settings() -> io::Result<()> {
+        let mut cooked: libc::termios = unsafe { std::mem::zeroed() };
+        cooked.c_lflag = libc::ICANON | libc::ECHO | libc::ISIG;
+        cooked.c_iflag = libc::ICRNL;
+        let settings = RefCell::new(cooked);
+
+        let raw_mode = RawMode::enable_with(FakeTerminal {
+            settings: &settings,
+        })?;
+        assert_eq!(settings.borrow().c_lflag & (libc::ICANON | libc::ECHO), 0);
+        assert_eq!(settings.borrow().c_iflag & libc::ICRNL, 0);
+
+        drop(raw_mode);
+        assert_eq!(settings.borrow().c_lflag, cooked.c_lflag);
+        assert_eq!(settings.borrow().c_iflag, cooked.c_iflag);
+        assert_eq!(settings.borrow().c_cc, cooked.c_cc);
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,31 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// The current directory is process-wide, so tests that change it take turns.
+static CURRENT_DIR_LOCK: Mutex<()> = Mutex::new(());
+
+struct RestoreDir(PathBuf);
+
+impl Drop for RestoreDir {
+    fn drop(&mut self) {
+        let _ = std::env::set_current_dir(&self.0);
+    }
+}
+
+/// Runs `f` with `dir` as the current directory, restoring the previous one after.
+pub fn in_dir<T>(dir: &Path, f: impl FnOnce() -> T) -> T {
+    let _lock = CURRENT_DIR_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _restore = RestoreDir(std::env::current_dir().expect("Failed to get current dir"));
+
+    std::env::set_current_dir(dir).expect("Failed to change current dir");
+    return f();
+}
+
+/// Builtin arguments as the shell passes them, from string literals.
+pub fn args(args: &[&str]) -> Vec<String> {
+    return args.iter().map(|arg| return arg.to_string()).collect();
+}
@@ -0,0 +1,154 @@
+use std::{
+    io::{self, Read},
+    process::{Child, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The status of a command that `timeout` had to stop.
+pub const TIMED_OUT_STATUS: i32 = 124;
+
+/// How long a command has to exit after `SIGTERM` before it is killed.
+const KILL_AFTER: Duration = Duration::from_secs(1);
+
+/// How often a running command is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Reads a time limit in seconds, such as `5`, `0.5` or `2s`.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let seconds = text.strip_suffix('s').unwrap_or(text);
+    if !seconds.starts_with(|c: char| return c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+
+    let seconds: f64 = seconds.parse().ok()?;
+    return Duration::try_from_secs_f64(seconds).ok();
+}
+
+/// What a command under `timeout` wrote and how it ended.
+pub struct TimedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub status: ExitStatus,
+    /// Set when the command was still running at the deadline and was stopped.
+    pub timed_out: bool,
+}
+
+/// Waits for `child` to exit, collecting its piped output. Once `limit` has passed it
+/// is sent `SIGTERM`, and killed if it is still running `KILL_AFTER` later.
+pub fn wait(mut child: Child, limit: Duration) -> io::Result<TimedOutput> {
+    // Read from threads so a command that fills a pipe is not stuck waiting on it.
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let mut timed_out = false;
+    let status = match wait_until(&mut child, Instant::now() + limit)? {
+        Some(status) => status,
+        None => {
+            timed_out = true;
+            terminate(&mut child)?;
+            match wait_until(&mut child, Instant::now() + KILL_AFTER)? {
+                Some(status) => status,
+                None => {
+                    child.kill()?;
+                    child.wait()?
+                }
+            }
+        }
+    };
+
+    let collect = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+        return reader
+            .and_then(|reader| return reader.join().ok())
+            .unwrap_or_default();
+    };
+    return Ok(TimedOutput {
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+        status,
+        timed_out,
+    });
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    return thread::spawn(move || {
+        let mut output = vec![];
+        let _ = pipe.read_to_end(&mut output);
+        return output;
+    });
+}
+
+/// The status of `child` once it exits, or `None` if it is still running at `deadline`.
+fn wait_until(child: &mut Child, deadline: Instant) -> io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+#[cfg(unix)]
+fn terminate(child: &mut Child) -> io::Result<()> {
+    // SAFETY: sending a signal has no memory safety requirements. The child has not
+    // been waited on, so its pid still names it.
+    if unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) -> io::Result<()> {
+    return child.kill();
+}
+
+#[cfg(all(test, unix))]
+mod timeout_tests {
+    use std::{
+        process::{Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("0.5"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration(".25"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("-1"), None);
+        assert_eq!(parse_duration("inf"), None);
+        assert_eq!(parse_duration("1m"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn stops_a_command_at_the_deadline() -> anyhow::Result<()> {
+        let child = Command::new("sleep")
+            .arg("10")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let start = Instant::now();
+        let output = wait(child, Duration::from_millis(100))?;
+
+        assert!(output.timed_out);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let child = Command::new("echo")
+            .arg("done")
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let output = wait(child, Duration::from_secs(10))?;
+        assert!(!output.timed_out);
+        assert_eq!(output.stdout, b"done\n");
+        assert_eq!(output.status.code(), Some(0));
+
+        return Ok(());
+    }
+}
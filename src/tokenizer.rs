@@ -0,0 +1,636 @@
+use std::fmt;
+
+use anyhow::anyhow;
+
+use crate::{expansion::Expander, substitution};
+
+/// One lexical unit of an input line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A word as written, quotes and escapes included; expansion removes them later.
+    Word(String),
+    /// `|`
+    Pipe,
+    /// `;`
+    Semicolon,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// A redirection operator: `<`, `>`, `>>`, `>|`, `2>`, `2>&1`, `>&-`, ...
+    Redirect { operator: String },
+    /// `&`
+    Background,
+}
+
+impl fmt::Display for Token {
+    /// The token as it is written on the command line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => return write!(f, "{}", word),
+            Token::Pipe => return write!(f, "|"),
+            Token::Semicolon => return write!(f, ";"),
+            Token::And => return write!(f, "&&"),
+            Token::Or => return write!(f, "||"),
+            Token::Redirect { operator } => return write!(f, "{}", operator),
+            Token::Background => return write!(f, "&"),
+        }
+    }
+}
+
+/// Splits the input into tokens. Words end at unquoted whitespace or an unquoted
+/// operator; quotes and escapes are kept as written so the words can be expanded later.
+pub fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = vec![];
+    // Where the word being read starts. Words are copied out of the input in one
+    // piece once they end, so even a multi-megabyte word is allocated only once.
+    let mut word_start: Option<usize> = None;
+
+    let mut inside_single_quotes = false;
+    let mut inside_double_quotes = false;
+    let mut chars = input.char_indices().peekable();
+
+    let finish_word = |word_start: &mut Option<usize>, end: usize, tokens: &mut Vec<Token>| {
+        if let Some(start) = word_start.take() {
+            tokens.push(Token::Word(input[start..end].to_string()));
+        }
+    };
+    // Digits right before a redirection operator name the stream (`2>`, `0<`) and
+    // start the operator; anything else is a word of its own.
+    let operator_start =
+        |word_start: &mut Option<usize>, end: usize, tokens: &mut Vec<Token>| match word_start
+            .map(|start| return &input[start..end])
+        {
+            Some(word) if word.chars().all(|c| return c.is_ascii_digit()) => {
+                *word_start = None;
+                return word.to_string();
+            }
+            _ => {
+                finish_word(word_start, end, tokens);
+                return String::new();
+            }
+        };
+
+    while let Some((index, current_char)) = chars.next() {
+        let is_quoted = inside_single_quotes || inside_double_quotes;
+        match current_char {
+            '\\' if !inside_single_quotes => {
+                word_start.get_or_insert(index);
+                chars.next();
+            }
+            '$' if !inside_single_quotes && chars.next_if(|(_, c)| *c == '(').is_some() => {
+                word_start.get_or_insert(index);
+                let mut rest = chars.by_ref().map(|(_, c)| return c);
+                if substitution::take_command(&mut rest).is_none() {
+                    return Err(anyhow!("unexpected EOF while looking for matching `)'"));
+                }
+            }
+            '<' if !is_quoted && chars.next_if(|(_, c)| *c == '(').is_some() => {
+                word_start.get_or_insert(index);
+                let mut rest = chars.by_ref().map(|(_, c)| return c);
+                if substitution::take_command(&mut rest).is_none() {
+                    return Err(anyhow!("unexpected EOF while looking for matching `)'"));
+                }
+            }
+            '<' if !is_quoted => {
+                let mut operator = operator_start(&mut word_start, index, &mut tokens);
+                operator.push('<');
+                tokens.push(Token::Redirect { operator });
+            }
+            '\'' if !inside_double_quotes => {
+                inside_single_quotes = !inside_single_quotes;
+                word_start.get_or_insert(index);
+            }
+            '"' if !inside_single_quotes => {
+                inside_double_quotes = !inside_double_quotes;
+                word_start.get_or_insert(index);
+            }
+            ' ' | '\t' if !is_quoted => finish_word(&mut word_start, index, &mut tokens),
+            ';' if !is_quoted => {
+                finish_word(&mut word_start, index, &mut tokens);
+                tokens.push(Token::Semicolon);
+            }
+            '|' if !is_quoted => {
+                finish_word(&mut word_start, index, &mut tokens);
+                match chars.next_if(|(_, c)| *c == '|') {
+                    Some(_) => tokens.push(Token::Or),
+                    None => tokens.push(Token::Pipe),
+                }
+            }
+            '&' if !is_quoted => {
+                finish_word(&mut word_start, index, &mut tokens);
+                if chars.next_if(|(_, c)| *c == '>').is_some() {
+                    let mut operator = String::from("&>");
+                    operator.extend(chars.next_if(|(_, c)| *c == '>').map(|(_, c)| c));
+                    tokens.push(Token::Redirect { operator });
+                    continue;
+                }
+                match chars.next_if(|(_, c)| *c == '&') {
+                    Some(_) => tokens.push(Token::And),
+                    None => tokens.push(Token::Background),
+                }
+            }
+            '>' if !is_quoted => {
+                let mut operator = operator_start(&mut word_start, index, &mut tokens);
+                operator.push('>');
+                match chars.peek() {
+                    Some((_, '>' | '|')) => operator.extend(chars.next().map(|(_, c)| c)),
+                    Some((_, '&')) => {
+                        operator.extend(chars.next().map(|(_, c)| c));
+                        while let Some((_, next_char)) =
+                            chars.next_if(|(_, next)| next.is_ascii_digit() || *next == '-')
+                        {
+                            operator.push(next_char);
+                        }
+                    }
+                    _ => {}
+                }
+                tokens.push(Token::Redirect { operator });
+            }
+            _ => {
+                word_start.get_or_insert(index);
+            }
+        }
+    }
+
+    if inside_single_quotes || inside_double_quotes {
+        let quote = if inside_single_quotes { '\'' } else { '"' };
+        return Err(anyhow!(
+            "unexpected EOF while looking for matching `{}'",
+            quote
+        ));
+    }
+
+    finish_word(&mut word_start, input.len(), &mut tokens);
+    return Ok(tokens);
+}
+
+/// The line's tokens after quote removal, with operators as written. Used by
+/// `--debug-parse`.
+pub fn parse_args(input: &str) -> anyhow::Result<Vec<String>> {
+    let expander = Expander::literal();
+
+    let mut args = vec![];
+    for token in tokenize(input)? {
+        match token {
+            Token::Word(word) => args.extend(expander.expand_word(&word)?),
+            _ => args.push(token.to_string()),
+        }
+    }
+
+    return Ok(args);
+}
+
+/// The inverse of `tokenize`: the tokens one space apart, as a line that tokenizes,
+/// and so parses, exactly as they do. Words keep their quotes and operators are
+/// written bare; a word made from a plain value with `quote` reads back as that value.
+pub fn reserialize(tokens: &[Token]) -> String {
+    return tokens
+        .iter()
+        .map(|token| return token.to_string())
+        .collect::<Vec<String>>()
+        .join(" ");
+}
+
+/// A line as the shell echoes it before running it: reserialized, or as typed when
+/// it does not tokenize.
+pub fn echoed(line: &str) -> String {
+    match tokenize(line) {
+        Ok(tokens) => return reserialize(&tokens),
+        Err(_) => return line.to_string(),
+    }
+}
+
+/// Single-quotes a word unless it reads back unchanged as a bare word. A single
+/// quote inside it is written as `'\''`.
+pub fn quote(word: &str) -> String {
+    let is_plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| return c.is_ascii_alphanumeric() || "_-./:,@%+=".contains(c));
+    if is_plain {
+        return word.to_string();
+    }
+
+    return format!("'{}'", word.replace('\'', r"'\''"));
+}
+
+#[cfg(test)]
+mod tokenizer_tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Vec<String> {
+        return super::parse_args(args).unwrap();
+    }
+
+    fn word(word: &str) -> Token {
+        return Token::Word(word.to_string());
+    }
+
+    fn redirect(operator: &str) -> Token {
+        return Token::Redirect {
+            operator: operator.to_string(),
+        };
+    }
+
+    #[test]
+    fn single_arg() {
+        let args = r#"single"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"single"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn single_arg_single_quotes() {
+        let args = r#"'single'"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"single"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn single_arg_escaped_single_quote() {
+        {
+            let args = r#"sing\'le"#;
+
+            let output = parse_args(args);
+            let expected = vec![r#"sing'le"#.to_string()];
+
+            assert_eq!(output, expected)
+        }
+
+        {
+            let args = r#"\'single"#;
+
+            let output = parse_args(args);
+            let expected = vec![r#"'single"#.to_string()];
+
+            assert_eq!(output, expected)
+        }
+
+        {
+            let args = r#"single\'"#;
+
+            let output = parse_args(args);
+            let expected = vec![r#"single'"#.to_string()];
+
+            assert_eq!(output, expected)
+        }
+    }
+
+    #[test]
+    fn multiple_args_escaped_quote() {
+        let args = r#"f\'irst secon\'d"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"f'irst"#.to_string(), r#"secon'd"#.to_string()];
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn single_arg_double_quotes() {
+        let args = r#""single""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"single"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn escaped_double_quotes() {
+        let args = r#"first\"second"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"first"second"#.to_string()];
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn escaped_double_quotes_inside_single_quotes() {
+        let args = r#"'sin\"gle'"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"sin\"gle"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn multiple_args() {
+        let args = r#"first second"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"first"#.to_string(), r#"second"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn double_quotes_inside_single_quotes() {
+        let args = r#"'first"second' '"first second'"#;
+
+        let output = parse_args(args);
+        let expected = vec![
+            r#"first"second"#.to_string(),
+            r#""first second"#.to_string(),
+        ];
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn multiple_args_double_quotes_whitespace() {
+        let args = r#""first  second"   "first""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"first  second"#.to_string(), r#"first"#.to_string()];
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn single_quoted_backslash() {
+        let args = r#""/'f \21\'""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"/'f \21\'"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn single_quoted_backslash2() {
+        let args = r#""/'f  \78'""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"/'f  \78'"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn non_quoted_backslash_space() {
+        let args = r#"first\ \ \second"#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"first  second"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn double_quoted_backslash() {
+        let args = r#""test'world'\\n'example""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"test'world'\n'example"#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn double_quoted_backslash2() {
+        let args = r#""mixed\"quote'test'\\""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"mixed"quote'test'\"#.to_string()];
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn double_quoted_backslash3() {
+        let args = r#""example\"insidequotes"script\""#;
+
+        let output = parse_args(args);
+        let expected = vec![r#"example"insidequotesscript""#.to_string()];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn glued_redirection_operators() -> anyhow::Result<()> {
+        assert_eq!(
+            tokenize("echo hi>file")?,
+            vec![word("echo"), word("hi"), redirect(">"), word("file")]
+        );
+        assert_eq!(
+            tokenize("echo a>>b")?,
+            vec![word("echo"), word("a"), redirect(">>"), word("b")]
+        );
+        assert_eq!(
+            tokenize("ls 2>err 1>out")?,
+            vec![
+                word("ls"),
+                redirect("2>"),
+                word("err"),
+                redirect("1>"),
+                word("out")
+            ]
+        );
+        assert_eq!(
+            tokenize("ls x2>err")?,
+            vec![word("ls"), word("x2"), redirect(">"), word("err")]
+        );
+        assert_eq!(
+            tokenize("ls &>out x&>>log")?,
+            vec![
+                word("ls"),
+                redirect("&>"),
+                word("out"),
+                word("x"),
+                redirect("&>>"),
+                word("log")
+            ]
+        );
+        assert_eq!(
+            tokenize("ls >|out 2>|err")?,
+            vec![
+                word("ls"),
+                redirect(">|"),
+                word("out"),
+                redirect("2>|"),
+                word("err")
+            ]
+        );
+        assert_eq!(
+            tokenize("ls 2>&1>out")?,
+            vec![word("ls"), redirect("2>&1"), redirect(">"), word("out")]
+        );
+        assert_eq!(
+            tokenize("echo 'a>b' \"c>d\" e\\>f")?,
+            vec![word("echo"), word("'a>b'"), word("\"c>d\""), word("e\\>f")]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn control_operators() -> anyhow::Result<()> {
+        assert_eq!(
+            tokenize("a|b||c&&d;e&")?,
+            vec![
+                word("a"),
+                Token::Pipe,
+                word("b"),
+                Token::Or,
+                word("c"),
+                Token::And,
+                word("d"),
+                Token::Semicolon,
+                word("e"),
+                Token::Background
+            ]
+        );
+        assert_eq!(
+            tokenize(r"echo '&&' \| '|' a\;b")?,
+            vec![
+                word("echo"),
+                word("'&&'"),
+                word(r"\|"),
+                word("'|'"),
+                word(r"a\;b")
+            ]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn command_substitutions_stay_in_one_word() -> anyhow::Result<()> {
+        assert_eq!(
+            tokenize(r#"echo $(echo a; echo b | cat)x "$(echo ')')""#)?,
+            vec![
+                word("echo"),
+                word("$(echo a; echo b | cat)x"),
+                word(r#""$(echo ')')""#)
+            ]
+        );
+        assert_eq!(tokenize(r"echo '$(' \$(")?.len(), 3);
+        assert!(tokenize("echo $(echo").is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn huge_single_word() -> anyhow::Result<()> {
+        let huge = "a\\ b".repeat(1_250_000);
+        let input = format!("echo \"{}\"", huge);
+
+        let tokens = tokenize(&input)?;
+        let [_, Token::Word(word)] = tokens.as_slice() else {
+            panic!("expected two words");
+        };
+        assert_eq!(word.len(), huge.len() + 2);
+        assert_eq!(word.capacity(), word.len());
+
+        let args = parse_args(&input);
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[1].len(), huge.len());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert!(tokenize("echo 'abc").is_err());
+        assert!(tokenize("echo \"abc").is_err());
+        assert!(tokenize(r#"echo "a\"""#).is_ok());
+    }
+
+    #[test]
+    fn input_redirect() -> anyhow::Result<()> {
+        assert_eq!(
+            tokenize("cat <in 0< other '<' x<y")?,
+            vec![
+                word("cat"),
+                redirect("<"),
+                word("in"),
+                redirect("0<"),
+                word("other"),
+                word("'<'"),
+                word("x"),
+                redirect("<"),
+                word("y"),
+            ]
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn stdout_redirect() {
+        let args = r#"'hello james' 1> /tmp/foo/foo.md"#;
+
+        let output = parse_args(args);
+        let expected = vec![
+            r#"hello james"#.to_string(),
+            "1>".to_string(),
+            "/tmp/foo/foo.md".to_string(),
+        ];
+
+        assert_eq!(output, expected)
+    }
+
+    #[test]
+    fn reserialized_tokens_parse_back_the_same() -> anyhow::Result<()> {
+        let inputs = [
+            r#"echo plain words"#,
+            r#"echo 'hello    world' "a b""#,
+            r#"echo "it's" 'say "hi"'"#,
+            r#"echo a\ b \'q\' "back\\slash""#,
+            r#"echo '$HOME' "\$PATH" '*.rs' '~' '#not a comment'"#,
+            r#"echo '' "" x"#,
+            r#"echo 'a;b' "c|d" 'e&f' '2>x' '$(date)' '<(ls)'"#,
+            r#"echo "tab	inside" 'new
+line'"#,
+            r#"echo 'it'\''s' "él""#,
+            r#"echo   a|cat>out 2>&1&&  ls x<in||true;sleep 1&"#,
+            r#"echo "$HOME" $(echo "a  b") <(ls) >&- 2>>err"#,
+        ];
+
+        for input in inputs {
+            let tokens = tokenize(input)?;
+            let line = reserialize(&tokens);
+            assert_eq!(tokenize(&line)?, tokens, "{}", input);
+            assert_eq!(parse_args(&line), parse_args(input), "{}", input);
+        }
+        assert_eq!(
+            reserialize(&tokenize("echo   a|cat>out 2>&1")?),
+            "echo a | cat > out 2>&1"
+        );
+
+        // Words made from plain values with `quote` read back as the values.
+        let values = ["a b", "it's", "$HOME", "", "|", "*", "tab\tnew\nline", "él"];
+        let mut tokens = vec![Token::Word(String::from("echo"))];
+        tokens.extend(values.iter().map(|value| return Token::Word(quote(value))));
+        tokens.extend([Token::Pipe, Token::Word(String::from("cat"))]);
+        let mut expected = vec![String::from("echo")];
+        expected.extend(values.iter().map(|value| return value.to_string()));
+        expected.extend([String::from("|"), String::from("cat")]);
+        assert_eq!(parse_args(&reserialize(&tokens)), expected);
+        assert_eq!(
+            tokenize(&reserialize(&tokens))?.get(values.len() + 1),
+            Some(&Token::Pipe)
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("plain-word_1.txt"), "plain-word_1.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+    }
+}
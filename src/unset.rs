@@ -0,0 +1,108 @@
+use crate::{command::CommandOutput, state::ShellState, variables::is_name};
+
+const USAGE: &str = "unset: usage: unset [-f] [-v] [name ...]";
+
+/// `unset [-f] [-v] [name ...]`: removes variables (`-v`, the default) or functions
+/// (`-f`). Without `-v` a name that is no variable removes the function of that name.
+/// Names that could not be variables are reported and the others still removed.
+pub fn unset(state: &mut ShellState, args: &[String]) -> CommandOutput {
+    let (mut functions, mut variables) = (false, false);
+    let mut names = args;
+    while let Some(flags) = names.first().and_then(|arg| return arg.strip_prefix('-')) {
+        names = &names[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'f' => functions = true,
+                'v' => variables = true,
+                _ => {
+                    return CommandOutput {
+                        stdout: None,
+                        stderr: Some(format!("unset: -{}: invalid option\n{}\n", flag, USAGE)),
+                        status: 2,
+                    };
+                }
+            }
+        }
+    }
+    if functions && variables {
+        return CommandOutput {
+            stdout: None,
+            stderr: Some(String::from(
+                "unset: cannot simultaneously unset a function and a variable\n",
+            )),
+            status: 1,
+        };
+    }
+
+    let mut stderr = String::new();
+    for name in names {
+        if functions {
+            state.functions.remove(name);
+            continue;
+        }
+        if !is_name(name) {
+            stderr.push_str(&format!("unset: `{}': not a valid identifier\n", name));
+            continue;
+        }
+
+        if state.variables.get(name).is_some() || variables {
+            state.variables.unset(name);
+        } else {
+            state.functions.remove(name);
+        }
+    }
+
+    return CommandOutput {
+        stdout: None,
+        status: if stderr.is_empty() { 0 } else { 1 },
+        stderr: Some(stderr).filter(|stderr| !stderr.is_empty()),
+    };
+}
+
+#[cfg(test)]
+mod unset_tests {
+    use crate::test_support::args;
+
+    use super::*;
+
+    #[test]
+    fn removes_variables_and_functions() {
+        let mut state = ShellState::default();
+        state.variables.set("NAME", "value");
+        state.variables.set("_other1", "value");
+        state
+            .functions
+            .insert(String::from("greet"), String::from("echo hi"));
+
+        assert_eq!(unset(&mut state, &args(&["NAME", "_other1"])).status, 0);
+        assert_eq!(state.variables.get("NAME"), None);
+        assert_eq!(state.variables.get("_other1"), None);
+
+        assert_eq!(unset(&mut state, &args(&["-v", "greet"])).status, 0);
+        assert!(state.functions.contains_key("greet"));
+        assert_eq!(unset(&mut state, &args(&["greet"])).status, 0);
+        assert!(!state.functions.contains_key("greet"));
+
+        assert_eq!(unset(&mut state, &args(&["-x"])).status, 2);
+        assert_eq!(unset(&mut state, &args(&["-fv", "a"])).status, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_identifiers() {
+        let mut state = ShellState::default();
+        state.variables.set("GOOD", "value");
+
+        let output = unset(&mut state, &args(&["bad name", "1abc", "a-b", "GOOD"]));
+        assert_eq!(
+            output.stderr,
+            Some(String::from(
+                "unset: `bad name': not a valid identifier\nunset: `1abc': not a valid identifier\nunset: `a-b': not a valid identifier\n"
+            ))
+        );
+        assert_eq!(output.status, 1);
+        assert_eq!(state.variables.get("GOOD"), None);
+    }
+}
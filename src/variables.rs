@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    entries: HashMap<String, Variable>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        return Self {
+            entries: HashMap::new(),
+        };
+    }
+
+    pub fn from_env() -> Self {
+        let entries = std::env::vars()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value,
+                    exported: true,
+                };
+                return (name, variable);
+            })
+            .collect();
+
+        return Self { entries };
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        return self.entries.get(name);
+    }
+
+    /// Puts back a variable saved with `variable`, removing it if it did not exist.
+    pub fn restore(&mut self, name: &str, variable: Option<Variable>) {
+        match variable {
+            Some(variable) => self.entries.insert(name.to_string(), variable),
+            None => self.entries.remove(name),
+        };
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self
+            .entries
+            .get(name)
+            .map(|variable| variable.value.as_str());
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.entries.get_mut(name) {
+            Some(variable) => variable.value = value.to_string(),
+            None => {
+                let variable = Variable {
+                    value: value.to_string(),
+                    exported: false,
+                };
+                self.entries.insert(name.to_string(), variable);
+            }
+        }
+    }
+
+    /// Marks a variable as exported, creating it empty when it does not exist.
+    pub fn export(&mut self, name: &str) {
+        self.entries
+            .entry(name.to_string())
+            .or_insert(Variable {
+                value: String::new(),
+                exported: false,
+            })
+            .exported = true;
+    }
+
+    /// Removes the export mark, keeping the variable and its value.
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(variable) = self.entries.get_mut(name) {
+            variable.exported = false;
+        }
+    }
+
+    /// The environment passed to child processes.
+    pub fn exported(&self) -> Vec<(String, String)> {
+        let mut exported: Vec<(String, String)> = self
+            .entries
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| return (name.clone(), variable.value.clone()))
+            .collect();
+        exported.sort();
+
+        return exported;
+    }
+
+    /// Every variable's name and value, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let mut entries: Vec<(&str, &str)> = self
+            .entries
+            .iter()
+            .map(|(name, variable)| return (name.as_str(), variable.value.as_str()))
+            .collect();
+        entries.sort();
+
+        return entries.into_iter();
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+}
+
+/// Whether `name` can name a shell variable: letters, digits and `_`, not starting
+/// with a digit.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    return (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+/// Splits a `NAME=value` word, as written, into the name and the unexpanded value.
+pub fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    return is_name(name).then_some((name, value));
+}
+
+#[cfg(test)]
+mod variables_tests {
+    use super::*;
+
+    #[test]
+    fn set_keeps_export_attribute() {
+        let mut variables = Variables::new();
+        variables.entries.insert(
+            "FOO".to_string(),
+            Variable {
+                value: "old".to_string(),
+                exported: true,
+            },
+        );
+
+        variables.set("FOO", "new");
+
+        assert_eq!(
+            variables.entries.get("FOO"),
+            Some(&Variable {
+                value: "new".to_string(),
+                exported: true
+            })
+        );
+    }
+
+    #[test]
+    fn exported_variables_form_the_environment() {
+        let mut variables = Variables::new();
+        variables.set("LOCAL", "1");
+        variables.set("SHARED", "2");
+        variables.export("SHARED");
+        variables.export("EMPTY");
+
+        assert_eq!(
+            variables.exported(),
+            vec![
+                ("EMPTY".to_string(), String::new()),
+                ("SHARED".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn assignment_words() {
+        assert_eq!(split_assignment("x=1"), Some(("x", "1")));
+        assert_eq!(split_assignment("_A2=a=b"), Some(("_A2", "a=b")));
+        assert_eq!(split_assignment("x="), Some(("x", "")));
+        assert_eq!(split_assignment("2x=1"), None);
+        assert_eq!(split_assignment("'x'=1"), None);
+        assert_eq!(split_assignment("=1"), None);
+        assert_eq!(split_assignment("echo"), None);
+    }
+
+    #[test]
+    fn new_variables_are_not_exported() {
+        let mut variables = Variables::new();
+        variables.set("FOO", "value");
+
+        assert_eq!(variables.get("FOO"), Some("value"));
+        assert!(!variables.entries["FOO"].exported);
+    }
+}
@@ -0,0 +1,90 @@
+#![allow(clippy::needless_return)]
+
+use shell_starter_rust::{
+    executable::{PathFinder, Runner},
+    prompt::Prompter,
+    Shell,
+};
+
+/// Keeps what the shell writes instead of printing it.
+#[derive(Default)]
+struct Capture {
+    stdout: String,
+    stderr: String,
+}
+
+impl Prompter for Capture {
+    fn read(&mut self) -> anyhow::Result<Option<String>> {
+        return Ok(None);
+    }
+
+    fn prompt(&mut self, prompt: &str) -> anyhow::Result<()> {
+        self.stdout.push_str(prompt);
+        return Ok(());
+    }
+
+    fn error(&mut self, message: &str) -> anyhow::Result<()> {
+        self.stderr.push_str(message);
+        return Ok(());
+    }
+}
+
+#[test]
+fn runs_lines_and_returns_their_status() -> anyhow::Result<()> {
+    let mut shell = Shell::new(Capture::default(), PathFinder::new(), Runner::new());
+
+    assert_eq!(shell.run_line("greeting=hello")?, 0);
+    assert_eq!(shell.run_line("echo $greeting world")?, 0);
+    assert_eq!(shell.run_line("false")?, 1);
+    assert_eq!(shell.run_line("echo $?")?, 0);
+    assert_eq!(shell.run_line("nonexistent_command_xyz")?, 127);
+    assert_eq!(shell.run_line("echo 'open")?, 2);
+
+    assert_eq!(shell.prompter.stdout, "hello world\n1\n");
+    assert!(shell
+        .prompter
+        .stderr
+        .starts_with("nonexistent_command_xyz: command not found\n"));
+    assert_eq!(shell.state.variables.get("greeting"), Some("hello"));
+
+    return Ok(());
+}
+
+#[test]
+fn runs_a_script_from_a_reader() -> anyhow::Result<()> {
+    let mut shell = Shell::new(Capture::default(), PathFinder::new(), Runner::new());
+
+    let script = "count=1\nf() { echo \"call $1\"; }\nf $count\nf two; true && false\n";
+    assert_eq!(shell.run_script(script.as_bytes())?, 1);
+    assert_eq!(shell.prompter.stdout, "call 1\ncall two\n");
+    assert_eq!(shell.state.line_number, 4);
+
+    return Ok(());
+}
+
+#[test]
+fn exit_hands_its_status_back() -> anyhow::Result<()> {
+    let mut shell = Shell::new(Capture::default(), PathFinder::new(), Runner::new());
+
+    assert_eq!(shell.run_line("echo a | exit 4; echo piped")?, 0);
+    assert_eq!(shell.exit_status(), None);
+
+    assert_eq!(shell.run_line("echo before; exit 3; echo after")?, 3);
+    assert_eq!(shell.exit_status(), Some(3));
+    assert_eq!(shell.prompter.stdout, "piped\nbefore\n");
+
+    let mut shell = Shell::new(Capture::default(), PathFinder::new(), Runner::new());
+    let script = "f() { exit 5; echo in f; }\nf\necho never\n";
+    assert_eq!(shell.run_script(script.as_bytes())?, 5);
+    assert_eq!(shell.prompter.stdout, "");
+
+    let mut shell = Shell::new(Capture::default(), PathFinder::new(), Runner::new());
+    assert_eq!(shell.run_line("exit nope")?, 2);
+    assert_eq!(
+        shell.prompter.stderr,
+        "exit: nope: numeric argument required\n"
+    );
+    assert_eq!(shell.exit_status(), Some(2));
+
+    return Ok(());
+}
@@ -0,0 +1,228 @@
+#![allow(clippy::needless_return)]
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn run_shell(input: &str) -> anyhow::Result<Output> {
+    return run_shell_with(
+        input,
+        Command::new(env!("CARGO_BIN_EXE_shell-starter-rust")),
+    );
+}
+
+fn run_shell_with(input: &str, mut command: Command) -> anyhow::Result<Output> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+
+    return Ok(child.wait_with_output()?);
+}
+
+#[test]
+fn runs_piped_commands_without_a_prompt() -> anyhow::Result<()> {
+    let output = run_shell("echo hi\n\necho there\n")?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "hi\nthere\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    return Ok(());
+}
+
+#[test]
+fn exits_with_the_last_status() -> anyhow::Result<()> {
+    let output = run_shell("echo hi\nnonexistent_command_xyz")?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "hi\n");
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "nonexistent_command_xyz: command not found\n"
+    );
+    assert_eq!(output.status.code(), Some(127));
+
+    return Ok(());
+}
+
+#[test]
+fn failed_builtin_does_not_stop_the_script() -> anyhow::Result<()> {
+    let output = run_shell("cd /nonexistent_dir\necho $?\necho after\n")?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "1\nafter\n");
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "cd: /nonexistent_dir: No such file or directory\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    return Ok(());
+}
+
+#[test]
+fn debug_parse_prints_the_parsed_command_to_stderr() -> anyhow::Result<()> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command.env("SHELL_DEBUG", "1");
+    let output = run_shell_with("echo 'a  b' c\n", command)?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "a  b c\n");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains(r#"parse_args: ["echo", "a  b", "c"]"#),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("command: Command {"), "{}", stderr);
+
+    let output = run_shell("echo 'a  b' c\n")?;
+    assert_eq!(String::from_utf8(output.stderr)?, "");
+
+    return Ok(());
+}
+
+#[test]
+fn crlf_applies_to_terminal_output_only() -> anyhow::Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let file = dir.path().join("out.txt");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command.env("SHELL_CRLF", "1");
+    let output = run_shell_with(
+        &format!("echo to file > {}\necho to terminal\n", file.display()),
+        command,
+    )?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "to terminal\r\n");
+    assert_eq!(std::fs::read_to_string(&file)?, "to file\n");
+
+    return Ok(());
+}
+
+#[test]
+fn version_prints_and_exits() -> anyhow::Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .arg("--version")
+        .stdin(Stdio::piped())
+        .output()?;
+
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        format!("shell-starter-rust {}\n", env!("CARGO_PKG_VERSION"))
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    return Ok(());
+}
+
+#[test]
+fn runs_a_command_string_and_a_script() -> anyhow::Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .args(["-c", "echo $1; exit 3", "name", "arg"])
+        .output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "arg\n");
+    assert_eq!(output.status.code(), Some(3));
+
+    let dir = tempfile::TempDir::new()?;
+    let script = dir.path().join("script.sh");
+    std::fs::write(&script, "echo first $1\n\necho second\n")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .arg(&script)
+        .arg("x")
+        .output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "first x\nsecond\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .arg("--nope")
+        .output()?;
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "--nope: invalid option\n"
+    );
+    assert_eq!(output.status.code(), Some(2));
+
+    return Ok(());
+}
+
+#[test]
+fn source_dash_reads_commands_from_stdin() -> anyhow::Result<()> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command.args(["-c", "source - one two; echo after $#"]);
+    let output = run_shell_with("echo from stdin $2\nf() { echo in f; }\nf\n", command)?;
+
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "from stdin two\nin f\nafter 0\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    return Ok(());
+}
+
+#[test]
+fn dash_n_checks_a_script_without_running_it() -> anyhow::Result<()> {
+    let dir = tempfile::TempDir::new()?;
+    let valid = dir.path().join("valid.sh");
+    std::fs::write(&valid, "echo ran\nif true; then\n  echo yes\nfi\n")?;
+    let unterminated = dir.path().join("unterminated.sh");
+    std::fs::write(&unterminated, "echo ran\nif true; then\n  echo yes\n")?;
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command.arg("-n").arg(&valid);
+    let output = run_shell_with("", command)?;
+    assert_eq!(String::from_utf8(output.stdout)?, "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"));
+    command.arg("-n").arg(&unterminated);
+    let output = run_shell_with("", command)?;
+    assert_eq!(String::from_utf8(output.stdout)?, "");
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        format!(
+            "{}: line 3: syntax error: unexpected end of file (expected 'fi')\n",
+            unterminated.display()
+        )
+    );
+    assert_eq!(output.status.code(), Some(2));
+
+    return Ok(());
+}
+
+#[test]
+fn unwritable_redirect_target_is_reported_and_the_shell_goes_on() -> anyhow::Result<()> {
+    // A directory cannot be opened for writing, even by root.
+    let dir = tempfile::TempDir::new()?;
+    let target = dir.path().display();
+    let output = run_shell(&format!(
+        "echo lost > {}\necho $?\necho lost 2>&1 | cat >> {}\necho still here\n",
+        target, target
+    ))?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "1\nstill here\n");
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        format!("{}: Is a directory\n{}: Is a directory\n", target, target)
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    return Ok(());
+}
+
+#[test]
+fn exit_trap_runs_as_the_shell_exits() -> anyhow::Result<()> {
+    let output = run_shell("trap 'echo bye' EXIT\necho hi\nexit 3\necho never\n")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "hi\nbye\n");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = run_shell("trap 'echo bye' EXIT\nfalse\n")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "bye\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    return Ok(());
+}